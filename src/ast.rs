@@ -1,39 +1,382 @@
-#[derive(Debug, PartialEq)]
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::target;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
-    Int,
+    /// Plain `char`, a distinct type from both `signed char` and
+    /// `unsigned char`.
     Char,
+    SChar,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    LongLong,
+    ULongLong,
     Float,
     Double,
     Bool,
     Void,
+    Pointer(Box<QualifiedType>),
+    /// Element type and length; the length is `None` until an initializer
+    /// or a later declaration completes the type.
+    Array(Box<QualifiedType>, Option<usize>),
+    Function(FunctionType),
+    /// Refers to an entry of `TypeTable::structs` by its unique name.
+    Struct(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TypeQualifiers {
+    pub is_const: bool,
+    pub is_volatile: bool,
+    pub is_restrict: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualifiedType {
+    pub data_type: DataType,
+    pub qualifiers: TypeQualifiers,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionType {
+    pub return_type: Box<QualifiedType>,
+    pub parameters: Vec<QualifiedType>,
+    /// `false` for declarations written with an empty parameter list, which
+    /// say nothing about the arguments the function takes.
+    pub has_prototype: bool,
+    /// Whether the parameter list ends in `...`.
+    pub is_variadic: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageClass {
+    Auto,
+    Register,
+    Static,
+    Extern,
+    /// Only seen by the parser, which resolves typedef names itself.
+    Typedef,
+}
+
+impl DataType {
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            DataType::Char
+                | DataType::SChar
+                | DataType::UChar
+                | DataType::Short
+                | DataType::UShort
+                | DataType::Int
+                | DataType::UInt
+                | DataType::Long
+                | DataType::ULong
+                | DataType::LongLong
+                | DataType::ULongLong
+                | DataType::Bool
+        )
+    }
+
+    pub fn is_signed(&self) -> bool {
+        match self {
+            DataType::Char => target::CHAR_IS_SIGNED,
+            DataType::SChar
+            | DataType::Short
+            | DataType::Int
+            | DataType::Long
+            | DataType::LongLong => true,
+            _ => self.is_floating(),
+        }
+    }
+
+    pub fn is_character(&self) -> bool {
+        matches!(self, DataType::Char | DataType::SChar | DataType::UChar)
+    }
+
+    pub fn integer_rank(&self) -> Option<usize> {
+        target::scalar_layout(self)?.rank
+    }
+
+    /// The unsigned type of the same rank; unsigned types map to themselves.
+    pub fn to_unsigned(&self) -> DataType {
+        match self {
+            DataType::Char | DataType::SChar => DataType::UChar,
+            DataType::Short => DataType::UShort,
+            DataType::Int => DataType::UInt,
+            DataType::Long => DataType::ULong,
+            DataType::LongLong => DataType::ULongLong,
+            _ => self.clone(),
+        }
+    }
+
+    /// Integer promotion (C11 6.3.1.1p2): every type of lower rank than
+    /// `int` fits in `int` on this target.
+    pub fn promoted(&self) -> DataType {
+        match self.integer_rank() {
+            Some(rank) if rank < DataType::Int.integer_rank().unwrap() => DataType::Int,
+            _ => self.clone(),
+        }
+    }
+
+    /// The usual arithmetic conversions (C11 6.3.1.8).
+    pub fn common_type(&self, other: &DataType) -> DataType {
+        if *self == DataType::Double || *other == DataType::Double {
+            return DataType::Double;
+        }
+        if *self == DataType::Float || *other == DataType::Float {
+            return DataType::Float;
+        }
+
+        let (left, right) = (self.promoted(), other.promoted());
+        if left == right {
+            return left;
+        }
+
+        let (left_rank, right_rank) = (left.integer_rank(), right.integer_rank());
+        if left.is_signed() == right.is_signed() {
+            return if left_rank >= right_rank { left } else { right };
+        }
+
+        let (signed, unsigned) = if left.is_signed() {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        if unsigned.integer_rank() >= signed.integer_rank() {
+            unsigned
+        } else if signed.size() > unsigned.size() {
+            signed
+        } else {
+            signed.to_unsigned()
+        }
+    }
+
+    fn size(&self) -> Option<usize> {
+        Some(target::scalar_layout(self)?.size)
+    }
+
+    /// Converts an integer value to this integer type: `_Bool` compares
+    /// against zero, other types keep the low bits, sign- or zero-extended.
+    pub fn wrap_integer(&self, value: i128) -> i128 {
+        if *self == DataType::Bool {
+            return (value != 0) as i128;
+        }
+
+        let bits = self.size().unwrap() * 8;
+        let value = value & ((1i128 << bits) - 1);
+        if self.is_signed() && value >> (bits - 1) != 0 {
+            value - (1i128 << bits)
+        } else {
+            value
+        }
+    }
+
+    pub fn can_represent(&self, value: i128) -> bool {
+        self.wrap_integer(value) == value
+    }
+
+    pub fn is_floating(&self) -> bool {
+        matches!(self, DataType::Float | DataType::Double)
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || self.is_floating()
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, DataType::Pointer(_))
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        self.is_arithmetic() || self.is_pointer()
+    }
+
+    pub fn is_function(&self) -> bool {
+        matches!(self, DataType::Function(_))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, DataType::Array(_, _))
+    }
+
+    pub fn is_aggregate(&self) -> bool {
+        matches!(self, DataType::Array(_, _) | DataType::Struct(_))
+    }
+
+    pub fn pointee(&self) -> Option<&QualifiedType> {
+        match self {
+            DataType::Pointer(pointee) => Some(pointee),
+            _ => None,
+        }
+    }
+
+    pub fn element(&self) -> Option<&QualifiedType> {
+        match self {
+            DataType::Array(element, _) => Some(element),
+            _ => None,
+        }
+    }
+}
+
+impl QualifiedType {
+    pub fn new(data_type: DataType) -> Self {
+        QualifiedType {
+            data_type,
+            qualifiers: TypeQualifiers::default(),
+        }
+    }
+
+    pub fn pointer_to(self) -> Self {
+        QualifiedType::new(DataType::Pointer(Box::new(self)))
+    }
+
+    /// The type of the value read out of an lvalue of this type.
+    pub fn unqualified(&self) -> Self {
+        QualifiedType::new(self.data_type.clone())
+    }
+}
+
+impl TypeQualifiers {
+    fn names(&self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.is_const, "const"),
+            (self.is_volatile, "volatile"),
+            (self.is_restrict, "restrict"),
+        ]
+        .into_iter()
+        .filter_map(|(present, name)| present.then_some(name))
+    }
+}
+
+/// Spells the type as C does around `declarator`, the part of an
+/// abstract declarator built so far: `int *`, `char (*)[4]`.
+fn spell(data_type: &DataType, qualifiers: TypeQualifiers, declarator: String) -> String {
+    // Suffixes bind tighter than `*`, which then needs parentheses.
+    let parenthesized = |declarator: String| {
+        if declarator.starts_with('*') {
+            format!("({})", declarator)
+        } else {
+            declarator
+        }
+    };
+    let base = match data_type {
+        DataType::Pointer(pointee) => {
+            let mut pointer = "*".to_string();
+            for name in qualifiers.names() {
+                pointer.push_str(name);
+                pointer.push(' ');
+            }
+            if declarator.is_empty() {
+                pointer.truncate(pointer.trim_end().len());
+            }
+            pointer.push_str(&declarator);
+            return spell(&pointee.data_type, pointee.qualifiers, pointer);
+        }
+        DataType::Array(element, length) => {
+            let length = length.map_or(String::new(), |length| length.to_string());
+            let array = format!("{}[{}]", parenthesized(declarator), length);
+            return spell(&element.data_type, element.qualifiers, array);
+        }
+        DataType::Function(function) => {
+            let mut parameters: Vec<String> = function
+                .parameters
+                .iter()
+                .map(|parameter| parameter.to_string())
+                .collect();
+            if function.is_variadic {
+                parameters.push("...".to_string());
+            }
+            if function.has_prototype && parameters.is_empty() {
+                parameters.push("void".to_string());
+            }
+            let function_declarator =
+                format!("{}({})", parenthesized(declarator), parameters.join(", "));
+            let return_type = &function.return_type;
+            return spell(
+                &return_type.data_type,
+                return_type.qualifiers,
+                function_declarator,
+            );
+        }
+        DataType::Char => "char".to_string(),
+        DataType::SChar => "signed char".to_string(),
+        DataType::UChar => "unsigned char".to_string(),
+        DataType::Short => "short".to_string(),
+        DataType::UShort => "unsigned short".to_string(),
+        DataType::Int => "int".to_string(),
+        DataType::UInt => "unsigned int".to_string(),
+        DataType::Long => "long".to_string(),
+        DataType::ULong => "unsigned long".to_string(),
+        DataType::LongLong => "long long".to_string(),
+        DataType::ULongLong => "unsigned long long".to_string(),
+        DataType::Float => "float".to_string(),
+        DataType::Double => "double".to_string(),
+        DataType::Bool => "_Bool".to_string(),
+        DataType::Void => "void".to_string(),
+        // The parser makes tags unique by appending `.n`.
+        DataType::Struct(name) => format!("struct {}", name.split('.').next().unwrap()),
+    };
+    let mut spelled: Vec<&str> = qualifiers.names().collect();
+    spelled.push(&base);
+    let spelled = spelled.join(" ");
+    match declarator.as_str() {
+        "" => spelled,
+        declarator if declarator.starts_with('[') => format!("{}{}", spelled, declarator),
+        declarator => format!("{} {}", spelled, declarator),
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&spell(self, TypeQualifiers::default(), String::new()))
+    }
+}
+
+impl fmt::Display for QualifiedType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&spell(&self.data_type, self.qualifiers, String::new()))
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BooleanLiteral {
     pub value: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IntegerLiteral {
-    pub value: i64,
+    pub value: u64,
+    /// Picked from the value, base and suffix following C11 6.4.4.1.
+    pub data_type: DataType,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FloatLiteral {
     pub value: f64,
+    /// `Float` with an `f` suffix and `Double` otherwise; there is no
+    /// `long double`, so an `l` suffix also gives `Double`.
+    pub data_type: DataType,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CharLiteral {
     pub value: char,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StringLiteral {
-    pub value: String,
+    /// The bytes of the literal, without the terminating null.
+    pub value: Vec<u8>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Literal {
     Boolean(BooleanLiteral),
     Integer(IntegerLiteral),
@@ -47,40 +390,572 @@ pub struct Identifier {
     pub name: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Plus,
+    LogicalNot,
+    BitwiseNot,
+    Dereference,
+    AddressOf,
+    PreIncrement,
+    PreDecrement,
+    PostIncrement,
+    PostDecrement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+    LogicalAnd,
+    LogicalOr,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl BinaryOperator {
+    /// The operator as written in C.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%",
+            BinaryOperator::BitwiseAnd => "&",
+            BinaryOperator::BitwiseOr => "|",
+            BinaryOperator::BitwiseXor => "^",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>",
+            BinaryOperator::LogicalAnd => "&&",
+            BinaryOperator::LogicalOr => "||",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Equal
+                | BinaryOperator::NotEqual
+                | BinaryOperator::Less
+                | BinaryOperator::LessEqual
+                | BinaryOperator::Greater
+                | BinaryOperator::GreaterEqual
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnaryExpr {
+    pub operator: UnaryOperator,
+    pub operand: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BinaryExpr {
+    pub operator: BinaryOperator,
+    pub left: Box<Expr>,
+    pub right: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    /// Set for compound assignments such as `+=`.
+    pub operator: Option<BinaryOperator>,
+    pub target: Box<Expr>,
+    pub value: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub callee: Box<Expr>,
+    pub arguments: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cast {
+    pub target_type: QualifiedType,
+    pub value: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Conditional {
+    pub condition: Box<Expr>,
+    pub then_value: Box<Expr>,
+    pub else_value: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Comma {
+    pub left: Box<Expr>,
+    pub right: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub array: Box<Expr>,
+    pub index: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub object: Box<Expr>,
+    pub member: String,
+    /// `->` rather than `.`
+    pub is_arrow: bool,
+}
+
+/// The `<stdarg.h>` macros, which are built in.
+#[derive(Debug, Clone)]
+pub enum Stdarg {
+    /// `va_start(list, last)`, where `last` names the last named parameter
+    /// and may be left out as C23 allows.
+    Start(Box<Expr>, Option<Box<Expr>>),
+    Arg(Box<Expr>, QualifiedType),
+    End(Box<Expr>),
+    /// `va_copy(destination, source)`.
+    Copy(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
 pub enum Expr {
     Literal(Literal),
     Identifier(Identifier),
+    Unary(UnaryExpr),
+    Binary(BinaryExpr),
+    Assignment(Assignment),
+    Call(Call),
+    Cast(Cast),
+    SizeofExpr(Box<Expr>),
+    SizeofType(QualifiedType),
+    AlignofType(QualifiedType),
+    Conditional(Conditional),
+    Comma(Comma),
+    Index(Index),
+    Member(Member),
+    Stdarg(Stdarg),
 }
 
-#[derive(Debug)]
-pub struct Assignment {
-    pub data_type: DataType,
+#[derive(Debug, Clone)]
+pub enum Designator {
+    Member(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct InitializerItem {
+    pub designators: Vec<Designator>,
+    pub initializer: Initializer,
+}
+
+#[derive(Debug, Clone)]
+pub enum Initializer {
+    Expr(Expr),
+    List(Vec<InitializerItem>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub storage_class: Option<StorageClass>,
+    pub data_type: QualifiedType,
     pub identifier: Identifier,
-    pub value: Expr,
+    pub initializer: Option<Initializer>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Return {
+    pub value: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct If {
+    pub condition: Expr,
+    pub then_branch: Box<Instruction>,
+    pub else_branch: Option<Box<Instruction>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct While {
+    pub condition: Expr,
+    pub body: Box<Instruction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DoWhile {
+    pub body: Box<Instruction>,
+    pub condition: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct For {
+    /// Declarations or an expression statement; scoped to the loop.
+    pub initializer: Vec<Instruction>,
+    pub condition: Option<Expr>,
+    pub increment: Option<Expr>,
+    pub body: Box<Instruction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Switch {
+    pub condition: Expr,
+    pub body: Box<Instruction>,
+}
+
+/// A `case` label and the statement it labels. The value must be an integer
+/// constant expression.
+#[derive(Debug, Clone)]
+pub struct Case {
     pub value: Expr,
+    pub body: Box<Instruction>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct Labeled {
+    pub label: String,
+    pub body: Box<Instruction>,
+}
+
+#[derive(Debug, Clone)]
 pub enum Instruction {
-    Assignment(Assignment),
+    Declaration(Declaration),
     Return(Return),
+    Expression(Expr),
+    Block(Block),
+    If(If),
+    While(While),
+    DoWhile(DoWhile),
+    For(For),
+    Switch(Switch),
+    Case(Case),
+    Default(Box<Instruction>),
+    Labeled(Labeled),
+    Goto(String),
+    Break,
+    Continue,
+    Empty,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub storage_class: Option<StorageClass>,
+    pub data_type: QualifiedType,
+    pub identifier: Option<Identifier>,
+}
+
+/// The `inline` specifier and the attributes that steer inlining, from
+/// the definition and every earlier declaration of a function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionAttributes {
+    pub is_inline: bool,
+    pub always_inline: bool,
+    pub noinline: bool,
+}
+
+impl FunctionAttributes {
+    pub fn merge(&mut self, other: FunctionAttributes) {
+        self.is_inline |= other.is_inline;
+        self.always_inline |= other.always_inline;
+        self.noinline |= other.noinline;
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Function {
-    pub return_type: DataType,
+    pub storage_class: Option<StorageClass>,
+    pub attributes: FunctionAttributes,
+    pub return_type: QualifiedType,
     pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub is_variadic: bool,
     pub instructions: Vec<Instruction>,
 }
 
-#[derive(Debug)]
+impl Function {
+    pub fn function_type(&self) -> FunctionType {
+        FunctionType {
+            return_type: Box::new(self.return_type.clone()),
+            parameters: self
+                .parameters
+                .iter()
+                .map(|parameter| parameter.data_type.clone())
+                .collect(),
+            has_prototype: true,
+            is_variadic: self.is_variadic,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExternalDeclaration {
+    Function(Function),
+    Declaration(Declaration),
+}
+
+#[derive(Debug, Clone)]
+pub struct StructMember {
+    pub name: String,
+    pub data_type: QualifiedType,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDefinition {
+    pub name: String,
+    /// `None` while the struct is only declared.
+    pub members: Option<Vec<StructMember>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructLayout {
+    pub size: usize,
+    pub alignment: usize,
+    pub offsets: Vec<usize>,
+}
+
+/// The struct `va_list` is an array of one of.
+pub const VA_LIST_TAG: &str = "__va_list_tag";
+
+/// Struct definitions of a translation unit, keyed by the unique names the
+/// parser gives each tag, and the size and alignment rules built on them.
+#[derive(Debug, Clone, Default)]
+pub struct TypeTable {
+    pub structs: HashMap<String, StructDefinition>,
+}
+
+impl TypeTable {
+    /// Size in bytes, or `None` for incomplete and function types.
+    pub fn size_of(&self, data_type: &DataType) -> Option<usize> {
+        match data_type {
+            DataType::Array(element, Some(length)) => {
+                Some(self.size_of(&element.data_type)? * length)
+            }
+            DataType::Struct(name) => Some(self.struct_layout(name)?.size),
+            DataType::Void | DataType::Function(_) | DataType::Array(_, None) => None,
+            scalar => Some(target::scalar_layout(scalar)?.size),
+        }
+    }
+
+    pub fn alignment_of(&self, data_type: &DataType) -> Option<usize> {
+        match data_type {
+            DataType::Array(element, _) => self.alignment_of(&element.data_type),
+            DataType::Struct(name) => Some(self.struct_layout(name)?.alignment),
+            _ => self.size_of(data_type),
+        }
+    }
+
+    pub fn is_complete(&self, data_type: &DataType) -> bool {
+        self.size_of(data_type).is_some()
+    }
+
+    pub fn struct_layout(&self, name: &str) -> Option<StructLayout> {
+        let members = self.structs.get(name)?.members.as_ref()?;
+
+        let mut offsets = vec![];
+        let mut size: usize = 0;
+        let mut alignment = 1;
+        for member in members {
+            let member_alignment = self.alignment_of(&member.data_type.data_type)?;
+            size = size.next_multiple_of(member_alignment);
+            offsets.push(size);
+            size += self.size_of(&member.data_type.data_type)?;
+            alignment = alignment.max(member_alignment);
+        }
+
+        Some(StructLayout {
+            size: size.next_multiple_of(alignment),
+            alignment,
+            offsets,
+        })
+    }
+
+    /// Defines the struct behind `va_list`, as the System V ABI lays it
+    /// out, and returns the type `va_list`.
+    pub fn define_va_list(&mut self) -> QualifiedType {
+        let member = |name: &str, data_type: DataType| StructMember {
+            name: name.to_string(),
+            data_type: QualifiedType::new(data_type),
+        };
+        let pointer = || DataType::Pointer(Box::new(QualifiedType::new(DataType::Void)));
+        self.structs.insert(
+            VA_LIST_TAG.to_string(),
+            StructDefinition {
+                name: VA_LIST_TAG.to_string(),
+                members: Some(vec![
+                    member("gp_offset", DataType::UInt),
+                    member("fp_offset", DataType::UInt),
+                    member("overflow_arg_area", pointer()),
+                    member("reg_save_area", pointer()),
+                ]),
+            },
+        );
+        let tag = QualifiedType::new(DataType::Struct(VA_LIST_TAG.to_string()));
+        QualifiedType::new(DataType::Array(Box::new(tag), Some(1)))
+    }
+
+    /// Looks up a member by name, returning its offset and declared type.
+    pub fn member(&self, struct_name: &str, member: &str) -> Option<(usize, QualifiedType)> {
+        let members = self.structs.get(struct_name)?.members.as_ref()?;
+        let index = members.iter().position(|m| m.name == member)?;
+        let layout = self.struct_layout(struct_name)?;
+        Some((layout.offsets[index], members[index].data_type.clone()))
+    }
+}
+
+impl Expr {
+    /// Evaluates an integer constant expression, or returns `None` if the
+    /// expression is not one.
+    pub fn evaluate_constant(&self, types: &TypeTable) -> Option<i64> {
+        let (value, _) = self.evaluate_typed_constant(types)?;
+        Some(value as i64)
+    }
+
+    /// Evaluates an integer constant expression to its value and type.
+    /// Operations with undefined behavior, such as signed overflow or
+    /// division by zero, make the expression non-constant.
+    pub fn evaluate_typed_constant(&self, types: &TypeTable) -> Option<(i128, DataType)> {
+        let checked = |value: i128, data_type: DataType| {
+            if data_type.is_signed() && !data_type.can_represent(value) {
+                return None;
+            }
+            Some((data_type.wrap_integer(value), data_type))
+        };
+
+        match self {
+            Expr::Literal(Literal::Integer(literal)) => {
+                Some((literal.value as i128, literal.data_type.clone()))
+            }
+            Expr::Literal(Literal::Char(literal)) => Some((
+                DataType::Char.wrap_integer(literal.value as i128),
+                DataType::Int,
+            )),
+            Expr::Literal(Literal::Boolean(literal)) => {
+                Some((literal.value as i128, DataType::Bool))
+            }
+            Expr::Unary(unary) => {
+                let (operand, operand_type) = unary.operand.evaluate_typed_constant(types)?;
+                let data_type = operand_type.promoted();
+                match unary.operator {
+                    UnaryOperator::Negate => checked(-operand, data_type),
+                    UnaryOperator::Plus => Some((operand, data_type)),
+                    UnaryOperator::LogicalNot => Some(((operand == 0) as i128, DataType::Int)),
+                    UnaryOperator::BitwiseNot => checked(!operand, data_type),
+                    _ => None,
+                }
+            }
+            Expr::Binary(binary) => {
+                let (left, left_type) = binary.left.evaluate_typed_constant(types)?;
+                if binary.operator == BinaryOperator::LogicalAnd && left == 0 {
+                    return Some((0, DataType::Int));
+                }
+                if binary.operator == BinaryOperator::LogicalOr && left != 0 {
+                    return Some((1, DataType::Int));
+                }
+                let (right, right_type) = binary.right.evaluate_typed_constant(types)?;
+
+                if matches!(
+                    binary.operator,
+                    BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight
+                ) {
+                    let data_type = left_type.promoted();
+                    let bits = types.size_of(&data_type)? as i128 * 8;
+                    if !(0..bits).contains(&right) {
+                        return None;
+                    }
+                    return match binary.operator {
+                        BinaryOperator::ShiftLeft if data_type.is_signed() && left < 0 => None,
+                        BinaryOperator::ShiftLeft => checked(left << right, data_type),
+                        _ => Some((left >> right, data_type)),
+                    };
+                }
+
+                let data_type = left_type.common_type(&right_type);
+                let left = data_type.wrap_integer(left);
+                let right = data_type.wrap_integer(right);
+                let compare = |result: bool| Some((result as i128, DataType::Int));
+
+                match binary.operator {
+                    BinaryOperator::Add => checked(left + right, data_type),
+                    BinaryOperator::Subtract => checked(left - right, data_type),
+                    BinaryOperator::Multiply => checked(left.wrapping_mul(right), data_type),
+                    BinaryOperator::Divide if right != 0 => checked(left / right, data_type),
+                    BinaryOperator::Remainder if right != 0 => checked(left % right, data_type),
+                    BinaryOperator::Divide | BinaryOperator::Remainder => None,
+                    BinaryOperator::BitwiseAnd => Some((left & right, data_type)),
+                    BinaryOperator::BitwiseOr => Some((left | right, data_type)),
+                    BinaryOperator::BitwiseXor => Some((left ^ right, data_type)),
+                    BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => compare(right != 0),
+                    BinaryOperator::Equal => compare(left == right),
+                    BinaryOperator::NotEqual => compare(left != right),
+                    BinaryOperator::Less => compare(left < right),
+                    BinaryOperator::LessEqual => compare(left <= right),
+                    BinaryOperator::Greater => compare(left > right),
+                    BinaryOperator::GreaterEqual => compare(left >= right),
+                    BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => unreachable!(),
+                }
+            }
+            Expr::Cast(cast) => {
+                let (value, _) = cast.value.evaluate_typed_constant(types)?;
+                let data_type = cast.target_type.data_type.clone();
+                if !data_type.is_integer() {
+                    return None;
+                }
+                Some((data_type.wrap_integer(value), data_type))
+            }
+            Expr::SizeofType(data_type) => Some((
+                types.size_of(&data_type.data_type)? as i128,
+                DataType::ULong,
+            )),
+            Expr::AlignofType(data_type) => Some((
+                types.alignment_of(&data_type.data_type)? as i128,
+                DataType::ULong,
+            )),
+            Expr::Conditional(conditional) => {
+                let (condition, _) = conditional.condition.evaluate_typed_constant(types)?;
+                let (then_value, then_type) =
+                    conditional.then_value.evaluate_typed_constant(types)?;
+                let (else_value, else_type) =
+                    conditional.else_value.evaluate_typed_constant(types)?;
+
+                let data_type = then_type.common_type(&else_type);
+                let value = if condition != 0 {
+                    then_value
+                } else {
+                    else_value
+                };
+                Some((data_type.wrap_integer(value), data_type))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Program {
-    pub functions: Vec<Function>,
+    pub declarations: Vec<ExternalDeclaration>,
+    pub types: TypeTable,
 }
 
 pub trait Visitor<T> {
@@ -88,8 +963,9 @@ pub trait Visitor<T> {
     fn visit_literal(&mut self, literal: &Literal) -> T;
     fn visit_identifier(&mut self, identifier: &Identifier) -> T;
     fn visit_expr(&mut self, expr: &Expr) -> T;
-    fn visit_assignment(&mut self, assignment: &Assignment) -> T;
+    fn visit_declaration(&mut self, declaration: &Declaration) -> T;
     fn visit_return(&mut self, return_stmt: &Return) -> T;
+    fn visit_block(&mut self, block: &Block) -> T;
     fn visit_instruction(&mut self, instruction: &Instruction) -> T;
     fn visit_function(&mut self, function: &Function) -> T;
     fn visit_program(&mut self, program: &Program) -> T;
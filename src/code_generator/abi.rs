@@ -0,0 +1,178 @@
+/*
+  Where the System V AMD64 calling convention puts arguments and return
+  values. Integers and pointers go in the six integer argument registers
+  and floats and doubles in the first eight vector registers, in order of
+  their class; the rest are passed on the stack in 8-byte units, in order,
+  from the callee's `16(%rbp)`.
+
+  A struct passed by value is classified by its eightbytes: structs over
+  16 bytes are in memory, and the others take one register per eightbyte,
+  a vector register when only floats and doubles lie in the eightbyte and
+  an integer register otherwise. When not all of those registers are
+  free the whole struct goes on the stack instead. Structs are returned
+  the same way in `%rax` and `%rdx` or `%xmm0` and `%xmm1`; those in
+  memory are stored where the hidden first argument points, and that
+  pointer is returned in `%rax`.
+
+  A variadic function saves the argument registers where `va_arg` can
+  find them, in a register save area: the integer registers come first
+  and then the vector ones, 16 bytes apart.
+*/
+
+use super::machine::{ARGUMENT_REGISTERS, PhysicalRegister, VECTOR_ARGUMENT_REGISTERS};
+use crate::ir::{Aggregate, ByValue, IrType};
+
+/// The size of the register save area.
+pub const REGISTER_SAVE_AREA: usize = 48 + 8 * 16;
+/// Where the vector registers start in the register save area.
+pub const VECTOR_SAVE_OFFSET: usize = 48;
+
+/// The class of an eightbyte of a struct in registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Integer,
+    Sse,
+}
+
+/// The classes of the eightbytes of the struct, or `None` when it is
+/// passed in memory.
+pub fn classify(aggregate: &Aggregate) -> Option<Vec<Class>> {
+    if aggregate.size > 16 {
+        return None;
+    }
+    let classes = (0..aggregate.size.div_ceil(8))
+        .map(|eightbyte| {
+            let floats = aggregate
+                .members
+                .iter()
+                .filter(|(offset, _)| offset / 8 == eightbyte)
+                .all(|(_, ty)| ty.is_float());
+            if floats { Class::Sse } else { Class::Integer }
+        })
+        .collect();
+    Some(classes)
+}
+
+/// Where an argument is passed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    Register(PhysicalRegister),
+    /// A struct in registers, one for each eightbyte.
+    Split(Vec<(Class, PhysicalRegister)>),
+    /// On the stack, at the offset in the argument area.
+    Stack(usize),
+    /// Not passed: the hidden pointer for a struct returned in registers.
+    Omitted,
+}
+
+/// The locations of the arguments of a call or parameters of a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub locations: Vec<Location>,
+    /// The size of the arguments on the stack, a multiple of 8.
+    pub stack_size: usize,
+    /// The number of integer and vector registers used.
+    pub integers: usize,
+    pub vectors: usize,
+}
+
+impl Assignment {
+    /// The argument registers, in order.
+    pub fn registers(&self) -> Vec<PhysicalRegister> {
+        self.locations
+            .iter()
+            .flat_map(|location| match location {
+                Location::Register(register) => vec![*register],
+                Location::Split(registers) => {
+                    registers.iter().map(|(_, register)| *register).collect()
+                }
+                _ => vec![],
+            })
+            .collect()
+    }
+}
+
+/// Assigns the arguments of the types, the pointers among them standing
+/// for the structs in `by_value`, their locations.
+pub fn assign(types: &[IrType], by_value: &ByValue) -> Assignment {
+    let mut integers = 0;
+    let mut vectors = 0;
+    let mut stack_size: usize = 0;
+    let mut stack = |size: usize, alignment: usize| {
+        let offset = stack_size.next_multiple_of(alignment.max(8));
+        stack_size = offset + size.next_multiple_of(8);
+        Location::Stack(offset)
+    };
+
+    let mut locations = vec![];
+    for (index, ty) in types.iter().enumerate() {
+        let location = match by_value.aggregate(index) {
+            Some(aggregate) if index == 0 && by_value.returned.is_some() => {
+                if classify(aggregate).is_some() {
+                    Location::Omitted
+                } else {
+                    integers += 1;
+                    Location::Register(ARGUMENT_REGISTERS[0])
+                }
+            }
+            Some(aggregate) => match classify(aggregate) {
+                Some(classes) => {
+                    let needed = |class| classes.iter().filter(|other| **other == class).count();
+                    if integers + needed(Class::Integer) > ARGUMENT_REGISTERS.len()
+                        || vectors + needed(Class::Sse) > VECTOR_ARGUMENT_REGISTERS.len()
+                    {
+                        stack(aggregate.size, aggregate.alignment)
+                    } else {
+                        let registers = classes
+                            .into_iter()
+                            .map(|class| match class {
+                                Class::Integer => {
+                                    integers += 1;
+                                    (class, ARGUMENT_REGISTERS[integers - 1])
+                                }
+                                Class::Sse => {
+                                    vectors += 1;
+                                    (class, VECTOR_ARGUMENT_REGISTERS[vectors - 1])
+                                }
+                            })
+                            .collect();
+                        Location::Split(registers)
+                    }
+                }
+                None => stack(aggregate.size, aggregate.alignment),
+            },
+            None if ty.is_float() && vectors < VECTOR_ARGUMENT_REGISTERS.len() => {
+                vectors += 1;
+                Location::Register(VECTOR_ARGUMENT_REGISTERS[vectors - 1])
+            }
+            None if !ty.is_float() && integers < ARGUMENT_REGISTERS.len() => {
+                integers += 1;
+                Location::Register(ARGUMENT_REGISTERS[integers - 1])
+            }
+            None => stack(8, 8),
+        };
+        locations.push(location);
+    }
+    Assignment {
+        locations,
+        stack_size,
+        integers,
+        vectors,
+    }
+}
+
+/// The registers a struct returned in registers comes back in, one for
+/// each eightbyte, or `None` when it is not returned in registers.
+pub fn returned(by_value: &ByValue) -> Option<Vec<(Class, PhysicalRegister)>> {
+    let classes = classify(by_value.returned.as_ref()?)?;
+    let mut integers = [PhysicalRegister::Rax, PhysicalRegister::Rdx].into_iter();
+    let mut vectors = [PhysicalRegister::Xmm0, PhysicalRegister::Xmm1].into_iter();
+    let registers = classes
+        .into_iter()
+        .map(|class| match class {
+            Class::Integer => (class, integers.next().unwrap()),
+            Class::Sse => (class, vectors.next().unwrap()),
+        })
+        .collect();
+    Some(registers)
+}
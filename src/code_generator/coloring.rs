@@ -0,0 +1,557 @@
+/*
+  Register allocation by iterated register coalescing (George and Appel,
+  1996). An interference graph is built from liveness, with the physical
+  registers that instructions and the calling convention name as
+  precolored nodes. Low-degree nodes are simplified away, moves are
+  coalesced when the Briggs or George test shows it cannot make the graph
+  uncolorable, and move-related nodes are frozen when nothing else can
+  proceed. When all else fails a node is picked as a potential spill by
+  its use count, weighted by loop depth, over its degree.
+
+  The general-purpose and vector registers are colored together, but
+  nodes only interfere with nodes of their own class, and each class has
+  as many colors as it has registers.
+
+  Colors are chosen by the value's live range: a value live across a call
+  already interferes with every caller-saved register, and any other
+  value prefers caller-saved registers, which cost nothing to use, over
+  callee-saved ones, which the prologue has to save. Among equally good
+  registers the color of a move partner is preferred, so that the move
+  disappears. Values that could not be colored are spilled to stack
+  slots, with a short-lived register around each instruction that uses
+  them, and allocation starts over.
+
+  A function with hundreds of values live at once has millions of
+  interferences, so the graph is kept in vectors indexed by node number
+  rather than in maps keyed by register, and the potential spills stay
+  ordered by their cost instead of being searched for each time.
+*/
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hasher};
+
+use super::machine::{
+    CALLEE_SAVED, CALLER_SAVED, MachineFunction, PhysicalRegister, Register, RegisterClass,
+    VECTOR_REGISTERS,
+};
+use super::{liveness, spill};
+
+/// The degree of precolored nodes, which can never be simplified.
+const INFINITE: usize = usize::MAX / 2;
+
+/// Physical registers are the first nodes, in declaration order, and
+/// virtual register `n` is node `PHYSICAL + n`, so nodes sort like the
+/// registers they stand for.
+const PHYSICAL: usize = PhysicalRegister::Xmm15 as usize + 1;
+
+fn node(register: Register) -> usize {
+    match register {
+        Register::Physical(register) => register as usize,
+        Register::Virtual(number) => PHYSICAL + number as usize,
+    }
+}
+
+fn virtual_register(node: usize) -> Register {
+    Register::Virtual((node - PHYSICAL) as u32)
+}
+
+fn is_precolored(node: usize) -> bool {
+    node < PHYSICAL
+}
+
+/// The key of an edge in the adjacency set, smaller node first.
+fn pair(u: usize, v: usize) -> u64 {
+    let (low, high) = if u < v { (u, v) } else { (v, u) };
+    ((low as u64) << 32) | high as u64
+}
+
+/// Hashes edge keys for the adjacency set, which a large function fills
+/// with millions of them; SipHash costs more than building the graph.
+#[derive(Default)]
+struct PairHasher(u64);
+
+impl Hasher for PairHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64((self.0 << 8) | byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        let product = value.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        self.0 = product ^ (product >> 32);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+struct Coloring {
+    adjacent_set: HashSet<u64, BuildHasherDefault<PairHasher>>,
+    adjacency: Vec<Vec<usize>>,
+    degree: Vec<usize>,
+    /// The moves, by instruction index, each node takes part in.
+    move_list: Vec<BTreeSet<usize>>,
+    /// Source and destination of each move.
+    moves: BTreeMap<usize, (usize, usize)>,
+    alias: Vec<usize>,
+    color: Vec<Option<PhysicalRegister>>,
+    crosses_call: Vec<bool>,
+    spill_cost: Vec<f64>,
+    classes: Vec<RegisterClass>,
+
+    simplify_worklist: BTreeSet<usize>,
+    freeze_worklist: BTreeSet<usize>,
+    /// Potential spills, cheapest first: keyed by the bits of their spill
+    /// cost over their degree, which order like the non-negative value.
+    /// Simplifying a neighbor only raises that ratio, so a node keeps its
+    /// key until it is picked, and then goes back if the key was stale.
+    spill_worklist: BTreeSet<(u64, usize)>,
+    /// The key each node has in the spill worklist, if it is there.
+    spill_keys: Vec<Option<u64>>,
+    spilled_nodes: BTreeSet<usize>,
+    coalesced: Vec<bool>,
+    select_stack: Vec<usize>,
+    on_stack: Vec<bool>,
+
+    worklist_moves: BTreeSet<usize>,
+    active_moves: BTreeSet<usize>,
+}
+
+impl Coloring {
+    fn new(function: &MachineFunction) -> Self {
+        let nodes = PHYSICAL + function.next_virtual as usize;
+        let mut classes = vec![RegisterClass::General; nodes];
+        for register in VECTOR_REGISTERS {
+            classes[register as usize] = RegisterClass::Vector;
+        }
+        for &number in &function.vector_registers {
+            classes[PHYSICAL + number as usize] = RegisterClass::Vector;
+        }
+        let mut color = vec![None; nodes];
+        for class in [RegisterClass::General, RegisterClass::Vector] {
+            for &register in class.allocatable() {
+                color[register as usize] = Some(register);
+            }
+        }
+
+        Coloring {
+            adjacent_set: HashSet::default(),
+            adjacency: vec![vec![]; nodes],
+            degree: vec![0; nodes],
+            move_list: vec![BTreeSet::new(); nodes],
+            moves: BTreeMap::new(),
+            alias: (0..nodes).collect(),
+            color,
+            crosses_call: vec![false; nodes],
+            spill_cost: vec![0.0; nodes],
+            classes,
+            simplify_worklist: BTreeSet::new(),
+            freeze_worklist: BTreeSet::new(),
+            spill_worklist: BTreeSet::new(),
+            spill_keys: vec![None; nodes],
+            spilled_nodes: BTreeSet::new(),
+            coalesced: vec![false; nodes],
+            select_stack: vec![],
+            on_stack: vec![false; nodes],
+            worklist_moves: BTreeSet::new(),
+            active_moves: BTreeSet::new(),
+        }
+    }
+
+    fn build(&mut self, function: &MachineFunction, unspillable: &HashSet<Register>) {
+        let instructions = &function.instructions;
+        let blocks = liveness::blocks(instructions);
+        let live_out = liveness::live_out(instructions, &blocks);
+        let depths = liveness::loop_depths(instructions, &blocks);
+
+        for (block, out) in blocks.iter().zip(live_out) {
+            let mut live = out;
+            for index in (block.start..block.end).rev() {
+                let instruction = &instructions[index];
+                let uses = instruction.uses();
+                let defs = instruction.defs();
+
+                if let Some((src, dst)) = instruction.as_move() {
+                    live.remove(&src);
+                    for register in [src, dst] {
+                        self.move_list[node(register)].insert(index);
+                    }
+                    self.moves.insert(index, (node(src), node(dst)));
+                    self.worklist_moves.insert(index);
+                }
+                if instruction.is_call() {
+                    for register in &live {
+                        if !defs.contains(register) {
+                            self.crosses_call[node(*register)] = true;
+                        }
+                    }
+                }
+
+                live.extend(defs.iter().copied());
+                for &def in &defs {
+                    let def = node(def);
+                    for &other in &live {
+                        let other = node(other);
+                        if self.classes[other] == self.classes[def] {
+                            self.add_edge(other, def);
+                        }
+                    }
+                }
+                for &def in &defs {
+                    live.remove(&def);
+                }
+                live.extend(uses.iter().copied());
+
+                let weight = 10f64.powi(depths[index].min(8) as i32);
+                for register in uses.into_iter().chain(defs) {
+                    let node = node(register);
+                    if !is_precolored(node) {
+                        self.spill_cost[node] += weight;
+                    }
+                }
+            }
+        }
+
+        for register in unspillable {
+            self.spill_cost[node(*register)] = f64::INFINITY;
+        }
+    }
+
+    /// The number of colors for the node.
+    fn k(&self, node: usize) -> usize {
+        self.classes[node].allocatable().len()
+    }
+
+    fn degree(&self, node: usize) -> usize {
+        if is_precolored(node) {
+            INFINITE
+        } else {
+            self.degree[node]
+        }
+    }
+
+    fn spill_key(&self, node: usize) -> u64 {
+        (self.spill_cost[node] / self.degree[node].max(1) as f64).to_bits()
+    }
+
+    fn add_spill(&mut self, node: usize) {
+        let key = self.spill_key(node);
+        self.spill_keys[node] = Some(key);
+        self.spill_worklist.insert((key, node));
+    }
+
+    fn remove_spill(&mut self, node: usize) -> bool {
+        match self.spill_keys[node].take() {
+            Some(key) => self.spill_worklist.remove(&(key, node)),
+            None => false,
+        }
+    }
+
+    fn add_edge(&mut self, u: usize, v: usize) {
+        if u == v || !self.adjacent_set.insert(pair(u, v)) {
+            return;
+        }
+        for (from, to) in [(u, v), (v, u)] {
+            if !is_precolored(from) {
+                self.adjacency[from].push(to);
+                self.degree[from] += 1;
+                if self.remove_spill(from) {
+                    self.add_spill(from);
+                }
+            }
+        }
+    }
+
+    fn make_worklist(&mut self, initial: BTreeSet<usize>) {
+        for node in initial {
+            if self.degree(node) >= self.k(node) {
+                self.add_spill(node);
+            } else if self.move_related(node) {
+                self.freeze_worklist.insert(node);
+            } else {
+                self.simplify_worklist.insert(node);
+            }
+        }
+    }
+
+    fn adjacent(&self, node: usize) -> Vec<usize> {
+        self.adjacency[node]
+            .iter()
+            .filter(|&&other| !self.on_stack[other] && !self.coalesced[other])
+            .copied()
+            .collect()
+    }
+
+    fn node_moves(&self, node: usize) -> Vec<usize> {
+        self.move_list[node]
+            .iter()
+            .filter(|index| {
+                self.active_moves.contains(index) || self.worklist_moves.contains(index)
+            })
+            .copied()
+            .collect()
+    }
+
+    fn move_related(&self, node: usize) -> bool {
+        !self.node_moves(node).is_empty()
+    }
+
+    fn simplify(&mut self, node: usize) {
+        self.simplify_worklist.remove(&node);
+        self.select_stack.push(node);
+        self.on_stack[node] = true;
+        for other in self.adjacent(node) {
+            self.decrement_degree(other);
+        }
+    }
+
+    fn decrement_degree(&mut self, node: usize) {
+        if is_precolored(node) {
+            return;
+        }
+        let degree = self.degree[node];
+        self.degree[node] = degree - 1;
+        if degree == self.k(node) {
+            let mut nodes = self.adjacent(node);
+            nodes.push(node);
+            self.enable_moves(&nodes);
+            self.remove_spill(node);
+            if self.move_related(node) {
+                self.freeze_worklist.insert(node);
+            } else {
+                self.simplify_worklist.insert(node);
+            }
+        }
+    }
+
+    fn enable_moves(&mut self, nodes: &[usize]) {
+        for &node in nodes {
+            for index in self.node_moves(node) {
+                if self.active_moves.remove(&index) {
+                    self.worklist_moves.insert(index);
+                }
+            }
+        }
+    }
+
+    fn alias(&self, mut node: usize) -> usize {
+        while self.coalesced[node] {
+            node = self.alias[node];
+        }
+        node
+    }
+
+    fn add_worklist(&mut self, node: usize) {
+        if !is_precolored(node) && !self.move_related(node) && self.degree(node) < self.k(node) {
+            self.freeze_worklist.remove(&node);
+            self.simplify_worklist.insert(node);
+        }
+    }
+
+    /// George's test: coalescing `u` into `v` is safe if every neighbor of
+    /// `v` is insignificant or already interferes with `u`.
+    fn ok(&self, neighbor: usize, u: usize) -> bool {
+        self.degree(neighbor) < self.k(neighbor)
+            || is_precolored(neighbor)
+            || self.adjacent_set.contains(&pair(neighbor, u))
+    }
+
+    /// Briggs's test: combining `u` and `v` leaves fewer than `k`
+    /// neighbors of significant degree, for the `k` of their class.
+    fn conservative(&self, u: usize, v: usize) -> bool {
+        let k = self.k(u);
+        let shared = |node: &usize| self.adjacent_set.contains(&pair(*node, u));
+        let significant = self
+            .adjacent(u)
+            .into_iter()
+            .chain(self.adjacent(v).into_iter().filter(|node| !shared(node)))
+            .filter(|node| self.degree(*node) >= k);
+        significant.take(k).count() < k
+    }
+
+    fn coalesce(&mut self, index: usize) {
+        self.worklist_moves.remove(&index);
+        let (src, dst) = self.moves[&index];
+        let (x, y) = (self.alias(src), self.alias(dst));
+        let (u, v) = if is_precolored(y) { (y, x) } else { (x, y) };
+
+        if u == v {
+            self.add_worklist(u);
+        } else if is_precolored(v) || self.adjacent_set.contains(&pair(u, v)) {
+            self.add_worklist(u);
+            self.add_worklist(v);
+        } else if (is_precolored(u)
+            && self
+                .adjacent(v)
+                .into_iter()
+                .all(|neighbor| self.ok(neighbor, u)))
+            || (!is_precolored(u) && self.conservative(u, v))
+        {
+            self.combine(u, v);
+            self.add_worklist(u);
+        } else {
+            self.active_moves.insert(index);
+        }
+    }
+
+    fn combine(&mut self, u: usize, v: usize) {
+        if !self.freeze_worklist.remove(&v) {
+            self.remove_spill(v);
+        }
+        self.coalesced[v] = true;
+        self.alias[v] = u;
+        let moves = self.move_list[v].clone();
+        self.move_list[u].extend(moves);
+        if self.crosses_call[v] {
+            self.crosses_call[u] = true;
+        }
+        self.enable_moves(&[v]);
+        for neighbor in self.adjacent(v) {
+            self.add_edge(neighbor, u);
+            self.decrement_degree(neighbor);
+        }
+        if self.degree(u) >= self.k(u) && self.freeze_worklist.remove(&u) {
+            self.add_spill(u);
+        }
+    }
+
+    fn freeze(&mut self, node: usize) {
+        self.freeze_worklist.remove(&node);
+        self.simplify_worklist.insert(node);
+        self.freeze_moves(node);
+    }
+
+    fn freeze_moves(&mut self, u: usize) {
+        for index in self.node_moves(u) {
+            let (x, y) = self.moves[&index];
+            let v = if self.alias(y) == self.alias(u) {
+                self.alias(x)
+            } else {
+                self.alias(y)
+            };
+            self.active_moves.remove(&index);
+            if self.freeze_worklist.contains(&v) && self.node_moves(v).is_empty() {
+                self.freeze_worklist.remove(&v);
+                self.simplify_worklist.insert(v);
+            }
+        }
+    }
+
+    fn select_spill(&mut self) {
+        let node = loop {
+            let (key, node) = self.spill_worklist.pop_first().unwrap();
+            self.spill_keys[node] = None;
+            if self.spill_key(node) == key {
+                break node;
+            }
+            self.add_spill(node);
+        };
+        self.simplify_worklist.insert(node);
+        self.freeze_moves(node);
+    }
+
+    fn assign_colors(&mut self) {
+        while let Some(node) = self.select_stack.pop() {
+            self.on_stack[node] = false;
+            let mut forbidden = [false; PHYSICAL];
+            for &neighbor in &self.adjacency[node] {
+                if let Some(color) = self.color[self.alias(neighbor)] {
+                    forbidden[color as usize] = true;
+                }
+            }
+
+            let preferred: Vec<PhysicalRegister> = match self.classes[node] {
+                RegisterClass::Vector => VECTOR_REGISTERS.to_vec(),
+                _ if self.crosses_call[node] => {
+                    CALLEE_SAVED.iter().chain(&CALLER_SAVED).copied().collect()
+                }
+                _ => CALLER_SAVED.iter().chain(&CALLEE_SAVED).copied().collect(),
+            };
+            let available: Vec<PhysicalRegister> = preferred
+                .into_iter()
+                .filter(|color| !forbidden[*color as usize])
+                .collect();
+            let partners: Vec<PhysicalRegister> = self.move_list[node]
+                .iter()
+                .filter_map(|index| {
+                    let (src, dst) = self.moves[index];
+                    let partner = if self.alias(src) == node { dst } else { src };
+                    self.color[self.alias(partner)]
+                })
+                .collect();
+
+            match available
+                .iter()
+                .find(|color| partners.contains(color))
+                .or(available.first())
+            {
+                Some(color) => {
+                    self.color[node] = Some(*color);
+                }
+                None => {
+                    self.spilled_nodes.insert(node);
+                }
+            }
+        }
+        for node in 0..self.coalesced.len() {
+            if self.coalesced[node] {
+                self.color[node] = self.color[self.alias(node)];
+            }
+        }
+    }
+}
+
+/// Allocates registers for the function, replacing every virtual register
+/// by a physical one and removing the moves that became redundant.
+/// Returns the callee-saved registers the function now uses.
+pub fn allocate(function: &mut MachineFunction) -> Vec<PhysicalRegister> {
+    let mut unspillable = HashSet::new();
+    let color = loop {
+        let mut coloring = Coloring::new(function);
+        coloring.build(function, &unspillable);
+
+        let initial: BTreeSet<usize> = function
+            .instructions
+            .iter()
+            .flat_map(|instruction| instruction.uses().into_iter().chain(instruction.defs()))
+            .map(node)
+            .filter(|node| !is_precolored(*node))
+            .collect();
+        coloring.make_worklist(initial);
+
+        loop {
+            if let Some(&node) = coloring.simplify_worklist.first() {
+                coloring.simplify(node);
+            } else if let Some(&index) = coloring.worklist_moves.first() {
+                coloring.coalesce(index);
+            } else if let Some(&node) = coloring.freeze_worklist.first() {
+                coloring.freeze(node);
+            } else if !coloring.spill_worklist.is_empty() {
+                coloring.select_spill();
+            } else {
+                break;
+            }
+        }
+        coloring.assign_colors();
+
+        if coloring.spilled_nodes.is_empty() {
+            break coloring.color;
+        }
+        let spilled: BTreeSet<Register> = coloring
+            .spilled_nodes
+            .iter()
+            .map(|&node| virtual_register(node))
+            .collect();
+        unspillable.extend(spill::rewrite(function, &spilled));
+    };
+
+    let color: HashMap<Register, PhysicalRegister> = color
+        .into_iter()
+        .enumerate()
+        .skip(PHYSICAL)
+        .filter_map(|(node, color)| Some((virtual_register(node), color?)))
+        .collect();
+    function.assign(&color)
+}
@@ -0,0 +1,495 @@
+/*
+  Prints machine code and data as GNU assembler input, in AT&T syntax or
+  in Intel syntax without register prefixes. Instruction suffixes, the
+  `PTR` sizes of Intel memory operands and register names all follow from
+  operand widths, and scalar SSE instructions end in `ss` or `sd` by
+  the width of their floating-point operand. Labels local to a function
+  are prefixed with its name, as are its floating-point constants, which
+  follow it in read-only data. Calls to functions defined elsewhere go
+  through the PLT so the output links into position-independent
+  executables.
+*/
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use super::machine::{
+    ArithmeticOp, Base, CallTarget, FloatOp, Instruction, MachineFunction, Memory, Operand,
+    PhysicalRegister, Register, ShiftOp, Size, UnaryOp, VECTOR_REGISTERS,
+};
+use crate::ir::{Global, GlobalInit, IrType};
+
+/// The assembly dialect to print.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// Source before destination, with `%` and `$` prefixes and size
+    /// suffixes.
+    #[default]
+    Att,
+    /// Destination before source, with sizes spelled out on memory.
+    Intel,
+}
+
+fn suffix(size: Size) -> char {
+    match size {
+        Size::Byte => 'b',
+        Size::Word => 'w',
+        Size::Long => 'l',
+        Size::Quad => 'q',
+    }
+}
+
+/// The letter for single or double precision in SSE mnemonics.
+fn precision(size: Size) -> char {
+    match size {
+        Size::Long => 's',
+        _ => 'd',
+    }
+}
+
+fn size_name(size: Size) -> &'static str {
+    match size {
+        Size::Byte => "BYTE",
+        Size::Word => "WORD",
+        Size::Long => "DWORD",
+        Size::Quad => "QWORD",
+    }
+}
+
+fn register_name(register: PhysicalRegister, size: Size) -> String {
+    use PhysicalRegister::*;
+    let legacy = |name: &str| match size {
+        Size::Byte => format!("{}l", name),
+        Size::Word => format!("{}x", name),
+        Size::Long => format!("e{}x", name),
+        Size::Quad => format!("r{}x", name),
+    };
+    let indexed = |name: &str| match size {
+        Size::Byte => format!("{}l", name),
+        Size::Word => name.to_string(),
+        Size::Long => format!("e{}", name),
+        Size::Quad => format!("r{}", name),
+    };
+    let numbered = |number: u8| match size {
+        Size::Byte => format!("r{}b", number),
+        Size::Word => format!("r{}w", number),
+        Size::Long => format!("r{}d", number),
+        Size::Quad => format!("r{}", number),
+    };
+    match register {
+        Rax => legacy("a"),
+        Rbx => legacy("b"),
+        Rcx => legacy("c"),
+        Rdx => legacy("d"),
+        Rsi => indexed("si"),
+        Rdi => indexed("di"),
+        Rbp => indexed("bp"),
+        Rsp => indexed("sp"),
+        R8 => numbered(8),
+        R9 => numbered(9),
+        R10 => numbered(10),
+        R11 => numbered(11),
+        R12 => numbered(12),
+        R13 => numbered(13),
+        R14 => numbered(14),
+        R15 => numbered(15),
+        // Vector registers have the same name whatever the width.
+        register => {
+            let number = VECTOR_REGISTERS
+                .iter()
+                .position(|other| *other == register)
+                .unwrap();
+            format!("xmm{}", number)
+        }
+    }
+}
+
+fn physical(register: &Register) -> PhysicalRegister {
+    match register {
+        Register::Physical(register) => *register,
+        Register::Virtual(number) => panic!("virtual register {} after allocation", number),
+    }
+}
+
+/// The width of the first operand that has one, for the instruction
+/// suffix.
+fn width(operands: &[&Operand]) -> Size {
+    operands
+        .iter()
+        .find_map(|operand| operand.size())
+        .unwrap_or(Size::Quad)
+}
+
+struct Emitter<'a> {
+    output: String,
+    function: &'a MachineFunction,
+    defined: &'a HashSet<String>,
+    syntax: Syntax,
+}
+
+impl Emitter<'_> {
+    fn line(&mut self, text: &str) {
+        writeln!(self.output, "\t{}", text).unwrap();
+    }
+
+    fn label(&self, label: u32) -> String {
+        format!(".L{}_{}", self.function.name, label)
+    }
+
+    fn constant(&self, index: usize) -> String {
+        format!(".L{}_c{}", self.function.name, index)
+    }
+
+    /// A called or jumped-to function, through the PLT unless it is ours.
+    fn symbol(&self, name: &str) -> String {
+        if self.defined.contains(name) {
+            name.to_string()
+        } else {
+            format!("{}@PLT", name)
+        }
+    }
+
+    fn register(&self, register: &Register, size: Size) -> String {
+        let name = register_name(physical(register), size);
+        match self.syntax {
+            Syntax::Att => format!("%{}", name),
+            Syntax::Intel => name,
+        }
+    }
+
+    /// An address, without the size Intel syntax gives memory operands.
+    fn address(&self, memory: &Memory) -> String {
+        let displacement = match memory.displacement {
+            0 => String::new(),
+            displacement => format!("{:+}", displacement),
+        };
+        match (&memory.base, self.syntax) {
+            (Base::Register(register), Syntax::Att) => format!(
+                "{}({})",
+                displacement.trim_start_matches('+'),
+                self.register(register, Size::Quad)
+            ),
+            (Base::Symbol(symbol), Syntax::Att) => format!("{}{}(%rip)", symbol, displacement),
+            (Base::Constant(index), Syntax::Att) => {
+                format!("{}{}(%rip)", self.constant(*index), displacement)
+            }
+            (Base::Register(register), Syntax::Intel) => {
+                let displacement = displacement.replacen('+', " + ", 1).replacen('-', " - ", 1);
+                format!("[{}{}]", self.register(register, Size::Quad), displacement)
+            }
+            (Base::Symbol(symbol), Syntax::Intel) => {
+                format!("[rip + {}{}]", symbol, displacement)
+            }
+            (Base::Constant(index), Syntax::Intel) => {
+                format!("[rip + {}{}]", self.constant(*index), displacement)
+            }
+            (Base::Slot(slot), _) => panic!("stack slot {} before frame layout", slot),
+            (Base::Incoming | Base::Outgoing, _) => panic!("stack argument before frame layout"),
+        }
+    }
+
+    fn operand(&self, operand: &Operand) -> String {
+        match (operand, self.syntax) {
+            (Operand::Register(register, size), _) => self.register(register, *size),
+            (Operand::Immediate(value), Syntax::Att) => format!("${}", value),
+            (Operand::Immediate(value), Syntax::Intel) => value.to_string(),
+            (Operand::Memory(memory, _), Syntax::Att) => self.address(memory),
+            (Operand::Memory(memory, size), Syntax::Intel) => {
+                format!("{} PTR {}", size_name(*size), self.address(memory))
+            }
+        }
+    }
+
+    /// An instruction on operands given source first, with the suffix of
+    /// `size` in AT&T syntax.
+    fn format(&self, name: &str, size: Option<Size>, operands: &[&Operand]) -> String {
+        let mut operands: Vec<String> = operands
+            .iter()
+            .map(|operand| self.operand(operand))
+            .collect();
+        let name = match (self.syntax, size) {
+            (Syntax::Att, Some(size)) => format!("{}{}", name, suffix(size)),
+            (Syntax::Att, None) => name.to_string(),
+            (Syntax::Intel, _) => {
+                operands.reverse();
+                name.to_string()
+            }
+        };
+        if operands.is_empty() {
+            name
+        } else {
+            format!("{} {}", name, operands.join(", "))
+        }
+    }
+
+    /// An instruction whose suffix follows from its operands.
+    fn sized(&self, name: &str, operands: &[&Operand]) -> String {
+        // Any operand with a width names the size, but the first one is
+        // the destination when printed.
+        let mut by_destination = operands.to_vec();
+        by_destination.reverse();
+        self.format(name, Some(width(&by_destination)), operands)
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        let att = self.syntax == Syntax::Att;
+        let text = match instruction {
+            Instruction::Label(label) => {
+                let label = self.label(*label);
+                writeln!(self.output, "{}:", label).unwrap();
+                return;
+            }
+            Instruction::Mov {
+                src: src @ Operand::Immediate(value),
+                dst: dst @ Operand::Register(..),
+            } if i32::try_from(*value).is_err() => {
+                self.format(if att { "movabsq" } else { "movabs" }, None, &[src, dst])
+            }
+            Instruction::Mov { src, dst } => self.sized("mov", &[src, dst]),
+            Instruction::MovSx { src, dst } | Instruction::MovZx { src, dst } => {
+                let (from, to) = (src.size().unwrap(), dst.size().unwrap());
+                match (instruction, from, to) {
+                    // Writing a 32-bit register clears the upper half.
+                    (Instruction::MovZx { .. }, Size::Long, Size::Quad) => {
+                        let Operand::Register(register, _) = dst else {
+                            panic!("zero extension into memory");
+                        };
+                        let dst = Operand::Register(*register, Size::Long);
+                        self.format(if att { "movl" } else { "mov" }, None, &[src, &dst])
+                    }
+                    _ if att => {
+                        let name = match instruction {
+                            Instruction::MovSx { .. } => "movs",
+                            _ => "movz",
+                        };
+                        let name = format!("{}{}{}", name, suffix(from), suffix(to));
+                        self.format(&name, None, &[src, dst])
+                    }
+                    (Instruction::MovSx { .. }, Size::Long, _) => {
+                        self.format("movsxd", None, &[src, dst])
+                    }
+                    (Instruction::MovSx { .. }, ..) => self.format("movsx", None, &[src, dst]),
+                    _ => self.format("movzx", None, &[src, dst]),
+                }
+            }
+            Instruction::Lea { src, dst } => {
+                let size = dst.size().unwrap_or(Size::Quad);
+                let name = match self.syntax {
+                    Syntax::Att => format!("lea{}", suffix(size)),
+                    Syntax::Intel => "lea".to_string(),
+                };
+                let (src, dst) = (self.address(src), self.operand(dst));
+                match self.syntax {
+                    Syntax::Att => format!("{} {}, {}", name, src, dst),
+                    Syntax::Intel => format!("{} {}, {}", name, dst, src),
+                }
+            }
+            Instruction::Arithmetic { op, src, dst } => {
+                let name = match op {
+                    ArithmeticOp::Add => "add",
+                    ArithmeticOp::Sub => "sub",
+                    ArithmeticOp::Imul => "imul",
+                    ArithmeticOp::And => "and",
+                    ArithmeticOp::Or => "or",
+                    ArithmeticOp::Xor => "xor",
+                };
+                self.sized(name, &[src, dst])
+            }
+            Instruction::Unary { op, dst } => {
+                let name = match op {
+                    UnaryOp::Neg => "neg",
+                    UnaryOp::Not => "not",
+                };
+                self.sized(name, &[dst])
+            }
+            Instruction::Shift { op, count, dst } => {
+                let name = match op {
+                    ShiftOp::Shl => "shl",
+                    ShiftOp::Sar => "sar",
+                    ShiftOp::Shr => "shr",
+                };
+                // The count is `%cl` or an immediate whatever the width.
+                self.format(name, dst.size(), &[count, dst])
+            }
+            Instruction::SignExtendAccumulator(size) => match (size, self.syntax) {
+                (Size::Quad, Syntax::Att) => "cqto",
+                (_, Syntax::Att) => "cltd",
+                (Size::Quad, Syntax::Intel) => "cqo",
+                (_, Syntax::Intel) => "cdq",
+            }
+            .to_string(),
+            Instruction::Div { signed, divisor } => {
+                self.sized(if *signed { "idiv" } else { "div" }, &[divisor])
+            }
+            // `cmp` and `test` compute `left - right`, which AT&T syntax
+            // writes the other way around.
+            Instruction::Cmp { left, right } => self.sized("cmp", &[right, left]),
+            Instruction::Test { left, right } => self.sized("test", &[right, left]),
+            Instruction::SetCC { condition, dst } => {
+                self.format(&format!("set{}", condition.name()), None, &[dst])
+            }
+            // Moves between registers copy the whole register, which
+            // avoids depending on its previous contents.
+            Instruction::FMov {
+                src: src @ Operand::Register(..),
+                dst: dst @ Operand::Register(..),
+            } => self.format("movaps", None, &[src, dst]),
+            Instruction::FMov { src, dst } => {
+                let name = format!("movs{}", precision(width(&[dst, src])));
+                self.format(&name, None, &[src, dst])
+            }
+            Instruction::FArithmetic {
+                op: FloatOp::Xor,
+                src,
+                dst,
+            } => self.format("xorps", None, &[src, dst]),
+            Instruction::FArithmetic { op, src, dst } => {
+                let name = match op {
+                    FloatOp::Add => "add",
+                    FloatOp::Sub => "sub",
+                    FloatOp::Mul => "mul",
+                    FloatOp::Div => "div",
+                    FloatOp::Xor => unreachable!(),
+                };
+                let name = format!("{}s{}", name, precision(width(&[dst, src])));
+                self.format(&name, None, &[src, dst])
+            }
+            Instruction::FCmp { left, right } => {
+                let name = format!("ucomis{}", precision(width(&[left, right])));
+                self.format(&name, None, &[right, left])
+            }
+            Instruction::IntToFloat { src, dst } => {
+                let name = format!("cvtsi2s{}", precision(width(&[dst])));
+                let name = match self.syntax {
+                    Syntax::Att => format!("{}{}", name, suffix(width(&[src]))),
+                    Syntax::Intel => name,
+                };
+                self.format(&name, None, &[src, dst])
+            }
+            Instruction::FloatToInt { src, dst } => {
+                let name = format!("cvtts{}2si", precision(width(&[src])));
+                self.format(&name, None, &[src, dst])
+            }
+            Instruction::FloatConvert { src, dst } => {
+                let name = match width(&[src]) {
+                    Size::Long => "cvtss2sd",
+                    _ => "cvtsd2ss",
+                };
+                self.format(name, None, &[src, dst])
+            }
+            Instruction::Jmp(label) => format!("jmp {}", self.label(*label)),
+            Instruction::Jcc { condition, target } => {
+                format!("j{} {}", condition.name(), self.label(*target))
+            }
+            Instruction::Call {
+                target: CallTarget::Symbol(name),
+                ..
+            } => format!("call {}", self.symbol(name)),
+            Instruction::Call {
+                target: CallTarget::Indirect(target),
+                ..
+            } if att => format!("call *{}", self.operand(target)),
+            Instruction::Call {
+                target: CallTarget::Indirect(target),
+                ..
+            } => format!("call {}", self.operand(target)),
+            Instruction::Ret(_) => "ret".to_string(),
+            Instruction::TailCall { target, .. } => format!("jmp {}", self.symbol(target)),
+            Instruction::Push(operand) => self.format("push", Some(Size::Quad), &[operand]),
+            Instruction::Pop(operand) => self.format("pop", Some(Size::Quad), &[operand]),
+        };
+        self.line(&text);
+    }
+}
+
+/// Prints a function whose registers are allocated and frame laid out.
+pub fn function(
+    output: &mut String,
+    function: &MachineFunction,
+    defined: &HashSet<String>,
+    syntax: Syntax,
+) {
+    let mut emitter = Emitter {
+        output: String::new(),
+        function,
+        defined,
+        syntax,
+    };
+    emitter.line(".text");
+    if function.is_global {
+        emitter.line(&format!(".globl {}", function.name));
+    }
+    emitter.line(&format!(".type {}, @function", function.name));
+    writeln!(emitter.output, "{}:", function.name).unwrap();
+    for instruction in &function.instructions {
+        emitter.instruction(instruction);
+    }
+    emitter.line(&format!(".size {0}, .-{0}", function.name));
+    if !function.constants.is_empty() {
+        emitter.line(".section .rodata");
+    }
+    for (index, constant) in function.constants.iter().enumerate() {
+        emitter.line(&format!(".balign {}", constant.size.bytes()));
+        let label = emitter.constant(index);
+        writeln!(emitter.output, "{}:", label).unwrap();
+        let directive = match constant.size {
+            Size::Long => ".long",
+            _ => ".quad",
+        };
+        emitter.line(&format!("{} {:#x}", directive, constant.bits));
+    }
+    output.push_str(&emitter.output);
+}
+
+pub fn global(output: &mut String, global: &Global) {
+    let zero = global
+        .init
+        .iter()
+        .all(|init| matches!(init, GlobalInit::Zero(_)));
+    let section = if global.is_constant {
+        ".section .rodata"
+    } else if zero {
+        ".bss"
+    } else {
+        ".data"
+    };
+    writeln!(output, "\t{}", section).unwrap();
+    if global.is_global {
+        writeln!(output, "\t.globl {}", global.name).unwrap();
+    }
+    writeln!(output, "\t.balign {}", global.alignment.max(1)).unwrap();
+    writeln!(output, "\t.type {}, @object", global.name).unwrap();
+    writeln!(output, "\t.size {}, {}", global.name, global.size()).unwrap();
+    writeln!(output, "{}:", global.name).unwrap();
+    let directive = |ty: IrType| match ty.size() {
+        1 => ".byte",
+        2 => ".short",
+        4 => ".long",
+        _ => ".quad",
+    };
+    for init in &global.init {
+        match init {
+            GlobalInit::Zero(0) => {}
+            GlobalInit::Zero(size) => writeln!(output, "\t.zero {}", size).unwrap(),
+            GlobalInit::Int(ty, value) => {
+                writeln!(output, "\t{} {}", directive(*ty), value).unwrap()
+            }
+            GlobalInit::Float(IrType::F32, value) => {
+                writeln!(output, "\t.long {:#x}", (*value as f32).to_bits()).unwrap()
+            }
+            GlobalInit::Float(_, value) => {
+                writeln!(output, "\t.quad {:#x}", value.to_bits()).unwrap()
+            }
+            GlobalInit::Address(symbol, 0) => writeln!(output, "\t.quad {}", symbol).unwrap(),
+            GlobalInit::Address(symbol, offset) => {
+                writeln!(output, "\t.quad {}{:+}", symbol, offset).unwrap()
+            }
+            GlobalInit::Bytes(bytes) => {
+                for chunk in bytes.chunks(16) {
+                    let bytes: Vec<String> = chunk.iter().map(u8::to_string).collect();
+                    writeln!(output, "\t.byte {}", bytes.join(", ")).unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+/*
+  Stack frame layout, once registers are allocated. The frame is addressed
+  from `%rbp`: stack slots come first below it, then the callee-saved
+  registers the function uses, and the arguments of calls passed on the
+  stack at the bottom, addressed from `%rsp`, which is kept 16-byte
+  aligned at calls. The arguments passed to the function on the stack
+  are above the return address. Each return, and each tail call, starts with the epilogue
+  restoring all of them.
+*/
+
+use super::machine::{
+    ArithmeticOp, Base, Instruction, MachineFunction, Memory, Operand, PhysicalRegister, Register,
+    Size,
+};
+
+fn align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+fn frame_memory(offset: usize) -> Memory {
+    Memory {
+        base: Base::Register(Register::Physical(PhysicalRegister::Rbp)),
+        displacement: -(offset as i64),
+    }
+}
+
+/// Replaces stack slots by their place in the frame and adds the prologue
+/// and epilogues, saving the given callee-saved registers.
+pub fn lay_out(function: &mut MachineFunction, callee_saved: &[PhysicalRegister]) {
+    let mut offset = 0;
+    let mut offsets = vec![];
+    for slot in &function.slots {
+        offset = align(offset + slot.size, slot.alignment.max(1));
+        offsets.push(offset);
+    }
+    let mut saves = vec![];
+    for register in callee_saved {
+        offset = align(offset + 8, 8);
+        saves.push((
+            Operand::register(*register, Size::Quad),
+            Operand::Memory(frame_memory(offset), Size::Quad),
+        ));
+    }
+    let size = align(offset + function.outgoing, 16);
+
+    let rbp = Operand::register(PhysicalRegister::Rbp, Size::Quad);
+    let rsp = Operand::register(PhysicalRegister::Rsp, Size::Quad);
+    let mut instructions = vec![
+        Instruction::Push(rbp.clone()),
+        Instruction::Mov {
+            src: rsp.clone(),
+            dst: rbp.clone(),
+        },
+    ];
+    if size > 0 {
+        instructions.push(Instruction::Arithmetic {
+            op: ArithmeticOp::Sub,
+            src: Operand::Immediate(size as i64),
+            dst: rsp.clone(),
+        });
+    }
+    for (register, memory) in &saves {
+        instructions.push(Instruction::Mov {
+            src: register.clone(),
+            dst: memory.clone(),
+        });
+    }
+
+    for mut instruction in std::mem::take(&mut function.instructions) {
+        if let Instruction::Ret(_) | Instruction::TailCall { .. } = instruction {
+            for (register, memory) in &saves {
+                instructions.push(Instruction::Mov {
+                    src: memory.clone(),
+                    dst: register.clone(),
+                });
+            }
+            instructions.push(Instruction::Mov {
+                src: rbp.clone(),
+                dst: rsp.clone(),
+            });
+            instructions.push(Instruction::Pop(rbp.clone()));
+        }
+        for memory in instruction.memory_mut() {
+            match memory.base {
+                Base::Slot(slot) => {
+                    *memory = Memory {
+                        displacement: memory.displacement - offsets[slot] as i64,
+                        ..frame_memory(0)
+                    };
+                }
+                Base::Incoming => {
+                    *memory = Memory {
+                        displacement: memory.displacement + 16,
+                        ..frame_memory(0)
+                    };
+                }
+                Base::Outgoing => {
+                    memory.base = Base::Register(Register::Physical(PhysicalRegister::Rsp));
+                }
+                _ => {}
+            }
+        }
+        instructions.push(instruction);
+    }
+    function.instructions = instructions;
+}
@@ -0,0 +1,209 @@
+/*
+  Linear-scan register allocation (Poletto and Sarkar, 1999). Each virtual
+  register gets one live interval, from the first to the last instruction
+  where it is live in the linear order of the code, and the intervals are
+  visited by increasing start, keeping the active ones sorted by end.
+  Physical registers are not given intervals: instead, a register cannot
+  be chosen for an interval that overlaps an instruction where it is
+  written or live, which is how calls, argument registers and division
+  constrain the choice. An interval only gets, or takes over, a register
+  of its own class.
+
+  When no register is free, the interval that ends last is spilled, which
+  may be the one being allocated. A spilled interval is split at its uses:
+  the value lives in a stack slot and each instruction that refers to it
+  gets a short interval of its own, loaded before and stored after, and the
+  scan runs again over the rewritten code.
+*/
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use super::machine::{
+    CALLEE_SAVED, CALLER_SAVED, MachineFunction, PhysicalRegister, Register, RegisterClass,
+    VECTOR_REGISTERS,
+};
+use super::{liveness, spill};
+
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    register: Register,
+    class: RegisterClass,
+    start: usize,
+    /// The last instruction that needs the value, or one past the last
+    /// instruction after which it is live.
+    end: usize,
+}
+
+struct Scan {
+    intervals: Vec<Interval>,
+    /// For each physical register, the instructions that write it or after
+    /// which it is live.
+    busy: HashMap<PhysicalRegister, BTreeSet<usize>>,
+    /// Physical registers each virtual register is moved to or from.
+    hints: HashMap<Register, Vec<Register>>,
+}
+
+impl Scan {
+    fn build(function: &MachineFunction) -> Scan {
+        let instructions = &function.instructions;
+        let blocks = liveness::blocks(instructions);
+        let live_out = liveness::live_out(instructions, &blocks);
+
+        let mut ranges: BTreeMap<Register, (usize, usize)> = BTreeMap::new();
+        let mut busy: HashMap<PhysicalRegister, BTreeSet<usize>> = HashMap::new();
+        let mut hints: HashMap<Register, Vec<Register>> = HashMap::new();
+        let mut extend = |register: Register, start: usize, end: usize| {
+            let range = ranges.entry(register).or_insert((start, end));
+            range.0 = range.0.min(start);
+            range.1 = range.1.max(end);
+        };
+
+        for (block, out) in blocks.iter().zip(live_out) {
+            let mut live = out;
+            for index in (block.start..block.end).rev() {
+                let instruction = &instructions[index];
+                let defs = instruction.defs();
+                for &register in &live {
+                    match register {
+                        Register::Physical(physical) => {
+                            busy.entry(physical).or_default().insert(index);
+                        }
+                        Register::Virtual(_) => extend(register, index, index + 1),
+                    }
+                }
+                for &register in &defs {
+                    match register {
+                        Register::Physical(physical) => {
+                            busy.entry(physical).or_default().insert(index);
+                        }
+                        Register::Virtual(_) => extend(register, index, index),
+                    }
+                    live.remove(&register);
+                }
+                for register in instruction.uses() {
+                    if let Register::Virtual(_) = register {
+                        extend(register, index, index);
+                    }
+                    live.insert(register);
+                }
+                if let Some((src, dst)) = instruction.as_move() {
+                    hints.entry(src).or_default().push(dst);
+                    hints.entry(dst).or_default().push(src);
+                }
+            }
+        }
+
+        let mut intervals: Vec<Interval> = ranges
+            .into_iter()
+            .map(|(register, (start, end))| Interval {
+                register,
+                class: function.class(register),
+                start,
+                end,
+            })
+            .collect();
+        intervals.sort_by_key(|interval| (interval.start, interval.register));
+        Scan {
+            intervals,
+            busy,
+            hints,
+        }
+    }
+
+    /// Whether the physical register is free for the whole interval.
+    fn fits(&self, register: PhysicalRegister, interval: &Interval) -> bool {
+        let end = interval.end.max(interval.start + 1);
+        self.busy
+            .get(&register)
+            .is_none_or(|busy| busy.range(interval.start..end).next().is_none())
+    }
+
+    /// Assigns registers, returning the assignment and the registers that
+    /// have to be spilled.
+    fn run(
+        &self,
+        unspillable: &HashSet<Register>,
+    ) -> (HashMap<Register, PhysicalRegister>, BTreeSet<Register>) {
+        let mut assigned: HashMap<Register, PhysicalRegister> = HashMap::new();
+        let mut spilled = BTreeSet::new();
+        // Active intervals, with their registers, by increasing end.
+        let mut active: Vec<(Interval, PhysicalRegister)> = vec![];
+
+        for interval in &self.intervals {
+            active.retain(|(other, _)| other.end > interval.start);
+            let taken: HashSet<PhysicalRegister> =
+                active.iter().map(|(_, register)| *register).collect();
+
+            let hinted = self
+                .hints
+                .get(&interval.register)
+                .into_iter()
+                .flatten()
+                .filter_map(|partner| match partner {
+                    Register::Physical(register) => Some(*register),
+                    partner => assigned.get(partner).copied(),
+                });
+            let candidates: Vec<PhysicalRegister> = match interval.class {
+                RegisterClass::General => {
+                    CALLER_SAVED.iter().chain(&CALLEE_SAVED).copied().collect()
+                }
+                RegisterClass::Vector => VECTOR_REGISTERS.to_vec(),
+            };
+            let free = hinted.chain(candidates).find(|register| {
+                interval.class.allocatable().contains(register)
+                    && !taken.contains(register)
+                    && self.fits(*register, interval)
+            });
+
+            let register = match free {
+                Some(register) => register,
+                None => {
+                    let victim = active
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (other, register))| {
+                            other.class == interval.class
+                                && !unspillable.contains(&other.register)
+                                && self.fits(*register, interval)
+                        })
+                        .max_by_key(|(_, (other, _))| other.end)
+                        .map(|(position, _)| position);
+                    match victim {
+                        Some(position)
+                            if active[position].0.end > interval.end
+                                || unspillable.contains(&interval.register) =>
+                        {
+                            let (other, register) = active.remove(position);
+                            spilled.insert(other.register);
+                            assigned.remove(&other.register);
+                            register
+                        }
+                        _ if !unspillable.contains(&interval.register) => {
+                            spilled.insert(interval.register);
+                            continue;
+                        }
+                        _ => panic!("no register for {:?}", interval.register),
+                    }
+                }
+            };
+            assigned.insert(interval.register, register);
+            let position = active.partition_point(|(other, _)| other.end <= interval.end);
+            active.insert(position, (*interval, register));
+        }
+        (assigned, spilled)
+    }
+}
+
+/// Allocates registers for the function, replacing every virtual register
+/// by a physical one and removing the moves that became redundant.
+/// Returns the callee-saved registers the function now uses.
+pub fn allocate(function: &mut MachineFunction) -> Vec<PhysicalRegister> {
+    let mut unspillable = HashSet::new();
+    loop {
+        let (assigned, spilled) = Scan::build(function).run(&unspillable);
+        if spilled.is_empty() {
+            return function.assign(&assigned);
+        }
+        unspillable.extend(spill::rewrite(function, &spilled));
+    }
+}
@@ -0,0 +1,125 @@
+/*
+  Basic blocks and liveness of machine code, shared by the register
+  allocators. A block ends at a jump or return, or just before a label;
+  a conditional jump falls through to the next block.
+*/
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::machine::{Instruction, Register};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// The range of instructions in the block.
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<usize>,
+}
+
+pub fn blocks(instructions: &[Instruction]) -> Vec<Block> {
+    let mut blocks = vec![];
+    let mut start = 0;
+    for (index, instruction) in instructions.iter().enumerate() {
+        let ends = matches!(
+            instruction,
+            Instruction::Jmp(_)
+                | Instruction::Jcc { .. }
+                | Instruction::Ret(_)
+                | Instruction::TailCall { .. }
+        ) || matches!(instructions.get(index + 1), Some(Instruction::Label(_)));
+        if ends || index + 1 == instructions.len() {
+            blocks.push(Block {
+                start,
+                end: index + 1,
+                successors: vec![],
+            });
+            start = index + 1;
+        }
+    }
+
+    let labels: HashMap<u32, usize> = blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(index, block)| match instructions[block.start] {
+            Instruction::Label(label) => Some((label, index)),
+            _ => None,
+        })
+        .collect();
+    let count = blocks.len();
+    for (index, block) in blocks.iter_mut().enumerate() {
+        block.successors = match &instructions[block.end - 1] {
+            Instruction::Jmp(target) => vec![labels[target]],
+            Instruction::Jcc { target, .. } if index + 1 < count => {
+                vec![labels[target], index + 1]
+            }
+            Instruction::Jcc { target, .. } => vec![labels[target]],
+            Instruction::Ret(_) | Instruction::TailCall { .. } => vec![],
+            _ if index + 1 < count => vec![index + 1],
+            _ => vec![],
+        };
+    }
+    blocks
+}
+
+/// The registers live at the end of each block.
+pub fn live_out(instructions: &[Instruction], blocks: &[Block]) -> Vec<BTreeSet<Register>> {
+    let mut live_in: Vec<BTreeSet<Register>> = vec![BTreeSet::new(); blocks.len()];
+    let mut live_out: Vec<BTreeSet<Register>> = vec![BTreeSet::new(); blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, block) in blocks.iter().enumerate().rev() {
+            let out: BTreeSet<Register> = block
+                .successors
+                .iter()
+                .flat_map(|successor| live_in[*successor].iter().copied())
+                .collect();
+            let mut live = out.clone();
+            for instruction in instructions[block.start..block.end].iter().rev() {
+                for register in instruction.defs() {
+                    live.remove(&register);
+                }
+                live.extend(instruction.uses());
+            }
+            live_out[index] = out;
+            if live != live_in[index] {
+                live_in[index] = live;
+                changed = true;
+            }
+        }
+    }
+    live_out
+}
+
+/// The registers live just after each instruction.
+pub fn live_after(instructions: &[Instruction]) -> Vec<BTreeSet<Register>> {
+    let blocks = blocks(instructions);
+    let mut after = vec![BTreeSet::new(); instructions.len()];
+    for (block, out) in blocks.iter().zip(live_out(instructions, &blocks)) {
+        let mut live = out;
+        for index in (block.start..block.end).rev() {
+            after[index] = live.clone();
+            for register in instructions[index].defs() {
+                live.remove(&register);
+            }
+            live.extend(instructions[index].uses());
+        }
+    }
+    after
+}
+
+/// An estimate of how deeply each instruction is nested in loops, counting
+/// the backward jumps that span it.
+pub fn loop_depths(instructions: &[Instruction], blocks: &[Block]) -> Vec<usize> {
+    let mut depths = vec![0; instructions.len()];
+    for (index, block) in blocks.iter().enumerate() {
+        for &successor in &block.successors {
+            if successor <= index {
+                for depth in &mut depths[blocks[successor].start..block.end] {
+                    *depth += 1;
+                }
+            }
+        }
+    }
+    depths
+}
@@ -0,0 +1,765 @@
+/*
+  Machine instructions for x86-64. Operands carry their width, so the
+  instruction suffix and register names follow from the operands when the
+  code is printed. Before register allocation, registers may be virtual
+  and memory may refer to a stack slot whose offset is only fixed once the
+  frame is laid out.
+
+  Registers come in two classes: general-purpose registers for integers
+  and pointers, and the XMM vector registers, whose low lanes hold floats
+  and doubles for the scalar SSE instructions. A virtual register belongs
+  to the class of the values it holds, and only ever gets a physical
+  register of that class.
+*/
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::ir::IrType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PhysicalRegister {
+    Rax,
+    Rbx,
+    Rcx,
+    Rdx,
+    Rsi,
+    Rdi,
+    Rbp,
+    Rsp,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    Xmm0,
+    Xmm1,
+    Xmm2,
+    Xmm3,
+    Xmm4,
+    Xmm5,
+    Xmm6,
+    Xmm7,
+    Xmm8,
+    Xmm9,
+    Xmm10,
+    Xmm11,
+    Xmm12,
+    Xmm13,
+    Xmm14,
+    Xmm15,
+}
+
+use PhysicalRegister::*;
+
+/// Integer argument registers in the order of the System V ABI.
+pub const ARGUMENT_REGISTERS: [PhysicalRegister; 6] = [Rdi, Rsi, Rdx, Rcx, R8, R9];
+
+/// Registers a call may overwrite.
+pub const CALLER_SAVED: [PhysicalRegister; 9] = [Rax, Rcx, Rdx, Rsi, Rdi, R8, R9, R10, R11];
+
+/// Registers a function must restore before returning, besides `%rbp`.
+pub const CALLEE_SAVED: [PhysicalRegister; 5] = [Rbx, R12, R13, R14, R15];
+
+/// General-purpose registers available to the allocators; `%rsp` and
+/// `%rbp` hold the frame.
+pub const ALLOCATABLE: [PhysicalRegister; 14] = [
+    Rax, Rcx, Rdx, Rsi, Rdi, R8, R9, R10, R11, Rbx, R12, R13, R14, R15,
+];
+
+/// The vector registers, all of which a call may overwrite.
+pub const VECTOR_REGISTERS: [PhysicalRegister; 16] = [
+    Xmm0, Xmm1, Xmm2, Xmm3, Xmm4, Xmm5, Xmm6, Xmm7, Xmm8, Xmm9, Xmm10, Xmm11, Xmm12, Xmm13, Xmm14,
+    Xmm15,
+];
+
+/// Vector argument registers in the order of the System V ABI.
+pub const VECTOR_ARGUMENT_REGISTERS: [PhysicalRegister; 8] =
+    [Xmm0, Xmm1, Xmm2, Xmm3, Xmm4, Xmm5, Xmm6, Xmm7];
+
+impl PhysicalRegister {
+    pub fn is_callee_saved(&self) -> bool {
+        CALLEE_SAVED.contains(self)
+    }
+
+    pub fn class(&self) -> RegisterClass {
+        if VECTOR_REGISTERS.contains(self) {
+            RegisterClass::Vector
+        } else {
+            RegisterClass::General
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegisterClass {
+    General,
+    Vector,
+}
+
+impl RegisterClass {
+    /// The registers of the class available to the allocators.
+    pub fn allocatable(&self) -> &'static [PhysicalRegister] {
+        match self {
+            RegisterClass::General => &ALLOCATABLE,
+            RegisterClass::Vector => &VECTOR_REGISTERS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Register {
+    Physical(PhysicalRegister),
+    Virtual(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Size {
+    Byte,
+    Word,
+    Long,
+    Quad,
+}
+
+impl Size {
+    pub fn of(ty: IrType) -> Size {
+        match ty.size() {
+            1 => Size::Byte,
+            2 => Size::Word,
+            4 => Size::Long,
+            _ => Size::Quad,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        match self {
+            Size::Byte => 1,
+            Size::Word => 2,
+            Size::Long => 4,
+            Size::Quad => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Base {
+    Register(Register),
+    /// A stack slot of the function, by index.
+    Slot(usize),
+    /// A symbol, addressed relative to `%rip`.
+    Symbol(String),
+    /// A floating-point constant of the function, by index.
+    Constant(usize),
+    /// The arguments the function was passed on the stack.
+    Incoming,
+    /// The area at the bottom of the frame for the arguments of calls
+    /// passed on the stack.
+    Outgoing,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Memory {
+    pub base: Base,
+    pub displacement: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Register(Register, Size),
+    Immediate(i64),
+    Memory(Memory, Size),
+}
+
+impl Operand {
+    pub fn register(register: PhysicalRegister, size: Size) -> Operand {
+        Operand::Register(Register::Physical(register), size)
+    }
+
+    pub fn slot(slot: usize, size: Size) -> Operand {
+        Operand::Memory(
+            Memory {
+                base: Base::Slot(slot),
+                displacement: 0,
+            },
+            size,
+        )
+    }
+
+    /// The width of the operand; immediates take the width of the other
+    /// operand.
+    pub fn size(&self) -> Option<Size> {
+        match self {
+            Operand::Register(_, size) | Operand::Memory(_, size) => Some(*size),
+            Operand::Immediate(_) => None,
+        }
+    }
+
+    /// The registers the operand reads, including a memory operand's base.
+    fn registers(&self) -> Vec<Register> {
+        match self {
+            Operand::Register(register, _)
+            | Operand::Memory(
+                Memory {
+                    base: Base::Register(register),
+                    ..
+                },
+                _,
+            ) => vec![*register],
+            _ => vec![],
+        }
+    }
+
+    fn registers_mut(&mut self) -> Vec<&mut Register> {
+        match self {
+            Operand::Register(register, _)
+            | Operand::Memory(
+                Memory {
+                    base: Base::Register(register),
+                    ..
+                },
+                _,
+            ) => vec![register],
+            _ => vec![],
+        }
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut Memory> {
+        match self {
+            Operand::Memory(memory, _) => Some(memory),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Sub,
+    Imul,
+    And,
+    Or,
+    Xor,
+}
+
+/// Scalar SSE arithmetic, on floats or doubles by the operand width.
+/// `Xor` works on the whole register and takes a register source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Xor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOp {
+    Shl,
+    Sar,
+    Shr,
+}
+
+/// Condition codes of `jcc` and `setcc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionCode {
+    E,
+    Ne,
+    L,
+    Le,
+    G,
+    Ge,
+    B,
+    Be,
+    A,
+    Ae,
+    /// Parity, which a floating-point comparison sets when either operand
+    /// is NaN.
+    P,
+    Np,
+}
+
+impl ConditionCode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConditionCode::E => "e",
+            ConditionCode::Ne => "ne",
+            ConditionCode::L => "l",
+            ConditionCode::Le => "le",
+            ConditionCode::G => "g",
+            ConditionCode::Ge => "ge",
+            ConditionCode::B => "b",
+            ConditionCode::Be => "be",
+            ConditionCode::A => "a",
+            ConditionCode::Ae => "ae",
+            ConditionCode::P => "p",
+            ConditionCode::Np => "np",
+        }
+    }
+
+    /// The condition that holds exactly when this one does not.
+    pub fn negate(&self) -> ConditionCode {
+        match self {
+            ConditionCode::E => ConditionCode::Ne,
+            ConditionCode::Ne => ConditionCode::E,
+            ConditionCode::L => ConditionCode::Ge,
+            ConditionCode::Le => ConditionCode::G,
+            ConditionCode::G => ConditionCode::Le,
+            ConditionCode::Ge => ConditionCode::L,
+            ConditionCode::B => ConditionCode::Ae,
+            ConditionCode::Be => ConditionCode::A,
+            ConditionCode::A => ConditionCode::Be,
+            ConditionCode::Ae => ConditionCode::B,
+            ConditionCode::P => ConditionCode::Np,
+            ConditionCode::Np => ConditionCode::P,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallTarget {
+    Symbol(String),
+    Indirect(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    /// A label local to the function.
+    Label(u32),
+    Mov {
+        src: Operand,
+        dst: Operand,
+    },
+    /// Sign extension from the width of `src` to the width of `dst`.
+    MovSx {
+        src: Operand,
+        dst: Operand,
+    },
+    /// Zero extension; from a long to a quad it is a 32-bit `mov`.
+    MovZx {
+        src: Operand,
+        dst: Operand,
+    },
+    Lea {
+        src: Memory,
+        dst: Operand,
+    },
+    Arithmetic {
+        op: ArithmeticOp,
+        src: Operand,
+        dst: Operand,
+    },
+    Unary {
+        op: UnaryOp,
+        dst: Operand,
+    },
+    /// Shifts `dst` by an immediate or by `%cl`.
+    Shift {
+        op: ShiftOp,
+        count: Operand,
+        dst: Operand,
+    },
+    /// Sign-extends `%eax` into `%edx` (`cltd`) or `%rax` into `%rdx`
+    /// (`cqto`).
+    SignExtendAccumulator(Size),
+    /// Divides `%rdx:%rax` by the divisor, leaving the quotient in `%rax`
+    /// and the remainder in `%rdx`.
+    Div {
+        signed: bool,
+        divisor: Operand,
+    },
+    /// Sets the flags for `left - right`.
+    Cmp {
+        left: Operand,
+        right: Operand,
+    },
+    Test {
+        left: Operand,
+        right: Operand,
+    },
+    SetCC {
+        condition: ConditionCode,
+        dst: Operand,
+    },
+    Jmp(u32),
+    Jcc {
+        condition: ConditionCode,
+        target: u32,
+    },
+    /// A move of a float (long) or double (quad) between vector registers
+    /// and memory.
+    FMov {
+        src: Operand,
+        dst: Operand,
+    },
+    FArithmetic {
+        op: FloatOp,
+        src: Operand,
+        dst: Operand,
+    },
+    /// Compares `left` with `right` like unsigned integers, setting the
+    /// parity flag as well when they are unordered.
+    FCmp {
+        left: Operand,
+        right: Operand,
+    },
+    /// Converts a signed long or quad to the float or double `dst`.
+    IntToFloat {
+        src: Operand,
+        dst: Operand,
+    },
+    /// Converts a float or double to a signed long or quad, truncating.
+    FloatToInt {
+        src: Operand,
+        dst: Operand,
+    },
+    /// Converts between float and double.
+    FloatConvert {
+        src: Operand,
+        dst: Operand,
+    },
+    /// A call passing arguments in the given registers, with the number of
+    /// vector registers used in `%al`.
+    Call {
+        target: CallTarget,
+        arguments: Vec<PhysicalRegister>,
+    },
+    /// Returns with the given registers holding the result. Until the frame
+    /// is laid out this also stands for the epilogue.
+    Ret(Vec<PhysicalRegister>),
+    /// A call in tail position: like `Ret`, it stands for the epilogue,
+    /// followed by a jump to the callee, which returns to our caller.
+    TailCall {
+        target: String,
+        arguments: Vec<PhysicalRegister>,
+    },
+    Push(Operand),
+    Pop(Operand),
+}
+
+impl Instruction {
+    /// The registers the instruction reads, explicitly or implicitly.
+    pub fn uses(&self) -> Vec<Register> {
+        let physical = |registers: &[PhysicalRegister]| {
+            registers
+                .iter()
+                .map(|register| Register::Physical(*register))
+                .collect::<Vec<_>>()
+        };
+        // A register destination is written, but memory destinations read
+        // their base register.
+        let address = |operand: &Operand| match operand {
+            Operand::Memory(..) => operand.registers(),
+            _ => vec![],
+        };
+
+        match self {
+            Instruction::Label(_) | Instruction::Jmp(_) | Instruction::Jcc { .. } => vec![],
+            Instruction::Push(operand) => operand.registers(),
+            Instruction::Pop(operand) => address(operand),
+            Instruction::Mov { src, dst }
+            | Instruction::MovSx { src, dst }
+            | Instruction::MovZx { src, dst }
+            | Instruction::FMov { src, dst }
+            | Instruction::IntToFloat { src, dst }
+            | Instruction::FloatToInt { src, dst }
+            | Instruction::FloatConvert { src, dst } => [src.registers(), address(dst)].concat(),
+            Instruction::Lea { src, dst } => [
+                Operand::Memory(src.clone(), Size::Quad).registers(),
+                address(dst),
+            ]
+            .concat(),
+            Instruction::Arithmetic { src, dst, .. }
+            | Instruction::FArithmetic { src, dst, .. } => {
+                [src.registers(), dst.registers()].concat()
+            }
+            Instruction::Unary { dst, .. } => dst.registers(),
+            Instruction::Shift { count, dst, .. } => [count.registers(), dst.registers()].concat(),
+            Instruction::SignExtendAccumulator(_) => physical(&[Rax]),
+            Instruction::Div { divisor, .. } => {
+                [divisor.registers(), physical(&[Rax, Rdx])].concat()
+            }
+            Instruction::Cmp { left, right }
+            | Instruction::Test { left, right }
+            | Instruction::FCmp { left, right } => [left.registers(), right.registers()].concat(),
+            Instruction::SetCC { dst, .. } => address(dst),
+            Instruction::Call { target, arguments } => {
+                let mut uses = physical(arguments);
+                uses.push(Register::Physical(Rax));
+                if let CallTarget::Indirect(operand) = target {
+                    uses.extend(operand.registers());
+                }
+                uses
+            }
+            Instruction::Ret(registers) => physical(registers),
+            Instruction::TailCall { arguments, .. } => {
+                let mut uses = physical(arguments);
+                uses.push(Register::Physical(Rax));
+                uses
+            }
+        }
+    }
+
+    /// The registers the instruction writes, explicitly or implicitly.
+    pub fn defs(&self) -> Vec<Register> {
+        let written = |operand: &Operand| match operand {
+            Operand::Register(register, _) => vec![*register],
+            _ => vec![],
+        };
+
+        match self {
+            Instruction::Mov { dst, .. }
+            | Instruction::MovSx { dst, .. }
+            | Instruction::MovZx { dst, .. }
+            | Instruction::Lea { dst, .. }
+            | Instruction::Arithmetic { dst, .. }
+            | Instruction::Unary { dst, .. }
+            | Instruction::Shift { dst, .. }
+            | Instruction::SetCC { dst, .. }
+            | Instruction::FMov { dst, .. }
+            | Instruction::FArithmetic { dst, .. }
+            | Instruction::IntToFloat { dst, .. }
+            | Instruction::FloatToInt { dst, .. }
+            | Instruction::FloatConvert { dst, .. }
+            | Instruction::Pop(dst) => written(dst),
+            Instruction::SignExtendAccumulator(_) => vec![Register::Physical(Rdx)],
+            Instruction::Div { .. } => vec![Register::Physical(Rax), Register::Physical(Rdx)],
+            Instruction::Call { .. } => CALLER_SAVED
+                .iter()
+                .chain(&VECTOR_REGISTERS)
+                .map(|register| Register::Physical(*register))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Every register named by the instruction's operands, for rewriting.
+    pub fn registers_mut(&mut self) -> Vec<&mut Register> {
+        match self {
+            Instruction::Mov { src, dst }
+            | Instruction::MovSx { src, dst }
+            | Instruction::MovZx { src, dst }
+            | Instruction::Arithmetic { src, dst, .. }
+            | Instruction::FMov { src, dst }
+            | Instruction::FArithmetic { src, dst, .. }
+            | Instruction::IntToFloat { src, dst }
+            | Instruction::FloatToInt { src, dst }
+            | Instruction::FloatConvert { src, dst } => {
+                let mut registers = src.registers_mut();
+                registers.extend(dst.registers_mut());
+                registers
+            }
+            Instruction::Lea { src, dst } => {
+                let mut registers = match &mut src.base {
+                    Base::Register(register) => vec![register],
+                    _ => vec![],
+                };
+                registers.extend(dst.registers_mut());
+                registers
+            }
+            Instruction::Shift { count, dst, .. } => {
+                let mut registers = count.registers_mut();
+                registers.extend(dst.registers_mut());
+                registers
+            }
+            Instruction::Cmp { left, right }
+            | Instruction::Test { left, right }
+            | Instruction::FCmp { left, right } => {
+                let mut registers = left.registers_mut();
+                registers.extend(right.registers_mut());
+                registers
+            }
+            Instruction::Unary { dst, .. }
+            | Instruction::SetCC { dst, .. }
+            | Instruction::Div { divisor: dst, .. }
+            | Instruction::Push(dst)
+            | Instruction::Pop(dst)
+            | Instruction::Call {
+                target: CallTarget::Indirect(dst),
+                ..
+            } => dst.registers_mut(),
+            _ => vec![],
+        }
+    }
+
+    /// Every memory operand of the instruction.
+    pub fn memory_mut(&mut self) -> Vec<&mut Memory> {
+        match self {
+            Instruction::Mov { src, dst }
+            | Instruction::MovSx { src, dst }
+            | Instruction::MovZx { src, dst }
+            | Instruction::Arithmetic { src, dst, .. }
+            | Instruction::FMov { src, dst }
+            | Instruction::FArithmetic { src, dst, .. }
+            | Instruction::IntToFloat { src, dst }
+            | Instruction::FloatToInt { src, dst }
+            | Instruction::FloatConvert { src, dst }
+            | Instruction::Shift {
+                count: src, dst, ..
+            }
+            | Instruction::Cmp {
+                left: src,
+                right: dst,
+            }
+            | Instruction::Test {
+                left: src,
+                right: dst,
+            }
+            | Instruction::FCmp {
+                left: src,
+                right: dst,
+            } => src
+                .memory_mut()
+                .into_iter()
+                .chain(dst.memory_mut())
+                .collect(),
+            Instruction::Lea { src, dst } => {
+                let mut memory = vec![src];
+                memory.extend(dst.memory_mut());
+                memory
+            }
+            Instruction::Unary { dst, .. }
+            | Instruction::SetCC { dst, .. }
+            | Instruction::Div { divisor: dst, .. }
+            | Instruction::Push(dst)
+            | Instruction::Pop(dst)
+            | Instruction::Call {
+                target: CallTarget::Indirect(dst),
+                ..
+            } => dst.memory_mut().into_iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The registers of a register-to-register move of the full value,
+    /// which the allocators may coalesce, as source and destination.
+    pub fn as_move(&self) -> Option<(Register, Register)> {
+        match self {
+            Instruction::Mov {
+                src: Operand::Register(src, src_size),
+                dst: Operand::Register(dst, dst_size),
+            }
+            | Instruction::FMov {
+                src: Operand::Register(src, src_size),
+                dst: Operand::Register(dst, dst_size),
+            } if src_size == dst_size => Some((*src, *dst)),
+            _ => None,
+        }
+    }
+
+    pub fn is_call(&self) -> bool {
+        matches!(self, Instruction::Call { .. })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackSlot {
+    pub size: usize,
+    pub alignment: usize,
+}
+
+/// A floating-point constant, emitted to read-only data with the function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Constant {
+    pub size: Size,
+    pub bits: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineFunction {
+    pub name: String,
+    pub is_global: bool,
+    pub instructions: Vec<Instruction>,
+    /// Stack slots for allocas and spilled registers.
+    pub slots: Vec<StackSlot>,
+    pub constants: Vec<Constant>,
+    /// The next unused virtual register.
+    pub next_virtual: u32,
+    /// The virtual registers in the vector class; all others are
+    /// general-purpose.
+    pub vector_registers: BTreeSet<u32>,
+    /// The size of the outgoing argument area.
+    pub outgoing: usize,
+}
+
+impl MachineFunction {
+    /// A new general-purpose virtual register.
+    pub fn new_virtual(&mut self) -> Register {
+        self.new_virtual_in(RegisterClass::General)
+    }
+
+    pub fn new_virtual_in(&mut self, class: RegisterClass) -> Register {
+        self.next_virtual += 1;
+        if class == RegisterClass::Vector {
+            self.vector_registers.insert(self.next_virtual - 1);
+        }
+        Register::Virtual(self.next_virtual - 1)
+    }
+
+    pub fn class(&self, register: Register) -> RegisterClass {
+        match register {
+            Register::Physical(register) => register.class(),
+            Register::Virtual(number) if self.vector_registers.contains(&number) => {
+                RegisterClass::Vector
+            }
+            Register::Virtual(_) => RegisterClass::General,
+        }
+    }
+
+    /// Memory holding the constant, added to the function's constants
+    /// unless it is there already.
+    pub fn constant(&mut self, constant: Constant) -> Memory {
+        let index = match self.constants.iter().position(|other| *other == constant) {
+            Some(index) => index,
+            None => {
+                self.constants.push(constant);
+                self.constants.len() - 1
+            }
+        };
+        Memory {
+            base: Base::Constant(index),
+            displacement: 0,
+        }
+    }
+
+    pub fn new_slot(&mut self, size: usize, alignment: usize) -> usize {
+        self.slots.push(StackSlot { size, alignment });
+        self.slots.len() - 1
+    }
+
+    /// Replaces every virtual register by the physical register it was
+    /// given and removes the moves that became redundant. Returns the
+    /// callee-saved registers the function now uses.
+    pub fn assign(
+        &mut self,
+        registers: &HashMap<Register, PhysicalRegister>,
+    ) -> Vec<PhysicalRegister> {
+        for instruction in &mut self.instructions {
+            for register in instruction.registers_mut() {
+                if let Register::Virtual(_) = register {
+                    *register = Register::Physical(registers[register]);
+                }
+            }
+        }
+        self.instructions
+            .retain(|instruction| !matches!(instruction.as_move(), Some((src, dst)) if src == dst));
+
+        let used: BTreeSet<PhysicalRegister> = self
+            .instructions
+            .iter()
+            .flat_map(|instruction| instruction.uses().into_iter().chain(instruction.defs()))
+            .filter_map(|register| match register {
+                Register::Physical(register) if register.is_callee_saved() => Some(register),
+                _ => None,
+            })
+            .collect();
+        used.into_iter().collect()
+    }
+}
@@ -0,0 +1,96 @@
+/*
+  The x86-64 backend. Each IR function is taken out of SSA form, turned
+  into machine instructions over virtual registers, given physical
+  registers by one of the allocators and a stack frame, cleaned up by a
+  peephole pass, and printed as assembly for the GNU assembler following
+  the System V ABI.
+*/
+
+pub mod abi;
+pub mod coloring;
+pub mod emit;
+pub mod frame;
+pub mod linear;
+pub mod liveness;
+pub mod machine;
+pub mod peephole;
+pub mod select;
+pub mod spill;
+
+use std::collections::HashSet;
+
+pub use emit::Syntax;
+
+use crate::ir::{self, Instr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodegenError {
+    Unsupported(String),
+}
+
+/// How virtual registers are given physical ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Allocator {
+    /// Iterated register coalescing.
+    Graph,
+    /// Linear scan, which is faster on large functions.
+    Linear,
+    /// A stack slot for every virtual register.
+    SpillEverything,
+}
+
+/// Selects instructions for a function and allocates its registers and
+/// frame.
+pub fn compile_function(
+    function: &ir::Function,
+    allocator: Allocator,
+) -> Result<machine::MachineFunction, CodegenError> {
+    let mut function = function.clone();
+    if function
+        .body
+        .iter()
+        .any(|instr| matches!(instr, Instr::Phi { .. }))
+    {
+        ir::ssa::destruct(&mut function);
+    }
+
+    let mut machine = select::select(&function)?;
+    let callee_saved = match allocator {
+        Allocator::Graph => coloring::allocate(&mut machine),
+        Allocator::Linear => linear::allocate(&mut machine),
+        Allocator::SpillEverything => {
+            spill::allocate(&mut machine);
+            vec![]
+        }
+    };
+    frame::lay_out(&mut machine, &callee_saved);
+    peephole::optimize(&mut machine);
+    Ok(machine)
+}
+
+/// Compiles the program to assembly in the given syntax.
+pub fn generate(
+    program: &ir::Program,
+    allocator: Allocator,
+    syntax: Syntax,
+) -> Result<String, CodegenError> {
+    let defined: HashSet<String> = program
+        .functions
+        .iter()
+        .map(|function| function.name.clone())
+        .collect();
+
+    let mut output = String::new();
+    if syntax == Syntax::Intel {
+        output.push_str("\t.intel_syntax noprefix\n");
+    }
+    for global in &program.globals {
+        emit::global(&mut output, global);
+    }
+    for function in &program.functions {
+        let machine = compile_function(function, allocator)?;
+        emit::function(&mut output, &machine, &defined, syntax);
+    }
+    output.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    Ok(output)
+}
@@ -0,0 +1,354 @@
+/*
+  Peephole optimization of machine code once registers are allocated and
+  the frame is laid out. Jumps to jumps are threaded, jumps to the next
+  instruction and unreachable code removed, and a conditional jump over
+  an unconditional one is inverted. A store to a frame slot followed by a
+  load from it becomes a move between registers, and a move back to where
+  its value came from goes away; only frame slots are forwarded, since
+  memory reached through a pointer may be volatile. A comparison whose
+  `setcc` result is only tested by a branch branches on the comparison
+  itself.
+
+  The rest changes which flags are set, so it only happens where a
+  dataflow pass finds no later instruction reading them: `mov $0`
+  becomes `xor` of the register with itself, and multiplying by a power
+  of two a shift.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::liveness;
+use super::machine::{
+    ArithmeticOp, Base, ConditionCode, Instruction, MachineFunction, Memory, Operand,
+    PhysicalRegister, Register, ShiftOp, Size, UnaryOp,
+};
+
+/// Where each label is.
+fn label_positions(instructions: &[Instruction]) -> HashMap<u32, usize> {
+    instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, instruction)| match instruction {
+            Instruction::Label(label) => Some((*label, index)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The labels right at `index`, before the next instruction that is not
+/// one.
+fn labels_at(instructions: &[Instruction], index: usize) -> impl Iterator<Item = u32> + '_ {
+    instructions[index..]
+        .iter()
+        .map_while(|instruction| match instruction {
+            Instruction::Label(label) => Some(*label),
+            _ => None,
+        })
+}
+
+/// Where a jump to the label ends up, following unconditional jumps.
+fn final_target(instructions: &[Instruction], positions: &HashMap<u32, usize>, label: u32) -> u32 {
+    let mut target = label;
+    let mut seen = HashSet::from([label]);
+    loop {
+        let position = positions[&target];
+        let next = instructions[position..]
+            .iter()
+            .find(|instruction| !matches!(instruction, Instruction::Label(_)));
+        match next {
+            Some(Instruction::Jmp(next)) if seen.insert(*next) => target = *next,
+            _ => return target,
+        }
+    }
+}
+
+fn thread_jumps(instructions: &mut [Instruction]) -> bool {
+    let positions = label_positions(instructions);
+    let mut changed = false;
+    for index in 0..instructions.len() {
+        let (Instruction::Jmp(label) | Instruction::Jcc { target: label, .. }) =
+            instructions[index]
+        else {
+            continue;
+        };
+        let target = final_target(instructions, &positions, label);
+        if target != label {
+            match &mut instructions[index] {
+                Instruction::Jmp(label) | Instruction::Jcc { target: label, .. } => *label = target,
+                _ => unreachable!(),
+            }
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Removes jumps to the next instruction and the code after unconditional
+/// jumps up to the next label, and inverts conditional jumps over
+/// unconditional ones.
+fn simplify_jumps(instructions: &mut Vec<Instruction>) -> bool {
+    let mut changed = false;
+    let mut index = 0;
+    while index < instructions.len() {
+        match instructions[index] {
+            Instruction::Jmp(label) | Instruction::Jcc { target: label, .. }
+                if labels_at(instructions, index + 1).any(|next| next == label) =>
+            {
+                instructions.remove(index);
+                changed = true;
+                continue;
+            }
+            Instruction::Jcc { condition, target } => {
+                if let Some(&Instruction::Jmp(other)) = instructions.get(index + 1)
+                    && labels_at(instructions, index + 2).any(|next| next == target)
+                {
+                    instructions[index] = Instruction::Jcc {
+                        condition: condition.negate(),
+                        target: other,
+                    };
+                    instructions.remove(index + 1);
+                    changed = true;
+                }
+            }
+            Instruction::Jmp(_) | Instruction::Ret(_) | Instruction::TailCall { .. } => {
+                let end = instructions[index + 1..]
+                    .iter()
+                    .position(|instruction| matches!(instruction, Instruction::Label(_)))
+                    .map_or(instructions.len(), |offset| index + 1 + offset);
+                if end > index + 1 {
+                    instructions.drain(index + 1..end);
+                    changed = true;
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+
+    let referenced: HashSet<u32> = instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Jmp(label) | Instruction::Jcc { target: label, .. } => Some(*label),
+            _ => None,
+        })
+        .collect();
+    let count = instructions.len();
+    instructions.retain(|instruction| match instruction {
+        Instruction::Label(label) => referenced.contains(label),
+        _ => true,
+    });
+    changed || instructions.len() != count
+}
+
+fn is_frame_slot(operand: &Operand) -> bool {
+    matches!(
+        operand,
+        Operand::Memory(
+            Memory {
+                base: Base::Register(Register::Physical(PhysicalRegister::Rbp)),
+                ..
+            },
+            _
+        )
+    )
+}
+
+/// Forwards stores to frame slots to the loads right after them and
+/// removes moves that put a value back where it came from.
+fn forward_moves(instructions: &mut Vec<Instruction>) -> bool {
+    let mut changed = false;
+    let mut index = 1;
+    while index < instructions.len() {
+        let (
+            Instruction::Mov {
+                src: first_src,
+                dst: first_dst,
+            },
+            Instruction::Mov { src, dst },
+        ) = (&instructions[index - 1], &instructions[index])
+        else {
+            index += 1;
+            continue;
+        };
+        if !is_frame_slot(first_dst) || first_dst != src {
+            index += 1;
+            continue;
+        }
+        if first_src == dst {
+            instructions.remove(index);
+        } else {
+            instructions[index] = Instruction::Mov {
+                src: first_src.clone(),
+                dst: dst.clone(),
+            };
+            index += 1;
+        }
+        changed = true;
+    }
+
+    // A load from a slot and a store of the same register back to it.
+    let mut index = 1;
+    while index < instructions.len() {
+        if let (
+            Instruction::Mov {
+                src: first_src,
+                dst: first_dst @ Operand::Register(..),
+            },
+            Instruction::Mov { src, dst },
+        ) = (&instructions[index - 1], &instructions[index])
+            && is_frame_slot(first_src)
+            && first_src == dst
+            && first_dst == src
+        {
+            instructions.remove(index);
+            changed = true;
+        } else {
+            index += 1;
+        }
+    }
+    changed
+}
+
+/// Branches on a comparison directly when its `setcc` result is only
+/// tested.
+fn merge_compares(instructions: &mut Vec<Instruction>) -> bool {
+    let live_after = liveness::live_after(instructions);
+    let mut merged = vec![];
+    for index in 0..instructions.len().saturating_sub(3) {
+        let [
+            Instruction::SetCC {
+                condition,
+                dst: Operand::Register(flag, Size::Byte),
+            },
+            Instruction::MovZx {
+                src: Operand::Register(extended_src, Size::Byte),
+                dst: Operand::Register(extended, Size::Long),
+            },
+            Instruction::Test {
+                left: Operand::Register(left, Size::Long),
+                right: Operand::Register(right, Size::Long),
+            },
+            Instruction::Jcc {
+                condition: branch @ (ConditionCode::Ne | ConditionCode::E),
+                target,
+            },
+        ] = &instructions[index..index + 4]
+        else {
+            continue;
+        };
+        if [extended_src, extended, left, right]
+            .iter()
+            .all(|register| *register == flag)
+            && !live_after[index + 3].contains(flag)
+        {
+            let condition = match branch {
+                ConditionCode::Ne => *condition,
+                _ => condition.negate(),
+            };
+            merged.push((index, condition, *target));
+        }
+    }
+    for &(index, condition, target) in merged.iter().rev() {
+        instructions.splice(index..index + 4, [Instruction::Jcc { condition, target }]);
+    }
+    !merged.is_empty()
+}
+
+/// Whether the flags may be read after each instruction.
+fn flags_live_after(instructions: &[Instruction]) -> Vec<bool> {
+    let transfer = |instruction: &Instruction, live: bool| match instruction {
+        Instruction::Jcc { .. } | Instruction::SetCC { .. } => true,
+        Instruction::Cmp { .. }
+        | Instruction::Test { .. }
+        | Instruction::FCmp { .. }
+        | Instruction::Arithmetic { .. }
+        | Instruction::Unary {
+            op: UnaryOp::Neg, ..
+        }
+        | Instruction::Div { .. }
+        | Instruction::Call { .. }
+        | Instruction::Ret(_)
+        | Instruction::TailCall { .. } => false,
+        // A shift by zero leaves the flags alone.
+        Instruction::Shift {
+            count: Operand::Immediate(count),
+            ..
+        } if *count != 0 => false,
+        _ => live,
+    };
+
+    let blocks = liveness::blocks(instructions);
+    let mut live_in = vec![false; blocks.len()];
+    let mut after = vec![false; instructions.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, block) in blocks.iter().enumerate().rev() {
+            let mut live = block.successors.iter().any(|successor| live_in[*successor]);
+            for position in (block.start..block.end).rev() {
+                after[position] = live;
+                live = transfer(&instructions[position], live);
+            }
+            if live != live_in[index] {
+                live_in[index] = live;
+                changed = true;
+            }
+        }
+    }
+    after
+}
+
+/// Replaces instructions by cheaper ones that set different flags, where
+/// nothing reads them.
+fn strength_reduce(instructions: &mut [Instruction]) {
+    let flags_live = flags_live_after(instructions);
+    for (instruction, flags_live) in instructions.iter_mut().zip(flags_live) {
+        if flags_live {
+            continue;
+        }
+        match instruction {
+            // Writing the low half of a register clears the high half.
+            Instruction::Mov {
+                src: Operand::Immediate(0),
+                dst: Operand::Register(register, size),
+            } => {
+                let size = match size {
+                    Size::Quad => Size::Long,
+                    size => *size,
+                };
+                *instruction = Instruction::Arithmetic {
+                    op: ArithmeticOp::Xor,
+                    src: Operand::Register(*register, size),
+                    dst: Operand::Register(*register, size),
+                };
+            }
+            Instruction::Arithmetic {
+                op: ArithmeticOp::Imul,
+                src: Operand::Immediate(factor),
+                dst,
+            } if *factor > 1 && (*factor as u64).is_power_of_two() => {
+                *instruction = Instruction::Shift {
+                    op: ShiftOp::Shl,
+                    count: Operand::Immediate(factor.trailing_zeros() as i64),
+                    dst: dst.clone(),
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Optimizes a function whose registers are allocated and frame laid out.
+pub fn optimize(function: &mut MachineFunction) {
+    let instructions = &mut function.instructions;
+    loop {
+        let mut changed = thread_jumps(instructions);
+        changed |= simplify_jumps(instructions);
+        changed |= forward_moves(instructions);
+        changed |= merge_compares(instructions);
+        if !changed {
+            break;
+        }
+    }
+    strength_reduce(instructions);
+}
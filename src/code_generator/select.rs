@@ -0,0 +1,1494 @@
+/*
+  Instruction selection: translates an IR function, out of SSA form, into
+  machine instructions over virtual registers. Temporary `%n` becomes
+  virtual register `n`, and the registers the selector needs for itself
+  are numbered after the function's temporaries.
+
+  Values narrower than 32 bits live in registers as longs whose upper bits
+  are unspecified, so only the operations that look at those bits
+  (comparisons, division, right shifts and widening conversions) extend
+  them first. Allocas become stack slots, addressed directly when loaded
+  from or stored to, and fixed registers of the ABI and of instructions
+  such as `idiv` are reached through moves the allocator can coalesce. A
+  call of a named function in tail position becomes a jump to it, unless
+  the function takes the address of one of its allocas.
+
+  Floats and doubles live in vector registers and are computed with scalar
+  SSE instructions, and their constants are loaded from read-only data.
+  Comparisons of them are unordered when either operand is NaN, which
+  `ucomiss` and `ucomisd` report by setting the zero, parity and carry
+  flags at once: ordered comparisons test the unsigned conditions that
+  the carry flag makes false, with the operands swapped for less-than,
+  and equality also tests the parity flag. SSE only converts between
+  floating-point and signed integers, so unsigned 32-bit values are
+  converted as 64-bit ones and unsigned 64-bit ones take a branch for
+  values with the top bit set.
+
+  Arguments and parameters go where `abi` puts them. A struct in
+  registers is loaded from, or stored to, the copy its pointer refers to
+  an eightbyte at a time, without touching the memory after it, and one
+  the callee returns in registers is stored to the caller's return slot
+  after the call. A callee's own copies of such structs, and its return
+  slot, are new stack slots. Calls passing arguments on the stack, or
+  structs, are never sibling calls.
+
+  A variadic function stores every argument register to its register
+  save area on entry. `va_start` points the `va_list` past the registers
+  and stack arguments of the named parameters, and `va_arg` takes the
+  next argument from the save area while its class still has registers
+  left there, and from the stack after that.
+*/
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::CodegenError;
+use super::abi::{self, Class, Location};
+use super::machine::{
+    ARGUMENT_REGISTERS, ArithmeticOp, Base, CallTarget, ConditionCode, Constant, FloatOp,
+    Instruction, MachineFunction, Memory, Operand, PhysicalRegister, Register, RegisterClass,
+    ShiftOp, Size, UnaryOp, VECTOR_ARGUMENT_REGISTERS,
+};
+use crate::ir::{
+    self, BinaryOp, ByValue, Condition, ConvertOp, Instr, IrType, Temp, Value, tailcall,
+};
+
+/// The width a value of the type occupies in a register.
+fn width(ty: IrType) -> Size {
+    match ty {
+        IrType::I64 | IrType::F64 => Size::Quad,
+        _ => Size::Long,
+    }
+}
+
+fn fits_immediate(value: i64) -> bool {
+    i32::try_from(value).is_ok()
+}
+
+fn unsupported(what: &str) -> CodegenError {
+    CodegenError::Unsupported(what.to_string())
+}
+
+/// The memory `offset` bytes further.
+fn at(memory: &Memory, offset: usize) -> Memory {
+    Memory {
+        base: memory.base.clone(),
+        displacement: memory.displacement + offset as i64,
+    }
+}
+
+fn stack_argument(base: Base, offset: usize) -> Memory {
+    Memory {
+        base,
+        displacement: offset as i64,
+    }
+}
+
+/// The sizes of the pieces `size` bytes are moved in, largest first.
+fn chunks(size: usize) -> impl Iterator<Item = (usize, Size)> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let chunk = [Size::Quad, Size::Long, Size::Word, Size::Byte]
+            .into_iter()
+            .find(|chunk| chunk.bytes() <= size - offset)?;
+        offset += chunk.bytes();
+        Some((offset - chunk.bytes(), chunk))
+    })
+}
+
+struct Selector {
+    function: MachineFunction,
+    /// The stack slot of each alloca.
+    slots: HashMap<Temp, usize>,
+    /// The next label not used by the IR function.
+    next_label: u32,
+    /// The size of the struct the function returns in registers, and
+    /// those registers.
+    returned: Option<(usize, Vec<(Class, PhysicalRegister)>)>,
+    /// For a variadic function, the slot of its register save area and
+    /// where its named parameters are.
+    variadic: Option<(usize, abi::Assignment)>,
+}
+
+impl Selector {
+    fn emit(&mut self, instruction: Instruction) {
+        self.function.instructions.push(instruction);
+    }
+
+    fn new_label(&mut self) -> u32 {
+        self.next_label += 1;
+        self.next_label - 1
+    }
+
+    fn virtual_register(temp: Temp, size: Size) -> Operand {
+        Operand::Register(Register::Virtual(temp.0), size)
+    }
+
+    /// The register of a float or double temporary, which is a vector
+    /// register.
+    fn vector_register(&mut self, temp: Temp, ty: IrType) -> Operand {
+        self.function.vector_registers.insert(temp.0);
+        Self::virtual_register(temp, Size::of(ty))
+    }
+
+    fn new_vector(&mut self, size: Size) -> Operand {
+        Operand::Register(self.function.new_virtual_in(RegisterClass::Vector), size)
+    }
+
+    fn constant(&mut self, bits: u64, size: Size) -> Operand {
+        Operand::Memory(self.function.constant(Constant { size, bits }), size)
+    }
+
+    /// The floating-point value as an operand: a vector register or a
+    /// constant in read-only data.
+    fn float_operand(&mut self, value: &Value, ty: IrType) -> Result<Operand, CodegenError> {
+        let value = match value {
+            Value::Temp(temp) => return Ok(self.vector_register(*temp, ty)),
+            Value::Float(value) => *value,
+            Value::Int(value) => *value as f64,
+            Value::Global(_) => return Err(unsupported("addresses as floating-point values")),
+        };
+        Ok(match ty {
+            IrType::F32 => self.constant((value as f32).to_bits() as u64, Size::Long),
+            _ => self.constant(value.to_bits(), Size::Quad),
+        })
+    }
+
+    /// A vector register holding the floating-point value.
+    fn float_materialize(&mut self, value: &Value, ty: IrType) -> Result<Operand, CodegenError> {
+        let operand = self.float_operand(value, ty)?;
+        if let Operand::Register(..) = operand {
+            return Ok(operand);
+        }
+        let register = self.new_vector(Size::of(ty));
+        self.emit(Instruction::FMov {
+            src: operand,
+            dst: register.clone(),
+        });
+        Ok(register)
+    }
+
+    fn slot_memory(slot: usize) -> Memory {
+        Memory {
+            base: Base::Slot(slot),
+            displacement: 0,
+        }
+    }
+
+    /// Copies `size` bytes between the memory.
+    fn copy(&mut self, destination: &Memory, source: &Memory, size: usize) {
+        for (offset, chunk) in chunks(size) {
+            let register = Operand::Register(self.function.new_virtual(), chunk);
+            self.emit(Instruction::Mov {
+                src: Operand::Memory(at(source, offset), chunk),
+                dst: register.clone(),
+            });
+            self.emit(Instruction::Mov {
+                src: register,
+                dst: Operand::Memory(at(destination, offset), chunk),
+            });
+        }
+    }
+
+    /// A register holding the `size` bytes of an eightbyte of a struct in
+    /// memory.
+    fn load_eightbyte(&mut self, memory: Memory, size: usize, class: Class) -> Operand {
+        if class == Class::Sse {
+            let size = if size > 4 { Size::Quad } else { Size::Long };
+            let register = self.new_vector(size);
+            self.emit(Instruction::FMov {
+                src: Operand::Memory(memory, size),
+                dst: register.clone(),
+            });
+            return register;
+        }
+        let register = self.function.new_virtual();
+        for (offset, chunk) in chunks(size) {
+            let piece = if offset == 0 {
+                register
+            } else {
+                self.function.new_virtual()
+            };
+            let src = Operand::Memory(at(&memory, offset), chunk);
+            self.emit(match chunk {
+                Size::Quad | Size::Long => Instruction::Mov {
+                    src,
+                    dst: Operand::Register(piece, chunk),
+                },
+                _ => Instruction::MovZx {
+                    src,
+                    dst: Operand::Register(piece, Size::Long),
+                },
+            });
+            if offset > 0 {
+                self.emit(Instruction::Shift {
+                    op: ShiftOp::Shl,
+                    count: Operand::Immediate(8 * offset as i64),
+                    dst: Operand::Register(piece, Size::Quad),
+                });
+                self.emit(Instruction::Arithmetic {
+                    op: ArithmeticOp::Or,
+                    src: Operand::Register(piece, Size::Quad),
+                    dst: Operand::Register(register, Size::Quad),
+                });
+            }
+        }
+        Operand::Register(register, Size::Quad)
+    }
+
+    /// Stores the `size` bytes of an eightbyte of a struct held in the
+    /// register to memory.
+    fn store_eightbyte(&mut self, register: Register, memory: Memory, size: usize, class: Class) {
+        if class == Class::Sse {
+            let size = if size > 4 { Size::Quad } else { Size::Long };
+            self.emit(Instruction::FMov {
+                src: Operand::Register(register, size),
+                dst: Operand::Memory(memory, size),
+            });
+            return;
+        }
+        // The bytes after the first chunks are shifted down to it.
+        let value = self.function.new_virtual();
+        self.emit(Instruction::Mov {
+            src: Operand::Register(register, Size::Quad),
+            dst: Operand::Register(value, Size::Quad),
+        });
+        let mut shifted = 0;
+        for (offset, chunk) in chunks(size) {
+            if offset > shifted {
+                self.emit(Instruction::Shift {
+                    op: ShiftOp::Shr,
+                    count: Operand::Immediate(8 * (offset - shifted) as i64),
+                    dst: Operand::Register(value, Size::Quad),
+                });
+                shifted = offset;
+            }
+            self.emit(Instruction::Mov {
+                src: Operand::Register(value, chunk),
+                dst: Operand::Memory(at(&memory, offset), chunk),
+            });
+        }
+    }
+
+    /// Loads the value of the type at the memory into the temporary.
+    fn load(&mut self, dst: Temp, ty: IrType, memory: Memory) {
+        let src = Operand::Memory(memory, Size::of(ty));
+        if ty.is_float() {
+            let dst = self.vector_register(dst, ty);
+            self.emit(Instruction::FMov { src, dst });
+            return;
+        }
+        let dst = Self::virtual_register(dst, width(ty));
+        self.emit(if Size::of(ty) == width(ty) {
+            Instruction::Mov { src, dst }
+        } else {
+            Instruction::MovZx { src, dst }
+        });
+    }
+
+    /// A register holding the value.
+    fn materialize(&mut self, value: &Value, size: Size) -> Result<Operand, CodegenError> {
+        let operand = self.operand(value, size)?;
+        if let Operand::Register(..) = operand {
+            return Ok(operand);
+        }
+        let register = Operand::Register(self.function.new_virtual(), size);
+        self.emit(Instruction::Mov {
+            src: operand,
+            dst: register.clone(),
+        });
+        Ok(register)
+    }
+
+    /// The value as an operand: a register or an immediate that fits in 32
+    /// bits.
+    fn operand(&mut self, value: &Value, size: Size) -> Result<Operand, CodegenError> {
+        match value {
+            Value::Temp(temp) => match self.slots.get(temp) {
+                Some(&slot) => {
+                    let register = Operand::Register(self.function.new_virtual(), Size::Quad);
+                    self.emit(Instruction::Lea {
+                        src: Self::slot_memory(slot),
+                        dst: register.clone(),
+                    });
+                    Ok(register)
+                }
+                None => Ok(Self::virtual_register(*temp, size)),
+            },
+            Value::Int(value) => {
+                // Constants may not be truncated to their type yet.
+                let value = match size {
+                    Size::Byte => *value as i8 as i64,
+                    Size::Word => *value as i16 as i64,
+                    Size::Long => *value as i32 as i64,
+                    Size::Quad => *value,
+                };
+                if fits_immediate(value) {
+                    return Ok(Operand::Immediate(value));
+                }
+                let register = Operand::Register(self.function.new_virtual(), Size::Quad);
+                self.emit(Instruction::Mov {
+                    src: Operand::Immediate(value),
+                    dst: register.clone(),
+                });
+                Ok(register)
+            }
+            Value::Global(name) => {
+                let register = Operand::Register(self.function.new_virtual(), Size::Quad);
+                self.emit(Instruction::Lea {
+                    src: Memory {
+                        base: Base::Symbol(name.clone()),
+                        displacement: 0,
+                    },
+                    dst: register.clone(),
+                });
+                Ok(register)
+            }
+            Value::Float(_) => Err(unsupported("floating-point values")),
+        }
+    }
+
+    /// The memory a pointer value refers to.
+    fn address(&mut self, value: &Value) -> Result<Memory, CodegenError> {
+        let base = match value {
+            Value::Temp(temp) => match self.slots.get(temp) {
+                Some(&slot) => Base::Slot(slot),
+                None => Base::Register(Register::Virtual(temp.0)),
+            },
+            Value::Global(name) => Base::Symbol(name.clone()),
+            value => match self.materialize(value, Size::Quad)? {
+                Operand::Register(register, _) => Base::Register(register),
+                _ => unreachable!(),
+            },
+        };
+        Ok(Memory {
+            base,
+            displacement: 0,
+        })
+    }
+
+    /// A register holding the value of a narrow type extended to 32 bits,
+    /// or the value itself for wider types.
+    fn extended(
+        &mut self,
+        value: &Value,
+        ty: IrType,
+        signed: bool,
+    ) -> Result<Operand, CodegenError> {
+        let narrow = Size::of(ty);
+        if width(ty) == narrow {
+            return self.operand(value, narrow);
+        }
+        if let Value::Int(value) = value {
+            let mask = (1i64 << (8 * narrow.bytes())) - 1;
+            return Ok(Operand::Immediate(if signed {
+                *value
+            } else {
+                value & mask
+            }));
+        }
+        let src = self.operand(value, narrow)?;
+        let dst = Operand::Register(self.function.new_virtual(), Size::Long);
+        self.emit(if signed {
+            Instruction::MovSx {
+                src,
+                dst: dst.clone(),
+            }
+        } else {
+            Instruction::MovZx {
+                src,
+                dst: dst.clone(),
+            }
+        });
+        Ok(dst)
+    }
+
+    fn binary(
+        &mut self,
+        dst: Temp,
+        op: BinaryOp,
+        ty: IrType,
+        left: &Value,
+        right: &Value,
+    ) -> Result<(), CodegenError> {
+        let float = match op {
+            BinaryOp::FAdd => Some(FloatOp::Add),
+            BinaryOp::FSub => Some(FloatOp::Sub),
+            BinaryOp::FMul => Some(FloatOp::Mul),
+            BinaryOp::FDiv => Some(FloatOp::Div),
+            _ => None,
+        };
+        if let Some(op) = float {
+            return self.float_binary(dst, op, ty, left, right);
+        }
+
+        let size = width(ty);
+        let result = Self::virtual_register(dst, size);
+        let arithmetic = match op {
+            BinaryOp::Add => Some(ArithmeticOp::Add),
+            BinaryOp::Sub => Some(ArithmeticOp::Sub),
+            BinaryOp::Mul => Some(ArithmeticOp::Imul),
+            BinaryOp::And => Some(ArithmeticOp::And),
+            BinaryOp::Or => Some(ArithmeticOp::Or),
+            BinaryOp::Xor => Some(ArithmeticOp::Xor),
+            _ => None,
+        };
+
+        if let Some(op) = arithmetic {
+            // Compute into a fresh register when the destination is also
+            // the right operand.
+            let target = if *right == Value::Temp(dst) {
+                Operand::Register(self.function.new_virtual(), size)
+            } else {
+                result.clone()
+            };
+            let left = self.operand(left, size)?;
+            let right = self.operand(right, size)?;
+            self.emit(Instruction::Mov {
+                src: left,
+                dst: target.clone(),
+            });
+            self.emit(Instruction::Arithmetic {
+                op,
+                src: right,
+                dst: target.clone(),
+            });
+            if target != result {
+                self.emit(Instruction::Mov {
+                    src: target,
+                    dst: result,
+                });
+            }
+            return Ok(());
+        }
+
+        match op {
+            BinaryOp::SDiv | BinaryOp::UDiv | BinaryOp::SRem | BinaryOp::URem => {
+                let signed = matches!(op, BinaryOp::SDiv | BinaryOp::SRem);
+                let left = self.extended(left, ty, signed)?;
+                let divisor = match self.extended(right, ty, signed)? {
+                    Operand::Immediate(value) => self.materialize(&Value::Int(value), size)?,
+                    divisor => divisor,
+                };
+                self.emit(Instruction::Mov {
+                    src: left,
+                    dst: Operand::register(PhysicalRegister::Rax, size),
+                });
+                self.emit(if signed {
+                    Instruction::SignExtendAccumulator(size)
+                } else {
+                    Instruction::Mov {
+                        src: Operand::Immediate(0),
+                        dst: Operand::register(PhysicalRegister::Rdx, Size::Long),
+                    }
+                });
+                self.emit(Instruction::Div { signed, divisor });
+                let quotient = matches!(op, BinaryOp::SDiv | BinaryOp::UDiv);
+                self.emit(Instruction::Mov {
+                    src: Operand::register(
+                        if quotient {
+                            PhysicalRegister::Rax
+                        } else {
+                            PhysicalRegister::Rdx
+                        },
+                        size,
+                    ),
+                    dst: result,
+                });
+            }
+            BinaryOp::Shl | BinaryOp::AShr | BinaryOp::LShr => {
+                let (op, value) = match op {
+                    BinaryOp::Shl => (ShiftOp::Shl, self.operand(left, size)?),
+                    BinaryOp::AShr => (ShiftOp::Sar, self.extended(left, ty, true)?),
+                    _ => (ShiftOp::Shr, self.extended(left, ty, false)?),
+                };
+                let count = match right {
+                    Value::Int(count) => Operand::Immediate(count & 63),
+                    right => {
+                        let count = self.operand(right, Size::Long)?;
+                        self.emit(Instruction::Mov {
+                            src: count,
+                            dst: Operand::register(PhysicalRegister::Rcx, Size::Long),
+                        });
+                        Operand::register(PhysicalRegister::Rcx, Size::Byte)
+                    }
+                };
+                let target = Operand::Register(self.function.new_virtual(), size);
+                self.emit(Instruction::Mov {
+                    src: value,
+                    dst: target.clone(),
+                });
+                self.emit(Instruction::Shift {
+                    op,
+                    count,
+                    dst: target.clone(),
+                });
+                self.emit(Instruction::Mov {
+                    src: target,
+                    dst: result,
+                });
+            }
+            _ => unreachable!("floating-point arithmetic is selected separately"),
+        }
+        Ok(())
+    }
+
+    fn float_binary(
+        &mut self,
+        dst: Temp,
+        op: FloatOp,
+        ty: IrType,
+        left: &Value,
+        right: &Value,
+    ) -> Result<(), CodegenError> {
+        let result = self.vector_register(dst, ty);
+        let target = if *right == Value::Temp(dst) {
+            self.new_vector(Size::of(ty))
+        } else {
+            result.clone()
+        };
+        let left = self.float_operand(left, ty)?;
+        let right = self.float_operand(right, ty)?;
+        self.emit(Instruction::FMov {
+            src: left,
+            dst: target.clone(),
+        });
+        self.emit(Instruction::FArithmetic {
+            op,
+            src: right,
+            dst: target.clone(),
+        });
+        if target != result {
+            self.emit(Instruction::FMov {
+                src: target,
+                dst: result,
+            });
+        }
+        Ok(())
+    }
+
+    fn compare(
+        &mut self,
+        dst: Temp,
+        condition: Condition,
+        ty: IrType,
+        left: &Value,
+        right: &Value,
+    ) -> Result<(), CodegenError> {
+        let (code, signed) = match condition {
+            Condition::Eq => (ConditionCode::E, false),
+            Condition::Ne => (ConditionCode::Ne, false),
+            Condition::SLt => (ConditionCode::L, true),
+            Condition::SLe => (ConditionCode::Le, true),
+            Condition::SGt => (ConditionCode::G, true),
+            Condition::SGe => (ConditionCode::Ge, true),
+            Condition::ULt => (ConditionCode::B, false),
+            Condition::ULe => (ConditionCode::Be, false),
+            Condition::UGt => (ConditionCode::A, false),
+            Condition::UGe => (ConditionCode::Ae, false),
+            _ => return self.float_compare(dst, condition, ty, left, right),
+        };
+        let left = match self.extended(left, ty, signed)? {
+            Operand::Immediate(value) => self.materialize(&Value::Int(value), width(ty))?,
+            left => left,
+        };
+        let right = self.extended(right, ty, signed)?;
+        self.emit(Instruction::Cmp { left, right });
+        self.emit(Instruction::SetCC {
+            condition: code,
+            dst: Self::virtual_register(dst, Size::Byte),
+        });
+        self.emit(Instruction::MovZx {
+            src: Self::virtual_register(dst, Size::Byte),
+            dst: Self::virtual_register(dst, Size::Long),
+        });
+        Ok(())
+    }
+
+    fn float_compare(
+        &mut self,
+        dst: Temp,
+        condition: Condition,
+        ty: IrType,
+        left: &Value,
+        right: &Value,
+    ) -> Result<(), CodegenError> {
+        let (left, right) = match condition {
+            Condition::FLt | Condition::FLe => (right, left),
+            _ => (left, right),
+        };
+        let left = self.float_materialize(left, ty)?;
+        let right = self.float_operand(right, ty)?;
+        self.emit(Instruction::FCmp { left, right });
+
+        // Equality also needs the operands ordered, and inequality holds
+        // when they are not.
+        let (code, parity) = match condition {
+            Condition::FEq => (
+                ConditionCode::E,
+                Some((ConditionCode::Np, ArithmeticOp::And)),
+            ),
+            Condition::FNe => (
+                ConditionCode::Ne,
+                Some((ConditionCode::P, ArithmeticOp::Or)),
+            ),
+            Condition::FGt | Condition::FLt => (ConditionCode::A, None),
+            Condition::FGe | Condition::FLe => (ConditionCode::Ae, None),
+            _ => unreachable!("integer comparisons are selected separately"),
+        };
+        let flag = Self::virtual_register(dst, Size::Byte);
+        self.emit(Instruction::SetCC {
+            condition: code,
+            dst: flag.clone(),
+        });
+        if let Some((condition, op)) = parity {
+            let ordered = Operand::Register(self.function.new_virtual(), Size::Byte);
+            self.emit(Instruction::SetCC {
+                condition,
+                dst: ordered.clone(),
+            });
+            self.emit(Instruction::Arithmetic {
+                op,
+                src: ordered,
+                dst: flag.clone(),
+            });
+        }
+        self.emit(Instruction::MovZx {
+            src: flag,
+            dst: Self::virtual_register(dst, Size::Long),
+        });
+        Ok(())
+    }
+
+    /// Converts an unsigned 64-bit integer: values with the top bit set are
+    /// halved, keeping the lowest bit so the result rounds the same, and
+    /// doubled after the conversion.
+    fn unsigned_to_float(
+        &mut self,
+        dst: Temp,
+        to: IrType,
+        value: &Value,
+    ) -> Result<(), CodegenError> {
+        let result = self.vector_register(dst, to);
+        let src = self.materialize(value, Size::Quad)?;
+        let (large, done) = (self.new_label(), self.new_label());
+        self.emit(Instruction::Cmp {
+            left: src.clone(),
+            right: Operand::Immediate(0),
+        });
+        self.emit(Instruction::Jcc {
+            condition: ConditionCode::L,
+            target: large,
+        });
+        self.emit(Instruction::IntToFloat {
+            src: src.clone(),
+            dst: result.clone(),
+        });
+        self.emit(Instruction::Jmp(done));
+
+        self.emit(Instruction::Label(large));
+        let half = Operand::Register(self.function.new_virtual(), Size::Quad);
+        let lowest = Operand::Register(self.function.new_virtual(), Size::Quad);
+        self.emit(Instruction::Mov {
+            src: src.clone(),
+            dst: half.clone(),
+        });
+        self.emit(Instruction::Shift {
+            op: ShiftOp::Shr,
+            count: Operand::Immediate(1),
+            dst: half.clone(),
+        });
+        self.emit(Instruction::Mov {
+            src,
+            dst: lowest.clone(),
+        });
+        self.emit(Instruction::Arithmetic {
+            op: ArithmeticOp::And,
+            src: Operand::Immediate(1),
+            dst: lowest.clone(),
+        });
+        self.emit(Instruction::Arithmetic {
+            op: ArithmeticOp::Or,
+            src: lowest,
+            dst: half.clone(),
+        });
+        self.emit(Instruction::IntToFloat {
+            src: half,
+            dst: result.clone(),
+        });
+        self.emit(Instruction::FArithmetic {
+            op: FloatOp::Add,
+            src: result.clone(),
+            dst: result,
+        });
+        self.emit(Instruction::Label(done));
+        Ok(())
+    }
+
+    /// Converts to an unsigned 64-bit integer: values from 2^63 up have
+    /// 2^63 subtracted before the conversion and the top bit set after it.
+    fn float_to_unsigned(
+        &mut self,
+        dst: Temp,
+        from: IrType,
+        value: &Value,
+    ) -> Result<(), CodegenError> {
+        let result = Self::virtual_register(dst, Size::Quad);
+        let src = self.float_materialize(value, from)?;
+        let limit = self.float_operand(&Value::Float(9223372036854775808.0), from)?;
+        let (large, done) = (self.new_label(), self.new_label());
+        self.emit(Instruction::FCmp {
+            left: src.clone(),
+            right: limit.clone(),
+        });
+        self.emit(Instruction::Jcc {
+            condition: ConditionCode::Ae,
+            target: large,
+        });
+        self.emit(Instruction::FloatToInt {
+            src: src.clone(),
+            dst: result.clone(),
+        });
+        self.emit(Instruction::Jmp(done));
+
+        self.emit(Instruction::Label(large));
+        let reduced = self.new_vector(Size::of(from));
+        self.emit(Instruction::FMov {
+            src,
+            dst: reduced.clone(),
+        });
+        self.emit(Instruction::FArithmetic {
+            op: FloatOp::Sub,
+            src: limit,
+            dst: reduced.clone(),
+        });
+        self.emit(Instruction::FloatToInt {
+            src: reduced,
+            dst: result.clone(),
+        });
+        let top = self.materialize(&Value::Int(i64::MIN), Size::Quad)?;
+        self.emit(Instruction::Arithmetic {
+            op: ArithmeticOp::Xor,
+            src: top,
+            dst: result,
+        });
+        self.emit(Instruction::Label(done));
+        Ok(())
+    }
+
+    fn convert(
+        &mut self,
+        dst: Temp,
+        op: ConvertOp,
+        from: IrType,
+        to: IrType,
+        value: &Value,
+    ) -> Result<(), CodegenError> {
+        let result = Self::virtual_register(dst, width(to));
+        if let Value::Int(_) | Value::Float(_) = value {
+            let constant = crate::ir::fold::evaluate(&Instr::Convert {
+                dst,
+                op,
+                from,
+                to,
+                value: value.clone(),
+            });
+            match constant {
+                Some(Value::Int(constant)) => {
+                    let src = self.operand(&Value::Int(constant), width(to))?;
+                    self.emit(Instruction::Mov { src, dst: result });
+                    return Ok(());
+                }
+                Some(constant @ Value::Float(_)) => {
+                    let src = self.float_operand(&constant, to)?;
+                    let dst = self.vector_register(dst, to);
+                    self.emit(Instruction::FMov { src, dst });
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        match op {
+            ConvertOp::SExt | ConvertOp::ZExt => {
+                let src = match self.operand(value, Size::of(from))? {
+                    Operand::Immediate(_) => self.materialize(value, Size::of(from))?,
+                    src => src,
+                };
+                self.emit(if op == ConvertOp::SExt {
+                    Instruction::MovSx { src, dst: result }
+                } else {
+                    Instruction::MovZx { src, dst: result }
+                });
+            }
+            ConvertOp::Trunc => {
+                let src = self.operand(value, width(to))?;
+                self.emit(Instruction::Mov { src, dst: result });
+            }
+            ConvertOp::UIToFP if from == IrType::I64 => self.unsigned_to_float(dst, to, value)?,
+            ConvertOp::SIToFP | ConvertOp::UIToFP => {
+                let signed = op == ConvertOp::SIToFP;
+                let mut src = match self.extended(value, from, signed)? {
+                    Operand::Immediate(value) => {
+                        self.materialize(&Value::Int(value), width(from))?
+                    }
+                    src => src,
+                };
+                if !signed {
+                    let wide = Operand::Register(self.function.new_virtual(), Size::Quad);
+                    self.emit(Instruction::MovZx {
+                        src,
+                        dst: wide.clone(),
+                    });
+                    src = wide;
+                }
+                let dst = self.vector_register(dst, to);
+                self.emit(Instruction::IntToFloat { src, dst });
+            }
+            ConvertOp::FPToUI if to == IrType::I64 => self.float_to_unsigned(dst, from, value)?,
+            ConvertOp::FPToSI => {
+                let src = self.float_operand(value, from)?;
+                self.emit(Instruction::FloatToInt { src, dst: result });
+            }
+            ConvertOp::FPToUI => {
+                let src = self.float_operand(value, from)?;
+                let wide = self.function.new_virtual();
+                self.emit(Instruction::FloatToInt {
+                    src,
+                    dst: Operand::Register(wide, Size::Quad),
+                });
+                self.emit(Instruction::Mov {
+                    src: Operand::Register(wide, Size::Long),
+                    dst: result,
+                });
+            }
+            ConvertOp::FPExt | ConvertOp::FPTrunc => {
+                let src = self.float_operand(value, from)?;
+                let dst = self.vector_register(dst, to);
+                self.emit(Instruction::FloatConvert { src, dst });
+            }
+        }
+        Ok(())
+    }
+
+    /// Selects a call, or a jump to the callee when `tail` and the callee
+    /// is named, leaving the return to it.
+    fn call(
+        &mut self,
+        dst: &Option<(Temp, IrType)>,
+        callee: &Value,
+        arguments: &[(IrType, Value)],
+        by_value: &ByValue,
+        tail: bool,
+    ) -> Result<(), CodegenError> {
+        let types: Vec<IrType> = arguments.iter().map(|(ty, _)| *ty).collect();
+        let assignment = abi::assign(&types, by_value);
+        self.function.outgoing = self.function.outgoing.max(assignment.stack_size);
+
+        let target = match callee {
+            Value::Global(name) => CallTarget::Symbol(name.clone()),
+            callee => CallTarget::Indirect(self.materialize(callee, Size::Quad)?),
+        };
+        // Evaluate every argument before the argument registers are set,
+        // since materializing one may need a register.
+        let mut moves = vec![];
+        for (index, ((ty, value), location)) in
+            arguments.iter().zip(&assignment.locations).enumerate()
+        {
+            match (location, by_value.aggregate(index)) {
+                (Location::Omitted, _) => {}
+                (Location::Register(register), _) if ty.is_float() => {
+                    moves.push(Instruction::FMov {
+                        src: self.float_operand(value, *ty)?,
+                        dst: Operand::register(*register, Size::of(*ty)),
+                    });
+                }
+                (Location::Register(register), _) => {
+                    moves.push(Instruction::Mov {
+                        src: self.operand(value, width(*ty))?,
+                        dst: Operand::register(*register, width(*ty)),
+                    });
+                }
+                (Location::Split(registers), Some(aggregate)) => {
+                    let memory = self.address(value)?;
+                    for (eightbyte, (class, register)) in registers.iter().enumerate() {
+                        let size = (aggregate.size - 8 * eightbyte).min(8);
+                        let src = self.load_eightbyte(at(&memory, 8 * eightbyte), size, *class);
+                        let dst = Operand::register(*register, Size::Quad);
+                        moves.push(match class {
+                            Class::Integer => Instruction::Mov { src, dst },
+                            Class::Sse => Instruction::FMov { src, dst },
+                        });
+                    }
+                }
+                (Location::Split(_), None) => unreachable!("only structs are split"),
+                (Location::Stack(offset), Some(aggregate)) => {
+                    let source = self.address(value)?;
+                    let destination = stack_argument(Base::Outgoing, *offset);
+                    self.copy(&destination, &source, aggregate.size);
+                }
+                (Location::Stack(offset), None) => {
+                    let dst = stack_argument(Base::Outgoing, *offset);
+                    if ty.is_float() {
+                        let src = self.float_materialize(value, *ty)?;
+                        self.emit(Instruction::FMov {
+                            src,
+                            dst: Operand::Memory(dst, Size::of(*ty)),
+                        });
+                    } else {
+                        let src = self.operand(value, width(*ty))?;
+                        self.emit(Instruction::Mov {
+                            src,
+                            dst: Operand::Memory(dst, width(*ty)),
+                        });
+                    }
+                }
+            }
+        }
+        for instruction in moves {
+            self.emit(instruction);
+        }
+        // Variadic callees learn how many vector registers carry arguments
+        // from `%al`.
+        self.emit(Instruction::Mov {
+            src: Operand::Immediate(assignment.vectors as i64),
+            dst: Operand::register(PhysicalRegister::Rax, Size::Long),
+        });
+        if let (true, CallTarget::Symbol(target)) = (tail, &target) {
+            self.emit(Instruction::TailCall {
+                target: target.clone(),
+                arguments: assignment.registers(),
+            });
+            return Ok(());
+        }
+        self.emit(Instruction::Call {
+            target,
+            arguments: assignment.registers(),
+        });
+
+        if let Some(registers) = abi::returned(by_value) {
+            // Take the registers before storing any of them.
+            let mut values = vec![];
+            for &(class, register) in &registers {
+                let src = Operand::register(register, Size::Quad);
+                let value = match class {
+                    Class::Integer => self.function.new_virtual(),
+                    Class::Sse => self.function.new_virtual_in(RegisterClass::Vector),
+                };
+                let dst = Operand::Register(value, Size::Quad);
+                self.emit(match class {
+                    Class::Integer => Instruction::Mov { src, dst },
+                    Class::Sse => Instruction::FMov { src, dst },
+                });
+                values.push((class, value));
+            }
+            let size = by_value
+                .returned
+                .as_ref()
+                .map_or(0, |aggregate| aggregate.size);
+            let slot = &arguments[0].1;
+            let memory = self.address(slot)?;
+            for (eightbyte, (class, value)) in values.into_iter().enumerate() {
+                let bytes = (size - 8 * eightbyte).min(8);
+                self.store_eightbyte(value, at(&memory, 8 * eightbyte), bytes, class);
+            }
+            if let Some((dst, _)) = dst {
+                let src = self.operand(slot, Size::Quad)?;
+                self.emit(Instruction::Mov {
+                    src,
+                    dst: Self::virtual_register(*dst, Size::Quad),
+                });
+            }
+            return Ok(());
+        }
+        if let Some((dst, ty)) = dst {
+            if ty.is_float() {
+                let dst = self.vector_register(*dst, *ty);
+                self.emit(Instruction::FMov {
+                    src: Operand::register(PhysicalRegister::Xmm0, Size::of(*ty)),
+                    dst,
+                });
+                return Ok(());
+            }
+            self.emit(Instruction::Mov {
+                src: Operand::register(PhysicalRegister::Rax, width(*ty)),
+                dst: Self::virtual_register(*dst, width(*ty)),
+            });
+        }
+        Ok(())
+    }
+
+    /// Initializes the `va_list` the value points to.
+    fn va_start(&mut self, list: &Value) -> Result<(), CodegenError> {
+        let Some((save_area, assignment)) = &self.variadic else {
+            return Err(unsupported("va_start outside a variadic function"));
+        };
+        let save_area = Self::slot_memory(*save_area);
+        let overflow_area = stack_argument(Base::Incoming, assignment.stack_size);
+        let gp_offset = 8 * assignment.integers;
+        let fp_offset = abi::VECTOR_SAVE_OFFSET + 16 * assignment.vectors;
+
+        let memory = self.address(list)?;
+        for (offset, value) in [(0, gp_offset), (4, fp_offset)] {
+            self.emit(Instruction::Mov {
+                src: Operand::Immediate(value as i64),
+                dst: Operand::Memory(at(&memory, offset), Size::Long),
+            });
+        }
+        for (offset, area) in [(8, overflow_area), (16, save_area)] {
+            let register = Operand::Register(self.function.new_virtual(), Size::Quad);
+            self.emit(Instruction::Lea {
+                src: area,
+                dst: register.clone(),
+            });
+            self.emit(Instruction::Mov {
+                src: register,
+                dst: Operand::Memory(at(&memory, offset), Size::Quad),
+            });
+        }
+        Ok(())
+    }
+
+    /// Takes the next argument from the `va_list` the value points to:
+    /// from the register save area while the offset for its class is
+    /// still in it, and from the overflow area after that.
+    fn va_arg(&mut self, dst: Temp, ty: IrType, list: &Value) -> Result<(), CodegenError> {
+        let memory = self.address(list)?;
+        let (offset_memory, limit, step) = if ty.is_float() {
+            (at(&memory, 4), abi::REGISTER_SAVE_AREA, 16)
+        } else {
+            (memory.clone(), abi::VECTOR_SAVE_OFFSET, 8)
+        };
+        let (overflow, done) = (self.new_label(), self.new_label());
+        let offset = self.function.new_virtual();
+        self.emit(Instruction::Mov {
+            src: Operand::Memory(offset_memory.clone(), Size::Long),
+            dst: Operand::Register(offset, Size::Long),
+        });
+        self.emit(Instruction::Cmp {
+            left: Operand::Register(offset, Size::Long),
+            right: Operand::Immediate((limit - step) as i64),
+        });
+        self.emit(Instruction::Jcc {
+            condition: ConditionCode::A,
+            target: overflow,
+        });
+
+        let address = self.function.new_virtual();
+        self.emit(Instruction::Mov {
+            src: Operand::Memory(at(&memory, 16), Size::Quad),
+            dst: Operand::Register(address, Size::Quad),
+        });
+        self.emit(Instruction::Arithmetic {
+            op: ArithmeticOp::Add,
+            src: Operand::Register(offset, Size::Quad),
+            dst: Operand::Register(address, Size::Quad),
+        });
+        let argument = Memory {
+            base: Base::Register(address),
+            displacement: 0,
+        };
+        self.load(dst, ty, argument);
+        self.emit(Instruction::Arithmetic {
+            op: ArithmeticOp::Add,
+            src: Operand::Immediate(step as i64),
+            dst: Operand::Register(offset, Size::Long),
+        });
+        self.emit(Instruction::Mov {
+            src: Operand::Register(offset, Size::Long),
+            dst: Operand::Memory(offset_memory, Size::Long),
+        });
+        self.emit(Instruction::Jmp(done));
+
+        self.emit(Instruction::Label(overflow));
+        let address = self.function.new_virtual();
+        self.emit(Instruction::Mov {
+            src: Operand::Memory(at(&memory, 8), Size::Quad),
+            dst: Operand::Register(address, Size::Quad),
+        });
+        let argument = Memory {
+            base: Base::Register(address),
+            displacement: 0,
+        };
+        self.load(dst, ty, argument);
+        self.emit(Instruction::Arithmetic {
+            op: ArithmeticOp::Add,
+            src: Operand::Immediate(8),
+            dst: Operand::Register(address, Size::Quad),
+        });
+        self.emit(Instruction::Mov {
+            src: Operand::Register(address, Size::Quad),
+            dst: Operand::Memory(at(&memory, 8), Size::Quad),
+        });
+        self.emit(Instruction::Label(done));
+        Ok(())
+    }
+
+    fn select(&mut self, instr: &Instr) -> Result<(), CodegenError> {
+        match instr {
+            Instr::Label(label) => self.emit(Instruction::Label(label.0)),
+            Instr::Phi { .. } => unreachable!("phis are removed before selection"),
+            Instr::Copy { dst, ty, src } if ty.is_float() => {
+                let src = self.float_operand(src, *ty)?;
+                let dst = self.vector_register(*dst, *ty);
+                self.emit(Instruction::FMov { src, dst });
+            }
+            Instr::Copy { dst, ty, src } => {
+                let src = self.operand(src, width(*ty))?;
+                self.emit(Instruction::Mov {
+                    src,
+                    dst: Self::virtual_register(*dst, width(*ty)),
+                });
+            }
+            // Negation flips the sign bit.
+            Instr::Unary {
+                dst,
+                op: ir::UnaryOp::FNeg,
+                ty,
+                operand,
+            } => {
+                let size = Size::of(*ty);
+                let sign = self.constant(1 << (8 * size.bytes() - 1), size);
+                let mask = self.new_vector(size);
+                self.emit(Instruction::FMov {
+                    src: sign,
+                    dst: mask.clone(),
+                });
+                let src = self.float_operand(operand, *ty)?;
+                let dst = self.vector_register(*dst, *ty);
+                self.emit(Instruction::FMov {
+                    src,
+                    dst: dst.clone(),
+                });
+                self.emit(Instruction::FArithmetic {
+                    op: FloatOp::Xor,
+                    src: mask,
+                    dst,
+                });
+            }
+            Instr::Unary {
+                dst,
+                op,
+                ty,
+                operand,
+            } => {
+                let op = match op {
+                    ir::UnaryOp::Neg => UnaryOp::Neg,
+                    ir::UnaryOp::Not => UnaryOp::Not,
+                    ir::UnaryOp::FNeg => unreachable!(),
+                };
+                let size = width(*ty);
+                let src = self.operand(operand, size)?;
+                let dst = Self::virtual_register(*dst, size);
+                self.emit(Instruction::Mov {
+                    src,
+                    dst: dst.clone(),
+                });
+                self.emit(Instruction::Unary { op, dst });
+            }
+            Instr::Binary {
+                dst,
+                op,
+                ty,
+                left,
+                right,
+            } => self.binary(*dst, *op, *ty, left, right)?,
+            Instr::Compare {
+                dst,
+                condition,
+                ty,
+                left,
+                right,
+            } => self.compare(*dst, *condition, *ty, left, right)?,
+            Instr::Convert {
+                dst,
+                op,
+                from,
+                to,
+                value,
+            } => self.convert(*dst, *op, *from, *to, value)?,
+            Instr::Alloca {
+                dst,
+                size,
+                alignment,
+            } => {
+                let slot = self.function.new_slot(*size, *alignment);
+                self.slots.insert(*dst, slot);
+            }
+            Instr::Load {
+                dst, ty, address, ..
+            } => {
+                let memory = self.address(address)?;
+                self.load(*dst, *ty, memory);
+            }
+            Instr::Store {
+                ty, value, address, ..
+            } if ty.is_float() => {
+                let src = self.float_materialize(value, *ty)?;
+                let dst = Operand::Memory(self.address(address)?, Size::of(*ty));
+                self.emit(Instruction::FMov { src, dst });
+            }
+            Instr::Store {
+                ty, value, address, ..
+            } => {
+                let src = self.operand(value, Size::of(*ty))?;
+                let dst = Operand::Memory(self.address(address)?, Size::of(*ty));
+                self.emit(Instruction::Mov { src, dst });
+            }
+            Instr::MemCopy {
+                destination,
+                source,
+                size,
+            } => {
+                let destination = self.address(destination)?;
+                let source = self.address(source)?;
+                self.copy(&destination, &source, *size);
+            }
+            Instr::Call {
+                callee: Value::Global(name),
+                arguments,
+                ..
+            } if name == ir::VA_START => self.va_start(&arguments[0].1)?,
+            Instr::Call {
+                dst: Some((dst, ty)),
+                callee: Value::Global(name),
+                arguments,
+                ..
+            } if name == ir::VA_ARG => self.va_arg(*dst, *ty, &arguments[0].1)?,
+            Instr::Call {
+                dst,
+                callee,
+                arguments,
+                by_value,
+            } => self.call(dst, callee, arguments, by_value, false)?,
+            Instr::Jump(label) => self.emit(Instruction::Jmp(label.0)),
+            Instr::Branch {
+                ty,
+                condition,
+                if_true,
+                if_false,
+            } => match condition {
+                Value::Int(_) => {
+                    let taken = match crate::ir::fold::is_true(condition, *ty) {
+                        Some(true) => if_true,
+                        _ => if_false,
+                    };
+                    self.emit(Instruction::Jmp(taken.0));
+                }
+                // A NaN is true, like any other nonzero value.
+                condition if ty.is_float() => {
+                    let zero = self.new_vector(Size::of(*ty));
+                    self.emit(Instruction::FArithmetic {
+                        op: FloatOp::Xor,
+                        src: zero.clone(),
+                        dst: zero.clone(),
+                    });
+                    let left = self.float_materialize(condition, *ty)?;
+                    self.emit(Instruction::FCmp { left, right: zero });
+                    for condition in [ConditionCode::Ne, ConditionCode::P] {
+                        self.emit(Instruction::Jcc {
+                            condition,
+                            target: if_true.0,
+                        });
+                    }
+                    self.emit(Instruction::Jmp(if_false.0));
+                }
+                condition => {
+                    let condition = self.operand(condition, Size::of(*ty))?;
+                    self.emit(Instruction::Test {
+                        left: condition.clone(),
+                        right: condition,
+                    });
+                    self.emit(Instruction::Jcc {
+                        condition: ConditionCode::Ne,
+                        target: if_true.0,
+                    });
+                    self.emit(Instruction::Jmp(if_false.0));
+                }
+            },
+            Instr::Return(value) => match value {
+                // The struct the pointer refers to is returned instead.
+                Some((_, value)) if self.returned.is_some() => {
+                    let (size, registers) = self.returned.clone().unwrap();
+                    let memory = self.address(value)?;
+                    let mut moves = vec![];
+                    for (eightbyte, (class, register)) in registers.iter().enumerate() {
+                        let bytes = (size - 8 * eightbyte).min(8);
+                        let src = self.load_eightbyte(at(&memory, 8 * eightbyte), bytes, *class);
+                        let dst = Operand::register(*register, Size::Quad);
+                        moves.push(match class {
+                            Class::Integer => Instruction::Mov { src, dst },
+                            Class::Sse => Instruction::FMov { src, dst },
+                        });
+                    }
+                    for instruction in moves {
+                        self.emit(instruction);
+                    }
+                    let registers = registers.iter().map(|(_, register)| *register).collect();
+                    self.emit(Instruction::Ret(registers));
+                }
+                Some((ty, value)) if ty.is_float() => {
+                    let src = self.float_operand(value, *ty)?;
+                    self.emit(Instruction::FMov {
+                        src,
+                        dst: Operand::register(PhysicalRegister::Xmm0, Size::of(*ty)),
+                    });
+                    self.emit(Instruction::Ret(vec![PhysicalRegister::Xmm0]));
+                }
+                Some((ty, value)) => {
+                    let src = self.operand(value, width(*ty))?;
+                    self.emit(Instruction::Mov {
+                        src,
+                        dst: Operand::register(PhysicalRegister::Rax, width(*ty)),
+                    });
+                    self.emit(Instruction::Ret(vec![PhysicalRegister::Rax]));
+                }
+                None => self.emit(Instruction::Ret(vec![])),
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Selects instructions for a function without phis.
+pub fn select(function: &ir::Function) -> Result<MachineFunction, CodegenError> {
+    let types: Vec<IrType> = function.parameters.iter().map(|(_, ty)| *ty).collect();
+    let assignment = abi::assign(&types, &function.by_value);
+    let returned = abi::returned(&function.by_value).map(|registers| {
+        let aggregate = function.by_value.returned.as_ref().unwrap();
+        (aggregate.size, registers)
+    });
+    let mut selector = Selector {
+        function: MachineFunction {
+            name: function.name.clone(),
+            is_global: function.is_global,
+            instructions: vec![],
+            slots: vec![],
+            constants: vec![],
+            next_virtual: function.next_temp().0,
+            vector_registers: BTreeSet::new(),
+            outgoing: 0,
+        },
+        slots: HashMap::new(),
+        next_label: function.next_label().0,
+        returned,
+        variadic: None,
+    };
+
+    if function.variadic {
+        let save_area = selector.function.new_slot(abi::REGISTER_SAVE_AREA, 16);
+        let memory = Selector::slot_memory(save_area);
+        for (index, register) in ARGUMENT_REGISTERS.into_iter().enumerate() {
+            selector.emit(Instruction::Mov {
+                src: Operand::register(register, Size::Quad),
+                dst: Operand::Memory(at(&memory, 8 * index), Size::Quad),
+            });
+        }
+        for (index, register) in VECTOR_ARGUMENT_REGISTERS.into_iter().enumerate() {
+            let offset = abi::VECTOR_SAVE_OFFSET + 16 * index;
+            selector.emit(Instruction::FMov {
+                src: Operand::register(register, Size::Quad),
+                dst: Operand::Memory(at(&memory, offset), Size::Quad),
+            });
+        }
+        selector.variadic = Some((save_area, assignment.clone()));
+    }
+
+    for (index, ((temp, ty), location)) in function
+        .parameters
+        .iter()
+        .zip(assignment.locations)
+        .enumerate()
+    {
+        let aggregate = function.by_value.aggregate(index);
+        match (location, aggregate) {
+            (Location::Register(register), _) if ty.is_float() => {
+                let dst = selector.vector_register(*temp, *ty);
+                selector.emit(Instruction::FMov {
+                    src: Operand::register(register, Size::of(*ty)),
+                    dst,
+                });
+            }
+            (Location::Register(register), _) => selector.emit(Instruction::Mov {
+                src: Operand::register(register, width(*ty)),
+                dst: Selector::virtual_register(*temp, width(*ty)),
+            }),
+            // Structs in registers, and the one returned in them, get a
+            // stack slot whole eightbytes fit in.
+            (location @ (Location::Split(_) | Location::Omitted), Some(aggregate)) => {
+                let slot = selector.function.new_slot(
+                    aggregate.size.next_multiple_of(8),
+                    aggregate.alignment.max(8),
+                );
+                let memory = Selector::slot_memory(slot);
+                if let Location::Split(registers) = location {
+                    for (eightbyte, (class, register)) in registers.into_iter().enumerate() {
+                        let src = Operand::register(register, Size::Quad);
+                        let dst = Operand::Memory(at(&memory, 8 * eightbyte), Size::Quad);
+                        selector.emit(match class {
+                            Class::Integer => Instruction::Mov { src, dst },
+                            Class::Sse => Instruction::FMov { src, dst },
+                        });
+                    }
+                }
+                selector.emit(Instruction::Lea {
+                    src: memory,
+                    dst: Selector::virtual_register(*temp, Size::Quad),
+                });
+            }
+            (Location::Split(_) | Location::Omitted, None) => {
+                unreachable!("only structs are split or omitted")
+            }
+            (Location::Stack(offset), Some(_)) => selector.emit(Instruction::Lea {
+                src: stack_argument(Base::Incoming, offset),
+                dst: Selector::virtual_register(*temp, Size::Quad),
+            }),
+            (Location::Stack(offset), None) if ty.is_float() => {
+                let dst = selector.vector_register(*temp, *ty);
+                selector.emit(Instruction::FMov {
+                    src: Operand::Memory(stack_argument(Base::Incoming, offset), Size::of(*ty)),
+                    dst,
+                });
+            }
+            (Location::Stack(offset), None) => selector.emit(Instruction::Mov {
+                src: Operand::Memory(stack_argument(Base::Incoming, offset), width(*ty)),
+                dst: Selector::virtual_register(*temp, width(*ty)),
+            }),
+        }
+    }
+    // Sibling calls reuse the frame, which must then be dead, and the
+    // caller's stack arguments.
+    let tail_calls = !tailcall::takes_local_address(function) && function.by_value.is_empty();
+    let mut body = function.body.iter().peekable();
+    while let Some(instr) = body.next() {
+        if let Instr::Call {
+            dst,
+            callee: callee @ Value::Global(name),
+            arguments,
+            by_value,
+        } = instr
+            && tail_calls
+            && name != ir::VA_START
+            && name != ir::VA_ARG
+            && by_value.is_empty()
+            && body
+                .peek()
+                .is_some_and(|next| tailcall::is_tail_call(instr, next))
+        {
+            let types: Vec<IrType> = arguments.iter().map(|(ty, _)| *ty).collect();
+            if abi::assign(&types, by_value).stack_size == 0 {
+                selector.call(dst, callee, arguments, by_value, true)?;
+                body.next();
+                continue;
+            }
+        }
+        selector.select(instr)?;
+    }
+    // Falling off the end of a function returns nothing in particular.
+    if !matches!(
+        selector.function.instructions.last(),
+        Some(Instruction::Ret(_) | Instruction::TailCall { .. } | Instruction::Jmp(_))
+    ) {
+        selector.emit(Instruction::Ret(vec![]));
+    }
+    Ok(selector.function)
+}
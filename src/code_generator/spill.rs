@@ -0,0 +1,124 @@
+/*
+  The simplest register allocation: every virtual register lives in a
+  stack slot of its own, and is loaded into a scratch register before
+  each instruction that reads it and stored back after each one that
+  writes it. `%r10` and `%r11` serve as scratch registers, and `%xmm14`
+  and `%xmm15` for vector registers, since nothing else names them, and
+  no instruction refers to more than two virtual registers of a class.
+
+  The other allocators spill the same way, but only the registers they
+  could not allocate, and allocate the short-lived registers afterwards.
+*/
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use super::machine::{
+    Instruction, MachineFunction, Operand, PhysicalRegister, Register, RegisterClass, Size,
+};
+
+fn scratch_registers(class: RegisterClass) -> [PhysicalRegister; 2] {
+    match class {
+        RegisterClass::General => [PhysicalRegister::R10, PhysicalRegister::R11],
+        RegisterClass::Vector => [PhysicalRegister::Xmm14, PhysicalRegister::Xmm15],
+    }
+}
+
+/// A move of a whole register of the class to or from its stack slot.
+fn spill_move(class: RegisterClass, src: Operand, dst: Operand) -> Instruction {
+    match class {
+        RegisterClass::General => Instruction::Mov { src, dst },
+        RegisterClass::Vector => Instruction::FMov { src, dst },
+    }
+}
+
+/// Replaces every virtual register in the function by a stack slot.
+pub fn allocate(function: &mut MachineFunction) {
+    let mut slots: HashMap<Register, usize> = HashMap::new();
+    let mut instructions = vec![];
+    for mut instruction in std::mem::take(&mut function.instructions) {
+        let uses = instruction.uses();
+        let defs = instruction.defs();
+        let mut assigned: Vec<(Register, PhysicalRegister)> = vec![];
+        for register in instruction.registers_mut() {
+            if let Register::Virtual(_) = register {
+                let class = function.class(*register);
+                let scratch = match assigned.iter().find(|(original, _)| original == register) {
+                    Some((_, scratch)) => *scratch,
+                    None => {
+                        let taken = assigned
+                            .iter()
+                            .filter(|(_, scratch)| scratch.class() == class)
+                            .count();
+                        let scratch = scratch_registers(class)[taken];
+                        assigned.push((*register, scratch));
+                        scratch
+                    }
+                };
+                *register = Register::Physical(scratch);
+            }
+        }
+
+        let mut after = vec![];
+        for (original, scratch) in assigned {
+            let slot = *slots
+                .entry(original)
+                .or_insert_with(|| function.new_slot(8, 8));
+            let slot = Operand::slot(slot, Size::Quad);
+            let class = scratch.class();
+            let scratch = Operand::register(scratch, Size::Quad);
+            if uses.contains(&original) {
+                instructions.push(spill_move(class, slot.clone(), scratch.clone()));
+            }
+            if defs.contains(&original) {
+                after.push(spill_move(class, scratch, slot));
+            }
+        }
+        instructions.push(instruction);
+        instructions.extend(after);
+    }
+    function.instructions = instructions;
+}
+
+/// Gives every spilled register a stack slot, loading it into a new
+/// register before each instruction that reads it and storing it after
+/// each one that writes it. Returns the new registers.
+pub fn rewrite(function: &mut MachineFunction, spilled: &BTreeSet<Register>) -> HashSet<Register> {
+    let slots: HashMap<Register, usize> = spilled
+        .iter()
+        .map(|register| (*register, function.new_slot(8, 8)))
+        .collect();
+    let mut created = HashSet::new();
+    let mut instructions = vec![];
+    for mut instruction in std::mem::take(&mut function.instructions) {
+        let uses = instruction.uses();
+        let defs = instruction.defs();
+        let mut replaced: BTreeMap<Register, Register> = BTreeMap::new();
+        for register in instruction.registers_mut() {
+            if slots.contains_key(register) {
+                let original = *register;
+                let class = function.class(original);
+                *register = *replaced
+                    .entry(original)
+                    .or_insert_with(|| function.new_virtual_in(class));
+            }
+        }
+
+        let mut after = vec![];
+        for (original, temporary) in &replaced {
+            created.insert(*temporary);
+            let class = function.class(*original);
+            let slot = Operand::slot(slots[original], Size::Quad);
+            let temporary = Operand::Register(*temporary, Size::Quad);
+            if uses.contains(original) {
+                instructions.push(spill_move(class, slot.clone(), temporary.clone()));
+            }
+            if defs.contains(original) {
+                after.push(spill_move(class, temporary, slot));
+            }
+        }
+        instructions.push(instruction);
+        instructions.extend(after);
+    }
+    function.instructions = instructions;
+    created
+}
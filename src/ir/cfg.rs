@@ -0,0 +1,301 @@
+/*
+  Control-flow graph of a function. Every block starts at a label and ends
+  with exactly one terminator; falling through into the next label becomes
+  an explicit jump. Block 0 is the entry.
+*/
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::{Function, Instr, Label};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub label: Label,
+    /// The block's instructions without its label; the last one is the
+    /// terminator.
+    pub instrs: Vec<Instr>,
+}
+
+impl BasicBlock {
+    pub fn terminator(&self) -> &Instr {
+        self.instrs.last().unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    pub predecessors: Vec<Vec<usize>>,
+    pub successors: Vec<Vec<usize>>,
+}
+
+impl Cfg {
+    pub fn build(function: &Function) -> Cfg {
+        let mut next_label = function.next_label().0;
+        let mut fresh_label = || {
+            next_label += 1;
+            Label(next_label - 1)
+        };
+
+        let mut blocks: Vec<BasicBlock> = vec![];
+        let mut current: Option<BasicBlock> = None;
+        for instr in &function.body {
+            if let Instr::Label(label) = instr {
+                if let Some(mut block) = current.take() {
+                    block.instrs.push(Instr::Jump(*label));
+                    blocks.push(block);
+                }
+                current = Some(BasicBlock {
+                    label: *label,
+                    instrs: vec![],
+                });
+                continue;
+            }
+
+            // Code directly after a terminator starts an unlabeled block.
+            let block = current.get_or_insert_with(|| BasicBlock {
+                label: fresh_label(),
+                instrs: vec![],
+            });
+            block.instrs.push(instr.clone());
+            if instr.is_terminator() {
+                blocks.push(current.take().unwrap());
+            }
+        }
+
+        match current {
+            Some(mut block) => {
+                block.instrs.push(Instr::Return(None));
+                blocks.push(block);
+            }
+            None if blocks.is_empty() => blocks.push(BasicBlock {
+                label: fresh_label(),
+                instrs: vec![Instr::Return(None)],
+            }),
+            None => {}
+        }
+
+        let mut cfg = Cfg {
+            blocks,
+            predecessors: vec![],
+            successors: vec![],
+        };
+        cfg.compute_edges();
+        cfg
+    }
+
+    /// Recomputes the edges after terminators have changed.
+    pub fn compute_edges(&mut self) {
+        let index = self.label_indices();
+        self.successors = self
+            .blocks
+            .iter()
+            .map(|block| {
+                let mut successors: Vec<usize> = vec![];
+                for label in block.terminator().targets() {
+                    let successor = index[&label];
+                    if !successors.contains(&successor) {
+                        successors.push(successor);
+                    }
+                }
+                successors
+            })
+            .collect();
+
+        self.predecessors = vec![vec![]; self.blocks.len()];
+        for (block, successors) in self.successors.iter().enumerate() {
+            for &successor in successors {
+                self.predecessors[successor].push(block);
+            }
+        }
+    }
+
+    pub fn label_indices(&self) -> HashMap<Label, usize> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (block.label, index))
+            .collect()
+    }
+
+    /// A label no block uses yet.
+    pub fn fresh_label(&self) -> Label {
+        let max = self.blocks.iter().map(|block| block.label.0).max();
+        Label(max.map_or(0, |max| max + 1))
+    }
+
+    /// Appends a new block that jumps to `target`, for splitting edges or
+    /// adding a new entry. Edges are not recomputed.
+    pub fn add_jump_block(&mut self, target: Label) -> Label {
+        let label = self.fresh_label();
+        self.blocks.push(BasicBlock {
+            label,
+            instrs: vec![Instr::Jump(target)],
+        });
+        label
+    }
+
+    /// Blocks reachable from the entry in reverse postorder.
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = vec![];
+        // Each entry is a block and how many of its successors were visited.
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+
+        while let Some((block, next)) = stack.pop() {
+            match self.successors[block].get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+
+        postorder.reverse();
+        postorder
+    }
+
+    /// Drops the phi inputs coming from blocks that are no longer
+    /// predecessors, after edges have been removed.
+    pub fn prune_phis(&mut self) {
+        let labels: Vec<Label> = self.blocks.iter().map(|block| block.label).collect();
+        for (block, predecessors) in self.blocks.iter_mut().zip(&self.predecessors) {
+            for instr in &mut block.instrs {
+                let Instr::Phi { incoming, .. } = instr else {
+                    break;
+                };
+                incoming.retain(|(label, _)| {
+                    predecessors
+                        .iter()
+                        .any(|&predecessor| labels[predecessor] == *label)
+                });
+            }
+        }
+    }
+
+    /// Removes blocks that cannot be reached from the entry, and their
+    /// inputs to phis. Returns whether any were removed.
+    pub fn remove_unreachable(&mut self) -> bool {
+        let mut reachable = vec![false; self.blocks.len()];
+        for block in self.reverse_postorder() {
+            reachable[block] = true;
+        }
+        if reachable.iter().all(|&reachable| reachable) {
+            return false;
+        }
+
+        let blocks = std::mem::take(&mut self.blocks);
+        self.blocks = blocks
+            .into_iter()
+            .zip(reachable)
+            .filter_map(|(block, reachable)| reachable.then_some(block))
+            .collect();
+        self.compute_edges();
+        self.prune_phis();
+        true
+    }
+
+    /// Flattens the graph back into a function body.
+    pub fn to_body(&self) -> Vec<Instr> {
+        let mut body = vec![];
+        for block in &self.blocks {
+            body.push(Instr::Label(block.label));
+            body.extend(block.instrs.iter().cloned());
+        }
+        body
+    }
+
+    /// A Graphviz cluster for the function; see `to_dot`.
+    pub fn write_dot(&self, name: &str, out: &mut String) {
+        let node = |block: usize| format!("\"{}.{}\"", name, self.blocks[block].label);
+
+        let mut reachable = vec![false; self.blocks.len()];
+        for index in self.reverse_postorder() {
+            reachable[index] = true;
+        }
+        // Unreachable blocks and the edges leaving them are dashed.
+        let dashed = |block: usize, attributes: &str| match (reachable[block], attributes) {
+            (true, "") => String::new(),
+            (true, _) => format!(" [{}]", attributes),
+            (false, "") => " [style = dashed]".to_string(),
+            (false, _) => format!(" [{}, style = dashed]", attributes),
+        };
+
+        writeln!(out, "  subgraph \"cluster_{}\" {{", name).unwrap();
+        writeln!(out, "    label = \"{}\";", name).unwrap();
+        for (index, block) in self.blocks.iter().enumerate() {
+            let marker = if reachable[index] {
+                ""
+            } else {
+                " (unreachable)"
+            };
+            let mut label = format!("{}{}:\\l", block.label, marker);
+            for instr in &block.instrs {
+                let text = instr
+                    .to_string()
+                    .trim_start()
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"");
+                write!(label, "{}\\l", text).unwrap();
+            }
+            writeln!(
+                out,
+                "    {}{};",
+                node(index),
+                dashed(index, &format!("shape = box, label = \"{}\"", label))
+            )
+            .unwrap();
+        }
+
+        for (index, block) in self.blocks.iter().enumerate() {
+            match block.terminator() {
+                Instr::Branch {
+                    if_true, if_false, ..
+                } => {
+                    let indices = self.label_indices();
+                    for (target, edge) in [(if_true, "T"), (if_false, "F")] {
+                        writeln!(
+                            out,
+                            "    {} -> {}{};",
+                            node(index),
+                            node(indices[target]),
+                            dashed(index, &format!("label = \"{}\"", edge))
+                        )
+                        .unwrap();
+                    }
+                }
+                _ => {
+                    for &successor in &self.successors[index] {
+                        writeln!(
+                            out,
+                            "    {} -> {}{};",
+                            node(index),
+                            node(successor),
+                            dashed(index, "")
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+        }
+        writeln!(out, "  }}").unwrap();
+    }
+}
+
+/// The control-flow graphs of the given functions as one Graphviz digraph,
+/// with a cluster per function. Blocks that cannot be reached from the
+/// entry are kept, dashed and marked unreachable.
+pub fn to_dot(functions: &[Function]) -> String {
+    let mut out = String::from("digraph cfg {\n  node [fontname = \"monospace\"];\n");
+    for function in functions {
+        Cfg::build(function).write_dot(&function.name, &mut out);
+    }
+    out.push_str("}\n");
+    out
+}
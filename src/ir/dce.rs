@@ -0,0 +1,318 @@
+/*
+  Dead code elimination on the control-flow graph. Branches on constants
+  become jumps, blocks that can no longer be reached are dropped, jumps
+  through empty blocks go straight to their target, and a block is merged
+  into its only predecessor when that predecessor has no other successor.
+
+  Stack slots that are stored to but never read are removed with their
+  stores. Instructions without side effects are removed when the temporary
+  they assign is not live afterwards. Liveness only counts the uses of
+  instructions that are themselves kept, so a value that merely feeds
+  itself around a loop is dead too. The pass works on SSA and non-SSA
+  functions alike.
+*/
+
+use std::collections::HashSet;
+
+use super::cfg::Cfg;
+use super::passes::Analyses;
+use super::{Function, Instr, Label, Temp, Value, fold};
+
+/// Turns branches whose condition is constant, or whose targets are the
+/// same, into jumps.
+fn simplify_branches(cfg: &mut Cfg) -> bool {
+    let mut changed = false;
+    for block in &mut cfg.blocks {
+        let Some(Instr::Branch {
+            ty,
+            condition,
+            if_true,
+            if_false,
+        }) = block.instrs.last()
+        else {
+            continue;
+        };
+
+        let target = if if_true == if_false {
+            *if_true
+        } else {
+            match fold::is_true(condition, *ty) {
+                Some(true) => *if_true,
+                Some(false) => *if_false,
+                None => continue,
+            }
+        };
+        *block.instrs.last_mut().unwrap() = Instr::Jump(target);
+        changed = true;
+    }
+
+    if changed {
+        cfg.compute_edges();
+        cfg.prune_phis();
+    }
+    changed
+}
+
+fn phi_inputs(instrs: &mut [Instr]) -> impl Iterator<Item = &mut Vec<(Label, Value)>> {
+    instrs.iter_mut().map_while(|instr| match instr {
+        Instr::Phi { incoming, .. } => Some(incoming),
+        _ => None,
+    })
+}
+
+/// Sends the predecessors of blocks that only jump elsewhere directly to
+/// the target. A predecessor already branching to the target is left alone
+/// when the target has phis, as it would need two inputs to the same phi.
+fn thread_empty_blocks(cfg: &mut Cfg) -> bool {
+    let mut changed = false;
+    for empty in 1..cfg.blocks.len() {
+        let [Instr::Jump(target)] = cfg.blocks[empty].instrs[..] else {
+            continue;
+        };
+        let label = cfg.blocks[empty].label;
+        if target == label {
+            continue;
+        }
+        let target_index = cfg.label_indices()[&target];
+        let has_phis = matches!(cfg.blocks[target_index].instrs[0], Instr::Phi { .. });
+
+        for predecessor in cfg.predecessors[empty].clone() {
+            let predecessor_label = cfg.blocks[predecessor].label;
+            let terminator = cfg.blocks[predecessor].instrs.last_mut().unwrap();
+            if has_phis && terminator.targets().contains(&target) {
+                continue;
+            }
+            match terminator {
+                Instr::Jump(next) => *next = target,
+                Instr::Branch {
+                    if_true, if_false, ..
+                } => {
+                    for next in [if_true, if_false] {
+                        if *next == label {
+                            *next = target;
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+
+            for incoming in phi_inputs(&mut cfg.blocks[target_index].instrs) {
+                let value = incoming
+                    .iter()
+                    .find(|(from, _)| *from == label)
+                    .map(|(_, value)| value.clone())
+                    .unwrap();
+                incoming.push((predecessor_label, value));
+            }
+            changed = true;
+        }
+        cfg.compute_edges();
+    }
+
+    if changed {
+        cfg.prune_phis();
+    }
+    changed
+}
+
+/// Appends blocks to their only predecessor when that predecessor jumps
+/// nowhere else. The appended block's phis have a single input and become
+/// copies.
+fn merge_blocks(cfg: &mut Cfg) -> bool {
+    let mut changed = false;
+    let mut index = 0;
+    while index < cfg.blocks.len() {
+        let [successor] = cfg.successors[index][..] else {
+            index += 1;
+            continue;
+        };
+        if successor == 0 || successor == index || cfg.predecessors[successor].len() != 1 {
+            index += 1;
+            continue;
+        }
+
+        let merged = cfg.blocks.remove(successor);
+        let index = if successor < index { index - 1 } else { index };
+        let block = &mut cfg.blocks[index];
+        block.instrs.pop();
+        for instr in merged.instrs {
+            block.instrs.push(match instr {
+                Instr::Phi { dst, ty, incoming } => Instr::Copy {
+                    dst,
+                    ty,
+                    src: incoming.into_iter().next().unwrap().1,
+                },
+                instr => instr,
+            });
+        }
+
+        // The merged block's successors now come from the block it joined.
+        let label = cfg.blocks[index].label;
+        for block in &mut cfg.blocks {
+            for incoming in phi_inputs(&mut block.instrs) {
+                for (from, _) in incoming {
+                    if *from == merged.label {
+                        *from = label;
+                    }
+                }
+            }
+        }
+        cfg.compute_edges();
+        changed = true;
+    }
+    changed
+}
+
+/// The temporaries live at the end of a block: those live into its
+/// successors, and the inputs to their live phis.
+fn live_out(cfg: &Cfg, block: usize, live_in: &[HashSet<Temp>]) -> HashSet<Temp> {
+    let label = cfg.blocks[block].label;
+    let mut live = HashSet::new();
+    for &successor in &cfg.successors[block] {
+        let mut phis = HashSet::new();
+        for instr in &cfg.blocks[successor].instrs {
+            let Instr::Phi { dst, incoming, .. } = instr else {
+                break;
+            };
+            phis.insert(*dst);
+            if !live_in[successor].contains(dst) {
+                continue;
+            }
+            for (from, value) in incoming {
+                if *from == label
+                    && let Value::Temp(temp) = value
+                {
+                    live.insert(*temp);
+                }
+            }
+        }
+        live.extend(live_in[successor].difference(&phis));
+    }
+    live
+}
+
+/// Walks a block backwards from the temporaries live at its end, marking
+/// which of its instructions are needed. Returns the temporaries live
+/// after its phis, including the results of phis that are needed.
+fn mark_block(instrs: &[Instr], mut live: HashSet<Temp>, needed: &mut [bool]) -> HashSet<Temp> {
+    for (position, instr) in instrs.iter().enumerate().rev() {
+        if matches!(instr, Instr::Phi { .. }) {
+            continue;
+        }
+        let defined = instr.defined();
+        needed[position] =
+            instr.has_side_effects() || defined.is_some_and(|temp| live.contains(&temp));
+        if needed[position] {
+            if let Some(temp) = defined {
+                live.remove(&temp);
+            }
+            live.extend(instr.used_temps());
+        }
+    }
+    live
+}
+
+/// Removes stack slots that are only ever stored to, along with the
+/// stores. Their address must not be used any other way.
+fn remove_dead_slots(cfg: &mut Cfg) -> bool {
+    let instrs = || cfg.blocks.iter().flat_map(|block| &block.instrs);
+    let mut slots: HashSet<Temp> = instrs()
+        .filter_map(|instr| match instr {
+            Instr::Alloca { dst, .. } => Some(*dst),
+            _ => None,
+        })
+        .collect();
+    for instr in instrs() {
+        let escaping = match instr {
+            Instr::Store {
+                value,
+                volatile: false,
+                ..
+            } => vec![value],
+            instr => instr.uses(),
+        };
+        for value in escaping {
+            if let Value::Temp(temp) = value {
+                slots.remove(temp);
+            }
+        }
+    }
+    if slots.is_empty() {
+        return false;
+    }
+
+    let is_dead = |instr: &Instr| match instr {
+        Instr::Alloca { dst, .. } => slots.contains(dst),
+        Instr::Store {
+            address: Value::Temp(address),
+            ..
+        } => slots.contains(address),
+        _ => false,
+    };
+    for block in &mut cfg.blocks {
+        block.instrs.retain(|instr| !is_dead(instr));
+    }
+    true
+}
+
+fn remove_dead_instructions(cfg: &mut Cfg) -> bool {
+    let mut live_in = vec![HashSet::new(); cfg.blocks.len()];
+    let mut postorder = cfg.reverse_postorder();
+    postorder.reverse();
+    loop {
+        let mut changed = false;
+        for &block in &postorder {
+            let mut needed = vec![false; cfg.blocks[block].instrs.len()];
+            let live = live_out(cfg, block, &live_in);
+            let live = mark_block(&cfg.blocks[block].instrs, live, &mut needed);
+            if live != live_in[block] {
+                live_in[block] = live;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut changed = false;
+    for block in 0..cfg.blocks.len() {
+        let instrs = &cfg.blocks[block].instrs;
+        let mut needed = vec![false; instrs.len()];
+        mark_block(instrs, live_out(cfg, block, &live_in), &mut needed);
+        for (position, instr) in instrs.iter().enumerate() {
+            if let Instr::Phi { dst, .. } = instr {
+                needed[position] = live_in[block].contains(dst);
+            }
+        }
+
+        if needed.contains(&false) {
+            let mut needed = needed.into_iter();
+            cfg.blocks[block].instrs.retain(|_| needed.next().unwrap());
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Removes dead code until nothing changes, returning whether anything did.
+/// The body is rebuilt from its control-flow graph either way.
+pub fn run(function: &mut Function, analyses: &mut Analyses) -> bool {
+    let mut cfg = analyses.cfg(function).clone();
+    let mut changed = cfg.remove_unreachable();
+    loop {
+        let mut progress = simplify_branches(&mut cfg);
+        progress |= cfg.remove_unreachable();
+        progress |= thread_empty_blocks(&mut cfg);
+        progress |= cfg.remove_unreachable();
+        progress |= merge_blocks(&mut cfg);
+        progress |= remove_dead_slots(&mut cfg);
+        progress |= remove_dead_instructions(&mut cfg);
+        if !progress {
+            break;
+        }
+        changed = true;
+    }
+    function.body = cfg.to_body();
+    changed
+}
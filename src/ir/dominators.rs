@@ -0,0 +1,130 @@
+/*
+  Dominators by the iterative algorithm of Cooper, Harvey and Kennedy ("A
+  Simple, Fast Dominance Algorithm"), and dominance frontiers from the
+  same paper. Unreachable blocks have no immediate dominator and are
+  dominated by nothing.
+*/
+
+use super::cfg::Cfg;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DominatorTree {
+    /// The immediate dominator of each block; the entry is its own.
+    pub idom: Vec<Option<usize>>,
+    pub children: Vec<Vec<usize>>,
+    pub frontiers: Vec<Vec<usize>>,
+    /// Position of each reachable block in reverse postorder.
+    order: Vec<Option<usize>>,
+}
+
+impl DominatorTree {
+    pub fn build(cfg: &Cfg) -> DominatorTree {
+        let count = cfg.blocks.len();
+        let postorder = cfg.reverse_postorder();
+        let mut order = vec![None; count];
+        for (position, &block) in postorder.iter().enumerate() {
+            order[block] = Some(position);
+        }
+
+        let mut idom: Vec<Option<usize>> = vec![None; count];
+        idom[0] = Some(0);
+
+        let intersect = |idom: &[Option<usize>], mut left: usize, mut right: usize| {
+            while left != right {
+                while order[left] > order[right] {
+                    left = idom[left].unwrap();
+                }
+                while order[right] > order[left] {
+                    right = idom[right].unwrap();
+                }
+            }
+            left
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in postorder.iter().skip(1) {
+                let mut new_idom: Option<usize> = None;
+                for &predecessor in &cfg.predecessors[block] {
+                    if idom[predecessor].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        Some(current) => intersect(&idom, predecessor, current),
+                        None => predecessor,
+                    });
+                }
+
+                if idom[block] != new_idom {
+                    idom[block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = vec![vec![]; count];
+        for &block in postorder.iter().skip(1) {
+            children[idom[block].unwrap()].push(block);
+        }
+
+        let mut frontiers: Vec<Vec<usize>> = vec![vec![]; count];
+        for &block in &postorder {
+            let predecessors: Vec<usize> = cfg.predecessors[block]
+                .iter()
+                .copied()
+                .filter(|&predecessor| idom[predecessor].is_some())
+                .collect();
+            if predecessors.len() < 2 {
+                continue;
+            }
+
+            for predecessor in predecessors {
+                let mut runner = predecessor;
+                while Some(runner) != idom[block] {
+                    if !frontiers[runner].contains(&block) {
+                        frontiers[runner].push(block);
+                    }
+                    runner = idom[runner].unwrap();
+                }
+            }
+        }
+
+        DominatorTree {
+            idom,
+            children,
+            frontiers,
+            order,
+        }
+    }
+
+    /// Whether every path from the entry to `block` passes through
+    /// `dominator`. A block dominates itself.
+    pub fn dominates(&self, dominator: usize, block: usize) -> bool {
+        if self.order[dominator].is_none() || self.order[block].is_none() {
+            return false;
+        }
+
+        let mut current = block;
+        loop {
+            if current == dominator {
+                return true;
+            }
+            match self.idom[current] {
+                Some(parent) if parent != current => current = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Reachable blocks in a preorder walk of the tree.
+    pub fn preorder(&self) -> Vec<usize> {
+        let mut preorder = vec![];
+        let mut stack = vec![0];
+        while let Some(block) = stack.pop() {
+            preorder.push(block);
+            stack.extend(self.children[block].iter().rev());
+        }
+        preorder
+    }
+}
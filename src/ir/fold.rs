@@ -0,0 +1,404 @@
+/*
+  Constant folding and algebraic simplification. Operations on constants
+  are evaluated at the width of their type with two's complement
+  wraparound, and float arithmetic is rounded to the precision of its
+  type as IEEE 754 prescribes. Operations that trap or are undefined in C
+  -- division by zero, the most negative value divided by -1, shifts by
+  the width or more, and out-of-range float to integer conversions -- are
+  left for run time. Identities such as `x * 1`, `x + 0` and `x - x` are
+  applied when the other operand is unknown.
+
+  A temporary assigned only once is replaced by its folded value in every
+  use, which works on both SSA and non-SSA functions.
+*/
+
+use std::collections::HashMap;
+
+use super::{BinaryOp, Condition, ConvertOp, Function, Instr, IrType, Temp, UnaryOp, Value};
+
+fn mask(bits: u64, ty: IrType) -> u64 {
+    match ty.size() {
+        8 => bits,
+        size => bits & ((1 << (size * 8)) - 1),
+    }
+}
+
+fn signed(bits: u64, ty: IrType) -> i64 {
+    let shift = 64 - ty.size() * 8;
+    ((bits << shift) as i64) >> shift
+}
+
+fn round(value: f64, ty: IrType) -> f64 {
+    match ty {
+        IrType::F32 => value as f32 as f64,
+        _ => value,
+    }
+}
+
+/// The bits of an integer constant read at the width of `ty`.
+fn integer(value: &Value, ty: IrType) -> Option<u64> {
+    match value {
+        Value::Int(value) => Some(mask(*value as u64, ty)),
+        Value::Float(value) => Some(mask(*value as i64 as u64, ty)),
+        _ => None,
+    }
+}
+
+/// A float constant in the precision of `ty`.
+fn float(value: &Value, ty: IrType) -> Option<f64> {
+    match value {
+        Value::Int(value) => Some(round(*value as f64, ty)),
+        Value::Float(value) => Some(round(*value, ty)),
+        _ => None,
+    }
+}
+
+/// The canonical constant for the given bits: integers are sign-extended
+/// from the width of their type.
+fn integer_constant(bits: u64, ty: IrType) -> Value {
+    Value::Int(signed(bits, ty))
+}
+
+fn float_constant(value: f64, ty: IrType) -> Value {
+    Value::Float(round(value, ty))
+}
+
+/// The canonical form of a constant read at type `ty`, or `None` for
+/// temporaries and addresses.
+pub fn constant(value: &Value, ty: IrType) -> Option<Value> {
+    if ty.is_float() {
+        Some(float_constant(float(value, ty)?, ty))
+    } else {
+        Some(integer_constant(integer(value, ty)?, ty))
+    }
+}
+
+fn unary(op: UnaryOp, ty: IrType, operand: &Value) -> Option<Value> {
+    match op {
+        UnaryOp::Neg => Some(integer_constant(integer(operand, ty)?.wrapping_neg(), ty)),
+        UnaryOp::Not => Some(integer_constant(!integer(operand, ty)?, ty)),
+        UnaryOp::FNeg => Some(float_constant(-float(operand, ty)?, ty)),
+    }
+}
+
+fn binary(op: BinaryOp, ty: IrType, left: &Value, right: &Value) -> Option<Value> {
+    if ty.is_float() {
+        let (left, right) = (float(left, ty)?, float(right, ty)?);
+        let result = match op {
+            BinaryOp::FAdd => left + right,
+            BinaryOp::FSub => left - right,
+            BinaryOp::FMul => left * right,
+            BinaryOp::FDiv => left / right,
+            _ => return None,
+        };
+        return Some(float_constant(result, ty));
+    }
+
+    let (left, right) = (integer(left, ty)?, integer(right, ty)?);
+    let (signed_left, signed_right) = (signed(left, ty), signed(right, ty));
+    let bits = (ty.size() * 8) as u64;
+    let result = match op {
+        BinaryOp::Add => left.wrapping_add(right),
+        BinaryOp::Sub => left.wrapping_sub(right),
+        BinaryOp::Mul => left.wrapping_mul(right),
+        BinaryOp::SDiv | BinaryOp::SRem => {
+            if right == 0 || (signed_right == -1 && signed_left == signed(1 << (bits - 1), ty)) {
+                return None;
+            }
+            if op == BinaryOp::SDiv {
+                (signed_left / signed_right) as u64
+            } else {
+                (signed_left % signed_right) as u64
+            }
+        }
+        BinaryOp::UDiv | BinaryOp::URem => {
+            if right == 0 {
+                return None;
+            }
+            if op == BinaryOp::UDiv {
+                left / right
+            } else {
+                left % right
+            }
+        }
+        BinaryOp::And => left & right,
+        BinaryOp::Or => left | right,
+        BinaryOp::Xor => left ^ right,
+        BinaryOp::Shl | BinaryOp::AShr | BinaryOp::LShr if right >= bits => return None,
+        BinaryOp::Shl => left << right,
+        BinaryOp::AShr => (signed_left >> right) as u64,
+        BinaryOp::LShr => left >> right,
+        BinaryOp::FAdd | BinaryOp::FSub | BinaryOp::FMul | BinaryOp::FDiv => return None,
+    };
+    Some(integer_constant(mask(result, ty), ty))
+}
+
+fn compare(condition: Condition, ty: IrType, left: &Value, right: &Value) -> Option<Value> {
+    let result = if ty.is_float() {
+        let (left, right) = (float(left, ty)?, float(right, ty)?);
+        match condition {
+            Condition::FEq => left == right,
+            Condition::FNe => left != right,
+            Condition::FLt => left < right,
+            Condition::FLe => left <= right,
+            Condition::FGt => left > right,
+            Condition::FGe => left >= right,
+            _ => return None,
+        }
+    } else {
+        let (left, right) = (integer(left, ty)?, integer(right, ty)?);
+        let (signed_left, signed_right) = (signed(left, ty), signed(right, ty));
+        match condition {
+            Condition::Eq => left == right,
+            Condition::Ne => left != right,
+            Condition::SLt => signed_left < signed_right,
+            Condition::SLe => signed_left <= signed_right,
+            Condition::SGt => signed_left > signed_right,
+            Condition::SGe => signed_left >= signed_right,
+            Condition::ULt => left < right,
+            Condition::ULe => left <= right,
+            Condition::UGt => left > right,
+            Condition::UGe => left >= right,
+            _ => return None,
+        }
+    };
+    Some(Value::Int(result as i64))
+}
+
+fn convert(op: ConvertOp, from: IrType, to: IrType, value: &Value) -> Option<Value> {
+    match op {
+        ConvertOp::SExt => Some(integer_constant(
+            signed(integer(value, from)?, from) as u64,
+            to,
+        )),
+        ConvertOp::ZExt | ConvertOp::Trunc => Some(integer_constant(integer(value, from)?, to)),
+        // Converting straight to the target precision rounds once.
+        ConvertOp::SIToFP => {
+            let value = signed(integer(value, from)?, from);
+            Some(match to {
+                IrType::F32 => Value::Float(value as f32 as f64),
+                _ => Value::Float(value as f64),
+            })
+        }
+        ConvertOp::UIToFP => {
+            let value = integer(value, from)?;
+            Some(match to {
+                IrType::F32 => Value::Float(value as f32 as f64),
+                _ => Value::Float(value as f64),
+            })
+        }
+        // The conversion is undefined when the truncated value does not
+        // fit, NaN included.
+        ConvertOp::FPToSI => {
+            let value = float(value, from)?.trunc();
+            let limit = 2f64.powi(to.size() as i32 * 8 - 1);
+            (value >= -limit && value < limit).then(|| integer_constant(value as i64 as u64, to))
+        }
+        ConvertOp::FPToUI => {
+            let value = float(value, from)?.trunc();
+            let limit = 2f64.powi(to.size() as i32 * 8);
+            (value >= 0.0 && value < limit).then(|| integer_constant(value as u64, to))
+        }
+        ConvertOp::FPExt | ConvertOp::FPTrunc => Some(float_constant(float(value, from)?, to)),
+    }
+}
+
+/// Whether a constant branch condition holds, or `None` when the
+/// condition is not a constant.
+pub fn is_true(condition: &Value, ty: IrType) -> Option<bool> {
+    if ty.is_float() {
+        Some(float(condition, ty)? != 0.0)
+    } else {
+        Some(integer(condition, ty)? != 0)
+    }
+}
+
+/// The constant an instruction computes when all of its operands are
+/// constants and the operation is safe to perform at compile time.
+pub fn evaluate(instr: &Instr) -> Option<Value> {
+    match instr {
+        Instr::Copy { ty, src, .. } => constant(src, *ty),
+        Instr::Unary {
+            op, ty, operand, ..
+        } => unary(*op, *ty, operand),
+        Instr::Binary {
+            op,
+            ty,
+            left,
+            right,
+            ..
+        } => binary(*op, *ty, left, right),
+        Instr::Compare {
+            condition,
+            ty,
+            left,
+            right,
+            ..
+        } => compare(*condition, *ty, left, right),
+        Instr::Convert {
+            op,
+            from,
+            to,
+            value,
+            ..
+        } => convert(*op, *from, *to, value),
+        _ => None,
+    }
+}
+
+/// The value of a binary operation or comparison that an algebraic
+/// identity gives away without knowing every operand. Float identities
+/// are only those that hold for NaN, infinities and signed zeros.
+pub fn simplify(instr: &Instr) -> Option<Value> {
+    let is = |value: &Value, ty: IrType, expected: i64| {
+        integer(value, ty) == Some(mask(expected as u64, ty))
+    };
+    let is_float = |value: &Value, expected: f64| match value {
+        Value::Float(value) => value.to_bits() == expected.to_bits(),
+        _ => false,
+    };
+
+    match instr {
+        Instr::Binary {
+            op,
+            ty,
+            left,
+            right,
+            ..
+        } => {
+            let ty = *ty;
+            let zero = Value::Int(0);
+            match op {
+                BinaryOp::Add if is(right, ty, 0) => Some(left.clone()),
+                BinaryOp::Add if is(left, ty, 0) => Some(right.clone()),
+                BinaryOp::Sub if is(right, ty, 0) => Some(left.clone()),
+                BinaryOp::Sub | BinaryOp::Xor if left == right => Some(zero),
+                BinaryOp::Mul if is(right, ty, 0) || is(left, ty, 0) => Some(zero),
+                BinaryOp::Mul if is(right, ty, 1) => Some(left.clone()),
+                BinaryOp::Mul if is(left, ty, 1) => Some(right.clone()),
+                BinaryOp::SDiv | BinaryOp::UDiv if is(right, ty, 1) => Some(left.clone()),
+                BinaryOp::SRem | BinaryOp::URem if is(right, ty, 1) => Some(zero),
+                BinaryOp::And if is(right, ty, 0) || is(left, ty, 0) => Some(zero),
+                BinaryOp::And if is(right, ty, -1) => Some(left.clone()),
+                BinaryOp::And if is(left, ty, -1) => Some(right.clone()),
+                BinaryOp::Or if is(right, ty, -1) || is(left, ty, -1) => Some(Value::Int(-1)),
+                BinaryOp::Or | BinaryOp::Xor if is(right, ty, 0) => Some(left.clone()),
+                BinaryOp::Or | BinaryOp::Xor if is(left, ty, 0) => Some(right.clone()),
+                BinaryOp::And | BinaryOp::Or if left == right => Some(left.clone()),
+                BinaryOp::Shl | BinaryOp::AShr | BinaryOp::LShr if is(right, ty, 0) => {
+                    Some(left.clone())
+                }
+                BinaryOp::Shl | BinaryOp::LShr if is(left, ty, 0) => Some(zero),
+                // x + -0.0 is x even for x = -0.0, whereas x + 0.0 is not.
+                BinaryOp::FAdd if is_float(right, -0.0) => Some(left.clone()),
+                BinaryOp::FAdd if is_float(left, -0.0) => Some(right.clone()),
+                BinaryOp::FSub if is_float(right, 0.0) => Some(left.clone()),
+                BinaryOp::FMul | BinaryOp::FDiv if is_float(right, 1.0) => Some(left.clone()),
+                BinaryOp::FMul if is_float(left, 1.0) => Some(right.clone()),
+                _ => None,
+            }
+        }
+        Instr::Compare {
+            condition,
+            ty,
+            left,
+            right,
+            ..
+        } if !ty.is_float() && left == right => {
+            let equal = matches!(
+                condition,
+                Condition::Eq | Condition::SLe | Condition::SGe | Condition::ULe | Condition::UGe
+            );
+            Some(Value::Int(equal as i64))
+        }
+        _ => None,
+    }
+}
+
+/// The type of the value an instruction defines.
+fn result_type(instr: &Instr) -> Option<IrType> {
+    match instr {
+        Instr::Copy { ty, .. } | Instr::Unary { ty, .. } | Instr::Binary { ty, .. } => Some(*ty),
+        Instr::Compare { .. } => Some(IrType::I32),
+        Instr::Convert { to, .. } => Some(*to),
+        _ => None,
+    }
+}
+
+/// Folds and simplifies the function until nothing changes, returning
+/// whether anything did.
+pub fn run(function: &mut Function) -> bool {
+    let mut definitions: HashMap<Temp, usize> = HashMap::new();
+    let defined = function.body.iter().filter_map(Instr::defined);
+    for temp in defined.chain(function.parameters.iter().map(|(temp, _)| *temp)) {
+        *definitions.entry(temp).or_default() += 1;
+    }
+    let single = |value: &Value| match value {
+        Value::Temp(temp) => definitions.get(temp) == Some(&1),
+        _ => true,
+    };
+
+    let mut known: HashMap<Temp, Value> = HashMap::new();
+    let mut changed = false;
+    loop {
+        let mut progress = false;
+        for instr in &mut function.body {
+            for value in instr.uses_mut() {
+                if let Value::Temp(temp) = value
+                    && let Some(replacement) = known.get(temp)
+                {
+                    *value = replacement.clone();
+                    progress = true;
+                }
+            }
+
+            let Some(dst) = instr.defined() else {
+                continue;
+            };
+            if known.contains_key(&dst) {
+                continue;
+            }
+
+            // A phi whose incoming values are all the same constant has
+            // that value; the phi itself is left for dead code removal.
+            if let Instr::Phi { ty, incoming, .. } = instr {
+                let mut values = incoming.iter().map(|(_, value)| value);
+                if let Some(first) = values.next()
+                    && !matches!(first, Value::Temp(_) | Value::Global(_))
+                    && values.all(|value| value == first)
+                    && definitions[&dst] == 1
+                {
+                    known.insert(dst, constant(first, *ty).unwrap());
+                    progress = true;
+                }
+                continue;
+            }
+
+            let Some(ty) = result_type(instr) else {
+                continue;
+            };
+            let Some(value) = evaluate(instr).or_else(|| simplify(instr)) else {
+                continue;
+            };
+            // Temporaries are only forwarded when neither they nor the
+            // value can be reassigned in between.
+            if definitions[&dst] == 1 && single(&value) {
+                known.insert(dst, value.clone());
+            }
+            let folded = Instr::Copy {
+                dst,
+                ty,
+                src: value,
+            };
+            if *instr != folded {
+                *instr = folded;
+                progress = true;
+            }
+        }
+
+        if !progress {
+            return changed;
+        }
+        changed = true;
+    }
+}
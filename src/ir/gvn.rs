@@ -0,0 +1,294 @@
+/*
+  Dominator-based global value numbering with copy propagation, on a
+  function in SSA form. Walking the dominator tree, each pure computation
+  is looked up in a table of the expressions available in the dominating
+  blocks; when an equal one is found, uses of the result are redirected to
+  the earlier temporary and the instruction is removed. Operands of
+  commutative operations are put in a fixed order first, so `a + b` and
+  `b + a` match. Copies, and phis whose inputs are all the same value,
+  are removed by forwarding their source to every use.
+
+  Memory is handled conservatively. Loads are only numbered within a
+  block and are forgotten at any store, memcpy or call; a load after a
+  store to the same address reads the stored value. Volatile accesses,
+  allocas and calls are never numbered.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::Cfg;
+use super::dominators::DominatorTree;
+use super::passes::Analyses;
+use super::{BinaryOp, Condition, ConvertOp, Function, Instr, IrType, Temp, UnaryOp, Value};
+
+/// A value in a form that can be hashed; floats by their bits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Operand {
+    Temp(Temp),
+    Int(i64),
+    Float(u64),
+    Global(String),
+}
+
+impl Operand {
+    fn new(value: &Value) -> Operand {
+        match value {
+            Value::Temp(temp) => Operand::Temp(*temp),
+            Value::Int(value) => Operand::Int(*value),
+            Value::Float(value) => Operand::Float(value.to_bits()),
+            Value::Global(name) => Operand::Global(name.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Expression {
+    Unary(UnaryOp, IrType, Operand),
+    Binary(BinaryOp, IrType, Operand, Operand),
+    Compare(Condition, IrType, Operand, Operand),
+    Convert(ConvertOp, IrType, IrType, Operand),
+}
+
+fn is_commutative(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::Add
+            | BinaryOp::Mul
+            | BinaryOp::And
+            | BinaryOp::Or
+            | BinaryOp::Xor
+            | BinaryOp::FAdd
+            | BinaryOp::FMul
+    )
+}
+
+/// The expression a pure instruction computes, with the operands of
+/// commutative operations sorted.
+fn expression(instr: &Instr) -> Option<Expression> {
+    let sorted = |left: &Value, right: &Value| {
+        let (left, right) = (Operand::new(left), Operand::new(right));
+        if left <= right {
+            (left, right)
+        } else {
+            (right, left)
+        }
+    };
+
+    Some(match instr {
+        Instr::Unary {
+            op, ty, operand, ..
+        } => Expression::Unary(*op, *ty, Operand::new(operand)),
+        Instr::Binary {
+            op,
+            ty,
+            left,
+            right,
+            ..
+        } => {
+            let (left, right) = if is_commutative(*op) {
+                sorted(left, right)
+            } else {
+                (Operand::new(left), Operand::new(right))
+            };
+            Expression::Binary(*op, *ty, left, right)
+        }
+        Instr::Compare {
+            condition,
+            ty,
+            left,
+            right,
+            ..
+        } => {
+            let (left, right) = if matches!(
+                condition,
+                Condition::Eq | Condition::Ne | Condition::FEq | Condition::FNe
+            ) {
+                sorted(left, right)
+            } else {
+                (Operand::new(left), Operand::new(right))
+            };
+            Expression::Compare(*condition, *ty, left, right)
+        }
+        Instr::Convert {
+            op,
+            from,
+            to,
+            value,
+            ..
+        } => Expression::Convert(*op, *from, *to, Operand::new(value)),
+        _ => return None,
+    })
+}
+
+struct Numbering<'a> {
+    cfg: &'a Cfg,
+    dominators: &'a DominatorTree,
+    /// The temporary holding each expression available at this point of
+    /// the dominator tree walk.
+    available: HashMap<Expression, Temp>,
+    /// Temporaries whose uses should read another value instead.
+    replacements: HashMap<Temp, Value>,
+    /// Positions of removed instructions, by block.
+    removed: Vec<HashSet<usize>>,
+}
+
+impl Numbering<'_> {
+    fn resolve(&self, value: &Value) -> Value {
+        let mut value = value.clone();
+        while let Value::Temp(temp) = value
+            && let Some(replacement) = self.replacements.get(&temp)
+        {
+            value = replacement.clone();
+        }
+        value
+    }
+
+    fn replace(&mut self, block: usize, position: usize, dst: Temp, value: Value) {
+        self.replacements.insert(dst, value);
+        self.removed[block].insert(position);
+    }
+
+    fn number(&mut self, block: usize) {
+        let mut added = vec![];
+        // Values in memory, keyed by address and type, known since the
+        // last store, memcpy or call in this block.
+        let mut memory: HashMap<(Operand, IrType), Value> = HashMap::new();
+
+        for (position, instr) in self.cfg.blocks[block].instrs.iter().enumerate() {
+            let mut instr = instr.clone();
+            for value in instr.uses_mut() {
+                *value = self.resolve(value);
+            }
+
+            match &instr {
+                Instr::Copy { dst, src, .. } => self.replace(block, position, *dst, src.clone()),
+                Instr::Phi { dst, incoming, .. } => {
+                    let mut inputs = incoming
+                        .iter()
+                        .map(|(_, value)| value)
+                        .filter(|value| **value != Value::Temp(*dst));
+                    if let Some(first) = inputs.next()
+                        && inputs.all(|value| value == first)
+                    {
+                        self.replace(block, position, *dst, first.clone());
+                    }
+                }
+                Instr::Load {
+                    dst,
+                    ty,
+                    address,
+                    volatile: false,
+                } => {
+                    let key = (Operand::new(address), *ty);
+                    match memory.get(&key) {
+                        Some(value) => self.replace(block, position, *dst, value.clone()),
+                        None => {
+                            memory.insert(key, Value::Temp(*dst));
+                        }
+                    }
+                }
+                Instr::Store {
+                    ty,
+                    value,
+                    address,
+                    volatile,
+                } => {
+                    memory.clear();
+                    if !volatile {
+                        memory.insert((Operand::new(address), *ty), value.clone());
+                    }
+                }
+                Instr::MemCopy { .. } | Instr::Call { .. } => memory.clear(),
+                instr => {
+                    if let (Some(dst), Some(expression)) = (instr.defined(), expression(instr)) {
+                        match self.available.get(&expression) {
+                            Some(leader) => {
+                                self.replace(block, position, dst, Value::Temp(*leader))
+                            }
+                            None => {
+                                self.available.insert(expression.clone(), dst);
+                                added.push(expression);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for &child in &self.dominators.children[block] {
+            self.number(child);
+        }
+
+        for expression in added {
+            self.available.remove(&expression);
+        }
+    }
+}
+
+/// Removes redundant computations and copies from the function, which must
+/// be in SSA form. Returns whether anything changed.
+pub fn run(function: &mut Function, analyses: &mut Analyses) -> bool {
+    let mut cfg = analyses.cfg(function).clone();
+    let pruned = cfg.remove_unreachable();
+
+    let mut definitions = HashSet::new();
+    for (temp, _) in &function.parameters {
+        definitions.insert(*temp);
+    }
+    for instr in cfg.blocks.iter().flat_map(|block| &block.instrs) {
+        if let Some(dst) = instr.defined()
+            && !definitions.insert(dst)
+        {
+            // Not in SSA form.
+            return false;
+        }
+    }
+
+    // Removing blocks renumbers the rest, so the cached tree no longer fits.
+    let rebuilt;
+    let dominators = if pruned {
+        rebuilt = DominatorTree::build(&cfg);
+        &rebuilt
+    } else {
+        analyses.dominators(function)
+    };
+    let mut numbering = Numbering {
+        cfg: &cfg,
+        dominators,
+        available: HashMap::new(),
+        replacements: HashMap::new(),
+        removed: vec![HashSet::new(); cfg.blocks.len()],
+    };
+    numbering.number(0);
+    let Numbering {
+        replacements,
+        removed,
+        ..
+    } = numbering;
+    let resolve = |value: &Value| {
+        let mut value = value.clone();
+        while let Value::Temp(temp) = value
+            && let Some(replacement) = replacements.get(&temp)
+        {
+            value = replacement.clone();
+        }
+        value
+    };
+
+    let changed = pruned || removed.iter().any(|removed| !removed.is_empty());
+    for (block, removed) in cfg.blocks.iter_mut().zip(removed) {
+        let mut position = 0;
+        block.instrs.retain(|_| {
+            position += 1;
+            !removed.contains(&(position - 1))
+        });
+        for instr in &mut block.instrs {
+            for value in instr.uses_mut() {
+                *value = resolve(value);
+            }
+        }
+    }
+
+    function.body = cfg.to_body();
+    changed
+}
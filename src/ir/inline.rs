@@ -0,0 +1,506 @@
+/*
+  Function inlining. The strongly connected components of the call graph
+  are visited bottom-up, callees before callers (Tarjan's algorithm finds
+  them in that order), so a function has already received the calls
+  inlined into it by the time it is weighed for inlining into its own
+  callers. Functions on a cycle of the call graph are never inlined, which
+  keeps recursion from being unrolled without end.
+
+  The cost of a function is its number of instructions. A call is inlined
+  when the callee costs no more than the threshold, or twice that when it
+  is declared `inline`, and callers stop growing at a fixed size.
+  `always_inline` functions are inlined regardless and `noinline` ones
+  never are. A static function called from a single place is always
+  inlined, since its own body then goes away.
+
+  The block of the call is split after it. The arguments are copied into
+  renamed parameters and control jumps to a renamed copy of the callee's
+  blocks, whose returns jump to the rest of the block, which receives the
+  return value through a phi when there are several. The callee's stack
+  slots move to the caller's entry. Static functions that are no longer
+  referenced are removed once every call has been considered.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::{BasicBlock, Cfg};
+use super::{Function, GlobalInit, Inlining, Instr, IrType, Label, Program, Temp, Value};
+
+/// Inlining stops growing a caller past this many instructions, except
+/// for `always_inline` callees.
+const CALLER_LIMIT: usize = 1000;
+
+/// The functions called directly by each function, by index.
+fn call_graph(program: &Program, index: &HashMap<&str, usize>) -> Vec<Vec<usize>> {
+    program
+        .functions
+        .iter()
+        .map(|function| {
+            let mut callees: Vec<usize> = function
+                .body
+                .iter()
+                .filter_map(|instr| match instr {
+                    Instr::Call {
+                        callee: Value::Global(name),
+                        ..
+                    } => index.get(name.as_str()).copied(),
+                    _ => None,
+                })
+                .collect();
+            callees.sort_unstable();
+            callees.dedup();
+            callees
+        })
+        .collect()
+}
+
+struct Tarjan<'a> {
+    graph: &'a [Vec<usize>],
+    next: usize,
+    order: Vec<Option<usize>>,
+    lowlink: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    components: Vec<Vec<usize>>,
+}
+
+impl Tarjan<'_> {
+    fn visit(&mut self, node: usize) {
+        self.order[node] = Some(self.next);
+        self.lowlink[node] = self.next;
+        self.next += 1;
+        self.stack.push(node);
+        self.on_stack[node] = true;
+
+        for &successor in &self.graph[node] {
+            match self.order[successor] {
+                None => {
+                    self.visit(successor);
+                    self.lowlink[node] = self.lowlink[node].min(self.lowlink[successor]);
+                }
+                Some(order) if self.on_stack[successor] => {
+                    self.lowlink[node] = self.lowlink[node].min(order);
+                }
+                Some(_) => {}
+            }
+        }
+
+        if Some(self.lowlink[node]) == self.order[node] {
+            let mut component = vec![];
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+/// The strongly connected components of the graph, each one after every
+/// component it has edges into.
+pub fn strongly_connected_components(graph: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut tarjan = Tarjan {
+        graph,
+        next: 0,
+        order: vec![None; graph.len()],
+        lowlink: vec![0; graph.len()],
+        stack: vec![],
+        on_stack: vec![false; graph.len()],
+        components: vec![],
+    };
+    for node in 0..graph.len() {
+        if tarjan.order[node].is_none() {
+            tarjan.visit(node);
+        }
+    }
+    tarjan.components
+}
+
+fn cost(function: &Function) -> usize {
+    function
+        .body
+        .iter()
+        .filter(|instr| !matches!(instr, Instr::Label(_)))
+        .count()
+}
+
+/// Functions whose address is used other than to call them.
+fn address_taken(program: &Program) -> HashSet<String> {
+    let mut taken = HashSet::new();
+    for function in &program.functions {
+        for instr in &function.body {
+            let mut uses = instr.uses();
+            if let Instr::Call { .. } = instr {
+                uses.remove(0);
+            }
+            for value in uses {
+                if let Value::Global(name) = value {
+                    taken.insert(name.clone());
+                }
+            }
+        }
+    }
+    for global in &program.globals {
+        for init in &global.init {
+            if let GlobalInit::Address(name, _) = init {
+                taken.insert(name.clone());
+            }
+        }
+    }
+    taken
+}
+
+struct Inliner {
+    threshold: usize,
+    recursive: Vec<bool>,
+    /// Static functions called from exactly one place, whose address is
+    /// never taken.
+    called_once: HashSet<String>,
+}
+
+impl Inliner {
+    /// Whether the call can be replaced by the callee's body at all: the
+    /// arguments and result must match the definition, which must not
+    /// take variable arguments from a frame of its own.
+    fn can_inline(&self, callee: usize, function: &Function, call: &Instr) -> bool {
+        let Instr::Call { dst, arguments, .. } = call else {
+            return false;
+        };
+        let entry_has_phis = function
+            .body
+            .iter()
+            .find(|instr| !matches!(instr, Instr::Label(_)))
+            .is_some_and(|instr| matches!(instr, Instr::Phi { .. }));
+        !self.recursive[callee]
+            && !function.variadic
+            && function.inlining != Inlining::Never
+            && !entry_has_phis
+            && arguments.len() == function.parameters.len()
+            && arguments
+                .iter()
+                .zip(&function.parameters)
+                .all(|((ty, _), (_, parameter))| ty == parameter)
+            && dst.is_none_or(|(_, ty)| function.return_type == Some(ty))
+    }
+
+    fn should_inline(&self, caller_size: usize, function: &Function) -> bool {
+        let cost = cost(function);
+        let limit = match function.inlining {
+            Inlining::Always => return true,
+            Inlining::Never => return false,
+            _ if self.called_once.contains(&function.name) => usize::MAX,
+            Inlining::Hint => 2 * self.threshold,
+            Inlining::Default => self.threshold,
+        };
+        cost <= limit && caller_size + cost <= CALLER_LIMIT
+    }
+}
+
+/// Inlines the calls of a program with the given cost threshold, then
+/// removes the static functions no longer referenced. Returns whether
+/// anything changed.
+pub fn run(program: &mut Program, threshold: usize) -> bool {
+    let index: HashMap<&str, usize> = program
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(index, function)| (function.name.as_str(), index))
+        .collect();
+    let graph = call_graph(program, &index);
+    let components = strongly_connected_components(&graph);
+
+    let mut recursive = vec![false; graph.len()];
+    for component in &components {
+        let first = component[0];
+        if component.len() > 1 || graph[first].contains(&first) {
+            for &member in component {
+                recursive[member] = true;
+            }
+        }
+    }
+
+    let taken = address_taken(program);
+    let mut calls: HashMap<&str, usize> = HashMap::new();
+    for function in &program.functions {
+        for instr in &function.body {
+            if let Instr::Call {
+                callee: Value::Global(name),
+                ..
+            } = instr
+            {
+                *calls.entry(name).or_default() += 1;
+            }
+        }
+    }
+    let called_once = program
+        .functions
+        .iter()
+        .filter(|function| {
+            !function.is_global
+                && calls.get(function.name.as_str()) == Some(&1)
+                && !taken.contains(&function.name)
+        })
+        .map(|function| function.name.clone())
+        .collect();
+
+    let inliner = Inliner {
+        threshold,
+        recursive,
+        called_once,
+    };
+    let index: HashMap<String, usize> = index
+        .into_iter()
+        .map(|(name, index)| (name.to_string(), index))
+        .collect();
+    let mut changed = false;
+    for component in &components {
+        for &caller in component {
+            changed |= inline_calls(program, caller, &index, &inliner);
+        }
+    }
+    changed | remove_unreferenced(program)
+}
+
+/// Inlines the calls in one function that the cost model accepts.
+fn inline_calls(
+    program: &mut Program,
+    caller: usize,
+    index: &HashMap<String, usize>,
+    inliner: &Inliner,
+) -> bool {
+    let function = &program.functions[caller];
+    let mut cfg = Cfg::build(function);
+    let mut size = cost(function);
+    let mut next_temp = function.next_temp().0;
+    let mut next_label = cfg.fresh_label().0;
+
+    // Later calls first, so that splitting a block leaves the positions of
+    // the calls still to be inlined unchanged.
+    let mut sites = vec![];
+    for block in &cfg.blocks {
+        for (position, instr) in block.instrs.iter().enumerate() {
+            if let Instr::Call {
+                callee: Value::Global(name),
+                ..
+            } = instr
+                && let Some(&callee) = index.get(name)
+                && inliner.can_inline(callee, &program.functions[callee], instr)
+            {
+                sites.push((block.label, position, callee));
+            }
+        }
+    }
+
+    let mut changed = false;
+    let mut slots = vec![];
+    for (label, position, callee) in sites.into_iter().rev() {
+        let callee = &program.functions[callee];
+        if !inliner.should_inline(size, callee) {
+            continue;
+        }
+        let block = cfg.label_indices()[&label];
+        slots.extend(inline_call(
+            &mut cfg,
+            block,
+            position,
+            callee,
+            &mut next_temp,
+            &mut next_label,
+        ));
+        size += cost(callee);
+        changed = true;
+    }
+
+    if changed {
+        cfg.blocks[0].instrs.splice(0..0, slots);
+        program.functions[caller].body = cfg.to_body();
+    }
+    changed
+}
+
+/// A value of the type for results that are never actually produced, such
+/// as when the callee falls off its end.
+fn zero(ty: IrType) -> Value {
+    if ty.is_float() {
+        Value::Float(0.0)
+    } else {
+        Value::Int(0)
+    }
+}
+
+/// Replaces the call at `position` in `block` by the body of `callee`,
+/// numbering the callee's temporaries and labels from the given counters.
+/// Returns the callee's stack slots, for the caller's entry.
+fn inline_call(
+    cfg: &mut Cfg,
+    block: usize,
+    position: usize,
+    callee: &Function,
+    next_temp: &mut u32,
+    next_label: &mut u32,
+) -> Vec<Instr> {
+    let body = Cfg::build(callee);
+    let temp_base = *next_temp;
+    let label_base = *next_label;
+    let continuation = Label(label_base + body.fresh_label().0);
+    *next_temp += callee.next_temp().0;
+    *next_label = continuation.0 + 1;
+    let temp = |temp: Temp| Temp(temp_base + temp.0);
+    let label = |label: Label| Label(label_base + label.0);
+
+    // The successors now continue from the rest of the block.
+    let original = cfg.blocks[block].label;
+    let labels = cfg.label_indices();
+    for target in cfg.blocks[block].terminator().targets() {
+        for instr in &mut cfg.blocks[labels[&target]].instrs {
+            let Instr::Phi { incoming, .. } = instr else {
+                break;
+            };
+            for (predecessor, _) in incoming {
+                if *predecessor == original {
+                    *predecessor = continuation;
+                }
+            }
+        }
+    }
+
+    let tail = cfg.blocks[block].instrs.split_off(position + 1);
+    let Some(Instr::Call { dst, arguments, .. }) = cfg.blocks[block].instrs.pop() else {
+        unreachable!("inlining a call that is not one");
+    };
+    let head = &mut cfg.blocks[block].instrs;
+    for ((ty, value), (parameter, _)) in arguments.into_iter().zip(&callee.parameters) {
+        head.push(Instr::Copy {
+            dst: temp(*parameter),
+            ty,
+            src: value,
+        });
+    }
+    head.push(Instr::Jump(label(body.blocks[0].label)));
+
+    let mut slots = vec![];
+    let mut returns: Vec<(Label, Value)> = vec![];
+    let mut blocks = vec![];
+    for callee_block in body.blocks {
+        let block_label = label(callee_block.label);
+        let mut instrs = vec![];
+        for mut instr in callee_block.instrs {
+            if let Instr::Return(value) = instr {
+                if let Some((_, ty)) = dst {
+                    let value = match value {
+                        Some((_, Value::Temp(value))) => Value::Temp(temp(value)),
+                        Some((_, value)) => value,
+                        None => zero(ty),
+                    };
+                    returns.push((block_label, value));
+                }
+                instrs.push(Instr::Jump(continuation));
+                continue;
+            }
+
+            if let Some(dst) = instr.defined_mut() {
+                *dst = temp(*dst);
+            }
+            for value in instr.uses_mut() {
+                if let Value::Temp(used) = value {
+                    *used = temp(*used);
+                }
+            }
+            match &mut instr {
+                Instr::Phi { incoming, .. } => {
+                    for (predecessor, _) in incoming {
+                        *predecessor = label(*predecessor);
+                    }
+                }
+                Instr::Jump(target) => *target = label(*target),
+                Instr::Branch {
+                    if_true, if_false, ..
+                } => {
+                    *if_true = label(*if_true);
+                    *if_false = label(*if_false);
+                }
+                _ => {}
+            }
+
+            if let Instr::Alloca { .. } = instr {
+                slots.push(instr);
+            } else {
+                instrs.push(instr);
+            }
+        }
+        blocks.push(BasicBlock {
+            label: block_label,
+            instrs,
+        });
+    }
+
+    let mut instrs = vec![];
+    if let Some((dst, ty)) = dst {
+        instrs.push(match returns.len() {
+            0 => Instr::Copy {
+                dst,
+                ty,
+                src: zero(ty),
+            },
+            1 => Instr::Copy {
+                dst,
+                ty,
+                src: returns.pop().unwrap().1,
+            },
+            _ => Instr::Phi {
+                dst,
+                ty,
+                incoming: returns,
+            },
+        });
+    }
+    instrs.extend(tail);
+    blocks.push(BasicBlock {
+        label: continuation,
+        instrs,
+    });
+
+    cfg.blocks.splice(block + 1..block + 1, blocks);
+    cfg.compute_edges();
+    slots
+}
+
+/// Removes the static functions that nothing refers to any more, other
+/// than themselves.
+fn remove_unreferenced(program: &mut Program) -> bool {
+    let mut changed = false;
+    loop {
+        let mut referenced: HashSet<String> = HashSet::new();
+        for function in &program.functions {
+            for instr in &function.body {
+                for value in instr.uses() {
+                    if let Value::Global(name) = value
+                        && *name != function.name
+                    {
+                        referenced.insert(name.clone());
+                    }
+                }
+            }
+        }
+        for global in &program.globals {
+            for init in &global.init {
+                if let GlobalInit::Address(name, _) = init {
+                    referenced.insert(name.clone());
+                }
+            }
+        }
+
+        let count = program.functions.len();
+        program
+            .functions
+            .retain(|function| function.is_global || referenced.contains(&function.name));
+        if program.functions.len() == count {
+            return changed;
+        }
+        changed = true;
+    }
+}
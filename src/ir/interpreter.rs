@@ -0,0 +1,809 @@
+/*
+  Executes IR directly, as a reference for the backend. Memory is one byte
+  array: the first page is never mapped so null dereferences trap, globals
+  come next, then a fixed-size stack for `alloca` and then the heap used
+  by `malloc`. Functions get addresses outside that array, so function
+  pointers can be stored and called but not read. Calls are executed on an
+  explicit frame stack, and a few C library functions are built in.
+
+  A `va_list` has the layout of the x86-64 one, and `va_arg` follows the
+  same protocol as the backend. `va_start` puts every variable argument in
+  the overflow area, as if the argument registers had all been used.
+
+  Temporaries hold the bits of their value truncated to their type; floats
+  are stored as their IEEE bits. The interpreter runs both SSA and non-SSA
+  code.
+*/
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use super::{
+    BinaryOp, Condition, ConvertOp, GlobalInit, Instr, IrType, Label, Program, Temp, UnaryOp,
+    VA_ARG, VA_START, Value,
+};
+
+const NULL_PAGE: u64 = 0x1000;
+const STACK_SIZE: u64 = 8 << 20;
+const FUNCTION_BASE: u64 = 1 << 48;
+const MAX_FRAMES: usize = 100_000;
+/// The size of the register save area, where `gp_offset` and `fp_offset`
+/// point: six integer registers and then eight vector registers.
+const GP_LIMIT: u64 = 6 * 8;
+const FP_LIMIT: u64 = GP_LIMIT + 8 * 16;
+
+#[derive(Debug, PartialEq)]
+pub enum RuntimeError {
+    InvalidAccess(String),
+    DivisionByZero(String),
+    UndefinedFunction(String),
+    StackOverflow,
+    InvalidInstruction(String),
+}
+
+/// Why execution stopped early: an error, or a call to `exit`.
+enum Trap {
+    Error(RuntimeError),
+    Exit(i32),
+}
+
+impl From<RuntimeError> for Trap {
+    fn from(error: RuntimeError) -> Self {
+        Trap::Error(error)
+    }
+}
+
+fn mask(bits: u64, ty: IrType) -> u64 {
+    match ty.size() {
+        8 => bits,
+        size => bits & ((1 << (size * 8)) - 1),
+    }
+}
+
+fn signed(bits: u64, ty: IrType) -> i64 {
+    let shift = 64 - ty.size() * 8;
+    ((bits << shift) as i64) >> shift
+}
+
+fn to_float(bits: u64, ty: IrType) -> f64 {
+    match ty {
+        IrType::F32 => f32::from_bits(bits as u32) as f64,
+        _ => f64::from_bits(bits),
+    }
+}
+
+fn from_float(value: f64, ty: IrType) -> u64 {
+    match ty {
+        IrType::F32 => (value as f32).to_bits() as u64,
+        _ => value.to_bits(),
+    }
+}
+
+struct Frame {
+    function: usize,
+    pc: usize,
+    temps: HashMap<Temp, u64>,
+    allocas: HashMap<Temp, u64>,
+    stack_base: u64,
+    /// The block being executed and the one executed before it, for phis.
+    block: Option<Label>,
+    previous_block: Option<Label>,
+    /// Where the caller wants the return value.
+    return_to: Option<Temp>,
+    /// The arguments past the parameters of a variadic function.
+    variadic: Vec<(IrType, u64)>,
+}
+
+struct Interpreter<'a> {
+    program: &'a Program,
+    functions: HashMap<&'a str, usize>,
+    labels: Vec<HashMap<Label, usize>>,
+    globals: HashMap<&'a str, u64>,
+    memory: Vec<u8>,
+    stack_pointer: u64,
+    stack_end: u64,
+    heap_pointer: u64,
+    output: &'a mut dyn Write,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(program: &'a Program, output: &'a mut dyn Write) -> Result<Self, RuntimeError> {
+        let functions = program
+            .functions
+            .iter()
+            .enumerate()
+            .map(|(index, function)| (function.name.as_str(), index))
+            .collect();
+        let labels = program
+            .functions
+            .iter()
+            .map(|function| {
+                function
+                    .body
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, instr)| match instr {
+                        Instr::Label(label) => Some((*label, index)),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut globals = HashMap::new();
+        let mut address = NULL_PAGE;
+        for global in &program.globals {
+            let alignment = global.alignment.max(1) as u64;
+            address = address.div_ceil(alignment) * alignment;
+            globals.insert(global.name.as_str(), address);
+            address += global.size() as u64;
+        }
+
+        let stack_start = address.div_ceil(16) * 16;
+        let mut interpreter = Interpreter {
+            program,
+            functions,
+            labels,
+            globals,
+            memory: vec![0; (stack_start + STACK_SIZE) as usize],
+            stack_pointer: stack_start,
+            stack_end: stack_start + STACK_SIZE,
+            heap_pointer: stack_start + STACK_SIZE,
+            output,
+        };
+
+        for global in &program.globals {
+            let mut address = interpreter.globals[global.name.as_str()];
+            for init in &global.init {
+                match init {
+                    GlobalInit::Zero(_) => {}
+                    GlobalInit::Int(ty, value) => {
+                        interpreter.store(address, *ty, mask(*value as u64, *ty))?
+                    }
+                    GlobalInit::Float(ty, value) => {
+                        interpreter.store(address, *ty, from_float(*value, *ty))?
+                    }
+                    GlobalInit::Address(name, offset) => {
+                        let target = interpreter.symbol(name)?;
+                        interpreter.store(
+                            address,
+                            IrType::I64,
+                            target.wrapping_add(*offset as u64),
+                        )?
+                    }
+                    GlobalInit::Bytes(bytes) => {
+                        let start = address as usize;
+                        interpreter.memory[start..start + bytes.len()].copy_from_slice(bytes);
+                    }
+                }
+                address += init.size() as u64;
+            }
+        }
+
+        Ok(interpreter)
+    }
+
+    fn symbol(&self, name: &str) -> Result<u64, RuntimeError> {
+        if let Some(address) = self.globals.get(name) {
+            return Ok(*address);
+        }
+        match self.functions.get(name) {
+            Some(index) => Ok(FUNCTION_BASE + *index as u64 * 16),
+            None => Err(RuntimeError::UndefinedFunction(name.to_string())),
+        }
+    }
+
+    fn check_access(&self, address: u64, size: usize) -> Result<usize, RuntimeError> {
+        let end = address.checked_add(size as u64);
+        if address < NULL_PAGE || end.is_none_or(|end| end > self.memory.len() as u64) {
+            return Err(RuntimeError::InvalidAccess(format!(
+                "{} bytes at {:#x}",
+                size, address
+            )));
+        }
+        Ok(address as usize)
+    }
+
+    fn load(&self, address: u64, ty: IrType) -> Result<u64, RuntimeError> {
+        let start = self.check_access(address, ty.size())?;
+        let mut bytes = [0; 8];
+        bytes[..ty.size()].copy_from_slice(&self.memory[start..start + ty.size()]);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn store(&mut self, address: u64, ty: IrType, bits: u64) -> Result<(), RuntimeError> {
+        let start = self.check_access(address, ty.size())?;
+        self.memory[start..start + ty.size()].copy_from_slice(&bits.to_le_bytes()[..ty.size()]);
+        Ok(())
+    }
+
+    fn c_string(&self, address: u64) -> Result<Vec<u8>, RuntimeError> {
+        let mut bytes = vec![];
+        let mut address = address;
+        loop {
+            let byte = self.load(address, IrType::I8)? as u8;
+            if byte == 0 {
+                return Ok(bytes);
+            }
+            bytes.push(byte);
+            address += 1;
+        }
+    }
+
+    fn value(&self, frame: &Frame, value: &Value, ty: IrType) -> Result<u64, RuntimeError> {
+        match value {
+            Value::Temp(temp) => frame.temps.get(temp).copied().ok_or_else(|| {
+                RuntimeError::InvalidInstruction(format!("{} is used before it is set", temp))
+            }),
+            Value::Int(value) if ty.is_float() => Ok(from_float(*value as f64, ty)),
+            Value::Int(value) => Ok(mask(*value as u64, ty)),
+            Value::Float(value) if ty.is_float() => Ok(from_float(*value, ty)),
+            Value::Float(value) => Ok(mask(*value as i64 as u64, ty)),
+            Value::Global(name) => self.symbol(name),
+        }
+    }
+
+    fn binary(&self, op: BinaryOp, ty: IrType, left: u64, right: u64) -> Result<u64, RuntimeError> {
+        let bits = (ty.size() * 8) as u32;
+        let (signed_left, signed_right) = (signed(left, ty), signed(right, ty));
+        let (float_left, float_right) = (to_float(left, ty), to_float(right, ty));
+        let division_by_zero = || RuntimeError::DivisionByZero(format!("{} {}", op.name(), ty));
+
+        let result = match op {
+            BinaryOp::Add => left.wrapping_add(right),
+            BinaryOp::Sub => left.wrapping_sub(right),
+            BinaryOp::Mul => left.wrapping_mul(right),
+            BinaryOp::SDiv | BinaryOp::SRem => {
+                if right == 0 {
+                    return Err(division_by_zero());
+                }
+                // The quotient of the most negative value by -1 overflows.
+                if signed_right == -1 && signed_left == signed(1 << (bits - 1), ty) {
+                    return Err(RuntimeError::InvalidInstruction(format!(
+                        "{} {} overflows",
+                        op.name(),
+                        ty
+                    )));
+                }
+                if op == BinaryOp::SDiv {
+                    (signed_left / signed_right) as u64
+                } else {
+                    (signed_left % signed_right) as u64
+                }
+            }
+            BinaryOp::UDiv | BinaryOp::URem => {
+                if right == 0 {
+                    return Err(division_by_zero());
+                }
+                if op == BinaryOp::UDiv {
+                    left / right
+                } else {
+                    left % right
+                }
+            }
+            BinaryOp::And => left & right,
+            BinaryOp::Or => left | right,
+            BinaryOp::Xor => left ^ right,
+            // Shift counts are taken modulo the width, as on x86.
+            BinaryOp::Shl => left << (right as u32 % bits),
+            BinaryOp::AShr => (signed_left >> (right as u32 % bits)) as u64,
+            BinaryOp::LShr => left >> (right as u32 % bits),
+            BinaryOp::FAdd => return Ok(from_float(float_left + float_right, ty)),
+            BinaryOp::FSub => return Ok(from_float(float_left - float_right, ty)),
+            BinaryOp::FMul => return Ok(from_float(float_left * float_right, ty)),
+            BinaryOp::FDiv => return Ok(from_float(float_left / float_right, ty)),
+        };
+
+        Ok(mask(result, ty))
+    }
+
+    fn compare(condition: Condition, ty: IrType, left: u64, right: u64) -> u64 {
+        let (signed_left, signed_right) = (signed(left, ty), signed(right, ty));
+        let (float_left, float_right) = (to_float(left, ty), to_float(right, ty));
+        let result = match condition {
+            Condition::Eq => left == right,
+            Condition::Ne => left != right,
+            Condition::SLt => signed_left < signed_right,
+            Condition::SLe => signed_left <= signed_right,
+            Condition::SGt => signed_left > signed_right,
+            Condition::SGe => signed_left >= signed_right,
+            Condition::ULt => left < right,
+            Condition::ULe => left <= right,
+            Condition::UGt => left > right,
+            Condition::UGe => left >= right,
+            Condition::FEq => float_left == float_right,
+            Condition::FNe => float_left != float_right,
+            Condition::FLt => float_left < float_right,
+            Condition::FLe => float_left <= float_right,
+            Condition::FGt => float_left > float_right,
+            Condition::FGe => float_left >= float_right,
+        };
+        result as u64
+    }
+
+    fn convert(op: ConvertOp, from: IrType, to: IrType, bits: u64) -> u64 {
+        match op {
+            ConvertOp::SExt => mask(signed(bits, from) as u64, to),
+            ConvertOp::ZExt | ConvertOp::Trunc => mask(bits, to),
+            ConvertOp::SIToFP => from_float(signed(bits, from) as f64, to),
+            ConvertOp::UIToFP => from_float(bits as f64, to),
+            ConvertOp::FPToSI => mask(to_float(bits, from) as i64 as u64, to),
+            ConvertOp::FPToUI => mask(to_float(bits, from) as u64, to),
+            ConvertOp::FPExt | ConvertOp::FPTrunc => from_float(to_float(bits, from), to),
+        }
+    }
+
+    fn allocate(&mut self, size: u64, alignment: u64) -> Result<u64, RuntimeError> {
+        let address = self.stack_pointer.div_ceil(alignment.max(1)) * alignment.max(1);
+        if address + size > self.stack_end {
+            return Err(RuntimeError::StackOverflow);
+        }
+        self.stack_pointer = address + size;
+        Ok(address)
+    }
+
+    fn malloc(&mut self, size: u64) -> u64 {
+        let address = self.heap_pointer.div_ceil(16) * 16;
+        self.heap_pointer = address + size.max(1);
+        self.memory.resize(self.heap_pointer as usize, 0);
+        address
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), RuntimeError> {
+        self.output
+            .write_all(bytes)
+            .map_err(|error| RuntimeError::InvalidInstruction(error.to_string()))
+    }
+
+    /// A minimal `printf`: flags `-` and `0`, a width, a precision for `%f`
+    /// and `%s`, the `h`, `hh`, `l` and `ll` modifiers and the conversions
+    /// `d i u x X o c s p f %`.
+    fn printf(&mut self, arguments: &[(IrType, u64)]) -> Result<u64, RuntimeError> {
+        let format = self.c_string(arguments[0].1)?;
+        let mut arguments = arguments[1..].iter();
+        let mut next = || {
+            arguments
+                .next()
+                .copied()
+                .ok_or_else(|| RuntimeError::InvalidInstruction("printf argument missing".into()))
+        };
+
+        let mut out = vec![];
+        let mut chars = format.iter().copied().peekable();
+        while let Some(c) = chars.next() {
+            if c != b'%' {
+                out.push(c);
+                continue;
+            }
+
+            let (mut left, mut zero) = (false, false);
+            while let Some(&flag @ (b'-' | b'0')) = chars.peek() {
+                left |= flag == b'-';
+                zero |= flag == b'0';
+                chars.next();
+            }
+            let mut width = 0;
+            while let Some(&digit @ b'0'..=b'9') = chars.peek() {
+                width = width * 10 + (digit - b'0') as usize;
+                chars.next();
+            }
+            let mut precision = None;
+            if chars.peek() == Some(&b'.') {
+                chars.next();
+                let mut value = 0;
+                while let Some(&digit @ b'0'..=b'9') = chars.peek() {
+                    value = value * 10 + (digit - b'0') as usize;
+                    chars.next();
+                }
+                precision = Some(value);
+            }
+            let mut size = 4;
+            while let Some(&modifier @ (b'h' | b'l')) = chars.peek() {
+                size = match (modifier, size) {
+                    (b'l', _) => 8,
+                    (_, 4) => 2,
+                    _ => 1,
+                };
+                chars.next();
+            }
+
+            let ty = match size {
+                1 => IrType::I8,
+                2 => IrType::I16,
+                4 => IrType::I32,
+                _ => IrType::I64,
+            };
+            let text = match chars.next() {
+                Some(b'%') => "%".to_string(),
+                Some(b'd' | b'i') => signed(next()?.1, ty).to_string(),
+                Some(b'u') => mask(next()?.1, ty).to_string(),
+                Some(b'x') => format!("{:x}", mask(next()?.1, ty)),
+                Some(b'X') => format!("{:X}", mask(next()?.1, ty)),
+                Some(b'o') => format!("{:o}", mask(next()?.1, ty)),
+                Some(b'c') => (next()?.1 as u8 as char).to_string(),
+                Some(b'p') => format!("{:#x}", next()?.1),
+                Some(b's') => {
+                    let bytes = self.c_string(next()?.1)?;
+                    let bytes = &bytes[..precision.unwrap_or(bytes.len()).min(bytes.len())];
+                    String::from_utf8_lossy(bytes).into_owned()
+                }
+                Some(b'f') => {
+                    let (ty, bits) = next()?;
+                    let value = to_float(bits, ty);
+                    if value.is_nan() {
+                        let sign = if value.is_sign_negative() { "-" } else { "" };
+                        format!("{}nan", sign)
+                    } else {
+                        format!("{:.*}", precision.unwrap_or(6), value)
+                    }
+                }
+                other => {
+                    return Err(RuntimeError::InvalidInstruction(format!(
+                        "unsupported printf conversion {:?}",
+                        other.map(|c| c as char)
+                    )));
+                }
+            };
+
+            let padding = width.saturating_sub(text.len());
+            if left {
+                out.extend(text.bytes());
+                out.extend(std::iter::repeat_n(b' ', padding));
+            } else if zero && text.starts_with('-') {
+                out.push(b'-');
+                out.extend(std::iter::repeat_n(b'0', padding));
+                out.extend(text[1..].bytes());
+            } else {
+                let fill = if zero { b'0' } else { b' ' };
+                out.extend(std::iter::repeat_n(fill, padding));
+                out.extend(text.bytes());
+            }
+        }
+
+        self.write(&out)?;
+        Ok(out.len() as u64)
+    }
+
+    /// Initializes the `va_list` at `list` with the variable arguments.
+    fn va_start(&mut self, list: u64, variadic: &[(IrType, u64)]) -> Result<(), RuntimeError> {
+        let area = self.allocate(8 * variadic.len() as u64, 16)?;
+        for (index, (_, bits)) in variadic.iter().enumerate() {
+            self.store(area + 8 * index as u64, IrType::I64, *bits)?;
+        }
+        self.store(list, IrType::I32, GP_LIMIT)?;
+        self.store(list + 4, IrType::I32, FP_LIMIT)?;
+        self.store(list + 8, IrType::I64, area)?;
+        self.store(list + 16, IrType::I64, 0)
+    }
+
+    /// Takes the next argument of type `ty` from the `va_list` at `list`:
+    /// from the register save area while the offset for its class is in
+    /// it, and from the overflow area after that.
+    fn va_arg(&mut self, list: u64, ty: IrType) -> Result<u64, RuntimeError> {
+        let (offset_address, limit, step) = if ty.is_float() {
+            (list + 4, FP_LIMIT, 16)
+        } else {
+            (list, GP_LIMIT, 8)
+        };
+        let offset = self.load(offset_address, IrType::I32)?;
+        if offset + step <= limit {
+            let save_area = self.load(list + 16, IrType::I64)?;
+            self.store(offset_address, IrType::I32, offset + step)?;
+            return self.load(save_area + offset, ty);
+        }
+        let area = self.load(list + 8, IrType::I64)?;
+        self.store(list + 8, IrType::I64, area + 8)?;
+        self.load(area, ty)
+    }
+
+    /// Calls a C library function the interpreter provides.
+    fn builtin(&mut self, name: &str, arguments: &[(IrType, u64)]) -> Result<u64, Trap> {
+        let argument = |index: usize| arguments.get(index).map_or(0, |(_, bits)| *bits);
+        match name {
+            "putchar" => {
+                self.write(&[argument(0) as u8])?;
+                Ok(mask(argument(0), IrType::I32))
+            }
+            "puts" => {
+                let mut bytes = self.c_string(argument(0))?;
+                bytes.push(b'\n');
+                self.write(&bytes)?;
+                Ok(0)
+            }
+            "printf" => Ok(self.printf(arguments)?),
+            "malloc" => Ok(self.malloc(argument(0))),
+            "calloc" => Ok(self.malloc(argument(0).wrapping_mul(argument(1)))),
+            "free" => Ok(0),
+            "exit" => Err(Trap::Exit(signed(argument(0), IrType::I32) as i32)),
+            "abort" => Err(Trap::Exit(134)),
+            _ => Err(RuntimeError::UndefinedFunction(name.to_string()).into()),
+        }
+    }
+
+    fn enter(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        function: usize,
+        arguments: Vec<(IrType, u64)>,
+        return_to: Option<Temp>,
+    ) -> Result<(), RuntimeError> {
+        if frames.len() >= MAX_FRAMES {
+            return Err(RuntimeError::StackOverflow);
+        }
+
+        let parameters = &self.program.functions[function].parameters;
+        let variadic = arguments
+            .get(parameters.len()..)
+            .unwrap_or_default()
+            .to_vec();
+        let temps = parameters
+            .iter()
+            .zip(arguments)
+            .map(|((temp, ty), (_, bits))| (*temp, mask(bits, *ty)))
+            .collect();
+        frames.push(Frame {
+            function,
+            pc: 0,
+            temps,
+            allocas: HashMap::new(),
+            stack_base: self.stack_pointer,
+            block: None,
+            previous_block: None,
+            return_to,
+            variadic,
+        });
+        Ok(())
+    }
+
+    /// Runs `function` with `arguments` to completion.
+    fn call(&mut self, function: usize, arguments: Vec<(IrType, u64)>) -> Result<u64, Trap> {
+        let mut frames = vec![];
+        self.enter(&mut frames, function, arguments, None)?;
+
+        loop {
+            let frame = frames.last_mut().unwrap();
+            let body: &'a [Instr] = &self.program.functions[frame.function].body;
+            let Some(instr) = body.get(frame.pc) else {
+                return Err(RuntimeError::InvalidInstruction(format!(
+                    "fell off the end of @{}",
+                    self.program.functions[frame.function].name
+                ))
+                .into());
+            };
+            frame.pc += 1;
+
+            let set = |frame: &mut Frame, dst: Temp, ty: IrType, bits: u64| {
+                frame.temps.insert(dst, mask(bits, ty));
+            };
+            let jump = |frame: &mut Frame, labels: &HashMap<Label, usize>, label: Label| {
+                frame.pc = labels.get(&label).copied().ok_or_else(|| {
+                    RuntimeError::InvalidInstruction(format!("jump to undefined {}", label))
+                })?;
+                Ok::<(), RuntimeError>(())
+            };
+            let labels = &self.labels[frame.function];
+
+            match instr {
+                Instr::Label(label) => {
+                    frame.previous_block = frame.block;
+                    frame.block = Some(*label);
+                }
+                Instr::Phi { .. } => {
+                    // The phis at the start of a block read their values
+                    // together, before any of them is written.
+                    let start = frame.pc - 1;
+                    let end = body[start..]
+                        .iter()
+                        .position(|instr| !matches!(instr, Instr::Phi { .. }))
+                        .map_or(body.len(), |length| start + length);
+                    let mut values = vec![];
+                    for phi in &body[start..end] {
+                        let Instr::Phi { dst, ty, incoming } = phi else {
+                            unreachable!();
+                        };
+                        let (_, value) = incoming
+                            .iter()
+                            .find(|(label, _)| Some(*label) == frame.previous_block)
+                            .ok_or_else(|| {
+                                RuntimeError::InvalidInstruction(format!(
+                                    "{} has no value for the incoming edge",
+                                    dst
+                                ))
+                            })?;
+                        values.push((*dst, *ty, self.value(frame, value, *ty)?));
+                    }
+                    for (dst, ty, bits) in values {
+                        set(frame, dst, ty, bits);
+                    }
+                    frame.pc = end;
+                }
+                Instr::Copy { dst, ty, src } => {
+                    let bits = self.value(frame, src, *ty)?;
+                    set(frame, *dst, *ty, bits);
+                }
+                Instr::Unary {
+                    dst,
+                    op,
+                    ty,
+                    operand,
+                } => {
+                    let bits = self.value(frame, operand, *ty)?;
+                    let result = match op {
+                        UnaryOp::Neg => bits.wrapping_neg(),
+                        UnaryOp::Not => !bits,
+                        UnaryOp::FNeg => from_float(-to_float(bits, *ty), *ty),
+                    };
+                    set(frame, *dst, *ty, result);
+                }
+                Instr::Binary {
+                    dst,
+                    op,
+                    ty,
+                    left,
+                    right,
+                } => {
+                    let left = self.value(frame, left, *ty)?;
+                    let right = self.value(frame, right, *ty)?;
+                    let result = self.binary(*op, *ty, left, right)?;
+                    set(frame, *dst, *ty, result);
+                }
+                Instr::Compare {
+                    dst,
+                    condition,
+                    ty,
+                    left,
+                    right,
+                } => {
+                    let left = self.value(frame, left, *ty)?;
+                    let right = self.value(frame, right, *ty)?;
+                    let result = Self::compare(*condition, *ty, left, right);
+                    set(frame, *dst, IrType::I32, result);
+                }
+                Instr::Convert {
+                    dst,
+                    op,
+                    from,
+                    to,
+                    value,
+                } => {
+                    let bits = self.value(frame, value, *from)?;
+                    set(frame, *dst, *to, Self::convert(*op, *from, *to, bits));
+                }
+                Instr::Alloca {
+                    dst,
+                    size,
+                    alignment,
+                } => {
+                    let address = match frame.allocas.get(dst) {
+                        Some(address) => *address,
+                        None => {
+                            let address = self.allocate(*size as u64, *alignment as u64)?;
+                            frame.allocas.insert(*dst, address);
+                            address
+                        }
+                    };
+                    set(frame, *dst, IrType::I64, address);
+                }
+                Instr::Load {
+                    dst, ty, address, ..
+                } => {
+                    let address = self.value(frame, address, IrType::I64)?;
+                    let bits = self.load(address, *ty)?;
+                    set(frame, *dst, *ty, bits);
+                }
+                Instr::Store {
+                    ty, value, address, ..
+                } => {
+                    let bits = self.value(frame, value, *ty)?;
+                    let address = self.value(frame, address, IrType::I64)?;
+                    self.store(address, *ty, bits)?;
+                }
+                Instr::MemCopy {
+                    destination,
+                    source,
+                    size,
+                } => {
+                    let destination = self.value(frame, destination, IrType::I64)?;
+                    let source = self.value(frame, source, IrType::I64)?;
+                    let source = self.check_access(source, *size)?;
+                    let destination = self.check_access(destination, *size)?;
+                    self.memory.copy_within(source..source + size, destination);
+                }
+                Instr::Call {
+                    dst,
+                    callee,
+                    arguments,
+                    ..
+                } => {
+                    let arguments = arguments
+                        .iter()
+                        .map(|(ty, value)| Ok((*ty, self.value(frame, value, *ty)?)))
+                        .collect::<Result<Vec<_>, RuntimeError>>()?;
+
+                    let callee = match callee {
+                        Value::Global(name) if name == VA_START => {
+                            self.va_start(arguments[0].1, &frame.variadic)?;
+                            continue;
+                        }
+                        Value::Global(name) if name == VA_ARG => {
+                            let (dst, ty) = dst.unwrap();
+                            let bits = self.va_arg(arguments[0].1, ty)?;
+                            set(frame, dst, ty, bits);
+                            continue;
+                        }
+                        Value::Global(name) if !self.functions.contains_key(name.as_str()) => {
+                            let result = self.builtin(name, &arguments)?;
+                            if let Some((dst, ty)) = dst {
+                                set(frame, *dst, *ty, result);
+                            }
+                            continue;
+                        }
+                        callee => self.value(frame, callee, IrType::I64)?,
+                    };
+
+                    let index = callee.wrapping_sub(FUNCTION_BASE) / 16;
+                    if callee < FUNCTION_BASE || index as usize >= self.program.functions.len() {
+                        return Err(
+                            RuntimeError::InvalidAccess(format!("call to {:#x}", callee)).into(),
+                        );
+                    }
+                    let return_to = dst.map(|(dst, _)| dst);
+                    self.enter(&mut frames, index as usize, arguments, return_to)?;
+                }
+                Instr::Jump(label) => jump(frame, labels, *label)?,
+                Instr::Branch {
+                    ty,
+                    condition,
+                    if_true,
+                    if_false,
+                } => {
+                    let bits = self.value(frame, condition, *ty)?;
+                    let taken = if ty.is_float() {
+                        to_float(bits, *ty) != 0.0
+                    } else {
+                        bits != 0
+                    };
+                    jump(frame, labels, if taken { *if_true } else { *if_false })?;
+                }
+                Instr::Return(value) => {
+                    let bits = match value {
+                        Some((ty, value)) => self.value(frame, value, *ty)?,
+                        None => 0,
+                    };
+                    let frame = frames.pop().unwrap();
+                    self.stack_pointer = frame.stack_base;
+
+                    match frames.last_mut() {
+                        Some(caller) => {
+                            if let Some(dst) = frame.return_to {
+                                caller.temps.insert(dst, bits);
+                            }
+                        }
+                        None => return Ok(bits),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs `main` and returns its exit status. Output of the built-in C
+/// library functions goes to `output`.
+pub fn run(program: &Program, output: &mut dyn Write) -> Result<i32, RuntimeError> {
+    let mut interpreter = Interpreter::new(program, output)?;
+    let main = *interpreter
+        .functions
+        .get("main")
+        .ok_or_else(|| RuntimeError::UndefinedFunction("main".to_string()))?;
+
+    let result = interpreter.call(main, vec![]);
+    interpreter
+        .output
+        .flush()
+        .map_err(|error| RuntimeError::InvalidInstruction(error.to_string()))?;
+    match result {
+        Ok(bits) => Ok(signed(bits, IrType::I32) as i32),
+        Err(Trap::Exit(code)) => Ok(code),
+        Err(Trap::Error(error)) => Err(error),
+    }
+}
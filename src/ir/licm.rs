@@ -0,0 +1,103 @@
+/*
+  Loop-invariant code motion on a function in SSA form. An instruction in
+  a loop is invariant when its operands are constants, are defined outside
+  the loop or are themselves invariant; invariant instructions move to the
+  loop's preheader, innermost loops first, so that what leaves an inner
+  loop can leave the enclosing one too.
+
+  In SSA form the moved definition still dominates every use, so any
+  instruction without side effects may move, even one that the loop only
+  runs on some paths, as long as it cannot trap. Loads stay, since the
+  memory may change in the loop or the address be invalid when the loop
+  does not run, and so do divisions by anything but a constant other
+  than 0 and -1.
+*/
+
+use std::collections::HashSet;
+
+use super::cfg::Cfg;
+use super::loops::{self, Loop};
+use super::passes::Analyses;
+use super::{BinaryOp, Function, Instr, Temp, Value, fold, ssa};
+
+fn can_move(instr: &Instr) -> bool {
+    match instr {
+        Instr::Binary {
+            op: BinaryOp::SDiv | BinaryOp::UDiv | BinaryOp::SRem | BinaryOp::URem,
+            ty,
+            right,
+            ..
+        } => {
+            matches!(fold::constant(right, *ty), Some(Value::Int(divisor)) if divisor != 0 && divisor != -1)
+        }
+        Instr::Copy { .. }
+        | Instr::Unary { .. }
+        | Instr::Binary { .. }
+        | Instr::Compare { .. }
+        | Instr::Convert { .. } => true,
+        _ => false,
+    }
+}
+
+/// Moves the invariant instructions of one loop to its preheader.
+fn hoist(cfg: &mut Cfg, natural_loop: &Loop) -> bool {
+    let Some(preheader) = natural_loop.preheader(cfg) else {
+        return false;
+    };
+    let mut variant: HashSet<Temp> = natural_loop
+        .blocks
+        .iter()
+        .flat_map(|&block| &cfg.blocks[block].instrs)
+        .filter_map(Instr::defined)
+        .collect();
+
+    let mut hoisted = vec![];
+    loop {
+        let count = hoisted.len();
+        for &block in &natural_loop.blocks {
+            let instrs = &mut cfg.blocks[block].instrs;
+            let mut position = 0;
+            while position < instrs.len() {
+                let instr = &instrs[position];
+                let invariant = instr
+                    .used_temps()
+                    .iter()
+                    .all(|temp| !variant.contains(temp));
+                if can_move(instr) && invariant {
+                    variant.remove(&instr.defined().unwrap());
+                    hoisted.push(instrs.remove(position));
+                } else {
+                    position += 1;
+                }
+            }
+        }
+        if hoisted.len() == count {
+            break;
+        }
+    }
+
+    let instrs = &mut cfg.blocks[preheader].instrs;
+    let terminator = instrs.len() - 1;
+    let changed = !hoisted.is_empty();
+    instrs.splice(terminator..terminator, hoisted);
+    changed
+}
+
+/// Moves loop-invariant computations out of loops in a function in SSA
+/// form. Returns whether anything changed.
+pub fn run(function: &mut Function, analyses: &mut Analyses) -> bool {
+    if !ssa::is_ssa(function) {
+        return false;
+    }
+    let mut cfg = analyses.cfg(function).clone();
+    let loops = analyses.loops(function).to_vec();
+    let mut next_temp = function.next_temp().0;
+    let (loops, mut changed) = loops::with_preheaders(&mut cfg, loops, &mut next_temp);
+    for natural_loop in loops.iter().rev() {
+        changed |= hoist(&mut cfg, natural_loop);
+    }
+    if changed {
+        function.body = cfg.to_body();
+    }
+    changed
+}
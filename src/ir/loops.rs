@@ -0,0 +1,237 @@
+/*
+  Natural loops. An edge whose target dominates its source is a back edge;
+  the loop it forms is its target (the header) plus every block that can
+  reach the source without passing through the header. Back edges to the
+  same header make up one loop.
+
+  Loop transforms want a preheader: a block outside the loop that is the
+  only way into the header from outside, and has no other successor, so
+  code placed there runs exactly once before the loop is entered. In SSA
+  form, a basic induction variable is a phi in the header that starts at
+  some value from the preheader and grows by a constant on the back edge.
+*/
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::cfg::{BasicBlock, Cfg};
+use super::dominators::DominatorTree;
+use super::{BinaryOp, Instr, IrType, Label, Temp, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub header: usize,
+    /// Sources of the back edges.
+    pub latches: Vec<usize>,
+    pub blocks: BTreeSet<usize>,
+}
+
+impl Loop {
+    /// Blocks outside the loop that are targets of edges leaving it.
+    pub fn exits(&self, cfg: &Cfg) -> BTreeSet<usize> {
+        self.blocks
+            .iter()
+            .flat_map(|&block| cfg.successors[block].iter().copied())
+            .filter(|successor| !self.blocks.contains(successor))
+            .collect()
+    }
+
+    /// The loop's preheader, if it has one.
+    pub fn preheader(&self, cfg: &Cfg) -> Option<usize> {
+        match self.entries(cfg)[..] {
+            [entry] if cfg.successors[entry].len() == 1 => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// Predecessors of the header outside the loop.
+    fn entries(&self, cfg: &Cfg) -> Vec<usize> {
+        cfg.predecessors[self.header]
+            .iter()
+            .copied()
+            .filter(|predecessor| !self.blocks.contains(predecessor))
+            .collect()
+    }
+}
+
+/// A header phi that starts at `init` and grows by `step` per iteration.
+pub struct Induction {
+    pub ty: IrType,
+    pub init: Value,
+    pub step: i64,
+}
+
+/// The basic induction variables of a loop entered only from the block
+/// labeled `entry`, by phi.
+pub fn inductions(cfg: &Cfg, natural_loop: &Loop, entry: Label) -> HashMap<Temp, Induction> {
+    let definitions: HashMap<Temp, &Instr> = natural_loop
+        .blocks
+        .iter()
+        .flat_map(|&block| &cfg.blocks[block].instrs)
+        .filter_map(|instr| Some((instr.defined()?, instr)))
+        .collect();
+
+    let mut variables = HashMap::new();
+    for instr in &cfg.blocks[natural_loop.header].instrs {
+        let Instr::Phi { dst, ty, incoming } = instr else {
+            break;
+        };
+        let (init, next) = match &incoming[..] {
+            [(label, init), (_, Value::Temp(next))] if *label == entry => (init, next),
+            [(_, Value::Temp(next)), (label, init)] if *label == entry => (init, next),
+            _ => continue,
+        };
+        let step = match definitions.get(next) {
+            Some(Instr::Binary {
+                op,
+                ty: step_type,
+                left,
+                right,
+                ..
+            }) if step_type == ty && !ty.is_float() => match (op, left, right) {
+                (BinaryOp::Add, Value::Temp(temp), Value::Int(step))
+                | (BinaryOp::Add, Value::Int(step), Value::Temp(temp))
+                    if temp == dst =>
+                {
+                    *step
+                }
+                (BinaryOp::Sub, Value::Temp(temp), Value::Int(step)) if temp == dst => {
+                    step.wrapping_neg()
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        variables.insert(
+            *dst,
+            Induction {
+                ty: *ty,
+                init: init.clone(),
+                step,
+            },
+        );
+    }
+    variables
+}
+
+/// Adds a preheader to every loop that is entered from outside but lacks
+/// one. The new blocks go at the end, so the loops and dominators have to
+/// be found again if any were added. Several entries into a header are
+/// merged by phis in the preheader, numbered from `next_temp`.
+pub fn insert_preheaders(cfg: &mut Cfg, loops: &[Loop], next_temp: &mut u32) -> bool {
+    let mut changed = false;
+    for natural_loop in loops {
+        let entries = natural_loop.entries(cfg);
+        if entries.is_empty() || natural_loop.preheader(cfg).is_some() {
+            continue;
+        }
+
+        let header = cfg.blocks[natural_loop.header].label;
+        let preheader = cfg.fresh_label();
+        let labels: Vec<_> = entries
+            .iter()
+            .map(|&entry| cfg.blocks[entry].label)
+            .collect();
+        for &entry in &entries {
+            match cfg.blocks[entry].instrs.last_mut() {
+                Some(Instr::Jump(target)) => *target = preheader,
+                Some(Instr::Branch {
+                    if_true, if_false, ..
+                }) => {
+                    for target in [if_true, if_false] {
+                        if *target == header {
+                            *target = preheader;
+                        }
+                    }
+                }
+                _ => unreachable!("an entry that does not branch to the header"),
+            }
+        }
+
+        let mut instrs = vec![];
+        for instr in &mut cfg.blocks[natural_loop.header].instrs {
+            let Instr::Phi { ty, incoming, .. } = instr else {
+                break;
+            };
+            let (outside, inside): (Vec<_>, Vec<_>) = std::mem::take(incoming)
+                .into_iter()
+                .partition(|(label, _)| labels.contains(label));
+            let value = match &outside[..] {
+                [(_, value)] => value.clone(),
+                _ => {
+                    let dst = Temp(*next_temp);
+                    *next_temp += 1;
+                    instrs.push(Instr::Phi {
+                        dst,
+                        ty: *ty,
+                        incoming: outside,
+                    });
+                    Value::Temp(dst)
+                }
+            };
+            *incoming = inside;
+            incoming.push((preheader, value));
+        }
+        instrs.push(Instr::Jump(header));
+        cfg.blocks.push(BasicBlock {
+            label: preheader,
+            instrs,
+        });
+        cfg.compute_edges();
+        changed = true;
+    }
+    changed
+}
+
+/// The natural loops of the graph, outermost first: a loop nested in another
+/// always comes after it.
+pub fn find_loops(cfg: &Cfg, dominators: &DominatorTree) -> Vec<Loop> {
+    let mut loops: Vec<Loop> = vec![];
+
+    for block in cfg.reverse_postorder() {
+        let latches: Vec<usize> = cfg.predecessors[block]
+            .iter()
+            .copied()
+            .filter(|&predecessor| dominators.dominates(block, predecessor))
+            .collect();
+        if latches.is_empty() {
+            continue;
+        }
+
+        let mut blocks = BTreeSet::from([block]);
+        let mut worklist = latches.clone();
+        while let Some(current) = worklist.pop() {
+            if blocks.insert(current) {
+                worklist.extend(cfg.predecessors[current].iter().copied());
+            }
+        }
+
+        loops.push(Loop {
+            header: block,
+            latches,
+            blocks,
+        });
+    }
+
+    loops.sort_by_key(|natural_loop| std::cmp::Reverse(natural_loop.blocks.len()));
+    loops
+}
+
+/// How many loops each block is nested in.
+pub fn loop_depths(cfg: &Cfg, loops: &[Loop]) -> Vec<usize> {
+    let mut depths = vec![0; cfg.blocks.len()];
+    for natural_loop in loops {
+        for &block in &natural_loop.blocks {
+            depths[block] += 1;
+        }
+    }
+    depths
+}
+
+/// Gives each of the loops of the graph a preheader where it can. Returns
+/// the loops of the resulting graph and whether it changed.
+pub fn with_preheaders(cfg: &mut Cfg, loops: Vec<Loop>, next_temp: &mut u32) -> (Vec<Loop>, bool) {
+    if !insert_preheaders(cfg, &loops, next_temp) {
+        return (loops, false);
+    }
+    (find_loops(cfg, &DominatorTree::build(cfg)), true)
+}
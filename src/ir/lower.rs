@@ -0,0 +1,1905 @@
+/*
+  Lowers a checked program to IR. Types are recomputed while lowering: each
+  expression yields its value together with its C type, so conversions can
+  be made explicit.
+
+  Structures are handled through their addresses: a struct-valued
+  expression yields the address of an object holding the value, struct
+  arguments are passed as the address of a copy made by the caller, and a
+  function returning a struct receives the address to store it at as a
+  hidden first parameter and returns that address. Functions and calls
+  record which of their pointers stand for structs, and the layout of
+  those, for the calling convention.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    Aggregate, BinaryOp, ByValue, Condition, ConvertOp, Function, Global, GlobalInit, Inlining,
+    Instr, IrType, Label, Program, Temp, UnaryOp, VA_ARG, VA_START, Value,
+};
+use crate::ast::{
+    self, BinaryOperator, DataType, Expr, Initializer, Literal, QualifiedType, StorageClass,
+    TypeQualifiers, TypeTable, UnaryOperator,
+};
+use crate::semantic_checker::initializer;
+
+#[derive(Debug, Clone)]
+enum Binding {
+    /// A stack slot, or for struct parameters the address of the caller's
+    /// copy.
+    Local(Temp, QualifiedType),
+    Global(String, QualifiedType),
+}
+
+/// An rvalue and its C type. Struct and array values are addresses.
+#[derive(Debug, Clone)]
+struct Operand {
+    value: Value,
+    data_type: DataType,
+}
+
+/// An lvalue: the address of an object of the given type.
+#[derive(Debug, Clone)]
+struct Place {
+    address: Value,
+    data_type: QualifiedType,
+}
+
+struct SwitchCases {
+    ty: IrType,
+    data_type: DataType,
+    cases: Vec<(i64, Label)>,
+    default: Option<Label>,
+}
+
+#[derive(Default)]
+struct FunctionState {
+    body: Vec<Instr>,
+    /// Stack slots, hoisted to the start of the function.
+    allocas: Vec<Instr>,
+    next_temp: u32,
+    next_label: u32,
+    return_type: Option<QualifiedType>,
+    /// Where a struct return value is stored.
+    return_slot: Option<Temp>,
+    breaks: Vec<Label>,
+    continues: Vec<Label>,
+    switches: Vec<SwitchCases>,
+    labels: HashMap<String, Label>,
+}
+
+struct Lowerer<'a> {
+    types: &'a TypeTable,
+    program: Program,
+    scopes: Vec<HashMap<String, Binding>>,
+    /// File-scope names with internal linkage.
+    internal: HashSet<String>,
+    /// File-scope objects that have a definition with an initializer.
+    defined: HashSet<String>,
+    /// Objects only declared tentatively so far, in declaration order.
+    tentative: Vec<(String, QualifiedType)>,
+    static_count: usize,
+    string_count: usize,
+    function: FunctionState,
+}
+
+pub fn ir_type(data_type: &DataType) -> Option<IrType> {
+    match data_type {
+        DataType::Bool | DataType::Char | DataType::SChar | DataType::UChar => Some(IrType::I8),
+        DataType::Short | DataType::UShort => Some(IrType::I16),
+        DataType::Int | DataType::UInt => Some(IrType::I32),
+        DataType::Long
+        | DataType::ULong
+        | DataType::LongLong
+        | DataType::ULongLong
+        | DataType::Pointer(_) => Some(IrType::I64),
+        DataType::Float => Some(IrType::F32),
+        DataType::Double => Some(IrType::F64),
+        _ => None,
+    }
+}
+
+/// `noinline` wins over `always_inline`, as in GCC, which warns about the
+/// conflict.
+fn inlining(attributes: &ast::FunctionAttributes) -> Inlining {
+    if attributes.noinline {
+        Inlining::Never
+    } else if attributes.always_inline {
+        Inlining::Always
+    } else if attributes.is_inline {
+        Inlining::Hint
+    } else {
+        Inlining::Default
+    }
+}
+
+fn merge_qualifiers(left: &TypeQualifiers, right: &TypeQualifiers) -> TypeQualifiers {
+    TypeQualifiers {
+        is_const: left.is_const || right.is_const,
+        is_volatile: left.is_volatile || right.is_volatile,
+        is_restrict: left.is_restrict || right.is_restrict,
+    }
+}
+
+fn is_null_pointer_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Literal::Integer(literal)) => literal.value == 0,
+        Expr::Cast(cast) => {
+            cast.target_type.data_type.is_pointer() && is_null_pointer_constant(&cast.value)
+        }
+        _ => false,
+    }
+}
+
+/// Evaluates an arithmetic constant expression as a double, for static
+/// initializers of floating type.
+fn float_constant(expr: &Expr, types: &TypeTable) -> Option<f64> {
+    match expr {
+        Expr::Literal(Literal::Float(literal)) => Some(literal.value),
+        Expr::Unary(unary) if unary.operator == UnaryOperator::Negate => {
+            Some(-float_constant(&unary.operand, types)?)
+        }
+        Expr::Unary(unary) if unary.operator == UnaryOperator::Plus => {
+            float_constant(&unary.operand, types)
+        }
+        Expr::Binary(binary) => {
+            let left = float_constant(&binary.left, types)?;
+            let right = float_constant(&binary.right, types)?;
+            match binary.operator {
+                BinaryOperator::Add => Some(left + right),
+                BinaryOperator::Subtract => Some(left - right),
+                BinaryOperator::Multiply => Some(left * right),
+                BinaryOperator::Divide => Some(left / right),
+                _ => None,
+            }
+        }
+        Expr::Cast(cast) if cast.target_type.data_type == DataType::Float => {
+            Some(float_constant(&cast.value, types)? as f32 as f64)
+        }
+        Expr::Cast(cast) if cast.target_type.data_type.is_floating() => {
+            float_constant(&cast.value, types)
+        }
+        _ => {
+            let (value, data_type) = expr.evaluate_typed_constant(types)?;
+            Some(if data_type.is_signed() {
+                value as i64 as f64
+            } else {
+                value as u64 as f64
+            })
+        }
+    }
+}
+
+impl<'a> Lowerer<'a> {
+    fn new(types: &'a TypeTable) -> Self {
+        Lowerer {
+            types,
+            program: Program::default(),
+            scopes: vec![HashMap::new()],
+            internal: HashSet::new(),
+            defined: HashSet::new(),
+            tentative: vec![],
+            static_count: 0,
+            string_count: 0,
+            function: FunctionState::default(),
+        }
+    }
+
+    fn size_of(&self, data_type: &DataType) -> usize {
+        self.types.size_of(data_type).unwrap_or(0)
+    }
+
+    fn alignment_of(&self, data_type: &DataType) -> usize {
+        self.types.alignment_of(data_type).unwrap_or(1)
+    }
+
+    /// The layout of a struct passed or returned by value.
+    fn aggregate(&self, data_type: &DataType) -> Aggregate {
+        let mut members = vec![];
+        self.scalars(data_type, 0, &mut members);
+        Aggregate {
+            size: self.size_of(data_type),
+            alignment: self.alignment_of(data_type),
+            members,
+        }
+    }
+
+    fn scalars(&self, data_type: &DataType, offset: usize, members: &mut Vec<(usize, IrType)>) {
+        match data_type {
+            DataType::Struct(name) => {
+                let definition = &self.types.structs[name];
+                let layout = self.types.struct_layout(name).unwrap();
+                for (member, member_offset) in
+                    definition.members.iter().flatten().zip(layout.offsets)
+                {
+                    self.scalars(&member.data_type.data_type, offset + member_offset, members);
+                }
+            }
+            DataType::Array(element, length) => {
+                let size = self.size_of(&element.data_type);
+                for index in 0..length.unwrap_or(0) {
+                    self.scalars(&element.data_type, offset + index * size, members);
+                }
+            }
+            scalar => members.extend(ir_type(scalar).map(|ty| (offset, ty))),
+        }
+    }
+
+    fn new_temp(&mut self) -> Temp {
+        let temp = Temp(self.function.next_temp);
+        self.function.next_temp += 1;
+        temp
+    }
+
+    fn new_label(&mut self) -> Label {
+        let label = Label(self.function.next_label);
+        self.function.next_label += 1;
+        label
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        self.function.body.push(instr);
+    }
+
+    fn emit_label(&mut self, label: Label) {
+        self.emit(Instr::Label(label));
+    }
+
+    fn lookup(&self, name: &str) -> Binding {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .unwrap_or_else(|| panic!("'{}' was not declared", name))
+    }
+
+    fn bind(&mut self, name: &str, binding: Binding) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), binding);
+    }
+
+    fn alloca(&mut self, data_type: &DataType) -> Temp {
+        let dst = self.new_temp();
+        self.function.allocas.push(Instr::Alloca {
+            dst,
+            size: self.size_of(data_type).max(1),
+            alignment: self.alignment_of(data_type),
+        });
+        dst
+    }
+
+    /// A stack slot holding one value of type `ty`.
+    fn slot(&mut self, ty: IrType) -> Temp {
+        let dst = self.new_temp();
+        self.function.allocas.push(Instr::Alloca {
+            dst,
+            size: ty.size(),
+            alignment: ty.size(),
+        });
+        dst
+    }
+
+    fn binary(&mut self, op: BinaryOp, ty: IrType, left: Value, right: Value) -> Value {
+        let dst = self.new_temp();
+        self.emit(Instr::Binary {
+            dst,
+            op,
+            ty,
+            left,
+            right,
+        });
+        Value::Temp(dst)
+    }
+
+    fn compare(&mut self, condition: Condition, ty: IrType, left: Value, right: Value) -> Value {
+        let dst = self.new_temp();
+        self.emit(Instr::Compare {
+            dst,
+            condition,
+            ty,
+            left,
+            right,
+        });
+        Value::Temp(dst)
+    }
+
+    fn convert_value(&mut self, op: ConvertOp, from: IrType, to: IrType, value: Value) -> Value {
+        let dst = self.new_temp();
+        self.emit(Instr::Convert {
+            dst,
+            op,
+            from,
+            to,
+            value,
+        });
+        Value::Temp(dst)
+    }
+
+    fn load(&mut self, ty: IrType, address: Value, volatile: bool) -> Value {
+        let dst = self.new_temp();
+        self.emit(Instr::Load {
+            dst,
+            ty,
+            address,
+            volatile,
+        });
+        Value::Temp(dst)
+    }
+
+    /// `address + offset` in bytes.
+    fn offset_address(&mut self, address: Value, offset: i64) -> Value {
+        if offset == 0 {
+            return address;
+        }
+        self.binary(BinaryOp::Add, IrType::I64, address, Value::Int(offset))
+    }
+
+    fn zero(ty: IrType) -> Value {
+        if ty.is_float() {
+            Value::Float(0.0)
+        } else {
+            Value::Int(0)
+        }
+    }
+
+    // Types
+
+    /// The type of an expression before decay, without emitting any code.
+    fn type_of(&mut self, expr: &Expr) -> DataType {
+        if let Expr::Literal(Literal::String(literal)) = expr {
+            return DataType::Array(
+                Box::new(QualifiedType::new(DataType::Char)),
+                Some(literal.value.len() + 1),
+            );
+        }
+
+        let body = self.function.body.len();
+        let allocas = self.function.allocas.len();
+        let globals = self.program.globals.len();
+        let string_count = self.string_count;
+
+        let data_type = self.lower_value(expr).data_type;
+
+        self.function.body.truncate(body);
+        self.function.allocas.truncate(allocas);
+        self.program.globals.truncate(globals);
+        self.string_count = string_count;
+        data_type
+    }
+
+    // Conversions
+
+    /// Converts an rvalue to `to` following C11 6.3.
+    fn convert(&mut self, operand: Operand, to: &DataType) -> Operand {
+        let from = &operand.data_type;
+        let (from_ty, to_ty) = match (ir_type(from), ir_type(to)) {
+            (Some(from_ty), Some(to_ty)) => (from_ty, to_ty),
+            _ => {
+                return Operand {
+                    value: operand.value,
+                    data_type: to.clone(),
+                };
+            }
+        };
+
+        let value = operand.value;
+        let value = if *to == DataType::Bool {
+            if *from == DataType::Bool {
+                value
+            } else {
+                let truth = self.compare(
+                    if from_ty.is_float() {
+                        Condition::FNe
+                    } else {
+                        Condition::Ne
+                    },
+                    from_ty,
+                    value,
+                    Self::zero(from_ty),
+                );
+                self.convert_value(ConvertOp::Trunc, IrType::I32, IrType::I8, truth)
+            }
+        } else if from_ty.is_float() && to_ty.is_float() {
+            match from_ty.size().cmp(&to_ty.size()) {
+                std::cmp::Ordering::Less => {
+                    self.convert_value(ConvertOp::FPExt, from_ty, to_ty, value)
+                }
+                std::cmp::Ordering::Greater => {
+                    self.convert_value(ConvertOp::FPTrunc, from_ty, to_ty, value)
+                }
+                std::cmp::Ordering::Equal => value,
+            }
+        } else if from_ty.is_float() {
+            let op = if to.is_signed() || to.is_pointer() {
+                ConvertOp::FPToSI
+            } else {
+                ConvertOp::FPToUI
+            };
+            self.convert_value(op, from_ty, to_ty, value)
+        } else if to_ty.is_float() {
+            let op = if from.is_signed() {
+                ConvertOp::SIToFP
+            } else {
+                ConvertOp::UIToFP
+            };
+            self.convert_value(op, from_ty, to_ty, value)
+        } else {
+            match from_ty.size().cmp(&to_ty.size()) {
+                std::cmp::Ordering::Less => {
+                    let op = if from.is_signed() {
+                        ConvertOp::SExt
+                    } else {
+                        ConvertOp::ZExt
+                    };
+                    self.convert_value(op, from_ty, to_ty, value)
+                }
+                std::cmp::Ordering::Greater => {
+                    self.convert_value(ConvertOp::Trunc, from_ty, to_ty, value)
+                }
+                std::cmp::Ordering::Equal => value,
+            }
+        };
+
+        Operand {
+            value,
+            data_type: to.clone(),
+        }
+    }
+
+    /// Arrays decay to pointers to their first element and functions to
+    /// pointers to themselves; both already are addresses.
+    fn decay(operand: Operand) -> Operand {
+        let data_type = match operand.data_type {
+            DataType::Array(element, _) => DataType::Pointer(element),
+            data_type @ DataType::Function(_) => {
+                DataType::Pointer(Box::new(QualifiedType::new(data_type)))
+            }
+            data_type => data_type,
+        };
+
+        Operand {
+            value: operand.value,
+            data_type,
+        }
+    }
+
+    fn promote(&mut self, operand: Operand) -> Operand {
+        let promoted = operand.data_type.promoted();
+        self.convert(operand, &promoted)
+    }
+
+    // Expressions
+
+    fn lower_expr(&mut self, expr: &Expr) -> Operand {
+        let operand = self.lower_value(expr);
+        Self::decay(operand)
+    }
+
+    fn lower_expr_as(&mut self, expr: &Expr, data_type: &DataType) -> Value {
+        let operand = self.lower_expr(expr);
+        self.convert(operand, data_type).value
+    }
+
+    /// Reads the object at `place`; aggregates and functions stay addresses.
+    fn read(&mut self, place: Place) -> Operand {
+        let data_type = place.data_type.data_type;
+        let value = match ir_type(&data_type) {
+            Some(ty) => self.load(ty, place.address, place.data_type.qualifiers.is_volatile),
+            None => place.address,
+        };
+
+        Operand { value, data_type }
+    }
+
+    fn store(&mut self, place: &Place, value: Value) {
+        match ir_type(&place.data_type.data_type) {
+            Some(ty) => self.emit(Instr::Store {
+                ty,
+                value,
+                address: place.address.clone(),
+                volatile: place.data_type.qualifiers.is_volatile,
+            }),
+            None => {
+                let size = self.size_of(&place.data_type.data_type);
+                self.emit(Instr::MemCopy {
+                    destination: place.address.clone(),
+                    source: value,
+                    size,
+                });
+            }
+        }
+    }
+
+    fn string_literal(&mut self, value: &[u8]) -> String {
+        let name = format!(".str.{}", self.string_count);
+        self.string_count += 1;
+
+        let mut bytes = value.to_vec();
+        bytes.push(0);
+        self.program.globals.push(Global {
+            name: name.clone(),
+            is_global: false,
+            is_constant: true,
+            alignment: 1,
+            init: vec![GlobalInit::Bytes(bytes)],
+        });
+
+        name
+    }
+
+    /// Expressions that designate objects.
+    fn is_place(expr: &Expr) -> bool {
+        match expr {
+            Expr::Identifier(_) | Expr::Index(_) => true,
+            Expr::Literal(Literal::String(_)) => true,
+            Expr::Unary(unary) => unary.operator == UnaryOperator::Dereference,
+            Expr::Member(member) => member.is_arrow || Self::is_place(&member.object),
+            _ => false,
+        }
+    }
+
+    fn lower_place(&mut self, expr: &Expr) -> Place {
+        match expr {
+            Expr::Identifier(identifier) => match self.lookup(&identifier.name) {
+                Binding::Local(address, data_type) => Place {
+                    address: Value::Temp(address),
+                    data_type,
+                },
+                Binding::Global(symbol, data_type) => Place {
+                    address: Value::Global(symbol),
+                    data_type,
+                },
+            },
+            Expr::Literal(Literal::String(literal)) => {
+                let name = self.string_literal(&literal.value);
+                Place {
+                    address: Value::Global(name),
+                    data_type: QualifiedType::new(DataType::Array(
+                        Box::new(QualifiedType::new(DataType::Char)),
+                        Some(literal.value.len() + 1),
+                    )),
+                }
+            }
+            Expr::Unary(unary) if unary.operator == UnaryOperator::Dereference => {
+                let pointer = self.lower_expr(&unary.operand);
+                Place {
+                    address: pointer.value,
+                    data_type: pointer.data_type.pointee().unwrap().clone(),
+                }
+            }
+            Expr::Index(index) => {
+                let array = self.lower_expr(&index.array);
+                let subscript = self.lower_expr(&index.index);
+                let (pointer, subscript) = if array.data_type.is_pointer() {
+                    (array, subscript)
+                } else {
+                    (subscript, array)
+                };
+
+                let element = pointer.data_type.pointee().unwrap().clone();
+                let address =
+                    self.pointer_offset(pointer.value, &element.data_type, subscript, false);
+                Place {
+                    address,
+                    data_type: element,
+                }
+            }
+            Expr::Member(member) => {
+                let object = if member.is_arrow {
+                    let pointer = self.lower_expr(&member.object);
+                    Place {
+                        address: pointer.value,
+                        data_type: pointer.data_type.pointee().unwrap().clone(),
+                    }
+                } else if Self::is_place(&member.object) {
+                    self.lower_place(&member.object)
+                } else {
+                    let operand = self.lower_value(&member.object);
+                    Place {
+                        address: operand.value,
+                        data_type: QualifiedType::new(operand.data_type),
+                    }
+                };
+
+                let DataType::Struct(name) = &object.data_type.data_type else {
+                    unreachable!("member access on a non-structure");
+                };
+                let (offset, member_type) = self.types.member(name, &member.member).unwrap();
+                let address = self.offset_address(object.address, offset as i64);
+                Place {
+                    address,
+                    data_type: QualifiedType {
+                        qualifiers: merge_qualifiers(
+                            &object.data_type.qualifiers,
+                            &member_type.qualifiers,
+                        ),
+                        data_type: member_type.data_type,
+                    },
+                }
+            }
+            _ => {
+                let operand = self.lower_value(expr);
+                Place {
+                    address: operand.value,
+                    data_type: QualifiedType::new(operand.data_type),
+                }
+            }
+        }
+    }
+
+    /// `pointer + index * sizeof(element)`, or minus when `subtract`.
+    fn pointer_offset(
+        &mut self,
+        pointer: Value,
+        element: &DataType,
+        index: Operand,
+        subtract: bool,
+    ) -> Value {
+        let index = self.convert(index, &DataType::Long).value;
+        let size = self.size_of(element) as i64;
+        let scaled = match index {
+            Value::Int(index) => Value::Int(index.wrapping_mul(size)),
+            index if size == 1 => index,
+            index => self.binary(BinaryOp::Mul, IrType::I64, index, Value::Int(size)),
+        };
+
+        let op = if subtract {
+            BinaryOp::Sub
+        } else {
+            BinaryOp::Add
+        };
+        self.binary(op, IrType::I64, pointer, scaled)
+    }
+
+    /// Lowers an expression to its value without array or function decay.
+    fn lower_value(&mut self, expr: &Expr) -> Operand {
+        match expr {
+            Expr::Literal(literal) => match literal {
+                Literal::Integer(literal) => Operand {
+                    value: Value::Int(literal.value as i64),
+                    data_type: literal.data_type.clone(),
+                },
+                Literal::Char(literal) => Operand {
+                    value: Value::Int(DataType::Char.wrap_integer(literal.value as i128) as i64),
+                    data_type: DataType::Int,
+                },
+                Literal::Boolean(literal) => Operand {
+                    value: Value::Int(literal.value as i64),
+                    data_type: DataType::Bool,
+                },
+                Literal::Float(literal) => Operand {
+                    value: Value::Float(literal.value),
+                    data_type: literal.data_type.clone(),
+                },
+                Literal::String(_) => {
+                    let place = self.lower_place(expr);
+                    self.read(place)
+                }
+            },
+            Expr::Identifier(_) | Expr::Index(_) | Expr::Member(_) => {
+                let place = self.lower_place(expr);
+                self.read(place)
+            }
+            Expr::Unary(unary) => self.lower_unary(unary),
+            Expr::Binary(binary) => self.lower_binary(binary),
+            Expr::Assignment(assignment) => self.lower_assignment(assignment),
+            Expr::Call(call) => self.lower_call(call),
+            Expr::Cast(cast) => {
+                let operand = self.lower_expr(&cast.value);
+                if cast.target_type.data_type == DataType::Void {
+                    return Operand {
+                        value: Value::Int(0),
+                        data_type: DataType::Void,
+                    };
+                }
+                self.convert(operand, &cast.target_type.data_type)
+            }
+            Expr::SizeofExpr(operand) => {
+                let data_type = self.type_of(operand);
+                Operand {
+                    value: Value::Int(self.size_of(&data_type) as i64),
+                    data_type: DataType::ULong,
+                }
+            }
+            Expr::SizeofType(data_type) => Operand {
+                value: Value::Int(self.size_of(&data_type.data_type) as i64),
+                data_type: DataType::ULong,
+            },
+            Expr::AlignofType(data_type) => Operand {
+                value: Value::Int(self.alignment_of(&data_type.data_type) as i64),
+                data_type: DataType::ULong,
+            },
+            Expr::Conditional(conditional) => self.lower_conditional(conditional),
+            Expr::Comma(comma) => {
+                self.lower_value(&comma.left);
+                self.lower_value(&comma.right)
+            }
+            Expr::Stdarg(stdarg) => self.lower_stdarg(stdarg),
+        }
+    }
+
+    /// `va_start` and `va_arg` become calls to builtins the backend
+    /// expands, since only it knows where the arguments were passed.
+    fn lower_stdarg(&mut self, stdarg: &ast::Stdarg) -> Operand {
+        let void = Operand {
+            value: Value::Int(0),
+            data_type: DataType::Void,
+        };
+        match stdarg {
+            ast::Stdarg::Start(list, _) => {
+                let list = self.lower_expr(list).value;
+                self.emit(Instr::Call {
+                    dst: None,
+                    callee: Value::Global(VA_START.to_string()),
+                    arguments: vec![(IrType::I64, list)],
+                    by_value: ByValue::default(),
+                });
+                void
+            }
+            ast::Stdarg::Arg(list, data_type) => {
+                let list = self.lower_expr(list).value;
+                let ty = ir_type(&data_type.data_type).unwrap();
+                let dst = self.new_temp();
+                self.emit(Instr::Call {
+                    dst: Some((dst, ty)),
+                    callee: Value::Global(VA_ARG.to_string()),
+                    arguments: vec![(IrType::I64, list)],
+                    by_value: ByValue::default(),
+                });
+                Operand {
+                    value: Value::Temp(dst),
+                    data_type: data_type.data_type.clone(),
+                }
+            }
+            ast::Stdarg::End(list) => {
+                self.lower_expr(list);
+                void
+            }
+            ast::Stdarg::Copy(destination, source) => {
+                let destination = self.lower_expr(destination).value;
+                let source = self.lower_expr(source).value;
+                let size = self.size_of(&DataType::Struct(ast::VA_LIST_TAG.to_string()));
+                self.emit(Instr::MemCopy {
+                    destination,
+                    source,
+                    size,
+                });
+                void
+            }
+        }
+    }
+
+    fn lower_unary(&mut self, unary: &ast::UnaryExpr) -> Operand {
+        match unary.operator {
+            UnaryOperator::Negate | UnaryOperator::BitwiseNot => {
+                let operand = self.lower_expr(&unary.operand);
+                let operand = self.promote(operand);
+                let ty = ir_type(&operand.data_type).unwrap();
+                let op = match unary.operator {
+                    UnaryOperator::Negate if ty.is_float() => UnaryOp::FNeg,
+                    UnaryOperator::Negate => UnaryOp::Neg,
+                    _ => UnaryOp::Not,
+                };
+
+                let dst = self.new_temp();
+                self.emit(Instr::Unary {
+                    dst,
+                    op,
+                    ty,
+                    operand: operand.value,
+                });
+                Operand {
+                    value: Value::Temp(dst),
+                    data_type: operand.data_type,
+                }
+            }
+            UnaryOperator::Plus => {
+                let operand = self.lower_expr(&unary.operand);
+                self.promote(operand)
+            }
+            UnaryOperator::LogicalNot => {
+                let operand = self.lower_expr(&unary.operand);
+                let ty = ir_type(&operand.data_type).unwrap();
+                let condition = if ty.is_float() {
+                    Condition::FEq
+                } else {
+                    Condition::Eq
+                };
+                Operand {
+                    value: self.compare(condition, ty, operand.value, Self::zero(ty)),
+                    data_type: DataType::Int,
+                }
+            }
+            UnaryOperator::Dereference => {
+                let place = self.lower_place(&Expr::Unary(unary.clone()));
+                self.read(place)
+            }
+            UnaryOperator::AddressOf => {
+                // `&*p` is `p` without an access.
+                if let Expr::Unary(inner) = unary.operand.as_ref()
+                    && inner.operator == UnaryOperator::Dereference
+                {
+                    return self.lower_expr(&inner.operand);
+                }
+
+                let place = self.lower_place(&unary.operand);
+                Operand {
+                    value: place.address,
+                    data_type: DataType::Pointer(Box::new(place.data_type)),
+                }
+            }
+            UnaryOperator::PreIncrement
+            | UnaryOperator::PreDecrement
+            | UnaryOperator::PostIncrement
+            | UnaryOperator::PostDecrement => {
+                let place = self.lower_place(&unary.operand);
+                let old = self.read(place.clone());
+                let is_increment = matches!(
+                    unary.operator,
+                    UnaryOperator::PreIncrement | UnaryOperator::PostIncrement
+                );
+
+                let one = Operand {
+                    value: Value::Int(1),
+                    data_type: DataType::Int,
+                };
+                let operator = if is_increment {
+                    BinaryOperator::Add
+                } else {
+                    BinaryOperator::Subtract
+                };
+                let new = self.arithmetic(operator, old.clone(), one);
+                let new = self.convert(new, &place.data_type.data_type);
+                self.store(&place, new.value.clone());
+
+                match unary.operator {
+                    UnaryOperator::PreIncrement | UnaryOperator::PreDecrement => new,
+                    _ => old,
+                }
+            }
+        }
+    }
+
+    fn lower_binary(&mut self, binary: &ast::BinaryExpr) -> Operand {
+        match binary.operator {
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                let result = self.slot(IrType::I32);
+                let (if_true, if_false, end) =
+                    (self.new_label(), self.new_label(), self.new_label());
+
+                self.lower_condition(&Expr::Binary(binary.clone()), if_true, if_false);
+                for (label, value) in [(if_true, 1), (if_false, 0)] {
+                    self.emit_label(label);
+                    self.emit(Instr::Store {
+                        ty: IrType::I32,
+                        value: Value::Int(value),
+                        address: Value::Temp(result),
+                        volatile: false,
+                    });
+                    self.emit(Instr::Jump(end));
+                }
+                self.emit_label(end);
+
+                Operand {
+                    value: self.load(IrType::I32, Value::Temp(result), false),
+                    data_type: DataType::Int,
+                }
+            }
+            operator => {
+                let left = self.lower_expr(&binary.left);
+                let right = self.lower_expr(&binary.right);
+                if operator.is_comparison() {
+                    self.comparison(operator, left, right)
+                } else {
+                    self.arithmetic(operator, left, right)
+                }
+            }
+        }
+    }
+
+    fn comparison(&mut self, operator: BinaryOperator, left: Operand, right: Operand) -> Operand {
+        let data_type = if left.data_type.is_pointer() || right.data_type.is_pointer() {
+            DataType::ULong
+        } else {
+            left.data_type.common_type(&right.data_type)
+        };
+        let left = self.convert(left, &data_type).value;
+        let right = self.convert(right, &data_type).value;
+        let ty = ir_type(&data_type).unwrap();
+
+        let condition = match (operator, ty.is_float(), data_type.is_signed()) {
+            (BinaryOperator::Equal, true, _) => Condition::FEq,
+            (BinaryOperator::NotEqual, true, _) => Condition::FNe,
+            (BinaryOperator::Less, true, _) => Condition::FLt,
+            (BinaryOperator::LessEqual, true, _) => Condition::FLe,
+            (BinaryOperator::Greater, true, _) => Condition::FGt,
+            (BinaryOperator::GreaterEqual, true, _) => Condition::FGe,
+            (BinaryOperator::Equal, _, _) => Condition::Eq,
+            (BinaryOperator::NotEqual, _, _) => Condition::Ne,
+            (BinaryOperator::Less, _, true) => Condition::SLt,
+            (BinaryOperator::LessEqual, _, true) => Condition::SLe,
+            (BinaryOperator::Greater, _, true) => Condition::SGt,
+            (BinaryOperator::GreaterEqual, _, true) => Condition::SGe,
+            (BinaryOperator::Less, _, false) => Condition::ULt,
+            (BinaryOperator::LessEqual, _, false) => Condition::ULe,
+            (BinaryOperator::Greater, _, false) => Condition::UGt,
+            (BinaryOperator::GreaterEqual, _, false) => Condition::UGe,
+            _ => unreachable!("not a comparison"),
+        };
+
+        Operand {
+            value: self.compare(condition, ty, left, right),
+            data_type: DataType::Int,
+        }
+    }
+
+    /// Arithmetic, bitwise and shift operators, including pointer
+    /// arithmetic.
+    fn arithmetic(&mut self, operator: BinaryOperator, left: Operand, right: Operand) -> Operand {
+        match (operator, &left.data_type, &right.data_type) {
+            (BinaryOperator::Add | BinaryOperator::Subtract, DataType::Pointer(element), r)
+                if r.is_integer() =>
+            {
+                let element = element.data_type.clone();
+                let subtract = operator == BinaryOperator::Subtract;
+                return Operand {
+                    value: self.pointer_offset(left.value, &element, right, subtract),
+                    data_type: left.data_type,
+                };
+            }
+            (BinaryOperator::Add, l, DataType::Pointer(element)) if l.is_integer() => {
+                let element = element.data_type.clone();
+                return Operand {
+                    value: self.pointer_offset(right.value, &element, left, false),
+                    data_type: right.data_type,
+                };
+            }
+            (BinaryOperator::Subtract, DataType::Pointer(element), DataType::Pointer(_)) => {
+                let size = self.size_of(&element.data_type) as i64;
+                let difference = self.binary(BinaryOp::Sub, IrType::I64, left.value, right.value);
+                return Operand {
+                    value: self.binary(BinaryOp::SDiv, IrType::I64, difference, Value::Int(size)),
+                    data_type: DataType::Long,
+                };
+            }
+            _ => {}
+        }
+
+        if matches!(
+            operator,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight
+        ) {
+            let left = self.promote(left);
+            let right = self.convert(right, &left.data_type);
+            let ty = ir_type(&left.data_type).unwrap();
+            let op = match operator {
+                BinaryOperator::ShiftLeft => BinaryOp::Shl,
+                _ if left.data_type.is_signed() => BinaryOp::AShr,
+                _ => BinaryOp::LShr,
+            };
+            return Operand {
+                value: self.binary(op, ty, left.value, right.value),
+                data_type: left.data_type,
+            };
+        }
+
+        let data_type = left.data_type.common_type(&right.data_type);
+        let left = self.convert(left, &data_type).value;
+        let right = self.convert(right, &data_type).value;
+        let ty = ir_type(&data_type).unwrap();
+        let signed = data_type.is_signed();
+
+        let op = match (operator, ty.is_float()) {
+            (BinaryOperator::Add, false) => BinaryOp::Add,
+            (BinaryOperator::Subtract, false) => BinaryOp::Sub,
+            (BinaryOperator::Multiply, false) => BinaryOp::Mul,
+            (BinaryOperator::Divide, false) if signed => BinaryOp::SDiv,
+            (BinaryOperator::Divide, false) => BinaryOp::UDiv,
+            (BinaryOperator::Remainder, _) if signed => BinaryOp::SRem,
+            (BinaryOperator::Remainder, _) => BinaryOp::URem,
+            (BinaryOperator::BitwiseAnd, _) => BinaryOp::And,
+            (BinaryOperator::BitwiseOr, _) => BinaryOp::Or,
+            (BinaryOperator::BitwiseXor, _) => BinaryOp::Xor,
+            (BinaryOperator::Add, true) => BinaryOp::FAdd,
+            (BinaryOperator::Subtract, true) => BinaryOp::FSub,
+            (BinaryOperator::Multiply, true) => BinaryOp::FMul,
+            (BinaryOperator::Divide, true) => BinaryOp::FDiv,
+            _ => unreachable!("{:?} is not an arithmetic operator", operator),
+        };
+
+        Operand {
+            value: self.binary(op, ty, left, right),
+            data_type,
+        }
+    }
+
+    fn lower_assignment(&mut self, assignment: &ast::Assignment) -> Operand {
+        let place = self.lower_place(&assignment.target);
+        let target_type = place.data_type.data_type.clone();
+
+        let value = match assignment.operator {
+            Some(operator) => {
+                let current = self.read(place.clone());
+                let value = self.lower_expr(&assignment.value);
+                self.arithmetic(operator, current, value)
+            }
+            None => self.lower_expr(&assignment.value),
+        };
+        let value = self.convert(value, &target_type);
+        self.store(&place, value.value.clone());
+
+        match ir_type(&target_type) {
+            Some(_) => value,
+            None => Operand {
+                value: place.address,
+                data_type: target_type,
+            },
+        }
+    }
+
+    fn lower_call(&mut self, call: &ast::Call) -> Operand {
+        let callee = self.lower_expr(&call.callee);
+        let DataType::Function(function) = &callee.data_type.pointee().unwrap().data_type else {
+            unreachable!("call of a non-function");
+        };
+        let function = function.clone();
+
+        let mut arguments = vec![];
+        let mut by_value = ByValue::default();
+        let return_type = function.return_type.data_type.clone();
+        let return_slot = if matches!(return_type, DataType::Struct(_)) {
+            let slot = self.alloca(&return_type);
+            arguments.push((IrType::I64, Value::Temp(slot)));
+            by_value.returned = Some(self.aggregate(&return_type));
+            Some(slot)
+        } else {
+            None
+        };
+
+        for (index, argument) in call.arguments.iter().enumerate() {
+            let operand = self.lower_expr(argument);
+            let operand = match function.parameters.get(index) {
+                Some(parameter) if function.has_prototype => {
+                    self.convert(operand, &parameter.data_type)
+                }
+                // Default argument promotions.
+                _ if operand.data_type == DataType::Float => {
+                    self.convert(operand, &DataType::Double)
+                }
+                _ => self.promote(operand),
+            };
+
+            let argument = match ir_type(&operand.data_type) {
+                Some(ty) => (ty, operand.value),
+                None => {
+                    let copy = self.alloca(&operand.data_type);
+                    let size = self.size_of(&operand.data_type);
+                    self.emit(Instr::MemCopy {
+                        destination: Value::Temp(copy),
+                        source: operand.value,
+                        size,
+                    });
+                    let aggregate = self.aggregate(&operand.data_type);
+                    by_value.passed.push((arguments.len(), aggregate));
+                    (IrType::I64, Value::Temp(copy))
+                }
+            };
+            arguments.push(argument);
+        }
+
+        let dst = match (&return_slot, ir_type(&return_type)) {
+            (Some(_), _) => Some((self.new_temp(), IrType::I64)),
+            (None, Some(ty)) => Some((self.new_temp(), ty)),
+            (None, None) => None,
+        };
+        self.emit(Instr::Call {
+            dst,
+            callee: callee.value,
+            arguments,
+            by_value,
+        });
+
+        let value = match (return_slot, dst) {
+            (Some(slot), _) => Value::Temp(slot),
+            (None, Some((dst, _))) => Value::Temp(dst),
+            (None, None) => Value::Int(0),
+        };
+        Operand {
+            value,
+            data_type: return_type,
+        }
+    }
+
+    fn lower_conditional(&mut self, conditional: &ast::Conditional) -> Operand {
+        let then_type = Self::decay(Operand {
+            value: Value::Int(0),
+            data_type: self.type_of(&conditional.then_value),
+        })
+        .data_type;
+        let else_type = Self::decay(Operand {
+            value: Value::Int(0),
+            data_type: self.type_of(&conditional.else_value),
+        })
+        .data_type;
+
+        let data_type = if then_type.is_arithmetic() && else_type.is_arithmetic() {
+            then_type.common_type(&else_type)
+        } else if then_type.is_pointer() && !is_null_pointer_constant(&conditional.then_value) {
+            then_type
+        } else {
+            else_type
+        };
+
+        // A struct result is a copy of the chosen operand, since the
+        // value may outlive a change to either.
+        let is_struct = matches!(data_type, DataType::Struct(_));
+        let ty = match &data_type {
+            DataType::Void => None,
+            DataType::Struct(_) => Some(IrType::I64),
+            data_type => ir_type(data_type),
+        };
+        let result = match ty {
+            Some(_) if is_struct => Some(self.alloca(&data_type)),
+            Some(ty) => Some(self.slot(ty)),
+            None => None,
+        };
+
+        let (if_true, if_false, end) = (self.new_label(), self.new_label(), self.new_label());
+        self.lower_condition(&conditional.condition, if_true, if_false);
+        for (label, value) in [
+            (if_true, &conditional.then_value),
+            (if_false, &conditional.else_value),
+        ] {
+            self.emit_label(label);
+            let operand = self.lower_expr(value);
+            match (ty, result) {
+                (Some(_), Some(result)) if is_struct => {
+                    let size = self.size_of(&data_type);
+                    self.emit(Instr::MemCopy {
+                        destination: Value::Temp(result),
+                        source: operand.value,
+                        size,
+                    });
+                }
+                (Some(ty), Some(result)) => {
+                    let value = self.convert(operand, &data_type).value;
+                    self.emit(Instr::Store {
+                        ty,
+                        value,
+                        address: Value::Temp(result),
+                        volatile: false,
+                    });
+                }
+                _ => {}
+            }
+            self.emit(Instr::Jump(end));
+        }
+        self.emit_label(end);
+
+        let value = match (ty, result) {
+            (Some(_), Some(result)) if is_struct => Value::Temp(result),
+            (Some(ty), Some(result)) => self.load(ty, Value::Temp(result), false),
+            _ => Value::Int(0),
+        };
+        Operand { value, data_type }
+    }
+
+    /// Jumps to `if_true` or `if_false` depending on `condition`,
+    /// short-circuiting `&&`, `||` and `!`.
+    fn lower_condition(&mut self, condition: &Expr, if_true: Label, if_false: Label) {
+        match condition {
+            Expr::Binary(binary) if binary.operator == BinaryOperator::LogicalAnd => {
+                let right = self.new_label();
+                self.lower_condition(&binary.left, right, if_false);
+                self.emit_label(right);
+                self.lower_condition(&binary.right, if_true, if_false);
+            }
+            Expr::Binary(binary) if binary.operator == BinaryOperator::LogicalOr => {
+                let right = self.new_label();
+                self.lower_condition(&binary.left, if_true, right);
+                self.emit_label(right);
+                self.lower_condition(&binary.right, if_true, if_false);
+            }
+            Expr::Unary(unary) if unary.operator == UnaryOperator::LogicalNot => {
+                self.lower_condition(&unary.operand, if_false, if_true);
+            }
+            _ => {
+                let operand = self.lower_expr(condition);
+                let ty = ir_type(&operand.data_type).unwrap();
+                let (ty, condition) = if ty.is_float() {
+                    let truth = self.compare(Condition::FNe, ty, operand.value, Self::zero(ty));
+                    (IrType::I32, truth)
+                } else {
+                    (ty, operand.value)
+                };
+
+                self.emit(Instr::Branch {
+                    ty,
+                    condition,
+                    if_true,
+                    if_false,
+                });
+            }
+        }
+    }
+
+    // Statements
+
+    fn lower_instruction(&mut self, instruction: &ast::Instruction) {
+        match instruction {
+            ast::Instruction::Declaration(declaration) => self.lower_local(declaration),
+            ast::Instruction::Return(return_stmt) => self.lower_return(return_stmt),
+            ast::Instruction::Expression(expr) => {
+                self.lower_value(expr);
+            }
+            ast::Instruction::Block(block) => {
+                self.scopes.push(HashMap::new());
+                for instruction in &block.instructions {
+                    self.lower_instruction(instruction);
+                }
+                self.scopes.pop();
+            }
+            ast::Instruction::If(if_stmt) => {
+                let (then_label, else_label, end) =
+                    (self.new_label(), self.new_label(), self.new_label());
+                self.lower_condition(&if_stmt.condition, then_label, else_label);
+                self.emit_label(then_label);
+                self.lower_instruction(&if_stmt.then_branch);
+                self.emit(Instr::Jump(end));
+                self.emit_label(else_label);
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    self.lower_instruction(else_branch);
+                }
+                self.emit(Instr::Jump(end));
+                self.emit_label(end);
+            }
+            ast::Instruction::While(while_stmt) => {
+                let (start, body, end) = (self.new_label(), self.new_label(), self.new_label());
+                self.emit_label(start);
+                self.lower_condition(&while_stmt.condition, body, end);
+                self.emit_label(body);
+                self.lower_loop_body(&while_stmt.body, end, start);
+                self.emit(Instr::Jump(start));
+                self.emit_label(end);
+            }
+            ast::Instruction::DoWhile(do_while) => {
+                let (start, condition, end) =
+                    (self.new_label(), self.new_label(), self.new_label());
+                self.emit_label(start);
+                self.lower_loop_body(&do_while.body, end, condition);
+                self.emit(Instr::Jump(condition));
+                self.emit_label(condition);
+                self.lower_condition(&do_while.condition, start, end);
+                self.emit_label(end);
+            }
+            ast::Instruction::For(for_stmt) => {
+                self.scopes.push(HashMap::new());
+                for instruction in &for_stmt.initializer {
+                    self.lower_instruction(instruction);
+                }
+
+                let (start, body, increment, end) = (
+                    self.new_label(),
+                    self.new_label(),
+                    self.new_label(),
+                    self.new_label(),
+                );
+                self.emit_label(start);
+                match &for_stmt.condition {
+                    Some(condition) => self.lower_condition(condition, body, end),
+                    None => self.emit(Instr::Jump(body)),
+                }
+                self.emit_label(body);
+                self.lower_loop_body(&for_stmt.body, end, increment);
+                self.emit(Instr::Jump(increment));
+                self.emit_label(increment);
+                if let Some(increment) = &for_stmt.increment {
+                    self.lower_value(increment);
+                }
+                self.emit(Instr::Jump(start));
+                self.emit_label(end);
+                self.scopes.pop();
+            }
+            ast::Instruction::Switch(switch) => self.lower_switch(switch),
+            ast::Instruction::Case(case) => {
+                let label = self.new_label();
+                let switch = self.function.switches.last().unwrap();
+                let (value, _) = case.value.evaluate_typed_constant(self.types).unwrap();
+                let value = switch.data_type.wrap_integer(value) as i64;
+                self.function
+                    .switches
+                    .last_mut()
+                    .unwrap()
+                    .cases
+                    .push((value, label));
+
+                self.emit(Instr::Jump(label));
+                self.emit_label(label);
+                self.lower_instruction(&case.body);
+            }
+            ast::Instruction::Default(body) => {
+                let label = self.new_label();
+                self.function.switches.last_mut().unwrap().default = Some(label);
+                self.emit(Instr::Jump(label));
+                self.emit_label(label);
+                self.lower_instruction(body);
+            }
+            ast::Instruction::Labeled(labeled) => {
+                let label = self.named_label(&labeled.label);
+                self.emit(Instr::Jump(label));
+                self.emit_label(label);
+                self.lower_instruction(&labeled.body);
+            }
+            ast::Instruction::Goto(name) => {
+                let label = self.named_label(name);
+                self.emit(Instr::Jump(label));
+                self.start_dead_block();
+            }
+            ast::Instruction::Break => {
+                let label = *self.function.breaks.last().unwrap();
+                self.emit(Instr::Jump(label));
+                self.start_dead_block();
+            }
+            ast::Instruction::Continue => {
+                let label = *self.function.continues.last().unwrap();
+                self.emit(Instr::Jump(label));
+                self.start_dead_block();
+            }
+            ast::Instruction::Empty => {}
+        }
+    }
+
+    /// Code after an unconditional jump is unreachable unless labeled; it
+    /// still needs a label so every block starts with one.
+    fn start_dead_block(&mut self) {
+        let label = self.new_label();
+        self.emit_label(label);
+    }
+
+    fn named_label(&mut self, name: &str) -> Label {
+        if let Some(label) = self.function.labels.get(name) {
+            return *label;
+        }
+        let label = self.new_label();
+        self.function.labels.insert(name.to_string(), label);
+        label
+    }
+
+    fn lower_loop_body(
+        &mut self,
+        body: &ast::Instruction,
+        break_label: Label,
+        continue_label: Label,
+    ) {
+        self.function.breaks.push(break_label);
+        self.function.continues.push(continue_label);
+        self.lower_instruction(body);
+        self.function.continues.pop();
+        self.function.breaks.pop();
+    }
+
+    /// The body is lowered first with a jump over it to the dispatch code,
+    /// which compares the condition against each case collected on the way.
+    fn lower_switch(&mut self, switch: &ast::Switch) {
+        let condition = self.lower_expr(&switch.condition);
+        let condition = self.promote(condition);
+        let ty = ir_type(&condition.data_type).unwrap();
+
+        let (dispatch, end) = (self.new_label(), self.new_label());
+        self.emit(Instr::Jump(dispatch));
+        self.start_dead_block();
+
+        self.function.switches.push(SwitchCases {
+            ty,
+            data_type: condition.data_type.clone(),
+            cases: vec![],
+            default: None,
+        });
+        self.function.breaks.push(end);
+        self.lower_instruction(&switch.body);
+        self.function.breaks.pop();
+        let cases = self.function.switches.pop().unwrap();
+        self.emit(Instr::Jump(end));
+
+        self.emit_label(dispatch);
+        for (value, label) in &cases.cases {
+            let matches = self.compare(
+                Condition::Eq,
+                cases.ty,
+                condition.value.clone(),
+                Value::Int(*value),
+            );
+            let next = self.new_label();
+            self.emit(Instr::Branch {
+                ty: IrType::I32,
+                condition: matches,
+                if_true: *label,
+                if_false: next,
+            });
+            self.emit_label(next);
+        }
+        self.emit(Instr::Jump(cases.default.unwrap_or(end)));
+        self.emit_label(end);
+    }
+
+    fn lower_return(&mut self, return_stmt: &ast::Return) {
+        let return_type = self.function.return_type.clone().unwrap().data_type;
+        let value = return_stmt
+            .value
+            .as_ref()
+            .map(|value| self.lower_expr_as(value, &return_type));
+
+        let value = match (value, ir_type(&return_type), self.function.return_slot) {
+            (Some(value), _, Some(slot)) => {
+                let size = self.size_of(&return_type);
+                self.emit(Instr::MemCopy {
+                    destination: Value::Temp(slot),
+                    source: value,
+                    size,
+                });
+                Some((IrType::I64, Value::Temp(slot)))
+            }
+            (Some(value), Some(ty), None) => Some((ty, value)),
+            _ => None,
+        };
+
+        self.emit(Instr::Return(value));
+        self.start_dead_block();
+    }
+
+    // Declarations
+
+    fn lower_local(&mut self, declaration: &ast::Declaration) {
+        let name = &declaration.identifier.name;
+        let data_type = &declaration.data_type;
+
+        if data_type.data_type.is_function()
+            || declaration.storage_class == Some(StorageClass::Extern)
+        {
+            let symbol = match self.scopes[0].get(name) {
+                Some(Binding::Global(symbol, _)) => symbol.clone(),
+                _ => name.clone(),
+            };
+            self.bind(name, Binding::Global(symbol, data_type.clone()));
+            return;
+        }
+
+        if declaration.storage_class == Some(StorageClass::Static) {
+            let symbol = format!("{}.{}", name, self.static_count);
+            self.static_count += 1;
+            let (data_type, init) =
+                self.static_initializer(data_type, declaration.initializer.as_ref());
+            self.bind(name, Binding::Global(symbol.clone(), data_type.clone()));
+            self.program.globals.push(Global {
+                name: symbol,
+                is_global: false,
+                is_constant: false,
+                alignment: self.alignment_of(&data_type.data_type),
+                init,
+            });
+            return;
+        }
+
+        let Some(initializer) = &declaration.initializer else {
+            let slot = self.alloca(&data_type.data_type);
+            self.bind(name, Binding::Local(slot, data_type.clone()));
+            return;
+        };
+
+        let types = self.types;
+        let resolved = initializer::resolve(data_type, initializer, types, &mut |expr| {
+            Some(
+                Self::decay(Operand {
+                    value: Value::Int(0),
+                    data_type: self.type_of(expr),
+                })
+                .data_type,
+            )
+        })
+        .expect("initializer was checked");
+
+        let slot = self.alloca(&resolved.data_type.data_type);
+        // The variable is in scope in its own initializer.
+        self.bind(name, Binding::Local(slot, resolved.data_type.clone()));
+
+        if resolved.data_type.data_type.is_aggregate() {
+            let size = self.size_of(&resolved.data_type.data_type);
+            self.zero_fill(Value::Temp(slot), size);
+        }
+
+        for element in &resolved.elements {
+            let address = self.offset_address(Value::Temp(slot), element.offset as i64);
+            let place = Place {
+                address,
+                data_type: element.data_type.clone(),
+            };
+
+            match (&element.data_type.data_type, element.value) {
+                (DataType::Array(_, length), Expr::Literal(Literal::String(literal))) => {
+                    let bytes = &literal.value;
+                    let length = length.unwrap_or(bytes.len() + 1);
+                    for (index, byte) in bytes.iter().chain(&[0]).take(length).enumerate() {
+                        let address = self.offset_address(place.address.clone(), index as i64);
+                        self.emit(Instr::Store {
+                            ty: IrType::I8,
+                            value: Value::Int(*byte as i8 as i64),
+                            address,
+                            volatile: false,
+                        });
+                    }
+                }
+                (data_type, value) => {
+                    let data_type = data_type.clone();
+                    let value = self.lower_expr_as(value, &data_type);
+                    self.store(&place, value);
+                }
+            }
+        }
+    }
+
+    fn zero_fill(&mut self, address: Value, size: usize) {
+        let mut offset = 0;
+        while offset < size {
+            let ty = if size - offset >= 8 {
+                IrType::I64
+            } else {
+                IrType::I8
+            };
+            let address = self.offset_address(address.clone(), offset as i64);
+            self.emit(Instr::Store {
+                ty,
+                value: Value::Int(0),
+                address,
+                volatile: false,
+            });
+            offset += ty.size();
+        }
+    }
+
+    // Static data
+
+    /// The completed type and contents of an object with static storage.
+    fn static_initializer(
+        &mut self,
+        data_type: &QualifiedType,
+        initializer: Option<&Initializer>,
+    ) -> (QualifiedType, Vec<GlobalInit>) {
+        let Some(initializer) = initializer else {
+            let data_type = match &data_type.data_type {
+                // A tentative array of unknown size has one element.
+                DataType::Array(element, None) => QualifiedType {
+                    data_type: DataType::Array(element.clone(), Some(1)),
+                    qualifiers: data_type.qualifiers,
+                },
+                _ => data_type.clone(),
+            };
+            let size = self.size_of(&data_type.data_type);
+            return (data_type, vec![GlobalInit::Zero(size)]);
+        };
+
+        let types = self.types;
+        let resolved = initializer::resolve(data_type, initializer, types, &mut |_| None)
+            .expect("initializer was checked");
+
+        // Later initializers of the same subobject override earlier ones.
+        let mut pieces: Vec<(usize, GlobalInit)> = vec![];
+        for element in &resolved.elements {
+            let init = self.constant_init(&element.data_type.data_type, element.value);
+            let (start, end) = (element.offset, element.offset + init.size());
+            pieces.retain(|(offset, piece)| offset + piece.size() <= start || *offset >= end);
+            pieces.push((start, init));
+        }
+        pieces.sort_by_key(|(offset, _)| *offset);
+
+        let mut init = vec![];
+        let mut position = 0;
+        for (offset, piece) in pieces {
+            if offset > position {
+                init.push(GlobalInit::Zero(offset - position));
+            }
+            position = offset + piece.size();
+            init.push(piece);
+        }
+        let size = self.size_of(&resolved.data_type.data_type);
+        if size > position {
+            init.push(GlobalInit::Zero(size - position));
+        }
+
+        (resolved.data_type, init)
+    }
+
+    fn constant_init(&mut self, data_type: &DataType, expr: &Expr) -> GlobalInit {
+        if let (DataType::Array(_, length), Expr::Literal(Literal::String(literal))) =
+            (data_type, expr)
+        {
+            let mut bytes = literal.value.clone();
+            bytes.resize(length.unwrap_or(bytes.len() + 1), 0);
+            return GlobalInit::Bytes(bytes);
+        }
+
+        let ty = ir_type(data_type).unwrap();
+        if ty.is_float() {
+            let value = float_constant(expr, self.types).unwrap_or(0.0);
+            let value = if ty == IrType::F32 {
+                value as f32 as f64
+            } else {
+                value
+            };
+            return GlobalInit::Float(ty, value);
+        }
+
+        if data_type.is_pointer()
+            && let Some((symbol, offset, _)) = self.address_constant(expr)
+        {
+            return GlobalInit::Address(symbol, offset);
+        }
+
+        let value = match expr.evaluate_typed_constant(self.types) {
+            Some((value, _)) => data_type.wrap_integer(value),
+            None => {
+                let value = float_constant(expr, self.types).unwrap_or(0.0);
+                if data_type.is_signed() {
+                    value as i64 as i128
+                } else {
+                    value as u64 as i128
+                }
+            }
+        };
+        GlobalInit::Int(ty, data_type.wrap_integer(value) as i64)
+    }
+
+    /// The symbol and offset an address constant points to, with the type it
+    /// points to.
+    fn address_constant(&mut self, expr: &Expr) -> Option<(String, i64, DataType)> {
+        match expr {
+            Expr::Identifier(_) | Expr::Literal(Literal::String(_)) => {
+                let (symbol, offset, data_type) = self.static_place(expr)?;
+                match data_type {
+                    DataType::Array(element, _) => Some((symbol, offset, element.data_type)),
+                    data_type @ DataType::Function(_) => Some((symbol, offset, data_type)),
+                    _ => None,
+                }
+            }
+            Expr::Unary(unary) if unary.operator == UnaryOperator::AddressOf => {
+                self.static_place(&unary.operand)
+            }
+            Expr::Cast(cast) => {
+                let (symbol, offset, _) = self.address_constant(&cast.value)?;
+                let pointee = cast.target_type.data_type.pointee()?.data_type.clone();
+                Some((symbol, offset, pointee))
+            }
+            Expr::Binary(binary)
+                if matches!(
+                    binary.operator,
+                    BinaryOperator::Add | BinaryOperator::Subtract
+                ) =>
+            {
+                let (pointer, index) = match self.address_constant(&binary.left) {
+                    Some(pointer) => (pointer, &binary.right),
+                    None => (self.address_constant(&binary.right)?, &binary.left),
+                };
+                let (symbol, offset, pointee) = pointer;
+                let index = index.evaluate_constant(self.types)?;
+                let scaled = index * self.size_of(&pointee) as i64;
+                let offset = if binary.operator == BinaryOperator::Subtract {
+                    offset - scaled
+                } else {
+                    offset + scaled
+                };
+                Some((symbol, offset, pointee))
+            }
+            _ => None,
+        }
+    }
+
+    /// The symbol, offset and type of an object with static storage.
+    fn static_place(&mut self, expr: &Expr) -> Option<(String, i64, DataType)> {
+        match expr {
+            Expr::Identifier(identifier) => match self.lookup(&identifier.name) {
+                Binding::Global(symbol, data_type) => Some((symbol, 0, data_type.data_type)),
+                Binding::Local(_, _) => None,
+            },
+            Expr::Literal(Literal::String(literal)) => {
+                let symbol = self.string_literal(&literal.value);
+                Some((symbol, 0, self.type_of(expr)))
+            }
+            Expr::Member(member) if !member.is_arrow => {
+                let (symbol, offset, data_type) = self.static_place(&member.object)?;
+                let DataType::Struct(name) = data_type else {
+                    return None;
+                };
+                let (member_offset, member_type) = self.types.member(&name, &member.member)?;
+                Some((symbol, offset + member_offset as i64, member_type.data_type))
+            }
+            Expr::Index(index) => {
+                let (symbol, offset, data_type) = self.static_place(&index.array)?;
+                let element = data_type.element()?.data_type.clone();
+                let position = index.index.evaluate_constant(self.types)?;
+                Some((
+                    symbol,
+                    offset + position * self.size_of(&element) as i64,
+                    element,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn lower_global(&mut self, declaration: &ast::Declaration) {
+        let name = &declaration.identifier.name;
+        let data_type = &declaration.data_type;
+        if declaration.storage_class == Some(StorageClass::Static) {
+            self.internal.insert(name.clone());
+        }
+
+        let binding_type = match self.scopes[0].get(name) {
+            Some(Binding::Global(_, existing))
+                if existing.data_type.is_array() && !data_type.data_type.is_array() =>
+            {
+                existing.clone()
+            }
+            Some(Binding::Global(_, existing))
+                if matches!(data_type.data_type, DataType::Array(_, None)) =>
+            {
+                existing.clone()
+            }
+            _ => data_type.clone(),
+        };
+        self.bind(name, Binding::Global(name.clone(), binding_type));
+
+        if data_type.data_type.is_function() || self.defined.contains(name) {
+            return;
+        }
+
+        match &declaration.initializer {
+            Some(initializer) => {
+                let (data_type, init) = self.static_initializer(data_type, Some(initializer));
+                self.defined.insert(name.clone());
+                self.bind(name, Binding::Global(name.clone(), data_type.clone()));
+                self.program.globals.push(Global {
+                    name: name.clone(),
+                    is_global: !self.internal.contains(name),
+                    is_constant: false,
+                    alignment: self.alignment_of(&data_type.data_type),
+                    init,
+                });
+            }
+            None if declaration.storage_class != Some(StorageClass::Extern) => {
+                match self
+                    .tentative
+                    .iter_mut()
+                    .find(|(tentative, _)| tentative == name)
+                {
+                    // A later tentative declaration may complete an array.
+                    Some((_, existing))
+                        if matches!(existing.data_type, DataType::Array(_, None)) =>
+                    {
+                        *existing = data_type.clone();
+                    }
+                    Some(_) => {}
+                    None => self.tentative.push((name.clone(), data_type.clone())),
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn lower_function(&mut self, function: &ast::Function) {
+        let function_type = QualifiedType::new(DataType::Function(function.function_type()));
+        if function.storage_class == Some(StorageClass::Static) {
+            self.internal.insert(function.name.clone());
+        }
+        self.bind(
+            &function.name,
+            Binding::Global(function.name.clone(), function_type),
+        );
+
+        self.function = FunctionState {
+            return_type: Some(function.return_type.clone()),
+            ..FunctionState::default()
+        };
+        self.scopes.push(HashMap::new());
+
+        let mut parameters = vec![];
+        let mut by_value = ByValue::default();
+        let return_type = &function.return_type.data_type;
+        if matches!(return_type, DataType::Struct(_)) {
+            let slot = self.new_temp();
+            parameters.push((slot, IrType::I64));
+            by_value.returned = Some(self.aggregate(return_type));
+            self.function.return_slot = Some(slot);
+        }
+
+        for parameter in &function.parameters {
+            let temp = self.new_temp();
+            let name = &parameter.identifier.as_ref().unwrap().name;
+            match ir_type(&parameter.data_type.data_type) {
+                Some(ty) => {
+                    parameters.push((temp, ty));
+                    let slot = self.alloca(&parameter.data_type.data_type);
+                    self.emit(Instr::Store {
+                        ty,
+                        value: Value::Temp(temp),
+                        address: Value::Temp(slot),
+                        volatile: parameter.data_type.qualifiers.is_volatile,
+                    });
+                    self.bind(name, Binding::Local(slot, parameter.data_type.clone()));
+                }
+                None => {
+                    let aggregate = self.aggregate(&parameter.data_type.data_type);
+                    by_value.passed.push((parameters.len(), aggregate));
+                    parameters.push((temp, IrType::I64));
+                    self.bind(name, Binding::Local(temp, parameter.data_type.clone()));
+                }
+            }
+        }
+
+        for instruction in &function.instructions {
+            self.lower_instruction(instruction);
+        }
+
+        // Falling off the end returns 0 from `main`; for other functions
+        // the value is undefined.
+        let fallthrough = match (ir_type(return_type), self.function.return_slot) {
+            (_, Some(slot)) => Some((IrType::I64, Value::Temp(slot))),
+            (Some(ty), None) => Some((ty, Self::zero(ty))),
+            (None, None) => None,
+        };
+        self.emit(Instr::Return(fallthrough));
+
+        self.scopes.pop();
+        let state = std::mem::take(&mut self.function);
+        let mut body = state.allocas;
+        body.extend(state.body);
+
+        let return_type = match state.return_slot {
+            Some(_) => Some(IrType::I64),
+            None => ir_type(return_type),
+        };
+        self.program.functions.push(Function {
+            name: function.name.clone(),
+            is_global: !self.internal.contains(&function.name),
+            inlining: inlining(&function.attributes),
+            parameters,
+            variadic: function.is_variadic,
+            return_type,
+            by_value,
+            body,
+        });
+    }
+
+    fn lower_program(&mut self, program: &ast::Program) {
+        for declaration in &program.declarations {
+            match declaration {
+                ast::ExternalDeclaration::Function(function) => self.lower_function(function),
+                ast::ExternalDeclaration::Declaration(declaration) => {
+                    self.lower_global(declaration)
+                }
+            }
+        }
+
+        for (name, data_type) in std::mem::take(&mut self.tentative) {
+            if self.defined.contains(&name) {
+                continue;
+            }
+            let (data_type, init) = self.static_initializer(&data_type, None);
+            self.program.globals.push(Global {
+                is_global: !self.internal.contains(&name),
+                name,
+                is_constant: false,
+                alignment: self.alignment_of(&data_type.data_type),
+                init,
+            });
+        }
+    }
+}
+
+pub fn lower(program: &ast::Program) -> Program {
+    let mut lowerer = Lowerer::new(&program.types);
+    lowerer.lower_program(program);
+    lowerer.program
+}
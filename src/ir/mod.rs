@@ -0,0 +1,498 @@
+/*
+  A flat three-address intermediate representation. A function body is a
+  list of instructions in which labels mark jump targets. Temporaries are
+  typed virtual registers; local variables live in stack slots created by
+  `alloca` and are accessed with loads and stores.
+*/
+
+pub mod cfg;
+pub mod dce;
+pub mod dominators;
+pub mod fold;
+pub mod gvn;
+pub mod inline;
+pub mod interpreter;
+pub mod licm;
+pub mod loops;
+pub mod lower;
+pub mod parser;
+pub mod passes;
+pub mod printer;
+pub mod reduce;
+pub mod sccp;
+pub mod ssa;
+pub mod tailcall;
+pub mod unroll;
+pub mod verify;
+
+/// Machine-level value types. Signedness is a property of the operations,
+/// not of the types, and addresses are `I64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IrType {
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl IrType {
+    pub fn size(&self) -> usize {
+        match self {
+            IrType::I8 => 1,
+            IrType::I16 => 2,
+            IrType::I32 | IrType::F32 => 4,
+            IrType::I64 | IrType::F64 => 8,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, IrType::F32 | IrType::F64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Temp(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Label(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Temp(Temp),
+    /// Integer constants are stored sign-extended; instructions read them at
+    /// the width of their type.
+    Int(i64),
+    Float(f64),
+    /// The address of a global variable or function.
+    Global(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    FNeg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    SDiv,
+    UDiv,
+    SRem,
+    URem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    /// Arithmetic (sign-filling) right shift.
+    AShr,
+    /// Logical (zero-filling) right shift.
+    LShr,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+}
+
+/// Comparisons produce an `I32` that is 0 or 1. Float comparisons are false
+/// when either operand is NaN, except `FNe`, which is true.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Condition {
+    Eq,
+    Ne,
+    SLt,
+    SLe,
+    SGt,
+    SGe,
+    ULt,
+    ULe,
+    UGt,
+    UGe,
+    FEq,
+    FNe,
+    FLt,
+    FLe,
+    FGt,
+    FGe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConvertOp {
+    SExt,
+    ZExt,
+    Trunc,
+    SIToFP,
+    UIToFP,
+    FPToSI,
+    FPToUI,
+    FPExt,
+    FPTrunc,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Label(Label),
+    /// Selects the value flowing in from the predecessor ending in the given
+    /// label. Phis only appear in SSA form, at the start of a block.
+    Phi {
+        dst: Temp,
+        ty: IrType,
+        incoming: Vec<(Label, Value)>,
+    },
+    Copy {
+        dst: Temp,
+        ty: IrType,
+        src: Value,
+    },
+    Unary {
+        dst: Temp,
+        op: UnaryOp,
+        ty: IrType,
+        operand: Value,
+    },
+    Binary {
+        dst: Temp,
+        op: BinaryOp,
+        ty: IrType,
+        left: Value,
+        right: Value,
+    },
+    /// `ty` is the type of the operands; the result is an `I32`.
+    Compare {
+        dst: Temp,
+        condition: Condition,
+        ty: IrType,
+        left: Value,
+        right: Value,
+    },
+    Convert {
+        dst: Temp,
+        op: ConvertOp,
+        from: IrType,
+        to: IrType,
+        value: Value,
+    },
+    /// Reserves a stack slot for the whole function, yielding its address.
+    Alloca {
+        dst: Temp,
+        size: usize,
+        alignment: usize,
+    },
+    Load {
+        dst: Temp,
+        ty: IrType,
+        address: Value,
+        volatile: bool,
+    },
+    Store {
+        ty: IrType,
+        value: Value,
+        address: Value,
+        volatile: bool,
+    },
+    MemCopy {
+        destination: Value,
+        source: Value,
+        size: usize,
+    },
+    Call {
+        dst: Option<(Temp, IrType)>,
+        callee: Value,
+        arguments: Vec<(IrType, Value)>,
+        by_value: ByValue,
+    },
+    Jump(Label),
+    /// Jumps to `if_true` when `condition` is non-zero.
+    Branch {
+        ty: IrType,
+        condition: Value,
+        if_true: Label,
+        if_false: Label,
+    },
+    Return(Option<(IrType, Value)>),
+}
+
+impl Instr {
+    /// Whether the instruction ends a basic block.
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            Instr::Jump(_) | Instr::Branch { .. } | Instr::Return(_)
+        )
+    }
+
+    /// Whether the instruction does anything besides assigning its
+    /// temporary, so that it must stay even when the result is unused.
+    pub fn has_side_effects(&self) -> bool {
+        match self {
+            Instr::Store { .. } | Instr::MemCopy { .. } | Instr::Call { .. } => true,
+            Instr::Load { volatile, .. } => *volatile,
+            instr => instr.is_terminator(),
+        }
+    }
+
+    pub fn defined_mut(&mut self) -> Option<&mut Temp> {
+        match self {
+            Instr::Phi { dst, .. }
+            | Instr::Copy { dst, .. }
+            | Instr::Unary { dst, .. }
+            | Instr::Binary { dst, .. }
+            | Instr::Compare { dst, .. }
+            | Instr::Convert { dst, .. }
+            | Instr::Alloca { dst, .. }
+            | Instr::Load { dst, .. }
+            | Instr::Call {
+                dst: Some((dst, _)),
+                ..
+            } => Some(dst),
+            _ => None,
+        }
+    }
+
+    /// The temporary the instruction assigns, if any.
+    pub fn defined(&self) -> Option<Temp> {
+        match self {
+            Instr::Phi { dst, .. }
+            | Instr::Copy { dst, .. }
+            | Instr::Unary { dst, .. }
+            | Instr::Binary { dst, .. }
+            | Instr::Compare { dst, .. }
+            | Instr::Convert { dst, .. }
+            | Instr::Alloca { dst, .. }
+            | Instr::Load { dst, .. }
+            | Instr::Call {
+                dst: Some((dst, _)),
+                ..
+            } => Some(*dst),
+            _ => None,
+        }
+    }
+
+    /// The values the instruction reads, including a phi's incoming values.
+    pub fn uses(&self) -> Vec<&Value> {
+        match self {
+            Instr::Label(_) | Instr::Alloca { .. } | Instr::Jump(_) | Instr::Return(None) => {
+                vec![]
+            }
+            Instr::Phi { incoming, .. } => incoming.iter().map(|(_, value)| value).collect(),
+            Instr::Copy { src: value, .. }
+            | Instr::Unary { operand: value, .. }
+            | Instr::Convert { value, .. }
+            | Instr::Load { address: value, .. }
+            | Instr::Branch {
+                condition: value, ..
+            }
+            | Instr::Return(Some((_, value))) => vec![value],
+            Instr::Binary { left, right, .. } | Instr::Compare { left, right, .. } => {
+                vec![left, right]
+            }
+            Instr::Store { value, address, .. } => vec![value, address],
+            Instr::MemCopy {
+                destination,
+                source,
+                ..
+            } => vec![destination, source],
+            Instr::Call {
+                callee, arguments, ..
+            } => std::iter::once(callee)
+                .chain(arguments.iter().map(|(_, value)| value))
+                .collect(),
+        }
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Instr::Label(_) | Instr::Alloca { .. } | Instr::Jump(_) | Instr::Return(None) => {
+                vec![]
+            }
+            Instr::Phi { incoming, .. } => incoming.iter_mut().map(|(_, value)| value).collect(),
+            Instr::Copy { src: value, .. }
+            | Instr::Unary { operand: value, .. }
+            | Instr::Convert { value, .. }
+            | Instr::Load { address: value, .. }
+            | Instr::Branch {
+                condition: value, ..
+            }
+            | Instr::Return(Some((_, value))) => vec![value],
+            Instr::Binary { left, right, .. } | Instr::Compare { left, right, .. } => {
+                vec![left, right]
+            }
+            Instr::Store { value, address, .. } => vec![value, address],
+            Instr::MemCopy {
+                destination,
+                source,
+                ..
+            } => vec![destination, source],
+            Instr::Call {
+                callee, arguments, ..
+            } => std::iter::once(callee)
+                .chain(arguments.iter_mut().map(|(_, value)| value))
+                .collect(),
+        }
+    }
+
+    /// The temporaries the instruction reads.
+    pub fn used_temps(&self) -> Vec<Temp> {
+        self.uses()
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Temp(temp) => Some(*temp),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The labels control may continue at after a terminator.
+    pub fn targets(&self) -> Vec<Label> {
+        match self {
+            Instr::Jump(label) => vec![*label],
+            Instr::Branch {
+                if_true, if_false, ..
+            } => vec![*if_true, *if_false],
+            _ => vec![],
+        }
+    }
+}
+
+/// What the source asks of the inliner for calls to a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Inlining {
+    #[default]
+    Default,
+    /// Declared `inline`.
+    Hint,
+    /// `__attribute__((always_inline))`.
+    Always,
+    /// `__attribute__((noinline))`.
+    Never,
+}
+
+/// The layout of a struct passed or returned by value, which the calling
+/// convention may pass in registers depending on the scalars in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub size: usize,
+    pub alignment: usize,
+    /// Every scalar in the struct, nested ones included, by offset.
+    pub members: Vec<(usize, IrType)>,
+}
+
+/// The pointer arguments of a call, or parameters of a function, that
+/// stand for structs passed or returned by value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ByValue {
+    /// The struct the function returns, stored where the first argument
+    /// points.
+    pub returned: Option<Aggregate>,
+    /// The structs passed by value, by argument index, each as a pointer
+    /// to a copy the callee may change.
+    pub passed: Vec<(usize, Aggregate)>,
+}
+
+impl ByValue {
+    /// The struct the argument stands for, if any.
+    pub fn aggregate(&self, index: usize) -> Option<&Aggregate> {
+        match &self.returned {
+            Some(aggregate) if index == 0 => Some(aggregate),
+            _ => self
+                .passed
+                .iter()
+                .find(|(passed, _)| *passed == index)
+                .map(|(_, aggregate)| aggregate),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.returned.is_none() && self.passed.is_empty()
+    }
+}
+
+/// The functions `va_start` and `va_arg` call, with the address of the
+/// `va_list` as their argument. They are not defined anywhere: the
+/// interpreter and the backend implement them in place.
+pub const VA_START: &str = "__builtin_va_start";
+pub const VA_ARG: &str = "__builtin_va_arg";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// Whether the function has external linkage.
+    pub is_global: bool,
+    pub inlining: Inlining,
+    pub parameters: Vec<(Temp, IrType)>,
+    /// Whether the parameters end in `...`.
+    pub variadic: bool,
+    pub return_type: Option<IrType>,
+    pub by_value: ByValue,
+    pub body: Vec<Instr>,
+}
+
+impl Function {
+    /// A temporary not used anywhere in the function.
+    pub fn next_temp(&self) -> Temp {
+        let defined = self
+            .body
+            .iter()
+            .filter_map(|instr| Some(instr.defined()?.0));
+        let parameters = self.parameters.iter().map(|(temp, _)| temp.0);
+        Temp(defined.chain(parameters).max().map_or(0, |max| max + 1))
+    }
+
+    /// A label not used anywhere in the function.
+    pub fn next_label(&self) -> Label {
+        let labels = self.body.iter().flat_map(|instr| match instr {
+            Instr::Label(label) => vec![label.0],
+            instr => instr.targets().iter().map(|label| label.0).collect(),
+        });
+        Label(labels.max().map_or(0, |max| max + 1))
+    }
+}
+
+/// One piece of a global's initial contents, laid out back to back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlobalInit {
+    Zero(usize),
+    Int(IrType, i64),
+    Float(IrType, f64),
+    /// The address of a symbol plus a byte offset, always 8 bytes.
+    Address(String, i64),
+    Bytes(Vec<u8>),
+}
+
+impl GlobalInit {
+    pub fn size(&self) -> usize {
+        match self {
+            GlobalInit::Zero(size) => *size,
+            GlobalInit::Int(ty, _) | GlobalInit::Float(ty, _) => ty.size(),
+            GlobalInit::Address(_, _) => 8,
+            GlobalInit::Bytes(bytes) => bytes.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub is_global: bool,
+    /// String literals, which may be placed in read-only memory.
+    pub is_constant: bool,
+    pub alignment: usize,
+    pub init: Vec<GlobalInit>,
+}
+
+impl Global {
+    pub fn size(&self) -> usize {
+        self.init.iter().map(GlobalInit::size).sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+}
@@ -0,0 +1,838 @@
+/*
+  Reads the textual IR written by `ir::printer`.
+*/
+
+use std::collections::HashSet;
+
+use super::{
+    Aggregate, BinaryOp, ByValue, Condition, ConvertOp, Function, Global, GlobalInit, Inlining,
+    Instr, IrType, Label, Program, Temp, UnaryOp, Value,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    UnexpectedToken(String, usize),
+    InvalidNumber(String, usize),
+    UnterminatedString(usize),
+    /// A jump or phi names a label the function does not define.
+    UndefinedLabel(String, usize),
+    /// A temporary is used but neither a parameter nor defined in the
+    /// function.
+    UndefinedTemp(String, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Keywords, type and operation names and labels.
+    Word(String),
+    Temp(u32),
+    Global(String),
+    Number(String),
+    String(Vec<u8>),
+    Punct(char),
+}
+
+/// A float in decimal, or given by its bits in hexadecimal.
+fn float(lexeme: &str) -> Option<f64> {
+    match lexeme.strip_prefix("0x") {
+        Some(bits) => u64::from_str_radix(bits, 16).ok().map(f64::from_bits),
+        None => lexeme.parse().ok(),
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+
+    let take_while = |chars: &mut std::iter::Peekable<std::str::Chars>,
+                      predicate: &dyn Fn(char) -> bool| {
+        let mut lexeme = String::new();
+        while let Some(&c) = chars.peek()
+            && predicate(c)
+        {
+            lexeme.push(c);
+            chars.next();
+        }
+        lexeme
+    };
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            ';' => {
+                take_while(&mut chars, &|c| c != '\n');
+            }
+            '%' => {
+                chars.next();
+                let lexeme = take_while(&mut chars, &|c| c.is_ascii_digit());
+                let temp = lexeme
+                    .parse()
+                    .map_err(|_| ParseError::InvalidNumber(format!("%{}", lexeme), line))?;
+                tokens.push((Token::Temp(temp), line));
+            }
+            '@' => {
+                chars.next();
+                let name = take_while(&mut chars, &is_name_char);
+                tokens.push((Token::Global(name), line));
+            }
+            '"' => {
+                chars.next();
+                let mut bytes = vec![];
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            let digits: String = chars.by_ref().take(2).collect();
+                            let byte = u8::from_str_radix(&digits, 16).map_err(|_| {
+                                ParseError::InvalidNumber(format!("\\{}", digits), line)
+                            })?;
+                            bytes.push(byte);
+                        }
+                        Some('\n') | None => return Err(ParseError::UnterminatedString(line)),
+                        Some(c) => {
+                            let mut buffer = [0; 4];
+                            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                        }
+                    }
+                }
+                tokens.push((Token::String(bytes), line));
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut lexeme = String::new();
+                lexeme.push(c);
+                chars.next();
+                // Exponents may be negative: `1e-7`.
+                while let Some(&c) = chars.peek()
+                    && (c.is_ascii_alphanumeric()
+                        || c == '.'
+                        || (c == '-' && lexeme.ends_with(['e', 'E'])))
+                {
+                    lexeme.push(c);
+                    chars.next();
+                }
+                tokens.push((Token::Number(lexeme), line));
+            }
+            c if is_name_char(c) => {
+                let word = take_while(&mut chars, &is_name_char);
+                tokens.push((Token::Word(word), line));
+            }
+            '=' | ',' | ':' | '(' | ')' | '{' | '}' | '[' | ']' | '+' => {
+                chars.next();
+                tokens.push((Token::Punct(c), line));
+            }
+            c => return Err(ParseError::UnexpectedToken(c.to_string(), line)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn ir_type(word: &str) -> Option<IrType> {
+    match word {
+        "i8" => Some(IrType::I8),
+        "i16" => Some(IrType::I16),
+        "i32" => Some(IrType::I32),
+        "i64" => Some(IrType::I64),
+        "f32" => Some(IrType::F32),
+        "f64" => Some(IrType::F64),
+        _ => None,
+    }
+}
+
+fn unary_op(word: &str) -> Option<UnaryOp> {
+    [UnaryOp::Neg, UnaryOp::Not, UnaryOp::FNeg]
+        .into_iter()
+        .find(|op| op.name() == word)
+}
+
+fn binary_op(word: &str) -> Option<BinaryOp> {
+    [
+        BinaryOp::Add,
+        BinaryOp::Sub,
+        BinaryOp::Mul,
+        BinaryOp::SDiv,
+        BinaryOp::UDiv,
+        BinaryOp::SRem,
+        BinaryOp::URem,
+        BinaryOp::And,
+        BinaryOp::Or,
+        BinaryOp::Xor,
+        BinaryOp::Shl,
+        BinaryOp::AShr,
+        BinaryOp::LShr,
+        BinaryOp::FAdd,
+        BinaryOp::FSub,
+        BinaryOp::FMul,
+        BinaryOp::FDiv,
+    ]
+    .into_iter()
+    .find(|op| op.name() == word)
+}
+
+fn condition(word: &str) -> Option<Condition> {
+    [
+        Condition::Eq,
+        Condition::Ne,
+        Condition::SLt,
+        Condition::SLe,
+        Condition::SGt,
+        Condition::SGe,
+        Condition::ULt,
+        Condition::ULe,
+        Condition::UGt,
+        Condition::UGe,
+        Condition::FEq,
+        Condition::FNe,
+        Condition::FLt,
+        Condition::FLe,
+        Condition::FGt,
+        Condition::FGe,
+    ]
+    .into_iter()
+    .find(|condition| condition.name() == word)
+}
+
+fn convert_op(word: &str) -> Option<ConvertOp> {
+    [
+        ConvertOp::SExt,
+        ConvertOp::ZExt,
+        ConvertOp::Trunc,
+        ConvertOp::SIToFP,
+        ConvertOp::UIToFP,
+        ConvertOp::FPToSI,
+        ConvertOp::FPToUI,
+        ConvertOp::FPExt,
+        ConvertOp::FPTrunc,
+    ]
+    .into_iter()
+    .find(|op| op.name() == word)
+}
+
+fn label(word: &str) -> Option<Label> {
+    word.strip_prefix('L')?.parse().ok().map(Label)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens
+            .get(self.position + offset)
+            .map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self) -> ParseError {
+        let lexeme = match self.peek() {
+            Some(token) => format!("{:?}", token),
+            None => "end of input".to_string(),
+        };
+        ParseError::UnexpectedToken(lexeme, self.line())
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        let token = self.peek().cloned().ok_or_else(|| self.error())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), ParseError> {
+        if !self.is_punct(c) {
+            return Err(self.error());
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), ParseError> {
+        if !self.is_word(word) {
+            return Err(self.error());
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn accept_word(&mut self, word: &str) -> bool {
+        let accepted = self.is_word(word);
+        if accepted {
+            self.position += 1;
+        }
+        accepted
+    }
+
+    fn word(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            _ => {
+                self.position -= 1;
+                Err(self.error())
+            }
+        }
+    }
+
+    /// Parses a word with `parse`, failing on the word itself.
+    fn word_as<T>(&mut self, parse: fn(&str) -> Option<T>) -> Result<T, ParseError> {
+        let start = self.position;
+        let word = self.word()?;
+        parse(&word).ok_or_else(|| {
+            self.position = start;
+            self.error()
+        })
+    }
+
+    fn ir_type(&mut self) -> Result<IrType, ParseError> {
+        self.word_as(ir_type)
+    }
+
+    /// A type or `void`.
+    fn return_type(&mut self) -> Result<Option<IrType>, ParseError> {
+        if self.accept_word("void") {
+            return Ok(None);
+        }
+        self.ir_type().map(Some)
+    }
+
+    fn label(&mut self) -> Result<Label, ParseError> {
+        self.word_as(label)
+    }
+
+    fn temp(&mut self) -> Result<Temp, ParseError> {
+        match self.next()? {
+            Token::Temp(temp) => Ok(Temp(temp)),
+            _ => {
+                self.position -= 1;
+                Err(self.error())
+            }
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, ParseError> {
+        let line = self.line();
+        match self.next()? {
+            Token::Number(lexeme) => lexeme
+                .parse()
+                .map_err(|_| ParseError::InvalidNumber(lexeme, line)),
+            _ => {
+                self.position -= 1;
+                Err(self.error())
+            }
+        }
+    }
+
+    fn float(&mut self) -> Result<f64, ParseError> {
+        let line = self.line();
+        match self.next()? {
+            Token::Word(word) if word == "NaN" || word == "inf" => Ok(word.parse().unwrap()),
+            Token::Number(lexeme) => float(&lexeme).ok_or(ParseError::InvalidNumber(lexeme, line)),
+            _ => {
+                self.position -= 1;
+                Err(self.error())
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        let line = self.line();
+        match self.next()? {
+            Token::Temp(temp) => Ok(Value::Temp(Temp(temp))),
+            Token::Global(name) => Ok(Value::Global(name)),
+            Token::Word(word) if word == "NaN" || word == "inf" => {
+                Ok(Value::Float(word.parse().unwrap()))
+            }
+            Token::Number(lexeme) => match lexeme.parse::<i64>() {
+                Ok(value) => Ok(Value::Int(value)),
+                Err(_) => float(&lexeme)
+                    .map(Value::Float)
+                    .ok_or(ParseError::InvalidNumber(lexeme, line)),
+            },
+            _ => {
+                self.position -= 1;
+                Err(self.error())
+            }
+        }
+    }
+
+    fn volatile(&mut self) -> bool {
+        self.accept_word("volatile")
+    }
+
+    /// `ty value (, ty value)*` up to the closing parenthesis.
+    fn arguments(&mut self) -> Result<(Vec<(IrType, Value)>, ByValue), ParseError> {
+        self.expect_punct('(')?;
+        let mut arguments = vec![];
+        let mut by_value = ByValue::default();
+        while !self.is_punct(')') {
+            if !arguments.is_empty() {
+                self.expect_punct(',')?;
+            }
+            let ty = self.ir_type()?;
+            self.by_value(arguments.len(), &mut by_value)?;
+            arguments.push((ty, self.value()?));
+        }
+        self.expect_punct(')')?;
+        Ok((arguments, by_value))
+    }
+
+    /// The layout of the struct an argument or parameter stands for, if
+    /// any.
+    fn by_value(&mut self, index: usize, by_value: &mut ByValue) -> Result<(), ParseError> {
+        let returned = index == 0 && self.accept_word("sret");
+        if !returned && !self.accept_word("byval") {
+            return Ok(());
+        }
+        let size = self.number()?;
+        self.expect_word("align")?;
+        let alignment = self.number()?;
+        self.expect_punct('{')?;
+        let mut members = vec![];
+        while !self.is_punct('}') {
+            if !members.is_empty() {
+                self.expect_punct(',')?;
+            }
+            let offset = self.number()?;
+            self.expect_punct(':')?;
+            members.push((offset, self.ir_type()?));
+        }
+        self.expect_punct('}')?;
+        let aggregate = Aggregate {
+            size,
+            alignment,
+            members,
+        };
+        if returned {
+            by_value.returned = Some(aggregate);
+        } else {
+            by_value.passed.push((index, aggregate));
+        }
+        Ok(())
+    }
+
+    /// An instruction that defines `dst`.
+    fn definition(&mut self, dst: Temp) -> Result<Instr, ParseError> {
+        let start = self.position;
+        let word = self.word()?;
+
+        if let Some(op) = unary_op(&word) {
+            let ty = self.ir_type()?;
+            let operand = self.value()?;
+            return Ok(Instr::Unary {
+                dst,
+                op,
+                ty,
+                operand,
+            });
+        }
+        if let Some(op) = binary_op(&word) {
+            let ty = self.ir_type()?;
+            let left = self.value()?;
+            self.expect_punct(',')?;
+            let right = self.value()?;
+            return Ok(Instr::Binary {
+                dst,
+                op,
+                ty,
+                left,
+                right,
+            });
+        }
+        if let Some(op) = convert_op(&word) {
+            let from = self.ir_type()?;
+            let value = self.value()?;
+            self.expect_word("to")?;
+            let to = self.ir_type()?;
+            return Ok(Instr::Convert {
+                dst,
+                op,
+                from,
+                to,
+                value,
+            });
+        }
+
+        match word.as_str() {
+            "phi" => {
+                let ty = self.ir_type()?;
+                let mut incoming = vec![];
+                while self.is_punct('[') {
+                    self.position += 1;
+                    let value = self.value()?;
+                    self.expect_punct(',')?;
+                    let label = self.label()?;
+                    self.expect_punct(']')?;
+                    incoming.push((label, value));
+                    if !self.is_punct(',') {
+                        break;
+                    }
+                    self.position += 1;
+                }
+                Ok(Instr::Phi { dst, ty, incoming })
+            }
+            "copy" => {
+                let ty = self.ir_type()?;
+                let src = self.value()?;
+                Ok(Instr::Copy { dst, ty, src })
+            }
+            "cmp" => {
+                let condition = self.word_as(condition)?;
+                let ty = self.ir_type()?;
+                let left = self.value()?;
+                self.expect_punct(',')?;
+                let right = self.value()?;
+                Ok(Instr::Compare {
+                    dst,
+                    condition,
+                    ty,
+                    left,
+                    right,
+                })
+            }
+            "alloca" => {
+                let size = self.number()?;
+                self.expect_punct(',')?;
+                self.expect_word("align")?;
+                let alignment = self.number()?;
+                Ok(Instr::Alloca {
+                    dst,
+                    size,
+                    alignment,
+                })
+            }
+            "load" => {
+                let volatile = self.volatile();
+                let ty = self.ir_type()?;
+                let address = self.value()?;
+                Ok(Instr::Load {
+                    dst,
+                    ty,
+                    address,
+                    volatile,
+                })
+            }
+            "call" => {
+                let ty = self.ir_type()?;
+                let callee = self.value()?;
+                let (arguments, by_value) = self.arguments()?;
+                Ok(Instr::Call {
+                    dst: Some((dst, ty)),
+                    callee,
+                    arguments,
+                    by_value,
+                })
+            }
+            _ => {
+                self.position = start;
+                Err(self.error())
+            }
+        }
+    }
+
+    fn instruction(&mut self) -> Result<Instr, ParseError> {
+        if let Some(Token::Temp(_)) = self.peek() {
+            let dst = self.temp()?;
+            self.expect_punct('=')?;
+            return self.definition(dst);
+        }
+
+        if let Some(Token::Word(word)) = self.peek()
+            && let Some(label) = label(word)
+            && self.peek_at(1) == Some(&Token::Punct(':'))
+        {
+            self.position += 2;
+            return Ok(Instr::Label(label));
+        }
+
+        let start = self.position;
+        match self.word()?.as_str() {
+            "store" => {
+                let volatile = self.volatile();
+                let ty = self.ir_type()?;
+                let value = self.value()?;
+                self.expect_punct(',')?;
+                let address = self.value()?;
+                Ok(Instr::Store {
+                    ty,
+                    value,
+                    address,
+                    volatile,
+                })
+            }
+            "memcpy" => {
+                let destination = self.value()?;
+                self.expect_punct(',')?;
+                let source = self.value()?;
+                self.expect_punct(',')?;
+                let size = self.number()?;
+                Ok(Instr::MemCopy {
+                    destination,
+                    source,
+                    size,
+                })
+            }
+            "call" => {
+                self.expect_word("void")?;
+                let callee = self.value()?;
+                let (arguments, by_value) = self.arguments()?;
+                Ok(Instr::Call {
+                    dst: None,
+                    callee,
+                    arguments,
+                    by_value,
+                })
+            }
+            "jmp" => Ok(Instr::Jump(self.label()?)),
+            "br" => {
+                let ty = self.ir_type()?;
+                let condition = self.value()?;
+                self.expect_punct(',')?;
+                let if_true = self.label()?;
+                self.expect_punct(',')?;
+                let if_false = self.label()?;
+                Ok(Instr::Branch {
+                    ty,
+                    condition,
+                    if_true,
+                    if_false,
+                })
+            }
+            "ret" => match self.peek() {
+                Some(Token::Word(word)) if ir_type(word).is_some() => {
+                    let ty = self.ir_type()?;
+                    Ok(Instr::Return(Some((ty, self.value()?))))
+                }
+                _ => Ok(Instr::Return(None)),
+            },
+            _ => {
+                self.position = start;
+                Err(self.error())
+            }
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, ParseError> {
+        self.expect_word("function")?;
+        let return_type = self.return_type()?;
+        let name = match self.next()? {
+            Token::Global(name) => name,
+            _ => {
+                self.position -= 1;
+                return Err(self.error());
+            }
+        };
+
+        self.expect_punct('(')?;
+        let mut parameters = vec![];
+        let mut by_value = ByValue::default();
+        let mut variadic = false;
+        while !self.is_punct(')') {
+            if !parameters.is_empty() {
+                self.expect_punct(',')?;
+            }
+            if self.accept_word("...") {
+                variadic = true;
+                break;
+            }
+            let ty = self.ir_type()?;
+            self.by_value(parameters.len(), &mut by_value)?;
+            parameters.push((self.temp()?, ty));
+        }
+        self.expect_punct(')')?;
+        let is_global = !self.accept_word("internal");
+        let inlining = if self.accept_word("inlinehint") {
+            Inlining::Hint
+        } else if self.accept_word("alwaysinline") {
+            Inlining::Always
+        } else if self.accept_word("noinline") {
+            Inlining::Never
+        } else {
+            Inlining::Default
+        };
+
+        self.expect_punct('{')?;
+        let mut body = vec![];
+        let mut lines = vec![];
+        while !self.is_punct('}') {
+            lines.push(self.line());
+            body.push(self.instruction()?);
+        }
+        self.expect_punct('}')?;
+        resolve(&name, &parameters, &body, &lines)?;
+
+        Ok(Function {
+            name,
+            is_global,
+            inlining,
+            parameters,
+            variadic,
+            return_type,
+            by_value,
+            body,
+        })
+    }
+
+    fn global_init(&mut self) -> Result<GlobalInit, ParseError> {
+        if self.accept_word("zero") {
+            return Ok(GlobalInit::Zero(self.number()?));
+        }
+        if self.accept_word("addr") {
+            let name = match self.next()? {
+                Token::Global(name) => name,
+                _ => {
+                    self.position -= 1;
+                    return Err(self.error());
+                }
+            };
+            self.expect_punct('+')?;
+            return Ok(GlobalInit::Address(name, self.number()?));
+        }
+        if self.accept_word("bytes") {
+            return match self.next()? {
+                Token::String(bytes) => Ok(GlobalInit::Bytes(bytes)),
+                _ => {
+                    self.position -= 1;
+                    Err(self.error())
+                }
+            };
+        }
+
+        let ty = self.ir_type()?;
+        if ty.is_float() {
+            Ok(GlobalInit::Float(ty, self.float()?))
+        } else {
+            Ok(GlobalInit::Int(ty, self.number()?))
+        }
+    }
+
+    fn global(&mut self) -> Result<Global, ParseError> {
+        self.expect_word("global")?;
+        let name = match self.next()? {
+            Token::Global(name) => name,
+            _ => {
+                self.position -= 1;
+                return Err(self.error());
+            }
+        };
+        let is_global = !self.accept_word("internal");
+        let is_constant = self.accept_word("constant");
+        self.expect_word("align")?;
+        let alignment = self.number()?;
+
+        self.expect_punct('{')?;
+        let mut init = vec![];
+        while !self.is_punct('}') {
+            if !init.is_empty() {
+                self.expect_punct(',')?;
+            }
+            init.push(self.global_init()?);
+        }
+        self.expect_punct('}')?;
+
+        Ok(Global {
+            name,
+            is_global,
+            is_constant,
+            alignment,
+            init,
+        })
+    }
+
+    fn program(&mut self) -> Result<Program, ParseError> {
+        let mut program = Program::default();
+        while self.peek().is_some() {
+            if self.is_word("global") {
+                program.globals.push(self.global()?);
+            } else {
+                program.functions.push(self.function()?);
+            }
+        }
+        Ok(program)
+    }
+}
+
+/// Checks that every label and temporary the body refers to exists, so
+/// the passes, the backend and the interpreter can rely on it.
+fn resolve(
+    name: &str,
+    parameters: &[(Temp, IrType)],
+    body: &[Instr],
+    lines: &[usize],
+) -> Result<(), ParseError> {
+    let labels: HashSet<Label> = body
+        .iter()
+        .filter_map(|instr| match instr {
+            Instr::Label(label) => Some(*label),
+            _ => None,
+        })
+        .collect();
+    let temps: HashSet<Temp> = parameters
+        .iter()
+        .map(|(temp, _)| *temp)
+        .chain(body.iter().filter_map(Instr::defined))
+        .collect();
+
+    for (instr, &line) in body.iter().zip(lines) {
+        let mut referenced = instr.targets();
+        if let Instr::Phi { incoming, .. } = instr {
+            referenced.extend(incoming.iter().map(|(label, _)| *label));
+        }
+        if let Some(label) = referenced.iter().find(|label| !labels.contains(label)) {
+            return Err(ParseError::UndefinedLabel(
+                format!("{} in @{}", label, name),
+                line,
+            ));
+        }
+        if let Some(temp) = instr
+            .used_temps()
+            .into_iter()
+            .find(|temp| !temps.contains(temp))
+        {
+            return Err(ParseError::UndefinedTemp(
+                format!("{} in @{}", temp, name),
+                line,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a program in the syntax produced by printing an `ir::Program`.
+/// Comments run from `;` to the end of the line.
+pub fn parse(source: &str) -> Result<Program, ParseError> {
+    let tokens = tokenize(source)?;
+    Parser {
+        tokens,
+        position: 0,
+    }
+    .program()
+}
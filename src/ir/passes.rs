@@ -0,0 +1,377 @@
+/*
+  The pass manager. Transforms are registered by name with the analyses
+  they keep valid, and run in order over every function of a program, or
+  over the whole program at once for those that move code between
+  functions.
+  Analyses are computed on demand and cached per function; when a
+  transform reports a change, every analysis it does not preserve is
+  dropped, so the next pass that asks for it gets a fresh one. Transforms
+  edit a copy of the cached control-flow graph, and only rebuild
+  dominators or loops for a graph they have changed themselves.
+
+  The optimization levels are fixed lists of pass names. A custom list can
+  be given instead, the time spent in each pass can be reported, and the
+  IR can be printed after chosen passes.
+*/
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use super::cfg::Cfg;
+use super::dominators::DominatorTree;
+use super::loops::{self, Loop};
+use super::{Function, Program, dce, fold, gvn, inline, licm, reduce, sccp, ssa, tailcall, unroll};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Analysis {
+    Cfg,
+    Dominators,
+    Loops,
+}
+
+/// The analyses of one function that are currently valid. Dominators and
+/// loops refer to blocks by their index in the control-flow graph, so they
+/// stay valid as long as the blocks do.
+#[derive(Default)]
+pub struct Analyses {
+    cfg: Option<Cfg>,
+    dominators: Option<DominatorTree>,
+    loops: Option<Vec<Loop>>,
+}
+
+impl Analyses {
+    pub fn cfg(&mut self, function: &Function) -> &Cfg {
+        self.cfg.get_or_insert_with(|| Cfg::build(function))
+    }
+
+    pub fn dominators(&mut self, function: &Function) -> &DominatorTree {
+        if self.dominators.is_none() {
+            let dominators = DominatorTree::build(self.cfg(function));
+            self.dominators = Some(dominators);
+        }
+        self.dominators.as_ref().unwrap()
+    }
+
+    pub fn loops(&mut self, function: &Function) -> &[Loop] {
+        if self.loops.is_none() {
+            self.dominators(function);
+            let loops = loops::find_loops(
+                self.cfg.as_ref().unwrap(),
+                self.dominators.as_ref().unwrap(),
+            );
+            self.loops = Some(loops);
+        }
+        self.loops.as_ref().unwrap()
+    }
+
+    pub fn is_cached(&self, analysis: Analysis) -> bool {
+        match analysis {
+            Analysis::Cfg => self.cfg.is_some(),
+            Analysis::Dominators => self.dominators.is_some(),
+            Analysis::Loops => self.loops.is_some(),
+        }
+    }
+
+    /// Drops every analysis not in `preserved`.
+    pub fn invalidate(&mut self, preserved: &[Analysis]) {
+        if !preserved.contains(&Analysis::Cfg) {
+            self.cfg = None;
+        }
+        if !preserved.contains(&Analysis::Dominators) {
+            self.dominators = None;
+        }
+        if !preserved.contains(&Analysis::Loops) {
+            self.loops = None;
+        }
+    }
+}
+
+/// Settings of the passes that depend on the optimization level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// The largest callee, in instructions, that is inlined without being
+    /// declared `inline`.
+    pub inline_threshold: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            inline_threshold: 40,
+        }
+    }
+}
+
+/// A transform, which returns whether anything changed. A function
+/// transform that changes the body must say so, or the cached analyses
+/// would no longer describe it.
+pub enum Transform {
+    Function(fn(&mut Function, &mut Analyses) -> bool),
+    /// Runs once over the whole program; every analysis is dropped after.
+    Program(fn(&mut Program, &Options) -> bool),
+}
+
+pub struct Pass {
+    pub name: &'static str,
+    pub run: Transform,
+    /// The analyses that remain valid after the pass changes a function.
+    pub preserves: &'static [Analysis],
+}
+
+pub const PASSES: &[Pass] = &[
+    Pass {
+        name: "ssa",
+        run: Transform::Function(|function, _| {
+            ssa::construct(function);
+            true
+        }),
+        preserves: &[],
+    },
+    Pass {
+        name: "out-of-ssa",
+        run: Transform::Function(|function, _| {
+            ssa::destruct(function);
+            true
+        }),
+        preserves: &[],
+    },
+    Pass {
+        name: "sccp",
+        run: Transform::Function(sccp::run),
+        preserves: &[],
+    },
+    Pass {
+        name: "gvn",
+        run: Transform::Function(gvn::run),
+        preserves: &[],
+    },
+    // Folding rewrites instructions in place and never touches jumps.
+    Pass {
+        name: "fold",
+        run: Transform::Function(|function, _| fold::run(function)),
+        preserves: &[Analysis::Dominators, Analysis::Loops],
+    },
+    Pass {
+        name: "dce",
+        run: Transform::Function(dce::run),
+        preserves: &[],
+    },
+    Pass {
+        name: "licm",
+        run: Transform::Function(licm::run),
+        preserves: &[],
+    },
+    Pass {
+        name: "loop-reduce",
+        run: Transform::Function(reduce::run),
+        preserves: &[],
+    },
+    Pass {
+        name: "loop-unroll",
+        run: Transform::Function(unroll::run),
+        preserves: &[],
+    },
+    Pass {
+        name: "inline",
+        run: Transform::Program(|program, options| inline::run(program, options.inline_threshold)),
+        preserves: &[],
+    },
+    Pass {
+        name: "tail-call",
+        run: Transform::Function(tailcall::run),
+        preserves: &[],
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static Pass> {
+    PASSES.iter().find(|pass| pass.name == name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    Os,
+}
+
+impl OptLevel {
+    pub fn pipeline(&self) -> &'static [&'static str] {
+        match self {
+            OptLevel::O0 => &[],
+            OptLevel::O1 | OptLevel::Os => &[
+                "ssa",
+                "sccp",
+                "gvn",
+                "fold",
+                "dce",
+                "inline",
+                "tail-call",
+                "sccp",
+                "dce",
+            ],
+            OptLevel::O2 => &[
+                "ssa",
+                "sccp",
+                "gvn",
+                "fold",
+                "dce",
+                "inline",
+                "tail-call",
+                "sccp",
+                "gvn",
+                "fold",
+                "dce",
+                "licm",
+                "loop-reduce",
+                "loop-unroll",
+                "sccp",
+                "gvn",
+                "fold",
+                "dce",
+            ],
+        }
+    }
+
+    pub fn options(&self) -> Options {
+        match self {
+            // Only callees about the size of the call itself.
+            OptLevel::Os => Options {
+                inline_threshold: 8,
+            },
+            _ => Options::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassError {
+    UnknownPass(String),
+}
+
+pub struct PassManager {
+    passes: Vec<&'static Pass>,
+    options: Options,
+    print_after: HashSet<&'static str>,
+    /// Time spent in each pass, in order of first run.
+    timings: Vec<(&'static str, Duration)>,
+}
+
+impl PassManager {
+    pub fn new(names: &[&str]) -> Result<PassManager, PassError> {
+        let passes = names
+            .iter()
+            .map(|name| lookup(name).ok_or_else(|| PassError::UnknownPass(name.to_string())))
+            .collect::<Result<_, _>>()?;
+        Ok(PassManager {
+            passes,
+            options: Options::default(),
+            print_after: HashSet::new(),
+            timings: vec![],
+        })
+    }
+
+    pub fn for_level(level: OptLevel) -> PassManager {
+        let mut manager = PassManager::new(level.pipeline()).unwrap();
+        manager.options = level.options();
+        manager
+    }
+
+    /// Prints each function after every run of the named pass.
+    pub fn print_after(&mut self, name: &str) -> Result<(), PassError> {
+        let pass = lookup(name).ok_or_else(|| PassError::UnknownPass(name.to_string()))?;
+        self.print_after.insert(pass.name);
+        Ok(())
+    }
+
+    /// Runs the passes in order over every function, printing to `dump`
+    /// where requested. Returns whether anything changed.
+    pub fn run(&mut self, program: &mut Program, dump: &mut dyn Write) -> bool {
+        let mut analyses: Vec<Analyses> = program
+            .functions
+            .iter()
+            .map(|_| Analyses::default())
+            .collect();
+        let mut changed = false;
+
+        for pass in &self.passes {
+            let start = Instant::now();
+            match pass.run {
+                Transform::Function(run) => {
+                    for (function, analyses) in program.functions.iter_mut().zip(&mut analyses) {
+                        if run(function, analyses) {
+                            analyses.invalidate(pass.preserves);
+                            changed = true;
+                        }
+                    }
+                }
+                Transform::Program(run) => {
+                    if run(program, &self.options) {
+                        analyses = program
+                            .functions
+                            .iter()
+                            .map(|_| Analyses::default())
+                            .collect();
+                        changed = true;
+                    }
+                }
+            }
+            let elapsed = start.elapsed();
+            match self.timings.iter_mut().find(|(name, _)| *name == pass.name) {
+                Some((_, total)) => *total += elapsed,
+                None => self.timings.push((pass.name, elapsed)),
+            }
+
+            if self.print_after.contains(pass.name) {
+                for function in &program.functions {
+                    writeln!(
+                        dump,
+                        "; *** IR after {} on @{} ***",
+                        pass.name, function.name
+                    )
+                    .unwrap();
+                    write!(dump, "{}", function).unwrap();
+                }
+            }
+        }
+        changed
+    }
+
+    /// A table of the time spent in each pass so far.
+    pub fn time_report(&self) -> String {
+        let total: Duration = self.timings.iter().map(|(_, time)| *time).sum();
+        let mut report = String::from("Pass execution timing report\n");
+        writeln!(
+            report,
+            "  {:<12} {:>12} {:>8}",
+            "pass", "time (ms)", "percent"
+        )
+        .unwrap();
+        for (name, time) in &self.timings {
+            let percent = if total.is_zero() {
+                0.0
+            } else {
+                100.0 * time.as_secs_f64() / total.as_secs_f64()
+            };
+            writeln!(
+                report,
+                "  {:<12} {:>12.3} {:>7.1}%",
+                name,
+                time.as_secs_f64() * 1000.0,
+                percent
+            )
+            .unwrap();
+        }
+        writeln!(
+            report,
+            "  {:<12} {:>12.3} {:>7.1}%",
+            "total",
+            total.as_secs_f64() * 1000.0,
+            100.0
+        )
+        .unwrap();
+        report
+    }
+}
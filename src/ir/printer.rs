@@ -0,0 +1,401 @@
+/*
+  Textual form of the IR, read back by `ir::parser`:
+
+    global @table align 4 { i32 1, zero 4, addr @table + 8, bytes "hi\00" }
+
+    function i32 @f(i32 %0) internal inlinehint {
+      %1 = alloca 4, align 4
+      store i32 %0, %1
+    L0:
+      %2 = load i32 %1
+      %3 = phi i32 [%2, L0], [0, L4]
+      %4 = cmp slt i32 %3, 10
+      br i32 %4, L1, L2
+      ...
+    }
+
+  A pointer that stands for a struct passed or returned by value carries
+  the struct's layout, as in `i64 byval 16 align 8 { 0: i32, 8: f64 } %1`
+  or `i64 sret ...` for the hidden return pointer.
+*/
+
+use std::fmt;
+
+use super::{
+    BinaryOp, ByValue, Condition, ConvertOp, Function, Global, GlobalInit, Inlining, Instr, IrType,
+    Label, Program, Temp, UnaryOp, Value,
+};
+
+impl IrType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IrType::I8 => "i8",
+            IrType::I16 => "i16",
+            IrType::I32 => "i32",
+            IrType::I64 => "i64",
+            IrType::F32 => "f32",
+            IrType::F64 => "f64",
+        }
+    }
+}
+
+impl UnaryOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "neg",
+            UnaryOp::Not => "not",
+            UnaryOp::FNeg => "fneg",
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::SDiv => "sdiv",
+            BinaryOp::UDiv => "udiv",
+            BinaryOp::SRem => "srem",
+            BinaryOp::URem => "urem",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::Xor => "xor",
+            BinaryOp::Shl => "shl",
+            BinaryOp::AShr => "ashr",
+            BinaryOp::LShr => "lshr",
+            BinaryOp::FAdd => "fadd",
+            BinaryOp::FSub => "fsub",
+            BinaryOp::FMul => "fmul",
+            BinaryOp::FDiv => "fdiv",
+        }
+    }
+}
+
+impl Condition {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Condition::Eq => "eq",
+            Condition::Ne => "ne",
+            Condition::SLt => "slt",
+            Condition::SLe => "sle",
+            Condition::SGt => "sgt",
+            Condition::SGe => "sge",
+            Condition::ULt => "ult",
+            Condition::ULe => "ule",
+            Condition::UGt => "ugt",
+            Condition::UGe => "uge",
+            Condition::FEq => "feq",
+            Condition::FNe => "fne",
+            Condition::FLt => "flt",
+            Condition::FLe => "fle",
+            Condition::FGt => "fgt",
+            Condition::FGe => "fge",
+        }
+    }
+}
+
+impl ConvertOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConvertOp::SExt => "sext",
+            ConvertOp::ZExt => "zext",
+            ConvertOp::Trunc => "trunc",
+            ConvertOp::SIToFP => "sitofp",
+            ConvertOp::UIToFP => "uitofp",
+            ConvertOp::FPToSI => "fptosi",
+            ConvertOp::FPToUI => "fptoui",
+            ConvertOp::FPExt => "fpext",
+            ConvertOp::FPTrunc => "fptrunc",
+        }
+    }
+}
+
+impl fmt::Display for IrType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Temp(temp) => write!(f, "{}", temp),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write_float(f, *value),
+            Value::Global(name) => write!(f, "@{}", name),
+        }
+    }
+}
+
+/// Finite floats print with `{:?}`, which always has a '.' or an exponent
+/// that tells them apart from integers, and round-trips exactly. NaNs and
+/// infinities print as their bits in hexadecimal, `0xfff8000000000000`,
+/// so that the sign of a NaN survives.
+fn write_float(f: &mut fmt::Formatter, value: f64) -> fmt::Result {
+    if value.is_finite() {
+        write!(f, "{:?}", value)
+    } else {
+        write!(f, "{:#018x}", value.to_bits())
+    }
+}
+
+fn write_return_type(f: &mut fmt::Formatter, ty: Option<IrType>) -> fmt::Result {
+    match ty {
+        Some(ty) => write!(f, "{}", ty),
+        None => write!(f, "void"),
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instr::Label(label) => write!(f, "{}:", label),
+            Instr::Phi { dst, ty, incoming } => {
+                write!(f, "  {} = phi {}", dst, ty)?;
+                for (index, (label, value)) in incoming.iter().enumerate() {
+                    let separator = if index > 0 { "," } else { "" };
+                    write!(f, "{} [{}, {}]", separator, value, label)?;
+                }
+                Ok(())
+            }
+            Instr::Copy { dst, ty, src } => write!(f, "  {} = copy {} {}", dst, ty, src),
+            Instr::Unary {
+                dst,
+                op,
+                ty,
+                operand,
+            } => write!(f, "  {} = {} {} {}", dst, op.name(), ty, operand),
+            Instr::Binary {
+                dst,
+                op,
+                ty,
+                left,
+                right,
+            } => write!(f, "  {} = {} {} {}, {}", dst, op.name(), ty, left, right),
+            Instr::Compare {
+                dst,
+                condition,
+                ty,
+                left,
+                right,
+            } => write!(
+                f,
+                "  {} = cmp {} {} {}, {}",
+                dst,
+                condition.name(),
+                ty,
+                left,
+                right
+            ),
+            Instr::Convert {
+                dst,
+                op,
+                from,
+                to,
+                value,
+            } => write!(f, "  {} = {} {} {} to {}", dst, op.name(), from, value, to),
+            Instr::Alloca {
+                dst,
+                size,
+                alignment,
+            } => write!(f, "  {} = alloca {}, align {}", dst, size, alignment),
+            Instr::Load {
+                dst,
+                ty,
+                address,
+                volatile,
+            } => {
+                let volatile = if *volatile { "volatile " } else { "" };
+                write!(f, "  {} = load {}{} {}", dst, volatile, ty, address)
+            }
+            Instr::Store {
+                ty,
+                value,
+                address,
+                volatile,
+            } => {
+                let volatile = if *volatile { "volatile " } else { "" };
+                write!(f, "  store {}{} {}, {}", volatile, ty, value, address)
+            }
+            Instr::MemCopy {
+                destination,
+                source,
+                size,
+            } => write!(f, "  memcpy {}, {}, {}", destination, source, size),
+            Instr::Call {
+                dst,
+                callee,
+                arguments,
+                by_value,
+            } => {
+                write!(f, "  ")?;
+                if let Some((dst, _)) = dst {
+                    write!(f, "{} = ", dst)?;
+                }
+                write!(f, "call ")?;
+                write_return_type(f, dst.map(|(_, ty)| ty))?;
+                write!(f, " {}(", callee)?;
+                for (index, (ty, argument)) in arguments.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", ty)?;
+                    write_by_value(f, by_value, index)?;
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
+            }
+            Instr::Jump(label) => write!(f, "  jmp {}", label),
+            Instr::Branch {
+                ty,
+                condition,
+                if_true,
+                if_false,
+            } => write!(f, "  br {} {}, {}, {}", ty, condition, if_true, if_false),
+            Instr::Return(Some((ty, value))) => write!(f, "  ret {} {}", ty, value),
+            Instr::Return(None) => write!(f, "  ret"),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "function ")?;
+        write_return_type(f, self.return_type)?;
+        write!(f, " @{}(", self.name)?;
+        for (index, (temp, ty)) in self.parameters.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", ty)?;
+            write_by_value(f, &self.by_value, index)?;
+            write!(f, " {}", temp)?;
+        }
+        if self.variadic {
+            if !self.parameters.is_empty() {
+                write!(f, ", ")?;
+            }
+            write!(f, "...")?;
+        }
+        write!(f, ")")?;
+        if !self.is_global {
+            write!(f, " internal")?;
+        }
+        match self.inlining {
+            Inlining::Default => {}
+            Inlining::Hint => write!(f, " inlinehint")?,
+            Inlining::Always => write!(f, " alwaysinline")?,
+            Inlining::Never => write!(f, " noinline")?,
+        }
+        writeln!(f, " {{")?;
+
+        for instr in &self.body {
+            writeln!(f, "{}", instr)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// The layout of the struct an argument or parameter points to, if it
+/// stands for one.
+fn write_by_value(f: &mut fmt::Formatter, by_value: &ByValue, index: usize) -> fmt::Result {
+    let Some(aggregate) = by_value.aggregate(index) else {
+        return Ok(());
+    };
+    let kind = if index == 0 && by_value.returned.is_some() {
+        "sret"
+    } else {
+        "byval"
+    };
+    write!(
+        f,
+        " {} {} align {} {{",
+        kind, aggregate.size, aggregate.alignment
+    )?;
+    for (position, (offset, ty)) in aggregate.members.iter().enumerate() {
+        if position > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, " {}: {}", offset, ty)?;
+    }
+    write!(f, " }}")
+}
+
+fn write_bytes(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{:02x}", byte)?,
+            0x20..=0x7e => write!(f, "{}", *byte as char)?,
+            _ => write!(f, "\\{:02x}", byte)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for GlobalInit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GlobalInit::Zero(size) => write!(f, "zero {}", size),
+            GlobalInit::Int(ty, value) => write!(f, "{} {}", ty, value),
+            GlobalInit::Float(ty, value) => {
+                write!(f, "{} ", ty)?;
+                write_float(f, *value)
+            }
+            GlobalInit::Address(name, offset) => write!(f, "addr @{} + {}", name, offset),
+            GlobalInit::Bytes(bytes) => {
+                write!(f, "bytes ")?;
+                write_bytes(f, bytes)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Global {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "global @{}", self.name)?;
+        if !self.is_global {
+            write!(f, " internal")?;
+        }
+        if self.is_constant {
+            write!(f, " constant")?;
+        }
+        write!(f, " align {} {{", self.alignment)?;
+        for (index, init) in self.init.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, " {}", init)?;
+        }
+        writeln!(f, " }}")
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for global in &self.globals {
+            write!(f, "{}", global)?;
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 || !self.globals.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+/*
+  Strength reduction of induction variables on a function in SSA form.
+  Multiplying a basic induction variable by a constant, possibly after
+  sign-extending it, gives a value that grows by a constant too, and so
+  does adding a loop-invariant base to that, as indexing `a[i]` does. Each
+  such value becomes an induction variable of its own, a phi advanced by
+  an addition on the back edge, and the multiplication is left for dead
+  code elimination. Array indexing thus turns into a pointer stepping
+  through the array.
+
+  Sign extension commutes with the additions only as long as the variable
+  does not overflow, which C leaves undefined for signed integers, the
+  only ones the lowering sign-extends.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::Cfg;
+use super::loops::{self, Induction, Loop};
+use super::passes::Analyses;
+use super::{BinaryOp, ConvertOp, Function, Instr, IrType, Temp, Value, fold, ssa};
+
+/// `scale * (the variable, extended to ty) + base`, computed by `dst`.
+struct Reduction {
+    dst: Temp,
+    variable: Temp,
+    ty: IrType,
+    extended: bool,
+    scale: i64,
+    base: Option<Value>,
+}
+
+/// The values in the loop that are a constant multiple of an induction
+/// variable, plus an invariant base for those worth a variable of their
+/// own.
+fn reductions(
+    cfg: &Cfg,
+    natural_loop: &Loop,
+    variables: &HashMap<Temp, Induction>,
+    uses: &HashMap<Temp, usize>,
+) -> Vec<Reduction> {
+    let instrs = || {
+        natural_loop
+            .blocks
+            .iter()
+            .flat_map(|&block| &cfg.blocks[block].instrs)
+    };
+    let defined: HashSet<Temp> = instrs().filter_map(Instr::defined).collect();
+    let invariant = |value: &Value| match value {
+        Value::Temp(temp) => !defined.contains(temp),
+        _ => true,
+    };
+
+    // Induction variables, possibly sign-extended.
+    let mut extended: HashMap<Temp, (Temp, IrType)> = variables
+        .iter()
+        .map(|(&variable, induction)| (variable, (variable, induction.ty)))
+        .collect();
+    for instr in instrs() {
+        if let Instr::Convert {
+            dst,
+            op: ConvertOp::SExt,
+            from,
+            to,
+            value: Value::Temp(value),
+        } = instr
+            && let Some(induction) = variables.get(value)
+            && induction.ty == *from
+        {
+            extended.insert(*dst, (*value, *to));
+        }
+    }
+
+    let mut multiples = HashMap::new();
+    for instr in instrs() {
+        let Instr::Binary {
+            dst,
+            op,
+            ty,
+            left,
+            right,
+        } = instr
+        else {
+            continue;
+        };
+        let scale = match (op, left, right) {
+            (BinaryOp::Mul, Value::Temp(temp), Value::Int(scale))
+            | (BinaryOp::Mul, Value::Int(scale), Value::Temp(temp)) => Some((temp, *scale)),
+            (BinaryOp::Shl, Value::Temp(temp), Value::Int(shift)) if (0..63).contains(shift) => {
+                Some((temp, 1 << shift))
+            }
+            _ => None,
+        };
+        if let Some((temp, scale)) = scale
+            && let Some(&(variable, extended_type)) = extended.get(temp)
+            && extended_type == *ty
+        {
+            multiples.insert(
+                *dst,
+                Reduction {
+                    dst: *dst,
+                    variable,
+                    ty: *ty,
+                    extended: temp != &variable,
+                    scale,
+                    base: None,
+                },
+            );
+        }
+    }
+
+    let mut reductions = vec![];
+    let mut reduced_uses: HashMap<Temp, usize> = HashMap::new();
+    for instr in instrs() {
+        let Instr::Binary {
+            dst,
+            op: BinaryOp::Add,
+            ty,
+            left,
+            right,
+        } = instr
+        else {
+            continue;
+        };
+        let (multiple, base) = match (left, right) {
+            (Value::Temp(temp), base) | (base, Value::Temp(temp))
+                if multiples.contains_key(temp) && invariant(base) =>
+            {
+                (&multiples[temp], base)
+            }
+            _ => continue,
+        };
+        if multiple.ty != *ty {
+            continue;
+        }
+        *reduced_uses.entry(multiple.dst).or_default() += 1;
+        reductions.push(Reduction {
+            dst: *dst,
+            base: Some(base.clone()),
+            ..*multiple
+        });
+    }
+    // A multiple used other than as an offset needs a variable too.
+    for (dst, multiple) in multiples {
+        if uses.get(&dst) != reduced_uses.get(&dst) {
+            reductions.push(multiple);
+        }
+    }
+    reductions.sort_by_key(|reduction| reduction.dst);
+    reductions
+}
+
+/// Replaces the reducible values of one loop by new induction variables.
+fn reduce(
+    cfg: &mut Cfg,
+    natural_loop: &Loop,
+    uses: &HashMap<Temp, usize>,
+    next_temp: &mut u32,
+) -> bool {
+    let (Some(preheader), [latch]) = (natural_loop.preheader(cfg), &natural_loop.latches[..])
+    else {
+        return false;
+    };
+    let entry = cfg.blocks[preheader].label;
+    let latch_label = cfg.blocks[*latch].label;
+    let variables = loops::inductions(cfg, natural_loop, entry);
+    let reductions = reductions(cfg, natural_loop, &variables, uses);
+    if reductions.is_empty() {
+        return false;
+    }
+
+    let mut fresh = || {
+        *next_temp += 1;
+        Temp(*next_temp - 1)
+    };
+    let mut setup = vec![];
+    let mut phis = vec![];
+    let mut increments = vec![];
+    let mut replaced = HashMap::new();
+    for reduction in reductions {
+        let induction = &variables[&reduction.variable];
+        let ty = reduction.ty;
+        let mut start = induction.init.clone();
+        if reduction.extended {
+            let dst = fresh();
+            setup.push(Instr::Convert {
+                dst,
+                op: ConvertOp::SExt,
+                from: induction.ty,
+                to: ty,
+                value: start,
+            });
+            start = Value::Temp(dst);
+        }
+        let dst = fresh();
+        setup.push(Instr::Binary {
+            dst,
+            op: BinaryOp::Mul,
+            ty,
+            left: start,
+            right: Value::Int(reduction.scale),
+        });
+        start = Value::Temp(dst);
+        if let Some(base) = reduction.base {
+            let dst = fresh();
+            setup.push(Instr::Binary {
+                dst,
+                op: BinaryOp::Add,
+                ty,
+                left: base,
+                right: start,
+            });
+            start = Value::Temp(dst);
+        }
+
+        let (variable, next) = (fresh(), fresh());
+        phis.push(Instr::Phi {
+            dst: variable,
+            ty,
+            incoming: vec![(entry, start), (latch_label, Value::Temp(next))],
+        });
+        let step = fold::constant(
+            &Value::Int(reduction.scale.wrapping_mul(induction.step)),
+            ty,
+        )
+        .unwrap();
+        increments.push(Instr::Binary {
+            dst: next,
+            op: BinaryOp::Add,
+            ty,
+            left: Value::Temp(variable),
+            right: step,
+        });
+        replaced.insert(reduction.dst, (ty, variable));
+    }
+
+    for &block in &natural_loop.blocks {
+        for instr in &mut cfg.blocks[block].instrs {
+            if let Some(dst) = instr.defined()
+                && let Some(&(ty, variable)) = replaced.get(&dst)
+            {
+                *instr = Instr::Copy {
+                    dst,
+                    ty,
+                    src: Value::Temp(variable),
+                };
+            }
+        }
+    }
+    let insert_before_terminator = |instrs: &mut Vec<Instr>, new: Vec<Instr>| {
+        let terminator = instrs.len() - 1;
+        instrs.splice(terminator..terminator, new);
+    };
+    insert_before_terminator(&mut cfg.blocks[preheader].instrs, setup);
+    insert_before_terminator(&mut cfg.blocks[*latch].instrs, increments);
+    let header = &mut cfg.blocks[natural_loop.header].instrs;
+    let first = header
+        .iter()
+        .position(|instr| !matches!(instr, Instr::Phi { .. }))
+        .unwrap();
+    header.splice(first..first, phis);
+    true
+}
+
+/// Strength-reduces the induction variables of the loops in a function in
+/// SSA form. Returns whether anything changed.
+pub fn run(function: &mut Function, analyses: &mut Analyses) -> bool {
+    if !ssa::is_ssa(function) {
+        return false;
+    }
+    let mut uses: HashMap<Temp, usize> = HashMap::new();
+    for temp in function.body.iter().flat_map(Instr::used_temps) {
+        *uses.entry(temp).or_default() += 1;
+    }
+
+    let mut cfg = analyses.cfg(function).clone();
+    let loops = analyses.loops(function).to_vec();
+    let mut next_temp = function.next_temp().0;
+    let (loops, mut changed) = loops::with_preheaders(&mut cfg, loops, &mut next_temp);
+    for natural_loop in loops.iter().rev() {
+        changed |= reduce(&mut cfg, natural_loop, &uses, &mut next_temp);
+    }
+    if changed {
+        function.body = cfg.to_body();
+    }
+    changed
+}
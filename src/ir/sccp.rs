@@ -0,0 +1,263 @@
+/*
+  Sparse conditional constant propagation (Wegman and Zadeck, 1991) on a
+  function in SSA form. Every temporary starts out unknown (top) and is
+  lowered to a constant or to overdefined (bottom) as evaluation proceeds,
+  while blocks only become executable once an edge into them does. Phis
+  meet the values on executable edges only, and a branch whose condition
+  is constant only makes one of its edges executable, so values and
+  reachability refine each other.
+
+  Afterwards uses of constant temporaries are replaced by the constant,
+  branches with a single executable edge become jumps and blocks that
+  never executed are removed. The definitions left unused are for dead
+  code elimination.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::Cfg;
+use super::passes::Analyses;
+use super::{Function, Instr, Label, Temp, Value, fold};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lattice {
+    Top,
+    Constant(Value),
+    Bottom,
+}
+
+impl Lattice {
+    fn meet(self, other: Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Top, other) | (other, Lattice::Top) => other,
+            // Floats are compared by bits so that NaN meets itself.
+            (Lattice::Constant(Value::Float(a)), Lattice::Constant(Value::Float(b)))
+                if a.to_bits() == b.to_bits() =>
+            {
+                Lattice::Constant(Value::Float(a))
+            }
+            (Lattice::Constant(a), Lattice::Constant(b))
+                if a == b && !matches!(a, Value::Float(_)) =>
+            {
+                Lattice::Constant(a)
+            }
+            _ => Lattice::Bottom,
+        }
+    }
+}
+
+struct Solver<'a> {
+    cfg: &'a Cfg,
+    indices: HashMap<Label, usize>,
+    values: HashMap<Temp, Lattice>,
+    /// Where each temporary is used, as block and position.
+    uses: HashMap<Temp, Vec<(usize, usize)>>,
+    executable: HashSet<(usize, usize)>,
+    visited: Vec<bool>,
+    flow_worklist: Vec<(usize, usize)>,
+    ssa_worklist: Vec<(usize, usize)>,
+}
+
+impl Solver<'_> {
+    fn lattice(&self, value: &Value) -> Lattice {
+        match value {
+            Value::Temp(temp) => self.values.get(temp).cloned().unwrap_or(Lattice::Top),
+            Value::Int(_) | Value::Float(_) => Lattice::Constant(value.clone()),
+            Value::Global(_) => Lattice::Bottom,
+        }
+    }
+
+    fn evaluate(&self, block: usize, instr: &Instr) -> Lattice {
+        match instr {
+            Instr::Phi { ty, incoming, .. } => {
+                let mut result = Lattice::Top;
+                for (label, value) in incoming {
+                    if !self.executable.contains(&(self.indices[label], block)) {
+                        continue;
+                    }
+                    result = result.meet(match self.lattice(value) {
+                        Lattice::Constant(value) => {
+                            Lattice::Constant(fold::constant(&value, *ty).unwrap())
+                        }
+                        lattice => lattice,
+                    });
+                }
+                result
+            }
+            Instr::Copy { .. }
+            | Instr::Unary { .. }
+            | Instr::Binary { .. }
+            | Instr::Compare { .. }
+            | Instr::Convert { .. } => {
+                let mut instr = instr.clone();
+                for value in instr.uses_mut() {
+                    match self.lattice(value) {
+                        Lattice::Top => return Lattice::Top,
+                        Lattice::Constant(constant) => *value = constant,
+                        Lattice::Bottom => {}
+                    }
+                }
+                // An identity such as `x * 0` can be constant even when `x`
+                // is not.
+                match fold::evaluate(&instr).or_else(|| fold::simplify(&instr)) {
+                    Some(value @ (Value::Int(_) | Value::Float(_))) => Lattice::Constant(value),
+                    _ => Lattice::Bottom,
+                }
+            }
+            _ => Lattice::Bottom,
+        }
+    }
+
+    fn visit(&mut self, block: usize, position: usize) {
+        let instr = &self.cfg.blocks[block].instrs[position];
+        match instr {
+            Instr::Jump(target) => self.flow_worklist.push((block, self.indices[target])),
+            Instr::Branch {
+                ty,
+                condition,
+                if_true,
+                if_false,
+            } => {
+                let targets = match self.lattice(condition) {
+                    Lattice::Top => vec![],
+                    Lattice::Constant(value) if fold::is_true(&value, *ty).unwrap() => {
+                        vec![if_true]
+                    }
+                    Lattice::Constant(_) => vec![if_false],
+                    Lattice::Bottom => vec![if_true, if_false],
+                };
+                for target in targets {
+                    self.flow_worklist.push((block, self.indices[target]));
+                }
+            }
+            instr => {
+                let Some(dst) = instr.defined() else {
+                    return;
+                };
+                let value = self.evaluate(block, instr);
+                if self.values.get(&dst).unwrap_or(&Lattice::Top) != &value {
+                    self.values.insert(dst, value);
+                    if let Some(uses) = self.uses.get(&dst) {
+                        self.ssa_worklist.extend(uses);
+                    }
+                }
+            }
+        }
+    }
+
+    fn solve(&mut self) {
+        self.visited[0] = true;
+        for position in 0..self.cfg.blocks[0].instrs.len() {
+            self.visit(0, position);
+        }
+
+        loop {
+            if let Some((from, to)) = self.flow_worklist.pop() {
+                if !self.executable.insert((from, to)) {
+                    continue;
+                }
+                let instrs = &self.cfg.blocks[to].instrs;
+                let phis = instrs
+                    .iter()
+                    .take_while(|instr| matches!(instr, Instr::Phi { .. }))
+                    .count();
+                for position in 0..phis {
+                    self.visit(to, position);
+                }
+                if !self.visited[to] {
+                    self.visited[to] = true;
+                    for position in phis..instrs.len() {
+                        self.visit(to, position);
+                    }
+                }
+            } else if let Some((block, position)) = self.ssa_worklist.pop() {
+                if self.visited[block] {
+                    self.visit(block, position);
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// Propagates constants through the function, which must be in SSA form,
+/// and returns whether anything changed.
+pub fn run(function: &mut Function, analyses: &mut Analyses) -> bool {
+    let mut cfg = analyses.cfg(function).clone();
+
+    let mut values = HashMap::new();
+    for (temp, _) in &function.parameters {
+        values.insert(*temp, Lattice::Bottom);
+    }
+    let mut uses: HashMap<Temp, Vec<(usize, usize)>> = HashMap::new();
+    let mut definitions = HashSet::new();
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        for (position, instr) in block.instrs.iter().enumerate() {
+            if let Some(dst) = instr.defined()
+                && !definitions.insert(dst)
+            {
+                // Not in SSA form.
+                return false;
+            }
+            for temp in instr.used_temps() {
+                uses.entry(temp).or_default().push((index, position));
+            }
+        }
+    }
+
+    let mut solver = Solver {
+        cfg: &cfg,
+        indices: cfg.label_indices(),
+        values,
+        uses,
+        executable: HashSet::new(),
+        visited: vec![false; cfg.blocks.len()],
+        flow_worklist: vec![],
+        ssa_worklist: vec![],
+    };
+    solver.solve();
+    let Solver {
+        values,
+        executable,
+        visited,
+        ..
+    } = solver;
+
+    let mut changed = false;
+    for (index, block) in cfg.blocks.iter_mut().enumerate() {
+        if !visited[index] {
+            continue;
+        }
+        for instr in &mut block.instrs {
+            for value in instr.uses_mut() {
+                if let Value::Temp(temp) = value
+                    && let Some(Lattice::Constant(constant)) = values.get(temp)
+                {
+                    *value = constant.clone();
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let indices = cfg.label_indices();
+    for (index, block) in cfg.blocks.iter_mut().enumerate() {
+        let terminator = block.instrs.last_mut().unwrap();
+        let taken: Vec<Label> = terminator
+            .targets()
+            .into_iter()
+            .filter(|target| executable.contains(&(index, indices[target])))
+            .collect();
+        if let (Instr::Branch { .. }, [target]) = (&terminator, &taken[..]) {
+            *terminator = Instr::Jump(*target);
+            changed = true;
+        }
+    }
+    cfg.compute_edges();
+    cfg.prune_phis();
+    changed |= cfg.remove_unreachable();
+
+    function.body = cfg.to_body();
+    changed
+}
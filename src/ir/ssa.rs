@@ -0,0 +1,443 @@
+/*
+  Conversion into and out of SSA form.
+
+  `construct` promotes stack slots whose address never escapes to SSA
+  temporaries (Cytron et al.): phis are placed at the iterated dominance
+  frontier of the blocks storing to a slot, pruned to the blocks where the
+  slot is live, and loads and stores are then renamed away in a preorder
+  walk of the dominator tree.
+
+  `destruct` replaces each phi with copies on the incoming edges (Briggs et
+  al.). Edges from blocks with several successors are split first, so a
+  copy never executes on a path that does not reach the phi; otherwise a
+  value still needed on the other path would be overwritten (the
+  "lost-copy" problem). The copies on one edge happen in parallel and are
+  ordered so that no copy overwrites a source another copy still reads,
+  going through a fresh temporary to break cycles (the "swap" problem).
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::{BasicBlock, Cfg};
+use super::dominators::DominatorTree;
+use super::{Function, Instr, IrType, Label, Temp, Value};
+
+/// Stack slots only ever loaded from and stored to as a whole, with one
+/// type and without `volatile`. Slots that are never accessed map to
+/// `None`.
+fn promotable_slots(cfg: &Cfg) -> HashMap<Temp, Option<IrType>> {
+    let mut sizes = HashMap::new();
+    for block in &cfg.blocks {
+        for instr in &block.instrs {
+            if let Instr::Alloca { dst, size, .. } = instr {
+                sizes.insert(*dst, *size);
+            }
+        }
+    }
+
+    let mut slots: HashMap<Temp, Option<IrType>> = sizes.keys().map(|&slot| (slot, None)).collect();
+    let mut escaped = HashSet::new();
+    let mut access = |slot: Temp, ty: IrType, volatile: bool, escaped: &mut HashSet<Temp>| {
+        let Some(size) = sizes.get(&slot) else {
+            return;
+        };
+        match slots[&slot] {
+            _ if volatile || ty.size() != *size => {
+                escaped.insert(slot);
+            }
+            Some(existing) if existing != ty => {
+                escaped.insert(slot);
+            }
+            _ => {
+                slots.insert(slot, Some(ty));
+            }
+        }
+    };
+
+    for block in &cfg.blocks {
+        for instr in &block.instrs {
+            match instr {
+                Instr::Load {
+                    ty,
+                    address: Value::Temp(slot),
+                    volatile,
+                    ..
+                } => access(*slot, *ty, *volatile, &mut escaped),
+                Instr::Store {
+                    ty,
+                    value,
+                    address: Value::Temp(slot),
+                    volatile,
+                } => {
+                    access(*slot, *ty, *volatile, &mut escaped);
+                    if let Value::Temp(value) = value {
+                        escaped.insert(*value);
+                    }
+                }
+                instr => escaped.extend(instr.used_temps()),
+            }
+        }
+    }
+
+    slots.retain(|slot, _| !escaped.contains(slot));
+    slots
+}
+
+/// For each block, the promoted slots live on entry to it.
+fn live_slots(cfg: &Cfg, slots: &HashMap<Temp, Option<IrType>>) -> Vec<HashSet<Temp>> {
+    let count = cfg.blocks.len();
+    let mut exposed = vec![HashSet::new(); count];
+    let mut stored = vec![HashSet::new(); count];
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        for instr in &block.instrs {
+            match instr {
+                Instr::Load {
+                    address: Value::Temp(slot),
+                    ..
+                } if slots.contains_key(slot) && !stored[index].contains(slot) => {
+                    exposed[index].insert(*slot);
+                }
+                Instr::Store {
+                    address: Value::Temp(slot),
+                    ..
+                } if slots.contains_key(slot) => {
+                    stored[index].insert(*slot);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut live_in = exposed.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in (0..count).rev() {
+            let mut live: HashSet<Temp> = exposed[index].clone();
+            for &successor in &cfg.successors[index] {
+                live.extend(
+                    live_in[successor]
+                        .iter()
+                        .filter(|slot| !stored[index].contains(slot)),
+                );
+            }
+            if live.len() != live_in[index].len() {
+                live_in[index] = live;
+                changed = true;
+            }
+        }
+    }
+
+    live_in
+}
+
+struct PhiNode {
+    slot: Temp,
+    dst: Temp,
+    ty: IrType,
+    incoming: Vec<(Label, Value)>,
+}
+
+struct Renamer<'a> {
+    cfg: &'a Cfg,
+    dominators: &'a DominatorTree,
+    slots: &'a HashMap<Temp, Option<IrType>>,
+    bodies: Vec<Vec<Instr>>,
+    phis: Vec<Vec<PhiNode>>,
+    /// The current value of each slot along the dominator tree path.
+    values: HashMap<Temp, Vec<Value>>,
+    /// Loads that were removed, and the values they read.
+    replacements: HashMap<Temp, Value>,
+}
+
+impl Renamer<'_> {
+    /// The value of a slot; reading an uninitialized slot yields zero.
+    fn current(&self, slot: Temp) -> Value {
+        match self.values[&slot].last() {
+            Some(value) => value.clone(),
+            None if self.slots[&slot].is_some_and(|ty| ty.is_float()) => Value::Float(0.0),
+            None => Value::Int(0),
+        }
+    }
+
+    fn rename(&mut self, block: usize) {
+        let mut pushed = vec![];
+        for phi in &self.phis[block] {
+            self.values
+                .get_mut(&phi.slot)
+                .unwrap()
+                .push(Value::Temp(phi.dst));
+            pushed.push(phi.slot);
+        }
+
+        let mut body = vec![];
+        for mut instr in std::mem::take(&mut self.bodies[block]) {
+            for value in instr.uses_mut() {
+                if let Value::Temp(temp) = value
+                    && let Some(replacement) = self.replacements.get(temp)
+                {
+                    *value = replacement.clone();
+                }
+            }
+
+            match instr {
+                Instr::Alloca { dst, .. } if self.slots.contains_key(&dst) => {}
+                Instr::Load {
+                    dst,
+                    address: Value::Temp(slot),
+                    ..
+                } if self.slots.contains_key(&slot) => {
+                    let value = self.current(slot);
+                    self.replacements.insert(dst, value);
+                }
+                Instr::Store {
+                    value,
+                    address: Value::Temp(slot),
+                    ..
+                } if self.slots.contains_key(&slot) => {
+                    self.values.get_mut(&slot).unwrap().push(value);
+                    pushed.push(slot);
+                }
+                instr => body.push(instr),
+            }
+        }
+        self.bodies[block] = body;
+
+        let label = self.cfg.blocks[block].label;
+        for &successor in &self.cfg.successors[block] {
+            for index in 0..self.phis[successor].len() {
+                let value = self.current(self.phis[successor][index].slot);
+                self.phis[successor][index].incoming.push((label, value));
+            }
+        }
+
+        for &child in &self.dominators.children[block] {
+            self.rename(child);
+        }
+
+        for slot in pushed {
+            self.values.get_mut(&slot).unwrap().pop();
+        }
+    }
+}
+
+/// Whether every temporary of the function, parameters included, is
+/// defined once.
+pub fn is_ssa(function: &Function) -> bool {
+    let mut definitions = HashSet::new();
+    function
+        .parameters
+        .iter()
+        .map(|(temp, _)| *temp)
+        .chain(function.body.iter().filter_map(Instr::defined))
+        .all(|temp| definitions.insert(temp))
+}
+
+/// Puts a function into SSA form, removing unreachable blocks.
+pub fn construct(function: &mut Function) {
+    let mut cfg = Cfg::build(function);
+    cfg.remove_unreachable();
+
+    // Phis in the entry block would have no value for the function entry.
+    if !cfg.predecessors[0].is_empty() {
+        cfg.add_jump_block(cfg.blocks[0].label);
+        let entry = cfg.blocks.pop().unwrap();
+        cfg.blocks.insert(0, entry);
+        cfg.compute_edges();
+    }
+
+    let dominators = DominatorTree::build(&cfg);
+    let slots = promotable_slots(&cfg);
+    let live_in = live_slots(&cfg, &slots);
+    let mut next_temp = function.next_temp().0;
+
+    let mut phis: Vec<Vec<PhiNode>> = cfg.blocks.iter().map(|_| vec![]).collect();
+    let mut ordered: Vec<(&Temp, &Option<IrType>)> = slots.iter().collect();
+    ordered.sort_by_key(|(slot, _)| **slot);
+    for (&slot, ty) in ordered {
+        let Some(ty) = *ty else {
+            continue;
+        };
+
+        let mut worklist: Vec<usize> = cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| {
+                block.instrs.iter().any(|instr| {
+                    matches!(instr, Instr::Store { address: Value::Temp(address), .. } if *address == slot)
+                })
+            })
+            .map(|(index, _)| index)
+            .collect();
+        let mut has_phi = HashSet::new();
+        while let Some(block) = worklist.pop() {
+            for &frontier in &dominators.frontiers[block] {
+                if !live_in[frontier].contains(&slot) || !has_phi.insert(frontier) {
+                    continue;
+                }
+                phis[frontier].push(PhiNode {
+                    slot,
+                    dst: Temp(next_temp),
+                    ty,
+                    incoming: vec![],
+                });
+                next_temp += 1;
+                worklist.push(frontier);
+            }
+        }
+    }
+
+    let mut renamer = Renamer {
+        cfg: &cfg,
+        dominators: &dominators,
+        slots: &slots,
+        bodies: cfg
+            .blocks
+            .iter()
+            .map(|block| block.instrs.clone())
+            .collect(),
+        phis,
+        values: slots.keys().map(|&slot| (slot, vec![])).collect(),
+        replacements: HashMap::new(),
+    };
+    renamer.rename(0);
+
+    let Renamer {
+        mut bodies, phis, ..
+    } = renamer;
+    for (index, block) in cfg.blocks.iter_mut().enumerate() {
+        let mut instrs: Vec<Instr> = phis[index]
+            .iter()
+            .map(|phi| Instr::Phi {
+                dst: phi.dst,
+                ty: phi.ty,
+                incoming: phi.incoming.clone(),
+            })
+            .collect();
+        instrs.append(&mut bodies[index]);
+        block.instrs = instrs;
+    }
+
+    function.body = cfg.to_body();
+}
+
+/// Orders the parallel copies `dst <- src` so they can run one after another.
+fn sequentialize(mut copies: Vec<(Temp, IrType, Value)>, next_temp: &mut u32) -> Vec<Instr> {
+    copies.retain(|(dst, _, src)| *src != Value::Temp(*dst));
+
+    let mut sequence = vec![];
+    while !copies.is_empty() {
+        let ready = copies
+            .iter()
+            .position(|(dst, _, _)| !copies.iter().any(|(_, _, src)| *src == Value::Temp(*dst)));
+
+        match ready {
+            Some(index) => {
+                let (dst, ty, src) = copies.remove(index);
+                sequence.push(Instr::Copy { dst, ty, src });
+            }
+            None => {
+                // Every destination is still to be read: the copies form
+                // cycles. Save one destination so its copy becomes ready.
+                let (dst, ty, _) = copies[0].clone();
+                let saved = Temp(*next_temp);
+                *next_temp += 1;
+                sequence.push(Instr::Copy {
+                    dst: saved,
+                    ty,
+                    src: Value::Temp(dst),
+                });
+                for (_, _, src) in &mut copies {
+                    if *src == Value::Temp(dst) {
+                        *src = Value::Temp(saved);
+                    }
+                }
+            }
+        }
+    }
+
+    sequence
+}
+
+/// Takes a function out of SSA form by replacing phis with copies.
+pub fn destruct(function: &mut Function) {
+    let mut cfg = Cfg::build(function);
+    let mut next_temp = function.next_temp().0;
+
+    let has_phis = |block: &BasicBlock| matches!(block.instrs.first(), Some(Instr::Phi { .. }));
+
+    let mut split = vec![];
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        if !has_phis(block) {
+            continue;
+        }
+        for &predecessor in &cfg.predecessors[index] {
+            if cfg.blocks[predecessor].terminator().targets().len() > 1 {
+                split.push((predecessor, index));
+            }
+        }
+    }
+
+    for (predecessor, block) in split {
+        let (from, to) = (cfg.blocks[predecessor].label, cfg.blocks[block].label);
+        let middle = cfg.add_jump_block(to);
+
+        let terminator = cfg.blocks[predecessor].instrs.last_mut().unwrap();
+        if let Instr::Branch {
+            if_true, if_false, ..
+        } = terminator
+        {
+            for target in [if_true, if_false] {
+                if *target == to {
+                    *target = middle;
+                }
+            }
+        }
+
+        for instr in &mut cfg.blocks[block].instrs {
+            if let Instr::Phi { incoming, .. } = instr {
+                for (label, _) in incoming {
+                    if *label == from {
+                        *label = middle;
+                    }
+                }
+            }
+        }
+    }
+    cfg.compute_edges();
+
+    for index in 0..cfg.blocks.len() {
+        let phis: Vec<Instr> = cfg.blocks[index]
+            .instrs
+            .iter()
+            .take_while(|instr| matches!(instr, Instr::Phi { .. }))
+            .cloned()
+            .collect();
+        if phis.is_empty() {
+            continue;
+        }
+        cfg.blocks[index].instrs.drain(..phis.len());
+
+        for predecessor in cfg.predecessors[index].clone() {
+            let label = cfg.blocks[predecessor].label;
+            let copies = phis
+                .iter()
+                .filter_map(|phi| match phi {
+                    Instr::Phi { dst, ty, incoming } => {
+                        let (_, value) = incoming.iter().find(|(from, _)| *from == label)?;
+                        Some((*dst, *ty, value.clone()))
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            let instrs = &mut cfg.blocks[predecessor].instrs;
+            let terminator = instrs.pop().unwrap();
+            instrs.extend(sequentialize(copies, &mut next_temp));
+            instrs.push(terminator);
+        }
+    }
+
+    function.body = cfg.to_body();
+}
@@ -0,0 +1,159 @@
+/*
+  Tail calls. A call is in tail position when the function returns its
+  result, or returns nothing, right after it. A function that calls itself
+  in tail position on a function in SSA form gets a new entry block, and
+  its old entry becomes a loop header: the parameters turn into phis fed
+  by the real parameters from the entry and by the arguments from each
+  tail call, which becomes a jump back to the header. The backend turns
+  the remaining tail calls into jumps to the callee.
+
+  Either way the frame is reused, which is wrong as soon as the address of
+  one of its stack slots may be used elsewhere: an argument or a store
+  may hand it to the callee, or to the next trip through the loop that
+  overwrites it. A slot whose address is only ever loaded from, stored
+  to or copied is safe. Variadic functions are left alone, since their
+  variable arguments would still be those of the first call.
+*/
+
+use std::collections::HashSet;
+
+use super::cfg::{BasicBlock, Cfg};
+use super::passes::Analyses;
+use super::{Function, Instr, Temp, Value, ssa};
+
+/// Whether the function may take the address of one of its stack slots,
+/// which keeps its frame alive until it returns.
+pub fn takes_local_address(function: &Function) -> bool {
+    let slots: HashSet<Temp> = function
+        .body
+        .iter()
+        .filter_map(|instr| match instr {
+            Instr::Alloca { dst, .. } => Some(*dst),
+            _ => None,
+        })
+        .collect();
+    let escapes = |value: &Value| matches!(value, Value::Temp(temp) if slots.contains(temp));
+    function.body.iter().any(|instr| match instr {
+        Instr::Load { .. } | Instr::MemCopy { .. } => false,
+        Instr::Store { value, .. } => escapes(value),
+        instr => instr.uses().into_iter().any(escapes),
+    })
+}
+
+/// Whether `call` is a call whose result, if any, `next` returns at once.
+pub fn is_tail_call(call: &Instr, next: &Instr) -> bool {
+    match (call, next) {
+        (Instr::Call { .. }, Instr::Return(None)) => true,
+        (
+            Instr::Call {
+                dst: Some((dst, _)),
+                ..
+            },
+            Instr::Return(Some((_, value))),
+        ) => *value == Value::Temp(*dst),
+        _ => false,
+    }
+}
+
+/// The blocks ending in a call of the function itself in tail position.
+fn self_calls(cfg: &Cfg, function: &Function) -> Vec<usize> {
+    let types: Vec<_> = function.parameters.iter().map(|(_, ty)| *ty).collect();
+    (0..cfg.blocks.len())
+        .filter(|&block| {
+            let [.., call, ret] = &cfg.blocks[block].instrs[..] else {
+                return false;
+            };
+            let Instr::Call {
+                callee: Value::Global(name),
+                arguments,
+                ..
+            } = call
+            else {
+                return false;
+            };
+            *name == function.name
+                && arguments
+                    .iter()
+                    .map(|(ty, _)| *ty)
+                    .eq(types.iter().copied())
+                && is_tail_call(call, ret)
+        })
+        .collect()
+}
+
+/// Turns self-recursive tail calls of a function in SSA form into a loop.
+/// Returns whether anything changed.
+pub fn run(function: &mut Function, analyses: &mut Analyses) -> bool {
+    if function.variadic || !ssa::is_ssa(function) || takes_local_address(function) {
+        return false;
+    }
+    let mut cfg = analyses.cfg(function).clone();
+    let sites = self_calls(&cfg, function);
+    if sites.is_empty() {
+        return false;
+    }
+
+    // The parameters become the incoming values of the header's phis.
+    let first = function.next_temp().0;
+    let mut parameters = vec![];
+    for (next, (temp, ty)) in (first..).zip(&mut function.parameters) {
+        parameters.push((*temp, *ty, Temp(next)));
+        *temp = Temp(next);
+    }
+
+    let header = cfg.blocks[0].label;
+    let entry = cfg.fresh_label();
+    let mut arguments = vec![];
+    for &site in &sites {
+        let block = &mut cfg.blocks[site];
+        block.instrs.pop();
+        let Some(Instr::Call {
+            arguments: values, ..
+        }) = block.instrs.pop()
+        else {
+            unreachable!("a tail call that is not one");
+        };
+        block.instrs.push(Instr::Jump(header));
+        arguments.push((block.label, values));
+    }
+    // Other edges into the old entry keep the values they had.
+    let others: Vec<_> = cfg.predecessors[0]
+        .iter()
+        .filter(|block| !sites.contains(block))
+        .map(|&block| cfg.blocks[block].label)
+        .collect();
+    let phis = parameters
+        .iter()
+        .enumerate()
+        .map(|(index, (dst, ty, parameter))| {
+            let mut incoming = vec![(entry, Value::Temp(*parameter))];
+            for (label, values) in &arguments {
+                incoming.push((*label, values[index].1.clone()));
+            }
+            for label in &others {
+                incoming.push((*label, Value::Temp(*dst)));
+            }
+            Instr::Phi {
+                dst: *dst,
+                ty: *ty,
+                incoming,
+            }
+        });
+
+    // Stack slots are reserved once, in the new entry.
+    let (mut instrs, body): (Vec<_>, Vec<_>) = std::mem::take(&mut cfg.blocks[0].instrs)
+        .into_iter()
+        .partition(|instr| matches!(instr, Instr::Alloca { .. }));
+    cfg.blocks[0].instrs = phis.chain(body).collect();
+    instrs.push(Instr::Jump(header));
+    cfg.blocks.insert(
+        0,
+        BasicBlock {
+            label: entry,
+            instrs,
+        },
+    );
+    cfg.compute_edges();
+    function.body = cfg.to_body();
+    true
+}
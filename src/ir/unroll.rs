@@ -0,0 +1,464 @@
+/*
+  Unrolling of innermost loops with a constant trip count, on a function
+  in SSA form. The loops handled are those that `for` statements lower to:
+  the header compares a basic induction variable with a constant and is
+  the only way out, and a single latch jumps back to it. The trip count
+  then follows from running the induction variable from its constant
+  start until the comparison fails.
+
+  Each copy of the loop body gets fresh temporaries and labels, and the
+  header phis of a copy become copies of the values the previous one left
+  on its back edge. A small enough loop is unrolled completely: the copies
+  are chained one after the other, followed by a last copy of the header
+  that leaves the loop, and the loop is gone. A larger one is unrolled by
+  a factor dividing the trip count, so that only the original header has
+  to test the condition and the copies run straight through.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::{BasicBlock, Cfg};
+use super::dominators::DominatorTree;
+use super::loops::{self, Loop, find_loops};
+use super::passes::Analyses;
+use super::{BinaryOp, Function, Instr, Label, Temp, Value, fold, ssa};
+
+/// The most instructions a completely unrolled loop may take.
+const FULL_UNROLL_SIZE: usize = 160;
+/// The most instructions a partially unrolled loop body may take.
+const PARTIAL_UNROLL_SIZE: usize = 96;
+/// Trip counts are only searched for this far.
+const MAX_TRIP_COUNT: usize = 1 << 16;
+
+/// A loop in the shape the unroller handles.
+struct Shape {
+    preheader: usize,
+    latch: usize,
+    /// The header's successor in the loop.
+    body: Label,
+    exit: Label,
+    trip_count: usize,
+}
+
+fn shape(cfg: &Cfg, natural_loop: &Loop, loops: &[Loop]) -> Option<Shape> {
+    let innermost = loops.iter().all(|other| {
+        other.header == natural_loop.header || !natural_loop.blocks.contains(&other.header)
+    });
+    let preheader = natural_loop.preheader(cfg)?;
+    let [latch] = natural_loop.latches[..] else {
+        return None;
+    };
+    let header = &cfg.blocks[natural_loop.header];
+    let exits_elsewhere = natural_loop.blocks.iter().any(|&block| {
+        block != natural_loop.header
+            && cfg.successors[block]
+                .iter()
+                .any(|successor| !natural_loop.blocks.contains(successor))
+    });
+    if !innermost || exits_elsewhere || latch == natural_loop.header {
+        return None;
+    }
+
+    let Instr::Branch {
+        ty: condition_type,
+        condition: Value::Temp(condition),
+        if_true,
+        if_false,
+    } = header.terminator()
+    else {
+        return None;
+    };
+    let labels = cfg.label_indices();
+    let continues_on_true = natural_loop.blocks.contains(&labels[if_true]);
+    let (body, exit) = if continues_on_true {
+        (*if_true, *if_false)
+    } else {
+        (*if_false, *if_true)
+    };
+    if natural_loop.blocks.contains(&labels[&exit]) {
+        return None;
+    }
+
+    // The comparison of an induction variable with a constant.
+    let variables = loops::inductions(cfg, natural_loop, cfg.blocks[preheader].label);
+    let compare = header
+        .instrs
+        .iter()
+        .find(|instr| instr.defined() == Some(*condition))?;
+    let Instr::Compare {
+        dst,
+        condition: comparison,
+        ty,
+        left,
+        right,
+    } = compare
+    else {
+        return None;
+    };
+    let (variable, left_side) = match (left, right) {
+        (Value::Temp(temp), _) if variables.contains_key(temp) => (temp, true),
+        (_, Value::Temp(temp)) if variables.contains_key(temp) => (temp, false),
+        _ => return None,
+    };
+    let induction = &variables[variable];
+    let bound = if left_side { right } else { left };
+    let mut value = fold::constant(&induction.init, induction.ty)?;
+    fold::constant(bound, *ty)?;
+
+    for trip_count in 0..=MAX_TRIP_COUNT {
+        let (left, right) = if left_side {
+            (value.clone(), bound.clone())
+        } else {
+            (bound.clone(), value.clone())
+        };
+        let result = fold::evaluate(&Instr::Compare {
+            dst: *dst,
+            condition: *comparison,
+            ty: *ty,
+            left,
+            right,
+        })?;
+        if fold::is_true(&result, *condition_type)? != continues_on_true {
+            return Some(Shape {
+                preheader,
+                latch,
+                body,
+                exit,
+                trip_count,
+            });
+        }
+        value = fold::evaluate(&Instr::Binary {
+            dst: *dst,
+            op: BinaryOp::Add,
+            ty: induction.ty,
+            left: value,
+            right: Value::Int(induction.step),
+        })?;
+    }
+    None
+}
+
+/// Renames the temporaries defined in the loop and its labels for one copy
+/// of its blocks.
+struct Renaming {
+    temps: HashMap<Temp, Temp>,
+    labels: HashMap<Label, Label>,
+}
+
+impl Renaming {
+    fn new(cfg: &Cfg, natural_loop: &Loop, next_temp: &mut u32, next_label: &mut u32) -> Renaming {
+        let mut temps = HashMap::new();
+        let mut labels = HashMap::new();
+        for &block in &natural_loop.blocks {
+            labels.insert(cfg.blocks[block].label, Label(*next_label));
+            *next_label += 1;
+            for temp in cfg.blocks[block].instrs.iter().filter_map(Instr::defined) {
+                temps.insert(temp, Temp(*next_temp));
+                *next_temp += 1;
+            }
+        }
+        Renaming { temps, labels }
+    }
+
+    fn temp(&self, temp: Temp) -> Temp {
+        self.temps.get(&temp).copied().unwrap_or(temp)
+    }
+
+    fn label(&self, label: Label) -> Label {
+        self.labels.get(&label).copied().unwrap_or(label)
+    }
+
+    fn value(&self, value: &Value) -> Value {
+        match value {
+            Value::Temp(temp) => Value::Temp(self.temp(*temp)),
+            value => value.clone(),
+        }
+    }
+
+    fn instr(&self, instr: &Instr) -> Instr {
+        let mut instr = instr.clone();
+        if let Some(dst) = instr.defined_mut() {
+            *dst = self.temp(*dst);
+        }
+        for value in instr.uses_mut() {
+            *value = self.value(value);
+        }
+        match &mut instr {
+            Instr::Phi { incoming, .. } => {
+                for (label, _) in incoming {
+                    *label = self.label(*label);
+                }
+            }
+            Instr::Jump(target) => *target = self.label(*target),
+            Instr::Branch {
+                if_true, if_false, ..
+            } => {
+                *if_true = self.label(*if_true);
+                *if_false = self.label(*if_false);
+            }
+            _ => {}
+        }
+        instr
+    }
+}
+
+/// One renamed copy of the loop's blocks, or of only its header. The
+/// header phis become copies of the values from `previous`, the renaming
+/// of the iteration before, or from the preheader for the first one. The
+/// header continues at `header_target` and the latch at `latch_target`.
+fn copy_iteration(
+    cfg: &Cfg,
+    natural_loop: &Loop,
+    shape: &Shape,
+    renaming: &Renaming,
+    previous: Option<&Renaming>,
+    header_only: bool,
+    (header_target, latch_target): (Label, Label),
+) -> Vec<BasicBlock> {
+    let latch = cfg.blocks[shape.latch].label;
+    let preheader = cfg.blocks[shape.preheader].label;
+    let mut blocks = vec![];
+    for &index in &natural_loop.blocks {
+        let block = &cfg.blocks[index];
+        if header_only && index != natural_loop.header {
+            continue;
+        }
+        let mut instrs: Vec<Instr> = block
+            .instrs
+            .iter()
+            .map(|instr| match instr {
+                Instr::Phi { dst, ty, incoming } if index == natural_loop.header => {
+                    let from = |block: Label| {
+                        let (_, value) =
+                            incoming.iter().find(|(label, _)| *label == block).unwrap();
+                        value
+                    };
+                    let src = match previous {
+                        Some(previous) => previous.value(from(latch)),
+                        None => from(preheader).clone(),
+                    };
+                    Instr::Copy {
+                        dst: renaming.temp(*dst),
+                        ty: *ty,
+                        src,
+                    }
+                }
+                instr => renaming.instr(instr),
+            })
+            .collect();
+        if index == natural_loop.header {
+            *instrs.last_mut().unwrap() = Instr::Jump(header_target);
+        }
+        if index == shape.latch {
+            *instrs.last_mut().unwrap() = Instr::Jump(latch_target);
+        }
+        blocks.push(BasicBlock {
+            label: renaming.label(block.label),
+            instrs,
+        });
+    }
+    blocks
+}
+
+fn size(cfg: &Cfg, natural_loop: &Loop) -> usize {
+    natural_loop
+        .blocks
+        .iter()
+        .map(|&block| cfg.blocks[block].instrs.len())
+        .sum()
+}
+
+/// Replaces the loop by `trip_count` copies of its blocks and a last copy
+/// of its header.
+fn unroll_fully(
+    cfg: &mut Cfg,
+    natural_loop: &Loop,
+    shape: &Shape,
+    next_temp: &mut u32,
+    next_label: &mut u32,
+) {
+    let renamings: Vec<Renaming> = (0..=shape.trip_count)
+        .map(|_| Renaming::new(cfg, natural_loop, next_temp, next_label))
+        .collect();
+    let mut copies = vec![];
+    for (iteration, renaming) in renamings.iter().enumerate() {
+        let previous = iteration
+            .checked_sub(1)
+            .map(|previous| &renamings[previous]);
+        let last = iteration == shape.trip_count;
+        let targets = if last {
+            (shape.exit, shape.exit)
+        } else {
+            let header = cfg.blocks[natural_loop.header].label;
+            (
+                renaming.label(shape.body),
+                renamings[iteration + 1].label(header),
+            )
+        };
+        copies.extend(copy_iteration(
+            cfg,
+            natural_loop,
+            shape,
+            renaming,
+            previous,
+            last,
+            targets,
+        ));
+    }
+
+    // Code after the loop sees the values of the last header.
+    let last = &renamings[shape.trip_count];
+    let first = &renamings[0];
+    for (index, block) in cfg.blocks.iter_mut().enumerate() {
+        if natural_loop.blocks.contains(&index) {
+            continue;
+        }
+        for instr in &mut block.instrs {
+            for value in instr.uses_mut() {
+                *value = last.value(value);
+            }
+            match instr {
+                Instr::Phi { incoming, .. } => {
+                    for (label, _) in incoming {
+                        *label = last.label(*label);
+                    }
+                }
+                Instr::Jump(target) => *target = first.label(*target),
+                Instr::Branch {
+                    if_true, if_false, ..
+                } => {
+                    *if_true = first.label(*if_true);
+                    *if_false = first.label(*if_false);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let position = *natural_loop.blocks.first().unwrap();
+    let mut index = 0;
+    cfg.blocks.retain(|_| {
+        index += 1;
+        !natural_loop.blocks.contains(&(index - 1))
+    });
+    cfg.blocks.splice(position..position, copies);
+}
+
+/// Adds `factor - 1` copies of the loop's blocks after it, through which
+/// each trip from the header runs before coming back.
+fn unroll_partially(
+    cfg: &mut Cfg,
+    natural_loop: &Loop,
+    shape: &Shape,
+    factor: usize,
+    next_temp: &mut u32,
+    next_label: &mut u32,
+) {
+    let identity = Renaming {
+        temps: HashMap::new(),
+        labels: HashMap::new(),
+    };
+    let mut renamings = vec![identity];
+    for _ in 1..factor {
+        renamings.push(Renaming::new(cfg, natural_loop, next_temp, next_label));
+    }
+    let header = cfg.blocks[natural_loop.header].label;
+    let latch = cfg.blocks[shape.latch].label;
+
+    let mut copies = vec![];
+    for iteration in 1..factor {
+        let renaming = &renamings[iteration];
+        let next = renamings
+            .get(iteration + 1)
+            .map_or(header, |next| next.label(header));
+        copies.extend(copy_iteration(
+            cfg,
+            natural_loop,
+            shape,
+            renaming,
+            Some(&renamings[iteration - 1]),
+            false,
+            (renaming.label(shape.body), next),
+        ));
+    }
+
+    let last = renamings.last().unwrap();
+    *cfg.blocks[shape.latch].instrs.last_mut().unwrap() = Instr::Jump(renamings[1].label(header));
+    for instr in &mut cfg.blocks[natural_loop.header].instrs {
+        let Instr::Phi { incoming, .. } = instr else {
+            break;
+        };
+        for (label, value) in incoming {
+            if *label == latch {
+                *label = last.label(latch);
+                *value = last.value(value);
+            }
+        }
+    }
+
+    let position = natural_loop.blocks.last().unwrap() + 1;
+    cfg.blocks.splice(position..position, copies);
+}
+
+/// Unrolls one loop if it is worth it. Returns whether it was.
+fn unroll(cfg: &mut Cfg, natural_loop: &Loop, loops: &[Loop], next_temp: &mut u32) -> bool {
+    let Some(shape) = shape(cfg, natural_loop, loops) else {
+        return false;
+    };
+    let size = size(cfg, natural_loop);
+    let mut next_label = cfg.fresh_label().0;
+    if (shape.trip_count + 1) * size <= FULL_UNROLL_SIZE {
+        unroll_fully(cfg, natural_loop, &shape, next_temp, &mut next_label);
+    } else if let Some(factor) = [4, 2]
+        .into_iter()
+        .find(|&factor| shape.trip_count % factor == 0 && factor * size <= PARTIAL_UNROLL_SIZE)
+    {
+        unroll_partially(
+            cfg,
+            natural_loop,
+            &shape,
+            factor,
+            next_temp,
+            &mut next_label,
+        );
+    } else {
+        return false;
+    }
+    cfg.compute_edges();
+    true
+}
+
+/// Unrolls the innermost loops with constant trip counts in a function in
+/// SSA form. Returns whether anything changed.
+pub fn run(function: &mut Function, analyses: &mut Analyses) -> bool {
+    if !ssa::is_ssa(function) {
+        return false;
+    }
+    let mut cfg = analyses.cfg(function).clone();
+    let mut loops = analyses.loops(function).to_vec();
+    let mut next_temp = function.next_temp().0;
+    let mut changed = false;
+    // Headers already considered; unrolling a loop completely can make the
+    // loop around it innermost.
+    let mut visited: HashSet<Label> = HashSet::new();
+    loop {
+        let added;
+        (loops, added) = loops::with_preheaders(&mut cfg, loops, &mut next_temp);
+        changed |= added;
+        let Some(natural_loop) = loops
+            .iter()
+            .rev()
+            .find(|natural_loop| visited.insert(cfg.blocks[natural_loop.header].label))
+        else {
+            break;
+        };
+        if unroll(&mut cfg, natural_loop, &loops, &mut next_temp) {
+            loops = find_loops(&cfg, &DominatorTree::build(&cfg));
+            changed = true;
+        }
+    }
+    if changed {
+        function.body = cfg.to_body();
+    }
+    changed
+}
@@ -0,0 +1,144 @@
+/*
+  Checks the invariants of a function in SSA form: every temporary is
+  defined once, every use is dominated by its definition, and phis come
+  first in their block with one value per predecessor. A phi's value is
+  used at the end of the predecessor it comes from.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::Cfg;
+use super::dominators::DominatorTree;
+use super::{Function, Instr, Temp, Value};
+
+#[derive(Debug, PartialEq)]
+pub enum VerifyError {
+    UndefinedLabel(String),
+    UndefinedTemp(String),
+    Redefinition(String),
+    UseNotDominated(String),
+    InvalidPhi(String),
+}
+
+pub fn verify(function: &Function) -> Result<(), VerifyError> {
+    let name = &function.name;
+
+    let mut labels = HashSet::new();
+    for instr in &function.body {
+        if let Instr::Label(label) = instr
+            && !labels.insert(*label)
+        {
+            return Err(VerifyError::Redefinition(format!("{} in @{}", label, name)));
+        }
+    }
+    for instr in &function.body {
+        for target in instr.targets() {
+            if !labels.contains(&target) {
+                return Err(VerifyError::UndefinedLabel(format!(
+                    "{} in @{}",
+                    target, name
+                )));
+            }
+        }
+    }
+
+    let cfg = Cfg::build(function);
+    let dominators = DominatorTree::build(&cfg);
+
+    // Where each temporary is defined: its block and position, with
+    // parameters before everything in the entry block.
+    let mut definitions: HashMap<Temp, (usize, Option<usize>)> = HashMap::new();
+    for (temp, _) in &function.parameters {
+        if definitions.insert(*temp, (0, None)).is_some() {
+            return Err(VerifyError::Redefinition(format!("{} in @{}", temp, name)));
+        }
+    }
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        for (position, instr) in block.instrs.iter().enumerate() {
+            if let Some(dst) = instr.defined()
+                && definitions.insert(dst, (index, Some(position))).is_some()
+            {
+                return Err(VerifyError::Redefinition(format!("{} in @{}", dst, name)));
+            }
+        }
+    }
+
+    // Whether the definition of `temp` is available at `position` in
+    // `block`; `None` stands for the end of the block.
+    let available = |temp: Temp, block: usize, position: Option<usize>| {
+        let (defining_block, defining_position) = definitions[&temp];
+        if defining_block != block {
+            return dominators.dominates(defining_block, block);
+        }
+        match (defining_position, position) {
+            (None, _) | (_, None) => true,
+            (Some(defined), Some(used)) => defined < used,
+        }
+    };
+
+    let reachable: HashSet<usize> = cfg.reverse_postorder().into_iter().collect();
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        if !reachable.contains(&index) {
+            continue;
+        }
+
+        let mut in_phis = true;
+        for (position, instr) in block.instrs.iter().enumerate() {
+            for temp in instr.used_temps() {
+                if !definitions.contains_key(&temp) {
+                    return Err(VerifyError::UndefinedTemp(format!("{} in @{}", temp, name)));
+                }
+            }
+
+            let Instr::Phi { dst, incoming, .. } = instr else {
+                in_phis = false;
+                for temp in instr.used_temps() {
+                    if !available(temp, index, Some(position)) {
+                        return Err(VerifyError::UseNotDominated(format!(
+                            "{} used in {} of @{}",
+                            temp, block.label, name
+                        )));
+                    }
+                }
+                continue;
+            };
+
+            if !in_phis {
+                return Err(VerifyError::InvalidPhi(format!(
+                    "{} is not at the start of {} in @{}",
+                    dst, block.label, name
+                )));
+            }
+
+            let mut expected: Vec<_> = cfg.predecessors[index]
+                .iter()
+                .map(|&predecessor| cfg.blocks[predecessor].label)
+                .collect();
+            let mut found: Vec<_> = incoming.iter().map(|(label, _)| *label).collect();
+            expected.sort();
+            found.sort();
+            if expected != found {
+                return Err(VerifyError::InvalidPhi(format!(
+                    "{} in {} of @{} does not have one value per predecessor",
+                    dst, block.label, name
+                )));
+            }
+
+            let indices = cfg.label_indices();
+            for (label, value) in incoming {
+                let predecessor = indices[label];
+                if let Value::Temp(temp) = value
+                    && reachable.contains(&predecessor)
+                    && !available(*temp, predecessor, None)
+                {
+                    return Err(VerifyError::UseNotDominated(format!(
+                        "{} flowing into {} from {} of @{}",
+                        temp, dst, label, name
+                    )));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
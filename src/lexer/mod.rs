@@ -8,6 +8,9 @@ use {token::KEYWORDS, token::Token, token::TokenType};
 pub enum LexerError {
     UnexpectedCharacter(char, usize),
     UnterminatedString(usize),
+    UnterminatedCharacter(usize),
+    UnterminatedComment(usize),
+    InvalidEscape(String, usize),
 }
 
 pub fn tokenize(source: String, test: bool) -> Result<Vec<Token>, Vec<LexerError>> {
@@ -53,23 +56,141 @@ fn scan_token(source_chars: &mut Peekable<Chars>, line: &mut usize) -> Result<To
         ')' => TokenType::RightParenthesis,
         '{' => TokenType::LeftBrace,
         '}' => TokenType::RightBrace,
-        '!' => {
-            if match_two_char_token('=', source_chars) {
-                final_text.push('=');
-                TokenType::BangEqual
+        '[' => TokenType::LeftBracket,
+        ']' => TokenType::RightBracket,
+        '.' => {
+            let mut ahead = source_chars.clone();
+            if source_chars.peek().is_some_and(char::is_ascii_digit) {
+                match_number(source_chars, &mut final_text);
+                TokenType::Number
+            } else if ahead.next() == Some('.') && ahead.next() == Some('.') {
+                source_chars.nth(1);
+                final_text.push_str("..");
+                TokenType::Ellipsis
             } else {
-                TokenType::Bang
+                TokenType::Dot
             }
         }
-        '=' => {
-            if match_two_char_token('=', source_chars) {
-                final_text.push('=');
-                TokenType::EqualEqual
+        ';' => TokenType::Semicolon,
+        ',' => TokenType::Comma,
+        '~' => TokenType::Tilde,
+        '?' => TokenType::Question,
+        ':' => TokenType::Colon,
+
+        // One, two or three character tokens
+        '!' => match_operator(
+            source_chars,
+            &mut final_text,
+            &[('=', TokenType::BangEqual)],
+            TokenType::Bang,
+        ),
+        '=' => match_operator(
+            source_chars,
+            &mut final_text,
+            &[('=', TokenType::EqualEqual)],
+            TokenType::Equal,
+        ),
+        '+' => match_operator(
+            source_chars,
+            &mut final_text,
+            &[('+', TokenType::PlusPlus), ('=', TokenType::PlusEqual)],
+            TokenType::Plus,
+        ),
+        '-' => match_operator(
+            source_chars,
+            &mut final_text,
+            &[
+                ('-', TokenType::MinusMinus),
+                ('=', TokenType::MinusEqual),
+                ('>', TokenType::Arrow),
+            ],
+            TokenType::Minus,
+        ),
+        '*' => match_operator(
+            source_chars,
+            &mut final_text,
+            &[('=', TokenType::StarEqual)],
+            TokenType::Star,
+        ),
+        '/' => {
+            if match_two_char_token('/', source_chars) {
+                skip_line_comment(source_chars);
+                return scan_token(source_chars, line);
+            }
+            if match_two_char_token('*', source_chars) {
+                skip_block_comment(source_chars, line)?;
+                return scan_token(source_chars, line);
+            }
+            match_operator(
+                source_chars,
+                &mut final_text,
+                &[('=', TokenType::SlashEqual)],
+                TokenType::Slash,
+            )
+        }
+        '%' => match_operator(
+            source_chars,
+            &mut final_text,
+            &[('=', TokenType::PercentEqual)],
+            TokenType::Percent,
+        ),
+        '&' => match_operator(
+            source_chars,
+            &mut final_text,
+            &[
+                ('&', TokenType::AmpersandAmpersand),
+                ('=', TokenType::AmpersandEqual),
+            ],
+            TokenType::Ampersand,
+        ),
+        '|' => match_operator(
+            source_chars,
+            &mut final_text,
+            &[('|', TokenType::PipePipe), ('=', TokenType::PipeEqual)],
+            TokenType::Pipe,
+        ),
+        '^' => match_operator(
+            source_chars,
+            &mut final_text,
+            &[('=', TokenType::CaretEqual)],
+            TokenType::Caret,
+        ),
+        '<' => {
+            if match_two_char_token('<', source_chars) {
+                final_text.push('<');
+                match_operator(
+                    source_chars,
+                    &mut final_text,
+                    &[('=', TokenType::LessLessEqual)],
+                    TokenType::LessLess,
+                )
             } else {
-                TokenType::Equal
+                match_operator(
+                    source_chars,
+                    &mut final_text,
+                    &[('=', TokenType::LessEqual)],
+                    TokenType::Less,
+                )
+            }
+        }
+        '>' => {
+            if match_two_char_token('>', source_chars) {
+                final_text.push('>');
+                match_operator(
+                    source_chars,
+                    &mut final_text,
+                    &[('=', TokenType::GreaterGreaterEqual)],
+                    TokenType::GreaterGreater,
+                )
+            } else {
+                match_operator(
+                    source_chars,
+                    &mut final_text,
+                    &[('=', TokenType::GreaterEqual)],
+                    TokenType::Greater,
+                )
             }
         }
-        ';' => TokenType::Semicolon,
 
         // Literals
         '"' => {
@@ -77,13 +198,16 @@ fn scan_token(source_chars: &mut Peekable<Chars>, line: &mut usize) -> Result<To
             final_text = string;
             TokenType::String
         }
+        '\'' => {
+            final_text = match_character(source_chars, *line)?.to_string();
+            TokenType::Character
+        }
         '0'..='9' => {
-            let number = match_number(source_chars);
-            final_text.push_str(&number);
+            match_number(source_chars, &mut final_text);
             TokenType::Number
         }
 
-        character if character.is_alphabetic() => {
+        character if character.is_alphabetic() || character == '_' => {
             let identifier = match_identifier(source_chars);
             final_text.push_str(&identifier);
 
@@ -119,11 +243,93 @@ fn match_two_char_token(expected: char, source_chars: &mut Peekable<Chars>) -> b
         return true;
     }
 
-    return false;
+    false
+}
+
+/// Extends an operator by one character if the next character is one of
+/// `candidates`, falling back to `single` otherwise.
+fn match_operator(
+    source_chars: &mut Peekable<Chars>,
+    final_text: &mut String,
+    candidates: &[(char, TokenType)],
+    single: TokenType,
+) -> TokenType {
+    for (expected, token_type) in candidates {
+        if match_two_char_token(*expected, source_chars) {
+            final_text.push(*expected);
+            return token_type.clone();
+        }
+    }
+
+    single
+}
+
+fn skip_line_comment(source_chars: &mut Peekable<Chars>) {
+    while let Some(c) = source_chars.peek() {
+        if *c == '\n' {
+            break;
+        }
+        source_chars.next();
+    }
+}
+
+fn skip_block_comment(
+    source_chars: &mut Peekable<Chars>,
+    line: &mut usize,
+) -> Result<(), LexerError> {
+    let start_line = *line;
+
+    loop {
+        match source_chars.next() {
+            Some('*') if match_two_char_token('/', source_chars) => return Ok(()),
+            Some('\n') => *line += 1,
+            Some(_) => {}
+            None => return Err(LexerError::UnterminatedComment(start_line)),
+        }
+    }
+}
+
+/// Reads the escape sequence after a backslash and returns the byte it
+/// stands for, or the sequence itself if it is not a valid escape.
+fn match_escape(source_chars: &mut Peekable<Chars>) -> Result<u8, String> {
+    let character = source_chars.next().unwrap_or_default();
+    let mut sequence = format!("\\{}", character);
+
+    // Octal escapes take up to three digits, hexadecimal ones as many as
+    // there are; either must fit in a byte.
+    let (radix, mut value, limit) = match character {
+        'n' => return Ok(b'\n'),
+        't' => return Ok(b'\t'),
+        'r' => return Ok(b'\r'),
+        'a' => return Ok(0x07),
+        'b' => return Ok(0x08),
+        'f' => return Ok(0x0c),
+        'v' => return Ok(0x0b),
+        '\\' | '\'' | '"' | '?' => return Ok(character as u8),
+        '0'..='7' => (8, character.to_digit(8).unwrap(), 2),
+        'x' => (16, 0, usize::MAX),
+        _ => return Err(sequence),
+    };
+    let mut digits = 0;
+    while digits < limit
+        && let Some(digit) = source_chars.peek().and_then(|c| c.to_digit(radix))
+    {
+        sequence.push(source_chars.next().unwrap());
+        value = value.saturating_mul(radix).saturating_add(digit);
+        digits += 1;
+    }
+    if radix == 16 && digits == 0 {
+        return Err(sequence);
+    }
+    u8::try_from(value).map_err(|_| sequence)
 }
 
+/// Reads a string literal into its bytes: characters of the source in
+/// UTF-8 and escapes as the byte they stand for. The lexeme holds one
+/// `char` per byte so that it can be carried in a `String`.
 fn match_string(source_chars: &mut Peekable<Chars>, line: usize) -> Result<String, LexerError> {
-    let mut string = String::new();
+    let mut bytes = vec![];
+    let mut invalid = None;
 
     loop {
         let character = match source_chars.next() {
@@ -132,25 +338,77 @@ fn match_string(source_chars: &mut Peekable<Chars>, line: usize) -> Result<Strin
         };
 
         match character {
-            '"' => return Ok(string),
-            _ => string.push(character),
+            '"' => break,
+            '\n' => return Err(LexerError::UnterminatedString(line)),
+            '\\' => match source_chars.peek() {
+                Some('\n') | None => return Err(LexerError::UnterminatedString(line)),
+                Some(_) => match match_escape(source_chars) {
+                    Ok(byte) => bytes.push(byte),
+                    Err(sequence) => {
+                        invalid.get_or_insert(sequence);
+                    }
+                },
+            },
+            _ => bytes.extend(character.encode_utf8(&mut [0; 4]).bytes()),
         }
     }
+
+    match invalid {
+        Some(sequence) => Err(LexerError::InvalidEscape(sequence, line)),
+        None => Ok(bytes.into_iter().map(char::from).collect()),
+    }
 }
 
-fn match_number(source_chars: &mut Peekable<Chars>) -> String {
-    let mut number = String::new();
+/// Reads a character literal. An escape gives a `char` below 256, the byte
+/// it stands for. After an error the rest of the literal is skipped, so it
+/// is reported once.
+fn match_character(source_chars: &mut Peekable<Chars>, line: usize) -> Result<char, LexerError> {
+    let character = match source_chars.peek() {
+        Some('\\') => {
+            source_chars.next();
+            match source_chars.peek() {
+                Some('\n') | None => Err(LexerError::UnterminatedCharacter(line)),
+                Some(_) => match_escape(source_chars)
+                    .map(char::from)
+                    .map_err(|sequence| LexerError::InvalidEscape(sequence, line)),
+            }
+        }
+        Some('\'') | Some('\n') | None => Err(LexerError::UnterminatedCharacter(line)),
+        Some(_) => Ok(source_chars.next().unwrap()),
+    };
+
+    if character.is_ok() && match_two_char_token('\'', source_chars) {
+        return character;
+    }
+    while let Some(&c) = source_chars.peek()
+        && c != '\n'
+    {
+        source_chars.next();
+        if c == '\'' {
+            break;
+        }
+    }
+    Err(character
+        .err()
+        .unwrap_or(LexerError::UnterminatedCharacter(line)))
+}
 
-    while let Some(c) = source_chars.peek() {
-        if c.is_ascii_digit() {
-            number.push(*c);
+/// Reads the rest of a number into `number`, including any base prefix,
+/// fraction, exponent and suffix; the parser decides whether the result is
+/// a valid literal.
+fn match_number(source_chars: &mut Peekable<Chars>, number: &mut String) {
+    let is_hexadecimal = number.starts_with('0') && matches!(source_chars.peek(), Some('x' | 'X'));
+
+    while let Some(&c) = source_chars.peek() {
+        let is_exponent_sign =
+            !is_hexadecimal && matches!(c, '+' | '-') && number.ends_with(['e', 'E']);
+        if c.is_ascii_alphanumeric() || c == '_' || c == '.' || is_exponent_sign {
+            number.push(c);
             source_chars.next();
         } else {
             break;
         }
     }
-
-    number
 }
 
 fn match_identifier(source_chars: &mut Peekable<Chars>) -> String {
@@ -168,16 +426,16 @@ fn match_identifier(source_chars: &mut Peekable<Chars>) -> String {
     identifier
 }
 
-pub fn pretty_print_tokens(tokens: &Vec<Token>) {
+pub fn pretty_print_tokens(tokens: &[Token]) {
     let mut current_line = 0;
 
     for token in tokens {
         let line = token.line;
         if line != current_line {
             current_line = line;
-            println!("\n{}: {}", line, token.to_string());
+            println!("\n{}: {}", line, token);
         } else {
-            print!("{} ", token.to_string());
+            print!("{} ", token);
         }
     }
     println!();
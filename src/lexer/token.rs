@@ -4,6 +4,7 @@
 
 use lazy_static::lazy_static;
 use std::collections::HashMap;
+use std::fmt;
 
 lazy_static! {
     pub static ref KEYWORDS: HashMap<String, TokenType> = {
@@ -13,15 +14,42 @@ lazy_static! {
         m.insert("class".to_string(), TokenType::Class);
         m.insert("if".to_string(), TokenType::If);
         m.insert("else".to_string(), TokenType::Else);
+        m.insert("while".to_string(), TokenType::While);
+        m.insert("do".to_string(), TokenType::Do);
+        m.insert("for".to_string(), TokenType::For);
+        m.insert("break".to_string(), TokenType::Break);
+        m.insert("continue".to_string(), TokenType::Continue);
+        m.insert("switch".to_string(), TokenType::Switch);
+        m.insert("case".to_string(), TokenType::Case);
+        m.insert("default".to_string(), TokenType::Default);
+        m.insert("goto".to_string(), TokenType::Goto);
         m.insert("true".to_string(), TokenType::True);
         m.insert("false".to_string(), TokenType::False);
         m.insert("int".to_string(), TokenType::Int);
         m.insert("bool".to_string(), TokenType::Bool);
+        m.insert("_Bool".to_string(), TokenType::Bool);
+        m.insert("short".to_string(), TokenType::Short);
+        m.insert("long".to_string(), TokenType::Long);
+        m.insert("signed".to_string(), TokenType::Signed);
+        m.insert("unsigned".to_string(), TokenType::Unsigned);
         m.insert("char".to_string(), TokenType::Char);
         m.insert("float".to_string(), TokenType::Float);
         m.insert("double".to_string(), TokenType::Double);
         m.insert("void".to_string(), TokenType::Void);
         m.insert("return".to_string(), TokenType::Return);
+        m.insert("static".to_string(), TokenType::Static);
+        m.insert("extern".to_string(), TokenType::Extern);
+        m.insert("register".to_string(), TokenType::Register);
+        m.insert("auto".to_string(), TokenType::Auto);
+        m.insert("inline".to_string(), TokenType::Inline);
+        m.insert("__attribute__".to_string(), TokenType::Attribute);
+        m.insert("const".to_string(), TokenType::Const);
+        m.insert("volatile".to_string(), TokenType::Volatile);
+        m.insert("restrict".to_string(), TokenType::Restrict);
+        m.insert("typedef".to_string(), TokenType::Typedef);
+        m.insert("sizeof".to_string(), TokenType::Sizeof);
+        m.insert("_Alignof".to_string(), TokenType::Alignof);
+        m.insert("struct".to_string(), TokenType::Struct);
 
         m
     };
@@ -34,17 +62,55 @@ pub enum TokenType {
     RightParenthesis,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Semicolon,
+    Comma,
+    Tilde,
+    Question,
+    Colon,
+    Dot,
+    Ellipsis,
 
-    // One or two character tokens
+    // One, two or three character tokens
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    Plus,
+    PlusPlus,
+    PlusEqual,
+    Minus,
+    MinusMinus,
+    MinusEqual,
+    Arrow,
+    Star,
+    StarEqual,
+    Slash,
+    SlashEqual,
+    Percent,
+    PercentEqual,
+    Ampersand,
+    AmpersandAmpersand,
+    AmpersandEqual,
+    Pipe,
+    PipePipe,
+    PipeEqual,
+    Caret,
+    CaretEqual,
+    Less,
+    LessEqual,
+    LessLess,
+    LessLessEqual,
+    Greater,
+    GreaterEqual,
+    GreaterGreater,
+    GreaterGreaterEqual,
 
     // Literals
     Identifier,
     String,
+    Character,
     Number,
 
     // Keywords
@@ -52,20 +118,46 @@ pub enum TokenType {
     Class,
     If,
     Else,
+    While,
+    Do,
+    For,
+    Break,
+    Continue,
+    Switch,
+    Case,
+    Default,
+    Goto,
     True,
     False,
     Int,
+    Short,
+    Long,
+    Signed,
+    Unsigned,
     Bool,
     Char,
     Float,
     Double,
     Void,
     Return,
+    Static,
+    Extern,
+    Register,
+    Auto,
+    Inline,
+    Attribute,
+    Const,
+    Volatile,
+    Restrict,
+    Typedef,
+    Sizeof,
+    Alignof,
+    Struct,
 
     EOF,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
@@ -80,8 +172,10 @@ impl Token {
             line,
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
-        format!("{:?} {}", self.token_type, self.lexeme)
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {}", self.token_type, self.lexeme)
     }
 }
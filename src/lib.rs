@@ -1 +1,7 @@
+pub mod ast;
+pub mod code_generator;
+pub mod ir;
 pub mod lexer;
+pub mod parser;
+pub mod semantic_checker;
+pub mod target;
@@ -1,7 +1,10 @@
 pub mod ast;
+pub mod code_generator;
+pub mod ir;
 pub mod lexer;
 pub mod parser;
 pub mod semantic_checker;
+pub mod target;
 
 use std::env;
 use std::fs;
@@ -13,6 +16,16 @@ fn main() {
 
     let mut test = false;
     let mut file = String::new();
+    let mut emit: Option<String> = None;
+    let mut dump_cfg = false;
+    let mut run = false;
+    let mut level = ir::passes::OptLevel::O0;
+    let mut passes: Option<Vec<String>> = None;
+    let mut time_report = false;
+    let mut print_after: Vec<String> = vec![];
+    let mut output: Option<String> = None;
+    let mut allocator = code_generator::Allocator::Graph;
+    let mut syntax = code_generator::Syntax::Att;
 
     let mut iter = args.iter().skip(1);
     while let Some(arg) = iter.next() {
@@ -20,6 +33,9 @@ fn main() {
             "test" => {
                 test = true;
             }
+            "run" => {
+                run = true;
+            }
             "-c" => {
                 if let Some(f) = iter.next() {
                     file = f.to_string();
@@ -28,12 +44,188 @@ fn main() {
                     std::process::exit(1);
                 }
             }
+            "-o" => {
+                if let Some(f) = iter.next() {
+                    output = Some(f.to_string());
+                } else {
+                    eprintln!("Expected file name after -o");
+                    std::process::exit(1);
+                }
+            }
+            "--dump-cfg" => {
+                dump_cfg = true;
+            }
+            "-O0" => {
+                level = ir::passes::OptLevel::O0;
+            }
+            "-O" | "-O1" => {
+                level = ir::passes::OptLevel::O1;
+            }
+            "-O2" => {
+                level = ir::passes::OptLevel::O2;
+            }
+            "-Os" => {
+                level = ir::passes::OptLevel::Os;
+            }
+            arg if arg.starts_with("-O") => {
+                eprintln!("Unknown optimization level: '{}'", &arg["-O".len()..]);
+                std::process::exit(1);
+            }
+            "-ftime-report" => {
+                time_report = true;
+            }
+            arg if arg.starts_with("--passes=") => {
+                passes = Some(
+                    arg["--passes=".len()..]
+                        .split(',')
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                );
+            }
+            arg if arg.starts_with("--print-after=") => {
+                print_after.push(arg["--print-after=".len()..].to_string());
+            }
+            "--regalloc=graph" => {
+                allocator = code_generator::Allocator::Graph;
+            }
+            "--regalloc=linear" => {
+                allocator = code_generator::Allocator::Linear;
+            }
+            arg if arg.starts_with("--regalloc=") => {
+                eprintln!(
+                    "Unknown register allocator: '{}'",
+                    &arg["--regalloc=".len()..]
+                );
+                std::process::exit(1);
+            }
+            "-masm=att" => {
+                syntax = code_generator::Syntax::Att;
+            }
+            "-masm=intel" => {
+                syntax = code_generator::Syntax::Intel;
+            }
+            arg if arg.starts_with("-masm=") => {
+                eprintln!("Unknown assembly syntax: '{}'", &arg["-masm=".len()..]);
+                std::process::exit(1);
+            }
+            arg if arg.starts_with("--emit=") => {
+                emit = Some(arg["--emit=".len()..].to_string());
+            }
+            arg if !arg.starts_with('-') && file.is_empty() => {
+                file = arg.to_string();
+            }
             _ => {}
         }
     }
 
-    let contents = fs::read_to_string(file).expect("Failed to read file");
+    if let Some(kind) = &emit
+        && kind != "ir"
+        && kind != "asm"
+    {
+        eprintln!("Unknown --emit kind: '{}'", kind);
+        std::process::exit(1);
+    }
+    let verbose = emit.is_none() && !dump_cfg && !run;
+
+    let contents = fs::read_to_string(&file).expect("Failed to read file");
 
+    // IR written by `--emit=ir` can be read back to run passes on it.
+    let mut ir = if file.ends_with(".ir") {
+        match ir::parser::parse(&contents) {
+            Ok(ir) => ir,
+            Err(error) => {
+                eprintln!("Errors occurred during parsing: ");
+                match error {
+                    ir::parser::ParseError::UnexpectedToken(token, line) => {
+                        eprintln!(
+                            "\x1b[31mUnexpected token '{}' at line {}\x1b[0m",
+                            token, line
+                        )
+                    }
+                    ir::parser::ParseError::InvalidNumber(number, line) => {
+                        eprintln!(
+                            "\x1b[31mInvalid number '{}' at line {}\x1b[0m",
+                            number, line
+                        )
+                    }
+                    ir::parser::ParseError::UnterminatedString(line) => {
+                        eprintln!("\x1b[31mUnterminated string at line {}\x1b[0m", line)
+                    }
+                    ir::parser::ParseError::UndefinedLabel(label, line) => {
+                        eprintln!("\x1b[31mUndefined label {} at line {}\x1b[0m", label, line)
+                    }
+                    ir::parser::ParseError::UndefinedTemp(temp, line) => {
+                        eprintln!(
+                            "\x1b[31mUndefined temporary {} at line {}\x1b[0m",
+                            temp, line
+                        )
+                    }
+                }
+                std::process::exit(1);
+            }
+        }
+    } else {
+        compile(contents, test, verbose)
+    };
+    let pass_manager = match &passes {
+        Some(names) => {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            ir::passes::PassManager::new(&names)
+        }
+        None => Ok(ir::passes::PassManager::for_level(level)),
+    };
+    let mut pass_manager =
+        pass_manager.unwrap_or_else(|ir::passes::PassError::UnknownPass(name)| {
+            eprintln!("Unknown pass: '{}'", name);
+            std::process::exit(1);
+        });
+    for name in &print_after {
+        if let Err(ir::passes::PassError::UnknownPass(name)) = pass_manager.print_after(name) {
+            eprintln!("Unknown pass: '{}'", name);
+            std::process::exit(1);
+        }
+    }
+    pass_manager.run(&mut ir, &mut std::io::stderr());
+    if time_report {
+        eprint!("{}", pass_manager.time_report());
+    }
+    if emit.as_deref() == Some("ir") {
+        print!("{}", ir);
+    }
+    if emit.as_deref() == Some("asm") {
+        let assembly = match code_generator::generate(&ir, allocator, syntax) {
+            Ok(assembly) => assembly,
+            Err(code_generator::CodegenError::Unsupported(message)) => {
+                eprintln!("\x1b[31mUnsupported by the backend: {}\x1b[0m", message);
+                std::process::exit(1);
+            }
+        };
+        match &output {
+            Some(path) => fs::write(path, assembly).expect("Failed to write output file"),
+            None => print!("{}", assembly),
+        }
+    }
+    if dump_cfg {
+        print!("{}", ir::cfg::to_dot(&ir.functions));
+    }
+    if verbose {
+        println!("IR: {:#?}", ir);
+    }
+
+    if run {
+        match ir::interpreter::run(&ir, &mut std::io::stdout()) {
+            Ok(status) => std::process::exit(status),
+            Err(error) => {
+                eprintln!("\x1b[31mRuntime error: {:?}\x1b[0m", error);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Lexes, parses, checks and lowers C source, exiting on errors.
+fn compile(contents: String, test: bool, verbose: bool) -> ir::Program {
     let tokens = match lexer::tokenize(contents, test) {
         Ok(tokens) => tokens,
         Err(errors) => {
@@ -49,13 +241,30 @@ fn main() {
                     LexerError::UnterminatedString(line) => {
                         eprintln!("\x1b[31mUnterminated string at line {}\x1b[0m", line)
                     }
+                    LexerError::UnterminatedCharacter(line) => {
+                        eprintln!(
+                            "\x1b[31mUnterminated character literal at line {}\x1b[0m",
+                            line
+                        )
+                    }
+                    LexerError::UnterminatedComment(line) => {
+                        eprintln!("\x1b[31mUnterminated comment at line {}\x1b[0m", line)
+                    }
+                    LexerError::InvalidEscape(sequence, line) => {
+                        eprintln!(
+                            "\x1b[31mInvalid escape sequence '{}' at line {}\x1b[0m",
+                            sequence, line
+                        )
+                    }
                 }
             }
             std::process::exit(1);
         }
     };
 
-    lexer::pretty_print_tokens(&tokens);
+    if verbose {
+        lexer::pretty_print_tokens(&tokens);
+    }
 
     let program = match parser::parse(tokens) {
         Ok(program) => program,
@@ -65,15 +274,30 @@ fn main() {
                 parser::ParseError::UnexpectedToken(line) => {
                     eprintln!("\x1b[31mUnexpected token at line {}\x1b[0m", line)
                 }
+                parser::ParseError::InvalidDeclaration(message, line) => {
+                    eprintln!(
+                        "\x1b[31mInvalid declaration at line {}: {}\x1b[0m",
+                        line, message
+                    )
+                }
+                parser::ParseError::InvalidLiteral(message, line) => {
+                    eprintln!(
+                        "\x1b[31mInvalid literal at line {}: {}\x1b[0m",
+                        line, message
+                    )
+                }
             }
             std::process::exit(1);
         }
     };
 
-    println!("Parsed program: {:#?}", program);
+    if verbose {
+        println!("Parsed program: {:#?}", program);
+    }
 
     match semantic_checker::check(&program) {
-        Ok(()) => println!("\x1b[32mSemantic check passed!\x1b[0m"),
+        Ok(()) if verbose => println!("\x1b[32mSemantic check passed!\x1b[0m"),
+        Ok(()) => {}
         Err(error) => {
             eprintln!("Errors occurred during semantic checking: ");
             match error {
@@ -89,8 +313,28 @@ fn main() {
                 semantic_checker::SemanticError::TypeError(message) => {
                     eprintln!("\x1b[31mType error: {}\x1b[0m", message)
                 }
+                semantic_checker::SemanticError::Redeclaration(message) => {
+                    eprintln!("\x1b[31mRedeclaration: {}\x1b[0m", message)
+                }
+                semantic_checker::SemanticError::LinkageConflict(message) => {
+                    eprintln!("\x1b[31mLinkage conflict: {}\x1b[0m", message)
+                }
+                semantic_checker::SemanticError::InvalidStorageClass(message) => {
+                    eprintln!("\x1b[31mInvalid storage class: {}\x1b[0m", message)
+                }
+                semantic_checker::SemanticError::ReadOnlyAssignment(message) => {
+                    eprintln!("\x1b[31mRead-only assignment: {}\x1b[0m", message)
+                }
+                semantic_checker::SemanticError::InvalidStatement(message) => {
+                    eprintln!("\x1b[31mInvalid statement: {}\x1b[0m", message)
+                }
+                semantic_checker::SemanticError::UndefinedLabel(message) => {
+                    eprintln!("\x1b[31mUndefined label: {}\x1b[0m", message)
+                }
             }
             std::process::exit(1);
         }
     };
+
+    ir::lower::lower(&program)
 }
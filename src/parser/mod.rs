@@ -1,222 +1,1488 @@
-use std::iter::Peekable;
+use std::collections::HashMap;
 
 use crate::ast::*;
 use crate::lexer::token::{Token, TokenType};
 
+#[derive(Debug)]
 pub enum ParseError {
     UnexpectedToken(usize),
+    InvalidDeclaration(String, usize),
+    InvalidLiteral(String, usize),
 }
 
-fn match_tokens(
-    tokens: &mut impl Iterator<Item = Token>,
-    expected_token_types: Vec<TokenType>,
-) -> Option<Vec<Token>> {
-    let mut new_tokens: Vec<Token> = vec![];
-    let mut expected_token_types_iter = expected_token_types.iter();
+struct DeclarationSpecifiers {
+    storage_class: Option<StorageClass>,
+    attributes: FunctionAttributes,
+    data_type: QualifiedType,
+}
 
-    while let Some(expected_token_type) = expected_token_types_iter.next() {
-        let token = match tokens.next() {
-            Some(token) => token,
-            None => return None,
-        };
+/// A declarator as written, before the base type from the specifiers has been
+/// wrapped around it. C declarators read inside-out, so the tree is applied
+/// from the outside in by `apply_declarator`.
+enum Declarator {
+    Name(Option<Identifier>),
+    Pointer(TypeQualifiers, Box<Declarator>),
+    Array(Box<Declarator>, Option<usize>),
+    Function(Box<Declarator>, ParameterList),
+}
 
-        if token.token_type != *expected_token_type {
-            return None;
-        }
+struct ParameterList {
+    parameters: Vec<Parameter>,
+    has_prototype: bool,
+    is_variadic: bool,
+}
 
-        new_tokens.push(token);
-    }
+#[derive(Default)]
+struct Scope {
+    /// Typedef names map to their type; other ordinary identifiers map to
+    /// `None` so that a variable can shadow a typedef name.
+    names: HashMap<String, Option<QualifiedType>>,
+    /// Struct tags, mapped to the unique name of their `TypeTable` entry.
+    tags: HashMap<String, String>,
+}
 
-    Some(new_tokens)
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// Innermost scope last.
+    scopes: Vec<Scope>,
+    types: TypeTable,
+    /// The attributes given so far to each function declared at file scope.
+    function_attributes: HashMap<String, FunctionAttributes>,
 }
 
-fn match_token(
-    tokens: &mut Peekable<impl Iterator<Item = Token>>,
-    expected_token_type: TokenType,
-) -> Option<Token> {
-    let token = match tokens.peek() {
-        Some(token) => token,
-        None => return None,
+fn binary_operator(token_type: &TokenType) -> Option<(BinaryOperator, u8)> {
+    let operator = match token_type {
+        TokenType::PipePipe => (BinaryOperator::LogicalOr, 1),
+        TokenType::AmpersandAmpersand => (BinaryOperator::LogicalAnd, 2),
+        TokenType::Pipe => (BinaryOperator::BitwiseOr, 3),
+        TokenType::Caret => (BinaryOperator::BitwiseXor, 4),
+        TokenType::Ampersand => (BinaryOperator::BitwiseAnd, 5),
+        TokenType::EqualEqual => (BinaryOperator::Equal, 6),
+        TokenType::BangEqual => (BinaryOperator::NotEqual, 6),
+        TokenType::Less => (BinaryOperator::Less, 7),
+        TokenType::LessEqual => (BinaryOperator::LessEqual, 7),
+        TokenType::Greater => (BinaryOperator::Greater, 7),
+        TokenType::GreaterEqual => (BinaryOperator::GreaterEqual, 7),
+        TokenType::LessLess => (BinaryOperator::ShiftLeft, 8),
+        TokenType::GreaterGreater => (BinaryOperator::ShiftRight, 8),
+        TokenType::Plus => (BinaryOperator::Add, 9),
+        TokenType::Minus => (BinaryOperator::Subtract, 9),
+        TokenType::Star => (BinaryOperator::Multiply, 10),
+        TokenType::Slash => (BinaryOperator::Divide, 10),
+        TokenType::Percent => (BinaryOperator::Remainder, 10),
+        _ => return None,
     };
 
-    if token.token_type != expected_token_type {
-        return None;
-    }
-
-    tokens.next()
+    Some(operator)
 }
 
-fn parse_literal(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Option<Literal> {
-    let literal: Literal = match tokens.peek() {
-        Some(token) => match token.token_type {
-            TokenType::Number => Literal::Integer(IntegerLiteral {
-                value: token.lexeme.parse().ok()?,
-            }),
-            TokenType::True => Literal::Boolean(BooleanLiteral { value: true }),
-            TokenType::False => Literal::Boolean(BooleanLiteral { value: false }),
-            TokenType::String => Literal::String(StringLiteral {
-                value: token.lexeme.clone(),
-            }),
-            _ => return None,
-        },
-        None => return None,
+fn assignment_operator(token_type: &TokenType) -> Option<Option<BinaryOperator>> {
+    let operator = match token_type {
+        TokenType::Equal => None,
+        TokenType::PlusEqual => Some(BinaryOperator::Add),
+        TokenType::MinusEqual => Some(BinaryOperator::Subtract),
+        TokenType::StarEqual => Some(BinaryOperator::Multiply),
+        TokenType::SlashEqual => Some(BinaryOperator::Divide),
+        TokenType::PercentEqual => Some(BinaryOperator::Remainder),
+        TokenType::AmpersandEqual => Some(BinaryOperator::BitwiseAnd),
+        TokenType::PipeEqual => Some(BinaryOperator::BitwiseOr),
+        TokenType::CaretEqual => Some(BinaryOperator::BitwiseXor),
+        TokenType::LessLessEqual => Some(BinaryOperator::ShiftLeft),
+        TokenType::GreaterGreaterEqual => Some(BinaryOperator::ShiftRight),
+        _ => return None,
     };
 
-    tokens.next();
-    Some(literal)
+    Some(operator)
 }
 
-fn parse_identifier(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Option<Identifier> {
-    match tokens.peek() {
-        Some(token) => match token.token_type {
-            TokenType::Identifier => {
-                let token = tokens.next().unwrap();
-                return Some(Identifier { name: token.lexeme });
-            }
-            _ => return None,
-        },
-        None => None,
+fn storage_class(token_type: &TokenType) -> Option<StorageClass> {
+    match token_type {
+        TokenType::Auto => Some(StorageClass::Auto),
+        TokenType::Register => Some(StorageClass::Register),
+        TokenType::Static => Some(StorageClass::Static),
+        TokenType::Extern => Some(StorageClass::Extern),
+        TokenType::Typedef => Some(StorageClass::Typedef),
+        _ => None,
     }
 }
 
-fn parse_expression(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Option<Expr> {
-    if let Some(literal) = parse_literal(tokens) {
-        return Some(Expr::Literal(literal));
-    }
+fn is_type_specifier(token_type: &TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::Int
+            | TokenType::Short
+            | TokenType::Long
+            | TokenType::Signed
+            | TokenType::Unsigned
+            | TokenType::Bool
+            | TokenType::Char
+            | TokenType::Float
+            | TokenType::Double
+            | TokenType::Void
+    )
+}
 
-    if let Some(identifier) = parse_identifier(tokens) {
-        return Some(Expr::Identifier(identifier));
+/// Resolves the basic type specifiers of a declaration, which may appear in
+/// any order (C11 6.7.2p2), e.g. `long unsigned int long`.
+fn combine_type_specifiers(specifiers: &[TokenType]) -> Option<DataType> {
+    let count = |token_type: TokenType| specifiers.iter().filter(|s| **s == token_type).count();
+    let (signed, unsigned, longs) = (
+        count(TokenType::Signed),
+        count(TokenType::Unsigned),
+        count(TokenType::Long),
+    );
+    if signed + unsigned > 1 {
+        return None;
     }
 
-    None
-}
+    let mut others: Vec<&TokenType> = specifiers
+        .iter()
+        .filter(|s| !matches!(s, TokenType::Signed | TokenType::Unsigned | TokenType::Long))
+        .collect();
+    // `int` may accompany `short` and `long` in any position.
+    if others.len() == 2 && others.contains(&&TokenType::Int) {
+        others.retain(|s| **s != TokenType::Int);
+    }
+    let has_sign = signed + unsigned > 0;
+    let is_unsigned = unsigned > 0;
 
-fn parse_type(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Option<DataType> {
-    let data_type = match tokens.peek() {
-        Some(token) => match token.token_type {
-            TokenType::Int => DataType::Int,
-            TokenType::Bool => DataType::Bool,
-            TokenType::Char => DataType::Char,
-            TokenType::Float => DataType::Float,
-            TokenType::Double => DataType::Double,
-            TokenType::Void => DataType::Void,
-            _ => return None,
+    let data_type = match (others.as_slice(), longs) {
+        ([], 0) if !has_sign => return None,
+        ([] | [TokenType::Int], 0) => DataType::Int,
+        ([] | [TokenType::Int], 1) => DataType::Long,
+        ([] | [TokenType::Int], 2) => DataType::LongLong,
+        ([TokenType::Short], 0) => DataType::Short,
+        ([TokenType::Char], 0) => match (signed, unsigned) {
+            (0, 0) => return Some(DataType::Char),
+            (1, _) => return Some(DataType::SChar),
+            _ => return Some(DataType::UChar),
         },
-        None => return None,
+        ([TokenType::Bool], 0) if !has_sign => return Some(DataType::Bool),
+        ([TokenType::Float], 0) if !has_sign => return Some(DataType::Float),
+        ([TokenType::Double], 0) if !has_sign => return Some(DataType::Double),
+        ([TokenType::Void], 0) if !has_sign => return Some(DataType::Void),
+        _ => return None,
     };
 
-    tokens.next();
-    Some(data_type)
+    Some(if is_unsigned {
+        data_type.to_unsigned()
+    } else {
+        data_type
+    })
 }
 
-fn parse_assignment(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Option<Assignment> {
-    let data_type = parse_type(tokens)?;
-    let identifier = parse_identifier(tokens)?;
+/// Splits an integer literal into its value and type (C11 6.4.4.1): the
+/// first type in the list for its suffix and base that can represent it.
+fn integer_literal(lexeme: &str) -> Result<IntegerLiteral, String> {
+    let (digits, radix) = if let Some(hex) = lexeme
+        .strip_prefix("0x")
+        .or_else(|| lexeme.strip_prefix("0X"))
+    {
+        (hex, 16)
+    } else if lexeme.starts_with('0') {
+        (lexeme, 8)
+    } else {
+        (lexeme, 10)
+    };
+
+    let suffix_start = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    let (digits, suffix) = digits.split_at(suffix_start);
 
-    match_token(tokens, TokenType::Equal)?;
+    let (is_unsigned, long_suffix) = match suffix
+        .strip_prefix(['u', 'U'])
+        .or_else(|| suffix.strip_suffix(['u', 'U']))
+    {
+        Some(rest) => (true, rest),
+        None => (false, suffix),
+    };
+    let longs = match long_suffix {
+        "" => 0,
+        "l" | "L" => 1,
+        "ll" | "LL" => 2,
+        _ => return Err(format!("invalid suffix on integer literal '{}'", lexeme)),
+    };
 
-    let expr = parse_expression(tokens)?;
+    let value = u64::from_str_radix(digits, radix)
+        .ok()
+        .ok_or_else(|| format!("invalid integer literal '{}'", lexeme))?;
 
-    match_token(tokens, TokenType::Semicolon)?;
+    let signed_candidates = [DataType::Int, DataType::Long, DataType::LongLong];
+    let mut candidates = vec![];
+    for data_type in &signed_candidates[longs.min(2)..] {
+        if !is_unsigned {
+            candidates.push(data_type.clone());
+        }
+        // Only octal and hexadecimal literals fall back to unsigned types
+        // unless the suffix asks for one.
+        if is_unsigned || radix != 10 {
+            candidates.push(data_type.to_unsigned());
+        }
+    }
 
-    Some(Assignment {
-        data_type: data_type,
-        identifier: identifier,
-        value: expr,
-    })
+    let data_type = candidates
+        .into_iter()
+        .find(|data_type| data_type.can_represent(value as i128))
+        .ok_or_else(|| format!("integer literal '{}' is too large", lexeme))?;
+
+    Ok(IntegerLiteral { value, data_type })
 }
 
-fn parse_return(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Option<Return> {
-    match_token(tokens, TokenType::Return)?;
+/// Whether a numeric lexeme is a floating constant rather than an integer.
+fn is_float_literal(lexeme: &str) -> bool {
+    !lexeme.starts_with("0x") && !lexeme.starts_with("0X") && lexeme.contains(['.', 'e', 'E'])
+}
 
-    let expression = parse_expression(tokens)?;
+fn float_literal(lexeme: &str) -> Result<FloatLiteral, String> {
+    let (digits, data_type) = match lexeme.strip_suffix(['f', 'F']) {
+        Some(digits) => (digits, DataType::Float),
+        None => (
+            lexeme.strip_suffix(['l', 'L']).unwrap_or(lexeme),
+            DataType::Double,
+        ),
+    };
+    // Rust also reads "inf" and "NaN", but those never start a number.
+    let value: f64 = digits
+        .parse()
+        .map_err(|_| format!("invalid floating literal '{}'", lexeme))?;
+    let value = match data_type {
+        DataType::Float => value as f32 as f64,
+        _ => value,
+    };
+    Ok(FloatLiteral { value, data_type })
+}
 
-    match_token(tokens, TokenType::Semicolon)?;
+/// Records a type qualifier in `qualifiers`, returning `false` if the token
+/// is not one.
+fn match_qualifier(token_type: &TokenType, qualifiers: &mut TypeQualifiers) -> bool {
+    match token_type {
+        TokenType::Const => qualifiers.is_const = true,
+        TokenType::Volatile => qualifiers.is_volatile = true,
+        TokenType::Restrict => qualifiers.is_restrict = true,
+        _ => return false,
+    }
 
-    Some(Return { value: expression })
+    true
 }
 
-fn parse_instructions(
-    tokens: &mut Peekable<impl Iterator<Item = Token>>,
-) -> Result<Vec<Instruction>, ParseError> {
-    let mut instructions: Vec<Instruction> = vec![];
+/// Wraps `base` in the derivations described by `declarator`, returning the
+/// declared name, its full type, and the parameters of the function
+/// declarator applied directly to the name, if any.
+fn apply_declarator(
+    base: QualifiedType,
+    declarator: Declarator,
+) -> (Option<Identifier>, QualifiedType, Option<Vec<Parameter>>) {
+    match declarator {
+        Declarator::Name(identifier) => (identifier, base, None),
+        Declarator::Pointer(qualifiers, inner) => {
+            let pointer = QualifiedType {
+                data_type: DataType::Pointer(Box::new(base)),
+                qualifiers,
+            };
+            apply_declarator(pointer, *inner)
+        }
+        Declarator::Array(inner, length) => {
+            let array = QualifiedType::new(DataType::Array(Box::new(base), length));
+            apply_declarator(array, *inner)
+        }
+        Declarator::Function(inner, parameter_list) => {
+            let function = QualifiedType::new(DataType::Function(FunctionType {
+                return_type: Box::new(base),
+                parameters: parameter_list
+                    .parameters
+                    .iter()
+                    .map(|parameter| parameter.data_type.clone())
+                    .collect(),
+                has_prototype: parameter_list.has_prototype,
+                is_variadic: parameter_list.is_variadic,
+            }));
+
+            let names_function = matches!(*inner, Declarator::Name(_));
+            let (identifier, data_type, parameters) = apply_declarator(function, *inner);
 
-    loop {
-        if let Some(assignment) = parse_assignment(tokens) {
-            instructions.push(Instruction::Assignment(assignment));
-            continue;
+            if names_function {
+                (identifier, data_type, Some(parameter_list.parameters))
+            } else {
+                (identifier, data_type, parameters)
+            }
         }
+    }
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Self {
+        // `va_list` is declared outside the file scope, as if by
+        // `<stdarg.h>`, so the program can still use the name itself.
+        let mut types = TypeTable::default();
+        let mut builtins = Scope::default();
+        builtins
+            .names
+            .insert("va_list".to_string(), Some(types.define_va_list()));
+        Parser {
+            tokens,
+            position: 0,
+            scopes: vec![builtins, Scope::default()],
+            types,
+            function_attributes: HashMap::new(),
+        }
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
 
-        if let Some(ret) = parse_return(tokens) {
-            instructions.push(Instruction::Return(ret));
-            continue;
+    fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn typedef_type(&self, name: &str) -> Option<&QualifiedType> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.names.get(name))
+            .and_then(|entry| entry.as_ref())
+    }
+
+    /// Records `name` in the current scope, as a typedef name when
+    /// `typedef_type` is given. Only identical typedef redefinitions may share
+    /// a scope with an existing name; the checker diagnoses the rest.
+    fn declare_name(
+        &mut self,
+        name: &str,
+        typedef_type: Option<QualifiedType>,
+    ) -> Result<(), ParseError> {
+        let scope = self.scopes.last().unwrap();
+        match (scope.names.get(name), &typedef_type) {
+            (Some(Some(existing)), Some(new)) if existing != new => {
+                return Err(self.invalid_declaration("conflicting typedef redefinition"));
+            }
+            (Some(Some(_)), None) | (Some(None), Some(_)) => {
+                return Err(self.invalid_declaration("redeclared as a different kind of symbol"));
+            }
+            _ => {}
         }
 
-        if let Some(next_token) = tokens.peek() {
-            if next_token.token_type == TokenType::RightBrace {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .names
+            .insert(name.to_string(), typedef_type);
+        Ok(())
+    }
+
+    /// Adds a new, incomplete struct to the current scope under `tag`.
+    fn declare_struct(&mut self, tag: Option<String>) -> String {
+        let base = tag.clone().unwrap_or_else(|| "anonymous".to_string());
+        let mut name = base.clone();
+        let mut suffix = 0;
+        while self.types.structs.contains_key(&name) {
+            suffix += 1;
+            name = format!("{}.{}", base, suffix);
+        }
+
+        self.types.structs.insert(
+            name.clone(),
+            StructDefinition {
+                name: name.clone(),
+                members: None,
+            },
+        );
+        if let Some(tag) = tag {
+            self.scopes
+                .last_mut()
+                .unwrap()
+                .tags
+                .insert(tag, name.clone());
+        }
+
+        name
+    }
+
+    fn lookup_tag(&self, tag: &str) -> Option<&String> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.tags.get(tag))
+    }
+
+    fn parse_struct_specifier(&mut self) -> Result<DataType, ParseError> {
+        self.expect(TokenType::Struct)?;
+        let tag = self.parse_identifier().map(|identifier| identifier.name);
+
+        if !self.check(TokenType::LeftBrace) {
+            let tag = tag.ok_or_else(|| self.unexpected())?;
+            let in_current_scope = self.scopes.last().unwrap().tags.get(&tag).cloned();
+
+            // `struct S;` always declares a new tag in the current scope.
+            let name = match (in_current_scope, self.lookup_tag(&tag)) {
+                (Some(name), _) => name,
+                (None, Some(name)) if !self.check(TokenType::Semicolon) => name.clone(),
+                _ => self.declare_struct(Some(tag)),
+            };
+            return Ok(DataType::Struct(name));
+        }
+
+        let name = match tag
+            .as_ref()
+            .and_then(|tag| self.scopes.last().unwrap().tags.get(tag))
+        {
+            Some(name) if self.types.structs[name].members.is_some() => {
+                return Err(self.invalid_declaration("redefinition of struct"));
+            }
+            Some(name) => name.clone(),
+            None => self.declare_struct(tag),
+        };
+
+        self.expect(TokenType::LeftBrace)?;
+        let mut members: Vec<StructMember> = vec![];
+        while self.match_token(TokenType::RightBrace).is_none() {
+            let specifiers = self.parse_declaration_specifiers()?;
+            if specifiers.storage_class.is_some() {
+                return Err(self.invalid_declaration("storage class on struct member"));
+            }
+
+            loop {
+                let declarator = self.parse_declarator()?;
+                let (identifier, data_type, _) =
+                    apply_declarator(specifiers.data_type.clone(), declarator);
+                let identifier =
+                    identifier.ok_or_else(|| self.invalid_declaration("member requires a name"))?;
+
+                if !self.types.is_complete(&data_type.data_type) {
+                    return Err(self.invalid_declaration("member has incomplete type"));
+                }
+                if members.iter().any(|member| member.name == identifier.name) {
+                    return Err(self.invalid_declaration("duplicate member"));
+                }
+
+                members.push(StructMember {
+                    name: identifier.name,
+                    data_type,
+                });
+
+                if self.match_token(TokenType::Comma).is_none() {
+                    break;
+                }
+            }
+            self.expect(TokenType::Semicolon)?;
+        }
+
+        self.types.structs.get_mut(&name).unwrap().members = Some(members);
+        Ok(DataType::Struct(name))
+    }
+
+    /// Whether the token `n` ahead can begin a type name: a type specifier,
+    /// a qualifier, or an identifier currently declared as a typedef name.
+    fn is_type_name_start(&self, n: usize) -> bool {
+        match self.tokens.get(self.position + n) {
+            Some(token) => {
+                is_type_specifier(&token.token_type)
+                    || token.token_type == TokenType::Struct
+                    || match_qualifier(&token.token_type, &mut TypeQualifiers::default())
+                    || (token.token_type == TokenType::Identifier
+                        && self.typedef_type(&token.lexeme).is_some())
+            }
+            None => false,
+        }
+    }
+
+    fn peek_type(&self) -> Option<&TokenType> {
+        self.peek_nth_type(0)
+    }
+
+    fn peek_nth_type(&self, n: usize) -> Option<&TokenType> {
+        self.tokens
+            .get(self.position + n)
+            .map(|token| &token.token_type)
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        self.peek_type() == Some(&token_type)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn match_token(&mut self, expected_token_type: TokenType) -> Option<Token> {
+        if !self.check(expected_token_type) {
+            return None;
+        }
+
+        self.advance()
+    }
+
+    fn expect(&mut self, expected_token_type: TokenType) -> Result<Token, ParseError> {
+        self.match_token(expected_token_type)
+            .ok_or_else(|| self.unexpected())
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |token| token.line)
+    }
+
+    fn unexpected(&self) -> ParseError {
+        ParseError::UnexpectedToken(self.line())
+    }
+
+    fn invalid_declaration(&self, message: &str) -> ParseError {
+        ParseError::InvalidDeclaration(message.to_string(), self.line())
+    }
+
+    fn is_declaration_start(&self) -> bool {
+        self.peek_type().and_then(storage_class).is_some()
+            || self.check(TokenType::Inline)
+            || self.check(TokenType::Attribute)
+            || self.is_type_name_start(0)
+    }
+
+    fn parse_literal(&mut self) -> Result<Option<Literal>, ParseError> {
+        let Some(token) = self.tokens.get(self.position) else {
+            return Ok(None);
+        };
+        let literal: Literal = match token.token_type {
+            TokenType::Number if is_float_literal(&token.lexeme) => Literal::Float(
+                float_literal(&token.lexeme)
+                    .map_err(|message| ParseError::InvalidLiteral(message, token.line))?,
+            ),
+            TokenType::Number => Literal::Integer(
+                integer_literal(&token.lexeme)
+                    .map_err(|message| ParseError::InvalidLiteral(message, token.line))?,
+            ),
+            TokenType::Character => Literal::Char(CharLiteral {
+                value: token.lexeme.chars().next().unwrap(),
+            }),
+            TokenType::True => Literal::Boolean(BooleanLiteral { value: true }),
+            TokenType::False => Literal::Boolean(BooleanLiteral { value: false }),
+            TokenType::String => {
+                let mut value = vec![];
+                while let Some(token) = self.match_token(TokenType::String) {
+                    value.extend(token.lexeme.chars().map(|c| c as u8));
+                }
+                return Ok(Some(Literal::String(StringLiteral { value })));
+            }
+            _ => return Ok(None),
+        };
+
+        self.advance();
+        Ok(Some(literal))
+    }
+
+    fn parse_identifier(&mut self) -> Option<Identifier> {
+        let token = self.match_token(TokenType::Identifier)?;
+        Some(Identifier { name: token.lexeme })
+    }
+
+    fn parse_primary_expression(&mut self) -> Result<Expr, ParseError> {
+        if let Some(literal) = self.parse_literal()? {
+            return Ok(Expr::Literal(literal));
+        }
+
+        if self.check(TokenType::Identifier)
+            && self.peek_nth_type(1) == Some(&TokenType::LeftParenthesis)
+            && let Some(stdarg) = self.parse_stdarg()?
+        {
+            return Ok(Expr::Stdarg(stdarg));
+        }
+
+        if let Some(identifier) = self.parse_identifier() {
+            return Ok(Expr::Identifier(identifier));
+        }
+
+        if self.match_token(TokenType::LeftParenthesis).is_some() {
+            let expr = self.parse_expression()?;
+            self.expect(TokenType::RightParenthesis)?;
+            return Ok(expr);
+        }
+
+        Err(self.unexpected())
+    }
+
+    /// Parses a use of one of the `<stdarg.h>` macros, which take a type
+    /// or an unused argument like no function does.
+    fn parse_stdarg(&mut self) -> Result<Option<Stdarg>, ParseError> {
+        let name = self.tokens[self.position].lexeme.clone();
+        if !["va_start", "va_arg", "va_end", "va_copy"].contains(&name.as_str()) {
+            return Ok(None);
+        }
+        self.advance();
+        self.expect(TokenType::LeftParenthesis)?;
+        let list = Box::new(self.parse_assignment_expression()?);
+        let stdarg = match name.as_str() {
+            "va_start" => {
+                let last = match self.match_token(TokenType::Comma) {
+                    Some(_) => Some(Box::new(self.parse_assignment_expression()?)),
+                    None => None,
+                };
+                Stdarg::Start(list, last)
+            }
+            "va_arg" => {
+                self.expect(TokenType::Comma)?;
+                Stdarg::Arg(list, self.parse_type_name()?)
+            }
+            "va_end" => Stdarg::End(list),
+            _ => {
+                self.expect(TokenType::Comma)?;
+                Stdarg::Copy(list, Box::new(self.parse_assignment_expression()?))
+            }
+        };
+        self.expect(TokenType::RightParenthesis)?;
+        Ok(Some(stdarg))
+    }
+
+    fn parse_postfix_expression(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_primary_expression()?;
+
+        loop {
+            if self.match_token(TokenType::LeftParenthesis).is_some() {
+                let mut arguments = vec![];
+                if !self.check(TokenType::RightParenthesis) {
+                    loop {
+                        arguments.push(self.parse_assignment_expression()?);
+                        if self.match_token(TokenType::Comma).is_none() {
+                            break;
+                        }
+                    }
+                }
+                self.expect(TokenType::RightParenthesis)?;
+
+                expr = Expr::Call(Call {
+                    callee: Box::new(expr),
+                    arguments,
+                });
+            } else if self.match_token(TokenType::LeftBracket).is_some() {
+                let index = self.parse_expression()?;
+                self.expect(TokenType::RightBracket)?;
+
+                expr = Expr::Index(Index {
+                    array: Box::new(expr),
+                    index: Box::new(index),
+                });
+            } else if let Some(token) = self
+                .match_token(TokenType::Dot)
+                .or_else(|| self.match_token(TokenType::Arrow))
+            {
+                let member = self.parse_identifier().ok_or_else(|| self.unexpected())?;
+
+                expr = Expr::Member(Member {
+                    object: Box::new(expr),
+                    member: member.name,
+                    is_arrow: token.token_type == TokenType::Arrow,
+                });
+            } else if self.match_token(TokenType::PlusPlus).is_some() {
+                expr = Expr::Unary(UnaryExpr {
+                    operator: UnaryOperator::PostIncrement,
+                    operand: Box::new(expr),
+                });
+            } else if self.match_token(TokenType::MinusMinus).is_some() {
+                expr = Expr::Unary(UnaryExpr {
+                    operator: UnaryOperator::PostDecrement,
+                    operand: Box::new(expr),
+                });
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Parses `( type-name )`; the caller has already checked that a type name
+    /// follows the parenthesis.
+    fn parse_parenthesized_type_name(&mut self) -> Result<QualifiedType, ParseError> {
+        self.expect(TokenType::LeftParenthesis)?;
+        let data_type = self.parse_type_name()?;
+        self.expect(TokenType::RightParenthesis)?;
+        Ok(data_type)
+    }
+
+    fn parse_type_name(&mut self) -> Result<QualifiedType, ParseError> {
+        let specifiers = self.parse_declaration_specifiers()?;
+        if specifiers.storage_class.is_some() {
+            return Err(self.invalid_declaration("storage class in type name"));
+        }
+
+        let declarator = self.parse_declarator()?;
+        match apply_declarator(specifiers.data_type, declarator) {
+            (None, data_type, _) => Ok(data_type),
+            (Some(_), _, _) => Err(self.invalid_declaration("type name cannot declare a name")),
+        }
+    }
+
+    fn parse_unary_expression(&mut self) -> Result<Expr, ParseError> {
+        if self.match_token(TokenType::Sizeof).is_some() {
+            if self.check(TokenType::LeftParenthesis) && self.is_type_name_start(1) {
+                let data_type = self.parse_parenthesized_type_name()?;
+                return Ok(Expr::SizeofType(data_type));
+            }
+
+            let operand = self.parse_unary_expression()?;
+            return Ok(Expr::SizeofExpr(Box::new(operand)));
+        }
+
+        if self.match_token(TokenType::Alignof).is_some() {
+            let data_type = self.parse_parenthesized_type_name()?;
+            return Ok(Expr::AlignofType(data_type));
+        }
+
+        if self.check(TokenType::LeftParenthesis) && self.is_type_name_start(1) {
+            let target_type = self.parse_parenthesized_type_name()?;
+            let value = self.parse_unary_expression()?;
+            return Ok(Expr::Cast(Cast {
+                target_type,
+                value: Box::new(value),
+            }));
+        }
+
+        let operator = match self.peek_type() {
+            Some(TokenType::Minus) => UnaryOperator::Negate,
+            Some(TokenType::Plus) => UnaryOperator::Plus,
+            Some(TokenType::Bang) => UnaryOperator::LogicalNot,
+            Some(TokenType::Tilde) => UnaryOperator::BitwiseNot,
+            Some(TokenType::Star) => UnaryOperator::Dereference,
+            Some(TokenType::Ampersand) => UnaryOperator::AddressOf,
+            Some(TokenType::PlusPlus) => UnaryOperator::PreIncrement,
+            Some(TokenType::MinusMinus) => UnaryOperator::PreDecrement,
+            _ => return self.parse_postfix_expression(),
+        };
+
+        self.advance();
+        let operand = self.parse_unary_expression()?;
+
+        Ok(Expr::Unary(UnaryExpr {
+            operator,
+            operand: Box::new(operand),
+        }))
+    }
+
+    /// Precedence climbing over the left-associative binary operators.
+    fn parse_binary_expression(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut left = self.parse_unary_expression()?;
+
+        while let Some((operator, precedence)) = self.peek_type().and_then(binary_operator) {
+            if precedence < min_precedence {
                 break;
             }
+
+            self.advance();
+            let right = self.parse_binary_expression(precedence + 1)?;
+
+            left = Expr::Binary(BinaryExpr {
+                operator,
+                left: Box::new(left),
+                right: Box::new(right),
+            });
+        }
+
+        Ok(left)
+    }
+
+    fn parse_conditional_expression(&mut self) -> Result<Expr, ParseError> {
+        let condition = self.parse_binary_expression(1)?;
+
+        if self.match_token(TokenType::Question).is_none() {
+            return Ok(condition);
+        }
+
+        let then_value = self.parse_expression()?;
+        self.expect(TokenType::Colon)?;
+        let else_value = self.parse_conditional_expression()?;
+
+        Ok(Expr::Conditional(Conditional {
+            condition: Box::new(condition),
+            then_value: Box::new(then_value),
+            else_value: Box::new(else_value),
+        }))
+    }
+
+    fn parse_assignment_expression(&mut self) -> Result<Expr, ParseError> {
+        let target = self.parse_conditional_expression()?;
+
+        let operator = match self.peek_type().and_then(assignment_operator) {
+            Some(operator) => operator,
+            None => return Ok(target),
+        };
+
+        self.advance();
+        let value = self.parse_assignment_expression()?;
+
+        Ok(Expr::Assignment(Assignment {
+            operator,
+            target: Box::new(target),
+            value: Box::new(value),
+        }))
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_assignment_expression()?;
+
+        while self.match_token(TokenType::Comma).is_some() {
+            let right = self.parse_assignment_expression()?;
+            expr = Expr::Comma(Comma {
+                left: Box::new(expr),
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_type_qualifiers(&mut self) -> TypeQualifiers {
+        let mut qualifiers = TypeQualifiers::default();
+
+        while let Some(token_type) = self.peek_type() {
+            if !match_qualifier(token_type, &mut qualifiers) {
+                break;
+            }
+            self.advance();
+        }
+
+        qualifiers
+    }
+
+    fn parse_declaration_specifiers(&mut self) -> Result<DeclarationSpecifiers, ParseError> {
+        let mut storage: Option<StorageClass> = None;
+        let mut data_type: Option<DataType> = None;
+        let mut specifiers: Vec<TokenType> = vec![];
+        let mut qualifiers = TypeQualifiers::default();
+        let mut attributes = FunctionAttributes::default();
+
+        while let Some(token) = self.tokens.get(self.position) {
+            if token.token_type == TokenType::Attribute {
+                self.parse_attribute(&mut attributes)?;
+                continue;
+            }
+            if token.token_type == TokenType::Struct {
+                if data_type.is_some() || !specifiers.is_empty() {
+                    return Err(self
+                        .invalid_declaration("two or more data types in declaration specifiers"));
+                }
+                data_type = Some(self.parse_struct_specifier()?);
+                continue;
+            }
+
+            let token_type = &token.token_type;
+
+            if *token_type == TokenType::Identifier {
+                match self.typedef_type(&token.lexeme) {
+                    Some(typedef_type) if data_type.is_none() && specifiers.is_empty() => {
+                        data_type = Some(typedef_type.data_type.clone());
+                        let inherited = typedef_type.qualifiers;
+                        qualifiers.is_const |= inherited.is_const;
+                        qualifiers.is_volatile |= inherited.is_volatile;
+                        qualifiers.is_restrict |= inherited.is_restrict;
+                    }
+                    _ => break,
+                }
+            } else if *token_type == TokenType::Inline {
+                attributes.is_inline = true;
+            } else if let Some(class) = storage_class(token_type) {
+                if storage.is_some() {
+                    return Err(self.invalid_declaration(
+                        "multiple storage classes in declaration specifiers",
+                    ));
+                }
+                storage = Some(class);
+            } else if is_type_specifier(token_type) {
+                if data_type.is_some() {
+                    return Err(self
+                        .invalid_declaration("two or more data types in declaration specifiers"));
+                }
+                specifiers.push(token_type.clone());
+            } else if !match_qualifier(token_type, &mut qualifiers) {
+                break;
+            }
+
+            self.advance();
+        }
+
+        if !specifiers.is_empty() {
+            data_type = Some(combine_type_specifiers(&specifiers).ok_or_else(|| {
+                self.invalid_declaration("invalid combination of type specifiers")
+            })?);
+        }
+
+        let data_type =
+            data_type.ok_or_else(|| self.invalid_declaration("missing type specifier"))?;
+
+        Ok(DeclarationSpecifiers {
+            storage_class: storage,
+            attributes,
+            data_type: QualifiedType {
+                data_type,
+                qualifiers,
+            },
+        })
+    }
+
+    /// Parses `__attribute__((name, name(arguments), ...))`, keeping the
+    /// attributes that steer inlining and skipping the others.
+    fn parse_attribute(&mut self, attributes: &mut FunctionAttributes) -> Result<(), ParseError> {
+        self.expect(TokenType::Attribute)?;
+        self.expect(TokenType::LeftParenthesis)?;
+        self.expect(TokenType::LeftParenthesis)?;
+        while self.match_token(TokenType::RightParenthesis).is_none() {
+            if self.match_token(TokenType::Comma).is_some() {
+                continue;
+            }
+            // Attribute names may also be keywords, such as `const`.
+            let name = self.advance().ok_or_else(|| self.unexpected())?.lexeme;
+            match name.trim_start_matches("__").trim_end_matches("__") {
+                "noinline" => attributes.noinline = true,
+                "always_inline" => attributes.always_inline = true,
+                _ => {}
+            }
+            if self.match_token(TokenType::LeftParenthesis).is_some() {
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.advance().map(|token| token.token_type) {
+                        Some(TokenType::LeftParenthesis) => depth += 1,
+                        Some(TokenType::RightParenthesis) => depth -= 1,
+                        Some(_) => {}
+                        None => return Err(self.unexpected()),
+                    }
+                }
+            }
+        }
+        self.expect(TokenType::RightParenthesis)?;
+        Ok(())
+    }
+
+    fn parse_parameter_list(&mut self) -> Result<ParameterList, ParseError> {
+        if self.match_token(TokenType::RightParenthesis).is_some() {
+            return Ok(ParameterList {
+                parameters: vec![],
+                has_prototype: false,
+                is_variadic: false,
+            });
+        }
+
+        if self.check(TokenType::Void)
+            && self.peek_nth_type(1) == Some(&TokenType::RightParenthesis)
+        {
+            self.advance();
+            self.advance();
+            return Ok(ParameterList {
+                parameters: vec![],
+                has_prototype: true,
+                is_variadic: false,
+            });
+        }
+
+        self.enter_scope();
+        let parameters = self.parse_parameters();
+        self.exit_scope();
+        let (parameters, is_variadic) = parameters?;
+
+        self.expect(TokenType::RightParenthesis)?;
+
+        Ok(ParameterList {
+            parameters,
+            has_prototype: true,
+            is_variadic,
+        })
+    }
+
+    /// Parses the parameters and whether `...` follows them.
+    fn parse_parameters(&mut self) -> Result<(Vec<Parameter>, bool), ParseError> {
+        let mut parameters = vec![];
+        loop {
+            let specifiers = self.parse_declaration_specifiers()?;
+            if !matches!(
+                specifiers.storage_class,
+                None | Some(StorageClass::Register)
+            ) {
+                return Err(self.invalid_declaration("invalid storage class for parameter"));
+            }
+
+            let declarator = self.parse_declarator()?;
+            let (identifier, data_type, _) = apply_declarator(specifiers.data_type, declarator);
+
+            // Parameters of array and function type are adjusted to pointers.
+            let data_type = match data_type.data_type {
+                DataType::Function(_) => data_type.pointer_to(),
+                DataType::Array(element, _) => QualifiedType::new(DataType::Pointer(element)),
+                DataType::Void => {
+                    return Err(self.invalid_declaration("parameter has void type"));
+                }
+                _ => data_type,
+            };
+
+            if let Some(identifier) = &identifier {
+                self.declare_name(&identifier.name, None)?;
+            }
+
+            parameters.push(Parameter {
+                storage_class: specifiers.storage_class,
+                data_type,
+                identifier,
+            });
+
+            if self.match_token(TokenType::Comma).is_none() {
+                return Ok((parameters, false));
+            }
+            if self.match_token(TokenType::Ellipsis).is_some() {
+                return Ok((parameters, true));
+            }
+        }
+    }
+
+    fn parse_declarator(&mut self) -> Result<Declarator, ParseError> {
+        if self.match_token(TokenType::Star).is_some() {
+            let qualifiers = self.parse_type_qualifiers();
+            let inner = self.parse_declarator()?;
+            return Ok(Declarator::Pointer(qualifiers, Box::new(inner)));
+        }
+
+        self.parse_direct_declarator()
+    }
+
+    fn parse_direct_declarator(&mut self) -> Result<Declarator, ParseError> {
+        let mut declarator = if let Some(identifier) = self.parse_identifier() {
+            Declarator::Name(Some(identifier))
+        } else if self.check(TokenType::LeftParenthesis)
+            && matches!(
+                self.peek_nth_type(1),
+                Some(TokenType::Star | TokenType::LeftParenthesis | TokenType::Identifier)
+            )
+            && !self.is_type_name_start(1)
+        {
+            self.advance();
+            let inner = self.parse_declarator()?;
+            self.expect(TokenType::RightParenthesis)?;
+            inner
         } else {
-            return Err(ParseError::UnexpectedToken(1));
+            Declarator::Name(None)
+        };
+
+        loop {
+            if self.match_token(TokenType::LeftParenthesis).is_some() {
+                let parameters = self.parse_parameter_list()?;
+                declarator = Declarator::Function(Box::new(declarator), parameters);
+            } else if self.match_token(TokenType::LeftBracket).is_some() {
+                let length = if self.check(TokenType::RightBracket) {
+                    None
+                } else {
+                    Some(self.parse_constant_index()?)
+                };
+                self.expect(TokenType::RightBracket)?;
+                declarator = Declarator::Array(Box::new(declarator), length);
+            } else {
+                return Ok(declarator);
+            }
         }
     }
 
-    Ok(instructions)
-}
+    /// Parses a non-negative integer constant expression such as an array
+    /// length or an array designator.
+    fn parse_constant_index(&mut self) -> Result<usize, ParseError> {
+        let line = self.line();
+        let expr = self.parse_conditional_expression()?;
 
-fn parse_function(
-    tokens: &mut Peekable<impl Iterator<Item = Token>>,
-) -> Result<Function, ParseError> {
-    if match_tokens(
-        tokens,
-        vec![
-            TokenType::Int,
-            TokenType::Identifier,
-            TokenType::LeftParenthesis,
-            TokenType::Void,
-            TokenType::RightParenthesis,
-            TokenType::LeftBrace,
-        ],
-    )
-    .is_none()
-    {
-        return Err(ParseError::UnexpectedToken(10));
-    };
+        expr.evaluate_constant(&self.types)
+            .and_then(|value| usize::try_from(value).ok())
+            .ok_or_else(|| {
+                ParseError::InvalidDeclaration(
+                    "expected a non-negative integer constant".to_string(),
+                    line,
+                )
+            })
+    }
 
-    let instructions = parse_instructions(tokens)?;
+    fn parse_named_declarator(
+        &mut self,
+        base: QualifiedType,
+    ) -> Result<(Identifier, QualifiedType, Option<Vec<Parameter>>), ParseError> {
+        let declarator = self.parse_declarator()?;
+        let (identifier, data_type, parameters) = apply_declarator(base, declarator);
+        let identifier =
+            identifier.ok_or_else(|| self.invalid_declaration("declarator requires a name"))?;
 
-    if match_token(tokens, TokenType::RightBrace).is_none() {
-        return Err(ParseError::UnexpectedToken(20));
+        Ok((identifier, data_type, parameters))
     }
 
-    Ok(Function {
-        return_type: DataType::Int,
-        name: "main".to_string(),
-        instructions: instructions,
-    })
-}
+    /// Parses the `= value` tails and remaining comma-separated declarators
+    /// that follow the first declarator of a declaration.
+    fn parse_init_declarators(
+        &mut self,
+        specifiers: &DeclarationSpecifiers,
+        identifier: Identifier,
+        data_type: QualifiedType,
+    ) -> Result<Vec<Declaration>, ParseError> {
+        let mut declarations = vec![];
+        let mut current = (identifier, data_type);
+        let is_typedef = specifiers.storage_class == Some(StorageClass::Typedef);
 
-pub fn parse(tokens: Vec<Token>) -> Result<Program, ParseError> {
-    let mut tokens_iterator = tokens.into_iter().peekable();
+        loop {
+            if is_typedef {
+                self.declare_name(&current.0.name, Some(current.1))?;
+                if self.check(TokenType::Equal) {
+                    return Err(self.invalid_declaration("typedef cannot be initialized"));
+                }
+                if self.match_token(TokenType::Comma).is_none() {
+                    break;
+                }
+                let (identifier, data_type, _) =
+                    self.parse_named_declarator(specifiers.data_type.clone())?;
+                current = (identifier, data_type);
+                continue;
+            }
 
-    let functions = parse_function(&mut tokens_iterator)?;
+            self.declare_name(&current.0.name, None)?;
 
-    Ok(Program {
-        functions: vec![functions],
-    })
-}
+            let initializer = if self.match_token(TokenType::Equal).is_some() {
+                Some(self.parse_initializer()?)
+            } else {
+                None
+            };
+
+            declarations.push(Declaration {
+                storage_class: specifiers.storage_class,
+                data_type: current.1,
+                identifier: current.0,
+                initializer,
+            });
+
+            if self.match_token(TokenType::Comma).is_none() {
+                break;
+            }
+
+            let (identifier, data_type, _) =
+                self.parse_named_declarator(specifiers.data_type.clone())?;
+            current = (identifier, data_type);
+        }
+
+        self.expect(TokenType::Semicolon)?;
+
+        Ok(declarations)
+    }
+
+    fn parse_initializer(&mut self) -> Result<Initializer, ParseError> {
+        if self.match_token(TokenType::LeftBrace).is_none() {
+            return Ok(Initializer::Expr(self.parse_assignment_expression()?));
+        }
+
+        let mut items = vec![];
+        while self.match_token(TokenType::RightBrace).is_none() {
+            let mut designators = vec![];
+            loop {
+                if self.match_token(TokenType::Dot).is_some() {
+                    let member = self.parse_identifier().ok_or_else(|| self.unexpected())?;
+                    designators.push(Designator::Member(member.name));
+                } else if self.match_token(TokenType::LeftBracket).is_some() {
+                    designators.push(Designator::Index(self.parse_constant_index()?));
+                    self.expect(TokenType::RightBracket)?;
+                } else {
+                    break;
+                }
+            }
+            if !designators.is_empty() {
+                self.expect(TokenType::Equal)?;
+            }
+
+            items.push(InitializerItem {
+                designators,
+                initializer: self.parse_initializer()?,
+            });
+
+            if self.match_token(TokenType::Comma).is_none() {
+                self.expect(TokenType::RightBrace)?;
+                break;
+            }
+        }
+
+        Ok(Initializer::List(items))
+    }
+
+    fn parse_declaration(&mut self) -> Result<Vec<Declaration>, ParseError> {
+        let specifiers = self.parse_declaration_specifiers()?;
+        if self.match_token(TokenType::Semicolon).is_some() {
+            return Ok(vec![]);
+        }
+
+        let (identifier, data_type, _) =
+            self.parse_named_declarator(specifiers.data_type.clone())?;
+
+        self.parse_init_declarators(&specifiers, identifier, data_type)
+    }
+
+    fn parse_return(&mut self) -> Result<Return, ParseError> {
+        self.expect(TokenType::Return)?;
+
+        let value = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+
+        self.expect(TokenType::Semicolon)?;
+
+        Ok(Return { value })
+    }
+
+    fn parse_block(&mut self) -> Result<Block, ParseError> {
+        self.enter_scope();
+        let block = self.parse_block_contents();
+        self.exit_scope();
+        block
+    }
+
+    /// Parses a braced block without opening a scope of its own, for function
+    /// bodies whose outermost block shares the parameters' scope.
+    fn parse_block_contents(&mut self) -> Result<Block, ParseError> {
+        self.expect(TokenType::LeftBrace)?;
+
+        let mut instructions: Vec<Instruction> = vec![];
+        while self.match_token(TokenType::RightBrace).is_none() {
+            if self.peek_type().is_none() {
+                return Err(self.unexpected());
+            }
+            instructions.extend(self.parse_block_item()?);
+        }
+
+        Ok(Block { instructions })
+    }
+
+    fn parse_block_item(&mut self) -> Result<Vec<Instruction>, ParseError> {
+        // Labels live in their own namespace, so `name:` is a label even when
+        // `name` is also a typedef name.
+        let is_label =
+            self.check(TokenType::Identifier) && self.peek_nth_type(1) == Some(&TokenType::Colon);
+        if !is_label && self.is_declaration_start() {
+            let declarations = self.parse_declaration()?;
+            return Ok(declarations
+                .into_iter()
+                .map(Instruction::Declaration)
+                .collect());
+        }
+
+        Ok(vec![self.parse_instruction()?])
+    }
+
+    fn parse_condition(&mut self) -> Result<Expr, ParseError> {
+        self.expect(TokenType::LeftParenthesis)?;
+        let condition = self.parse_expression()?;
+        self.expect(TokenType::RightParenthesis)?;
+        Ok(condition)
+    }
+
+    fn parse_instruction(&mut self) -> Result<Instruction, ParseError> {
+        let instruction = match self.peek_type() {
+            Some(TokenType::LeftBrace) => Instruction::Block(self.parse_block()?),
+            Some(TokenType::Return) => Instruction::Return(self.parse_return()?),
+            Some(TokenType::Semicolon) => {
+                self.advance();
+                Instruction::Empty
+            }
+            Some(TokenType::Break) => {
+                self.advance();
+                self.expect(TokenType::Semicolon)?;
+                Instruction::Break
+            }
+            Some(TokenType::Continue) => {
+                self.advance();
+                self.expect(TokenType::Semicolon)?;
+                Instruction::Continue
+            }
+            Some(TokenType::If) => {
+                self.advance();
+                let condition = self.parse_condition()?;
+                let then_branch = Box::new(self.parse_instruction()?);
+                let else_branch = if self.match_token(TokenType::Else).is_some() {
+                    Some(Box::new(self.parse_instruction()?))
+                } else {
+                    None
+                };
+
+                Instruction::If(If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                })
+            }
+            Some(TokenType::While) => {
+                self.advance();
+                let condition = self.parse_condition()?;
+                let body = Box::new(self.parse_instruction()?);
+
+                Instruction::While(While { condition, body })
+            }
+            Some(TokenType::Do) => {
+                self.advance();
+                let body = Box::new(self.parse_instruction()?);
+                self.expect(TokenType::While)?;
+                let condition = self.parse_condition()?;
+                self.expect(TokenType::Semicolon)?;
+
+                Instruction::DoWhile(DoWhile { body, condition })
+            }
+            Some(TokenType::For) => Instruction::For(self.parse_for()?),
+            Some(TokenType::Switch) => {
+                self.advance();
+                let condition = self.parse_condition()?;
+                let body = Box::new(self.parse_instruction()?);
 
-#[cfg(tests)]
-mod tests {
-    use super::*;
+                Instruction::Switch(Switch { condition, body })
+            }
+            Some(TokenType::Case) => {
+                self.advance();
+                let value = self.parse_conditional_expression()?;
+                self.expect(TokenType::Colon)?;
+                let body = Box::new(self.parse_instruction()?);
+
+                Instruction::Case(Case { value, body })
+            }
+            Some(TokenType::Default) => {
+                self.advance();
+                self.expect(TokenType::Colon)?;
+                Instruction::Default(Box::new(self.parse_instruction()?))
+            }
+            Some(TokenType::Goto) => {
+                self.advance();
+                let label = self.expect(TokenType::Identifier)?.lexeme;
+                self.expect(TokenType::Semicolon)?;
+                Instruction::Goto(label)
+            }
+            Some(TokenType::Identifier) if self.peek_nth_type(1) == Some(&TokenType::Colon) => {
+                let label = self.advance().unwrap().lexeme;
+                self.advance();
+                let body = Box::new(self.parse_instruction()?);
+
+                Instruction::Labeled(Labeled { label, body })
+            }
+            Some(_) => {
+                let expr = self.parse_expression()?;
+                self.expect(TokenType::Semicolon)?;
+                Instruction::Expression(expr)
+            }
+            None => return Err(self.unexpected()),
+        };
 
-    #[test]
-    fn test_ast_printer() {
-        let mut printer = AstPrinter;
-        let expr = Expr::Literal(Literal {
-            value: "42".to_string(),
-        });
-        printer.print(&expr);
-        // Expected output: 42
+        Ok(instruction)
     }
+
+    fn parse_for(&mut self) -> Result<For, ParseError> {
+        self.enter_scope();
+        let for_stmt = self.parse_for_clauses();
+        self.exit_scope();
+        for_stmt
+    }
+
+    fn parse_for_clauses(&mut self) -> Result<For, ParseError> {
+        self.expect(TokenType::For)?;
+        self.expect(TokenType::LeftParenthesis)?;
+
+        let initializer = if self.match_token(TokenType::Semicolon).is_some() {
+            vec![]
+        } else if self.is_declaration_start() {
+            self.parse_block_item()?
+        } else {
+            let expr = self.parse_expression()?;
+            self.expect(TokenType::Semicolon)?;
+            vec![Instruction::Expression(expr)]
+        };
+
+        let condition = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect(TokenType::Semicolon)?;
+
+        let increment = if self.check(TokenType::RightParenthesis) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect(TokenType::RightParenthesis)?;
+
+        let body = Box::new(self.parse_instruction()?);
+
+        Ok(For {
+            initializer,
+            condition,
+            increment,
+            body,
+        })
+    }
+
+    fn parse_external_declaration(&mut self) -> Result<Vec<ExternalDeclaration>, ParseError> {
+        let specifiers = self.parse_declaration_specifiers()?;
+        if self.match_token(TokenType::Semicolon).is_some() {
+            return Ok(vec![]);
+        }
+
+        let (identifier, data_type, parameters) =
+            self.parse_named_declarator(specifiers.data_type.clone())?;
+        let mut attributes = specifiers.attributes;
+        while self.check(TokenType::Attribute) {
+            self.parse_attribute(&mut attributes)?;
+        }
+        if let DataType::Function(_) = data_type.data_type {
+            self.function_attributes
+                .entry(identifier.name.clone())
+                .or_default()
+                .merge(attributes);
+        }
+
+        if let (DataType::Function(function_type), Some(parameters)) =
+            (&data_type.data_type, &parameters)
+            && self.check(TokenType::LeftBrace)
+        {
+            if specifiers.storage_class == Some(StorageClass::Typedef) {
+                return Err(self.invalid_declaration("typedef cannot have a function body"));
+            }
+            self.declare_name(&identifier.name, None)?;
+
+            self.enter_scope();
+            let block = parameters
+                .iter()
+                .filter_map(|parameter| parameter.identifier.as_ref())
+                .try_for_each(|identifier| self.declare_name(&identifier.name, None))
+                .and_then(|_| self.parse_block_contents());
+            self.exit_scope();
+            let block = block?;
+
+            return Ok(vec![ExternalDeclaration::Function(Function {
+                storage_class: specifiers.storage_class,
+                attributes: self.function_attributes[&identifier.name],
+                return_type: *function_type.return_type.clone(),
+                name: identifier.name,
+                parameters: parameters.clone(),
+                is_variadic: function_type.is_variadic,
+                instructions: block.instructions,
+            })]);
+        }
+
+        let declarations = self.parse_init_declarators(&specifiers, identifier, data_type)?;
+
+        Ok(declarations
+            .into_iter()
+            .map(ExternalDeclaration::Declaration)
+            .collect())
+    }
+
+    fn parse_program(&mut self) -> Result<Program, ParseError> {
+        let mut declarations = vec![];
+
+        while self.peek_type().is_some() {
+            declarations.extend(self.parse_external_declaration()?);
+        }
+
+        Ok(Program {
+            declarations,
+            types: std::mem::take(&mut self.types),
+        })
+    }
+}
+
+pub fn parse(tokens: Vec<Token>) -> Result<Program, ParseError> {
+    Parser::new(tokens).parse_program()
 }
@@ -0,0 +1,268 @@
+/*
+  Brace elision and designators follow C11 6.7.9. An initializer is resolved
+  into the scalar (or whole-struct, or string) initializations it performs,
+  each at a byte offset into the declared object; everything not listed is
+  zero-filled by whoever emits the object.
+*/
+
+use crate::ast::{
+    DataType, Designator, Expr, Initializer, InitializerItem, Literal, QualifiedType, TypeTable,
+};
+
+pub struct InitElement<'a> {
+    pub offset: usize,
+    pub data_type: QualifiedType,
+    pub value: &'a Expr,
+}
+
+pub struct ResolvedInitializer<'a> {
+    /// The declared type, with the length of an unsized array filled in.
+    pub data_type: QualifiedType,
+    pub elements: Vec<InitElement<'a>>,
+}
+
+/// A partially initialized aggregate: the next subobject to be initialized
+/// is `index`.
+struct Frame {
+    data_type: QualifiedType,
+    offset: usize,
+    index: usize,
+}
+
+struct Resolver<'a, 't, 'f> {
+    types: &'t TypeTable,
+    /// Types of expressions, needed to tell a struct-valued expression apart
+    /// from the first member of a brace-elided struct.
+    expr_type: &'f mut dyn FnMut(&Expr) -> Option<DataType>,
+    elements: Vec<InitElement<'a>>,
+}
+
+fn is_char_array(data_type: &DataType) -> bool {
+    matches!(data_type.element(), Some(element) if element.data_type.is_character())
+}
+
+fn string_literal(expr: &Expr) -> Option<&[u8]> {
+    match expr {
+        Expr::Literal(Literal::String(literal)) => Some(&literal.value),
+        _ => None,
+    }
+}
+
+impl<'a> Resolver<'a, '_, '_> {
+    fn length(&self, data_type: &DataType) -> usize {
+        match data_type {
+            DataType::Array(_, length) => length.unwrap_or(usize::MAX),
+            DataType::Struct(name) => self.types.structs[name]
+                .members
+                .as_ref()
+                .map_or(0, |members| members.len()),
+            _ => 0,
+        }
+    }
+
+    fn subobject(&self, frame: &Frame) -> (QualifiedType, usize) {
+        match &frame.data_type.data_type {
+            DataType::Array(element, _) => {
+                let size = self.types.size_of(&element.data_type).unwrap_or(0);
+                ((**element).clone(), frame.offset + size * frame.index)
+            }
+            DataType::Struct(name) => {
+                let members = self.types.structs[name].members.as_ref().unwrap();
+                let layout = self.types.struct_layout(name).unwrap();
+                (
+                    members[frame.index].data_type.clone(),
+                    frame.offset + layout.offsets[frame.index],
+                )
+            }
+            _ => unreachable!("only aggregates have subobjects"),
+        }
+    }
+
+    fn designate(&self, frame: &mut Frame, designator: &Designator) -> Result<(), String> {
+        match (&frame.data_type.data_type, designator) {
+            (DataType::Array(_, length), Designator::Index(index)) => {
+                if length.is_some_and(|length| *index >= length) {
+                    return Err(format!(
+                        "Array index {} in initializer exceeds array bounds",
+                        index
+                    ));
+                }
+                frame.index = *index;
+            }
+            (DataType::Struct(name), Designator::Member(member)) => {
+                let members = self.types.structs[name].members.as_ref().unwrap();
+                frame.index = members
+                    .iter()
+                    .position(|m| m.name == *member)
+                    .ok_or_else(|| format!("Unknown field '{}' in initializer", member))?;
+            }
+            _ => return Err("Designator does not match the initialized type".to_string()),
+        }
+
+        Ok(())
+    }
+
+    /// Initializes the object at `offset` from `initializer`, returning the
+    /// length it implies if the object is an array of unknown size.
+    fn initialize(
+        &mut self,
+        data_type: &QualifiedType,
+        offset: usize,
+        initializer: &'a Initializer,
+    ) -> Result<Option<usize>, String> {
+        let expr = match initializer {
+            Initializer::Expr(expr) => expr,
+            Initializer::List(items) => {
+                if data_type.data_type.is_aggregate() {
+                    return self.initialize_list(data_type, offset, items);
+                }
+
+                return match items.as_slice() {
+                    [] => Ok(None),
+                    [item] if item.designators.is_empty() => {
+                        self.initialize(data_type, offset, &item.initializer)
+                    }
+                    _ => Err("Excess elements in scalar initializer".to_string()),
+                };
+            }
+        };
+
+        if let (true, Some(string)) = (is_char_array(&data_type.data_type), string_literal(expr)) {
+            let length = string.len();
+            if let DataType::Array(_, Some(capacity)) = data_type.data_type
+                && length > capacity
+            {
+                return Err("Initializer string for char array is too long".to_string());
+            }
+
+            self.elements.push(InitElement {
+                offset,
+                data_type: data_type.clone(),
+                value: expr,
+            });
+            return Ok(Some(length + 1));
+        }
+
+        if data_type.data_type.is_array() {
+            return Err("Array initializer must be an initializer list".to_string());
+        }
+
+        self.elements.push(InitElement {
+            offset,
+            data_type: data_type.clone(),
+            value: expr,
+        });
+        Ok(None)
+    }
+
+    fn initialize_list(
+        &mut self,
+        data_type: &QualifiedType,
+        offset: usize,
+        items: &'a [InitializerItem],
+    ) -> Result<Option<usize>, String> {
+        let mut stack = vec![Frame {
+            data_type: data_type.clone(),
+            offset,
+            index: 0,
+        }];
+        let mut length = 0;
+
+        for item in items {
+            if !item.designators.is_empty() {
+                stack.truncate(1);
+                for (position, designator) in item.designators.iter().enumerate() {
+                    let mut frame = stack.pop().unwrap();
+                    self.designate(&mut frame, designator)?;
+
+                    let descend = position + 1 < item.designators.len();
+                    let (subobject_type, subobject_offset) = self.subobject(&frame);
+                    stack.push(frame);
+
+                    if descend {
+                        if !subobject_type.data_type.is_aggregate() {
+                            return Err("Designator applied to a scalar".to_string());
+                        }
+                        stack.push(Frame {
+                            data_type: subobject_type,
+                            offset: subobject_offset,
+                            index: 0,
+                        });
+                    }
+                }
+            }
+
+            loop {
+                // Leave aggregates entered through brace elision once full.
+                while stack.last().unwrap().index
+                    >= self.length(&stack.last().unwrap().data_type.data_type)
+                {
+                    if stack.len() == 1 {
+                        return Err("Excess elements in initializer".to_string());
+                    }
+                    stack.pop();
+                    stack.last_mut().unwrap().index += 1;
+                }
+
+                let (subobject_type, subobject_offset) = self.subobject(stack.last().unwrap());
+
+                if let Initializer::Expr(expr) = &item.initializer {
+                    let is_whole_object = match &subobject_type.data_type {
+                        DataType::Struct(_) => {
+                            (self.expr_type)(expr).as_ref() == Some(&subobject_type.data_type)
+                        }
+                        data_type @ DataType::Array(_, _) => {
+                            is_char_array(data_type) && string_literal(expr).is_some()
+                        }
+                        _ => true,
+                    };
+
+                    if !is_whole_object {
+                        stack.push(Frame {
+                            data_type: subobject_type,
+                            offset: subobject_offset,
+                            index: 0,
+                        });
+                        continue;
+                    }
+                }
+
+                self.initialize(&subobject_type, subobject_offset, &item.initializer)?;
+                break;
+            }
+
+            length = length.max(stack[0].index + 1);
+            stack.last_mut().unwrap().index += 1;
+        }
+
+        Ok(Some(length))
+    }
+}
+
+pub fn resolve<'a>(
+    data_type: &QualifiedType,
+    initializer: &'a Initializer,
+    types: &TypeTable,
+    expr_type: &mut dyn FnMut(&Expr) -> Option<DataType>,
+) -> Result<ResolvedInitializer<'a>, String> {
+    let mut resolver = Resolver {
+        types,
+        expr_type,
+        elements: vec![],
+    };
+
+    let length = resolver.initialize(data_type, 0, initializer)?;
+
+    let data_type = match (&data_type.data_type, length) {
+        (DataType::Array(element, None), Some(length)) => QualifiedType {
+            data_type: DataType::Array(element.clone(), Some(length)),
+            qualifiers: data_type.qualifiers,
+        },
+        _ => data_type.clone(),
+    };
+
+    Ok(ResolvedInitializer {
+        data_type,
+        elements: resolver.elements,
+    })
+}
@@ -1,164 +1,1463 @@
-use crate::ast::{self, DataType, Expr, Identifier, Literal, Visitor};
+use crate::ast::{
+    self, BinaryOperator, DataType, Expr, FunctionType, Literal, QualifiedType, Stdarg,
+    StorageClass, TypeQualifiers, TypeTable, UnaryOperator, VA_LIST_TAG, Visitor,
+};
 use crate::lexer::token::KEYWORDS;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+pub mod initializer;
+
+#[derive(Debug)]
 pub enum SemanticError {
     UndefinedVariable(String),
     ReservedKeyword(String),
     TypeError(String),
+    Redeclaration(String),
+    LinkageConflict(String),
+    InvalidStorageClass(String),
+    ReadOnlyAssignment(String),
+    InvalidStatement(String),
+    UndefinedLabel(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Linkage {
+    External,
+    Internal,
+    None,
+}
+
+#[derive(Debug, Clone)]
+struct Symbol {
+    data_type: QualifiedType,
+    linkage: Linkage,
+    storage_class: Option<StorageClass>,
+    defined: bool,
+}
+
+impl Symbol {
+    fn has_static_storage(&self) -> bool {
+        self.linkage != Linkage::None || self.storage_class == Some(StorageClass::Static)
+    }
+}
+
+struct Switch {
+    /// The promoted type of the condition, which case values convert to.
+    data_type: DataType,
+    values: HashSet<i128>,
+    has_default: bool,
+}
+
+/// The type of a checked expression, before lvalue conversion.
+struct ExprType {
+    data_type: QualifiedType,
+    is_lvalue: bool,
+}
+
+impl ExprType {
+    fn rvalue(data_type: DataType) -> Self {
+        ExprType {
+            data_type: QualifiedType::new(data_type),
+            is_lvalue: false,
+        }
+    }
+}
+
+struct SemanticChecker<'a> {
+    types: &'a TypeTable,
+    /// Innermost scope last; the first entry is file scope.
+    scopes: Vec<HashMap<String, Symbol>>,
+    /// Every identifier declared with linkage so far, whichever scope the
+    /// declaration appeared in, so block-scope `extern` declarations can be
+    /// matched against each other and against file scope.
+    linked: HashMap<String, Symbol>,
+    return_type: Option<QualifiedType>,
+    /// Whether the function being checked takes variable arguments.
+    is_variadic: bool,
+    /// The name of its last named parameter, which `va_start` must be
+    /// given.
+    last_parameter: Option<String>,
+    loop_depth: usize,
+    /// Enclosing `switch` statements, innermost last.
+    switches: Vec<Switch>,
+    /// Labels are function-wide, so `goto` targets are resolved once the
+    /// whole body has been seen.
+    labels: HashSet<String>,
+    gotos: Vec<String>,
+}
+
+/// Arrays decay to pointers to their first element and function
+/// designators to pointers to the function when used as values.
+fn decay(data_type: &QualifiedType) -> QualifiedType {
+    match &data_type.data_type {
+        DataType::Function(_) => data_type.clone().pointer_to(),
+        DataType::Array(element, _) => (**element).clone().pointer_to(),
+        _ => data_type.unqualified(),
+    }
+}
+
+fn is_null_pointer_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Literal::Integer(literal)) => literal.value == 0,
+        Expr::Cast(cast) => {
+            cast.target_type.data_type.pointee().map(|p| &p.data_type) == Some(&DataType::Void)
+                && is_null_pointer_constant(&cast.value)
+        }
+        _ => false,
+    }
+}
+
+fn merge_qualifiers(left: &TypeQualifiers, right: &TypeQualifiers) -> TypeQualifiers {
+    TypeQualifiers {
+        is_const: left.is_const || right.is_const,
+        is_volatile: left.is_volatile || right.is_volatile,
+        is_restrict: left.is_restrict || right.is_restrict,
+    }
+}
+
+fn compatible_function_types(left: &FunctionType, right: &FunctionType) -> bool {
+    if !compatible_types(&left.return_type, &right.return_type) {
+        return false;
+    }
+
+    if !left.has_prototype || !right.has_prototype {
+        return true;
+    }
+
+    left.parameters.len() == right.parameters.len()
+        && left.is_variadic == right.is_variadic
+        && left
+            .parameters
+            .iter()
+            .zip(&right.parameters)
+            .all(|(l, r)| compatible_types(&l.unqualified(), &r.unqualified()))
+}
+
+fn compatible_types(left: &QualifiedType, right: &QualifiedType) -> bool {
+    if left.qualifiers != right.qualifiers {
+        return false;
+    }
+
+    match (&left.data_type, &right.data_type) {
+        (DataType::Pointer(l), DataType::Pointer(r)) => compatible_types(l, r),
+        (DataType::Function(l), DataType::Function(r)) => compatible_function_types(l, r),
+        (DataType::Array(l, l_length), DataType::Array(r, r_length)) => {
+            compatible_types(l, r)
+                && (l_length.is_none() || r_length.is_none() || l_length == r_length)
+        }
+        (l, r) => l == r,
+    }
+}
+
+/// Picks the more informative of two compatible declarations' types, so a
+/// prototype seen after a non-prototype declaration is not lost.
+fn composite_type(existing: &QualifiedType, new: &QualifiedType) -> QualifiedType {
+    match (&existing.data_type, &new.data_type) {
+        (DataType::Function(old), DataType::Function(_)) if old.has_prototype => existing.clone(),
+        (DataType::Array(_, Some(_)), DataType::Array(_, None)) => existing.clone(),
+        _ => new.clone(),
+    }
 }
 
-struct SemanticChecker {
-    symbol_table: HashMap<String, Identifier>,
+/// Only pointers to object types may be `restrict`-qualified.
+fn validate_restrict(data_type: &QualifiedType) -> Result<(), SemanticError> {
+    if data_type.qualifiers.is_restrict {
+        match &data_type.data_type {
+            DataType::Pointer(pointee) if !pointee.data_type.is_function() => {}
+            _ => {
+                return Err(SemanticError::TypeError(
+                    "'restrict' requires a pointer to an object type".to_string(),
+                ));
+            }
+        }
+    }
+
+    match &data_type.data_type {
+        DataType::Pointer(pointee) | DataType::Array(pointee, _) => validate_restrict(pointee),
+        DataType::Function(function) => {
+            validate_restrict(&function.return_type)?;
+            function.parameters.iter().try_for_each(validate_restrict)
+        }
+        _ => Ok(()),
+    }
 }
 
-impl SemanticChecker {
-    fn new() -> Self {
+fn describe(expr: &Expr) -> String {
+    match expr {
+        Expr::Identifier(identifier) => format!("'{}'", identifier.name),
+        _ => "expression".to_string(),
+    }
+}
+
+impl<'a> SemanticChecker<'a> {
+    fn new(types: &'a TypeTable) -> Self {
         SemanticChecker {
-            symbol_table: HashMap::new(),
+            types,
+            scopes: vec![HashMap::new()],
+            linked: HashMap::new(),
+            return_type: None,
+            is_variadic: false,
+            last_parameter: None,
+            loop_depth: 0,
+            switches: vec![],
+            labels: HashSet::new(),
+            gotos: vec![],
+        }
+    }
+
+    fn at_file_scope(&self) -> bool {
+        self.scopes.len() == 1
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn get_variable(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// The linkage a declaration inherits from the visible declaration of the
+    /// same name, defaulting to external (C11 6.2.2p4).
+    fn inherited_linkage(&self, name: &str) -> Linkage {
+        match self.get_variable(name) {
+            Some(symbol) if symbol.linkage != Linkage::None => symbol.linkage,
+            _ => Linkage::External,
         }
     }
 
-    fn declare_variable(&mut self, identifier: &Identifier) -> Result<(), SemanticError> {
-        if KEYWORDS.contains_key(&identifier.name) {
+    fn record_linkage(&mut self, name: &str, symbol: &Symbol) -> Result<Symbol, SemanticError> {
+        let existing = match self.linked.get(name) {
+            Some(existing) => existing,
+            None => {
+                self.linked.insert(name.to_string(), symbol.clone());
+                return Ok(symbol.clone());
+            }
+        };
+
+        if existing.linkage != symbol.linkage {
+            return Err(SemanticError::LinkageConflict(format!(
+                "'{}' declared with both internal and external linkage",
+                name
+            )));
+        }
+
+        if !compatible_types(&existing.data_type, &symbol.data_type) {
+            return Err(SemanticError::TypeError(format!(
+                "Conflicting types for '{}'",
+                name
+            )));
+        }
+
+        if existing.defined && symbol.defined {
+            return Err(SemanticError::Redeclaration(format!(
+                "Redefinition of '{}'",
+                name
+            )));
+        }
+
+        let merged = Symbol {
+            data_type: composite_type(&existing.data_type, &symbol.data_type),
+            linkage: symbol.linkage,
+            storage_class: existing.storage_class.or(symbol.storage_class),
+            defined: existing.defined || symbol.defined,
+        };
+        self.linked.insert(name.to_string(), merged.clone());
+
+        Ok(merged)
+    }
+
+    fn declare_variable(
+        &mut self,
+        name: &str,
+        data_type: &QualifiedType,
+        storage_class: Option<StorageClass>,
+        defined: bool,
+    ) -> Result<(), SemanticError> {
+        if KEYWORDS.contains_key(name) {
             return Err(SemanticError::ReservedKeyword(format!(
                 "Cannot use reserved keyword '{}' as variable name",
-                identifier.name
+                name
             )));
         }
 
-        if self.symbol_table.contains_key(&identifier.name) {
-            return Err(SemanticError::TypeError(format!(
+        validate_restrict(data_type)?;
+
+        let is_function = data_type.data_type.is_function();
+
+        let linkage = if self.at_file_scope() {
+            match storage_class {
+                Some(StorageClass::Auto | StorageClass::Register | StorageClass::Typedef) => {
+                    return Err(SemanticError::InvalidStorageClass(format!(
+                        "Invalid storage class for file-scope declaration of '{}'",
+                        name
+                    )));
+                }
+                Some(StorageClass::Static) => Linkage::Internal,
+                Some(StorageClass::Extern) => self.inherited_linkage(name),
+                None if is_function => self.inherited_linkage(name),
+                None => Linkage::External,
+            }
+        } else {
+            match storage_class {
+                Some(StorageClass::Extern) => self.inherited_linkage(name),
+                None if is_function => self.inherited_linkage(name),
+                Some(_) if is_function => {
+                    return Err(SemanticError::InvalidStorageClass(format!(
+                        "Invalid storage class for block-scope function '{}'",
+                        name
+                    )));
+                }
+                _ => Linkage::None,
+            }
+        };
+
+        let mut symbol = Symbol {
+            data_type: data_type.clone(),
+            linkage,
+            storage_class,
+            defined,
+        };
+
+        let scope = self.scopes.last().unwrap();
+        if let Some(existing) = scope.get(name)
+            && (existing.linkage == Linkage::None || linkage == Linkage::None)
+        {
+            return Err(SemanticError::Redeclaration(format!(
                 "Variable '{}' already declared",
-                identifier.name
+                name
             )));
         }
 
-        self.symbol_table
-            .insert(identifier.name.clone(), identifier.clone());
+        if linkage != Linkage::None {
+            symbol = self.record_linkage(name, &symbol)?;
+            // Block-scope declarations only see the merged type; the
+            // definition state stays with the translation-unit record.
+            symbol.storage_class = storage_class;
+        }
+
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), symbol);
 
         Ok(())
     }
 
-    fn get_variable(&self, name: &str) -> Option<&Identifier> {
-        self.symbol_table.get(name)
-    }
-}
+    /// Checks that an initializer of a static-storage object is something the
+    /// backend can emit as data.
+    fn check_constant(&self, expr: &Expr) -> Result<(), SemanticError> {
+        let is_constant = match expr {
+            Expr::Literal(_) => true,
+            // Arrays and functions with static storage decay to address
+            // constants.
+            Expr::Identifier(identifier) => {
+                self.get_variable(&identifier.name).is_some_and(|symbol| {
+                    symbol.has_static_storage()
+                        && (symbol.data_type.data_type.is_array()
+                            || symbol.data_type.data_type.is_function())
+                })
+            }
+            Expr::Unary(unary) => match unary.operator {
+                UnaryOperator::AddressOf => self.is_static_object(&unary.operand),
+                UnaryOperator::Negate
+                | UnaryOperator::Plus
+                | UnaryOperator::LogicalNot
+                | UnaryOperator::BitwiseNot => return self.check_constant(&unary.operand),
+                _ => false,
+            },
+            Expr::Binary(binary) => {
+                self.check_constant(&binary.left)?;
+                return self.check_constant(&binary.right);
+            }
+            Expr::Cast(cast) => return self.check_constant(&cast.value),
+            Expr::SizeofExpr(_) | Expr::SizeofType(_) | Expr::AlignofType(_) => true,
+            Expr::Conditional(conditional) => {
+                self.check_constant(&conditional.condition)?;
+                self.check_constant(&conditional.then_value)?;
+                return self.check_constant(&conditional.else_value);
+            }
+            _ => false,
+        };
+
+        if !is_constant {
+            return Err(SemanticError::TypeError(
+                "Initializer element is not a compile-time constant".to_string(),
+            ));
+        }
 
-impl ast::Visitor<Result<(), SemanticError>> for SemanticChecker {
-    fn visit_type(&mut self, _data_type: &ast::DataType) -> Result<(), SemanticError> {
         Ok(())
     }
 
-    fn visit_literal(&mut self, _literal: &ast::Literal) -> Result<(), SemanticError> {
+    /// Whether `expr` designates an object whose address is known at link
+    /// time.
+    fn is_static_object(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Identifier(identifier) => self
+                .get_variable(&identifier.name)
+                .is_some_and(Symbol::has_static_storage),
+            Expr::Literal(Literal::String(_)) => true,
+            Expr::Member(member) => !member.is_arrow && self.is_static_object(&member.object),
+            Expr::Index(index) => {
+                self.is_static_object(&index.array)
+                    && index.index.evaluate_constant(self.types).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    fn check_assignable(
+        &self,
+        target: &QualifiedType,
+        value_type: &QualifiedType,
+        value: &Expr,
+        context: &str,
+    ) -> Result<(), SemanticError> {
+        let target_type = &target.data_type;
+        let source_type = &value_type.data_type;
+
+        let compatible = match (target_type, source_type) {
+            (t, s) if t.is_arithmetic() && s.is_arithmetic() => true,
+            (DataType::Struct(t), DataType::Struct(s)) => t == s,
+            (DataType::Bool, DataType::Pointer(_)) => true,
+            (DataType::Pointer(_), s) if s.is_integer() => is_null_pointer_constant(value),
+            (DataType::Pointer(target_pointee), DataType::Pointer(source_pointee)) => {
+                let qualifiers = &source_pointee.qualifiers;
+                let kept = &target_pointee.qualifiers;
+                if (qualifiers.is_const && !kept.is_const)
+                    || (qualifiers.is_volatile && !kept.is_volatile)
+                {
+                    return Err(SemanticError::ReadOnlyAssignment(format!(
+                        "{} discards qualifiers from pointer target type",
+                        context
+                    )));
+                }
+
+                let is_void = |data_type: &DataType| *data_type == DataType::Void;
+                (is_void(&target_pointee.data_type) && !source_pointee.data_type.is_function())
+                    || (is_void(&source_pointee.data_type)
+                        && !target_pointee.data_type.is_function())
+                    || compatible_types(
+                        &target_pointee.unqualified(),
+                        &source_pointee.unqualified(),
+                    )
+            }
+            _ => false,
+        };
+
+        if !compatible {
+            return Err(SemanticError::TypeError(format!(
+                "Type mismatch: {} of '{}' from incompatible type '{}'",
+                context, target.data_type, value_type.data_type
+            )));
+        }
+
         Ok(())
     }
 
-    fn visit_identifier(&mut self, identifier: &ast::Identifier) -> Result<(), SemanticError> {
-        if self.get_variable(&identifier.name).is_none() {
-            return Err(SemanticError::UndefinedVariable(format!(
-                "Variable '{}' is not defined",
-                identifier.name
+    fn require_modifiable_lvalue(
+        &self,
+        expr: &Expr,
+        expr_type: &ExprType,
+    ) -> Result<(), SemanticError> {
+        let data_type = &expr_type.data_type.data_type;
+        if !expr_type.is_lvalue || data_type.is_function() || data_type.is_array() {
+            return Err(SemanticError::TypeError(format!(
+                "Cannot assign to {}: not a modifiable lvalue",
+                describe(expr)
             )));
         }
+
+        if expr_type.data_type.qualifiers.is_const || self.has_const_member(data_type) {
+            return Err(SemanticError::ReadOnlyAssignment(format!(
+                "Cannot assign to const-qualified {}",
+                describe(expr)
+            )));
+        }
+
         Ok(())
     }
 
-    fn visit_expr(&mut self, expr: &ast::Expr) -> Result<(), SemanticError> {
-        match expr {
-            ast::Expr::Literal(literal) => self.visit_literal(literal),
-            ast::Expr::Identifier(identifier) => self.visit_identifier(identifier),
+    /// Checks an expression used for its value and returns the type after
+    /// lvalue conversion and function-to-pointer decay.
+    fn check_value(&mut self, expr: &Expr) -> Result<QualifiedType, SemanticError> {
+        let expr_type = self.check_expr(expr)?;
+        Ok(decay(&expr_type.data_type))
+    }
+
+    fn check_scalar(&mut self, expr: &Expr) -> Result<(), SemanticError> {
+        let data_type = self.check_value(expr)?;
+        if !data_type.data_type.is_scalar() {
+            return Err(SemanticError::TypeError(
+                "Expected a scalar expression".to_string(),
+            ));
         }
+        Ok(())
     }
 
-    fn visit_assignment(&mut self, assignment: &ast::Assignment) -> Result<(), SemanticError> {
-        self.visit_expr(&assignment.value)?;
+    fn check_expr(&mut self, expr: &Expr) -> Result<ExprType, SemanticError> {
+        match expr {
+            Expr::Literal(literal) => {
+                let data_type = match literal {
+                    Literal::Boolean(_) => DataType::Bool,
+                    Literal::Integer(literal) => literal.data_type.clone(),
+                    Literal::Char(_) => DataType::Int,
+                    Literal::Float(literal) => literal.data_type.clone(),
+                    Literal::String(literal) => {
+                        return Ok(ExprType {
+                            data_type: QualifiedType::new(DataType::Array(
+                                Box::new(QualifiedType::new(DataType::Char)),
+                                Some(literal.value.len() + 1),
+                            )),
+                            is_lvalue: true,
+                        });
+                    }
+                };
+                Ok(ExprType::rvalue(data_type))
+            }
+            Expr::Identifier(identifier) => {
+                self.visit_identifier(identifier)?;
+                let symbol = self.get_variable(&identifier.name).unwrap();
+                Ok(ExprType {
+                    data_type: symbol.data_type.clone(),
+                    is_lvalue: !symbol.data_type.data_type.is_function(),
+                })
+            }
+            Expr::Unary(unary) => self.check_unary(unary),
+            Expr::Binary(binary) => self.check_binary(binary),
+            Expr::Assignment(assignment) => {
+                let target = self.check_expr(&assignment.target)?;
+                self.require_modifiable_lvalue(&assignment.target, &target)?;
+
+                match assignment.operator {
+                    Some(operator) => {
+                        let result = self.check_binary_operands(
+                            operator,
+                            &assignment.target,
+                            &assignment.value,
+                        )?;
+                        self.check_assignable(
+                            &target.data_type,
+                            &result,
+                            &assignment.value,
+                            "compound assignment",
+                        )?;
+                    }
+                    None => {
+                        let value = self.check_value(&assignment.value)?;
+                        self.check_assignable(
+                            &target.data_type,
+                            &value,
+                            &assignment.value,
+                            "assignment",
+                        )?;
+                    }
+                }
 
-        self.declare_variable(&assignment.identifier)?;
+                Ok(ExprType {
+                    data_type: target.data_type.unqualified(),
+                    is_lvalue: false,
+                })
+            }
+            Expr::Call(call) => {
+                let callee = self.check_value(&call.callee)?;
+                let function = match callee.data_type.pointee().map(|p| &p.data_type) {
+                    Some(DataType::Function(function)) => function.clone(),
+                    _ => {
+                        return Err(SemanticError::TypeError(format!(
+                            "Called object {} is not a function",
+                            describe(&call.callee)
+                        )));
+                    }
+                };
 
-        let _ = match assignment.data_type {
-            DataType::Int => match &assignment.value {
-                Expr::Literal(Literal::Integer(_)) => Ok(()),
-                _ => {
+                let (expected, given) = (function.parameters.len(), call.arguments.len());
+                if function.has_prototype
+                    && (given < expected || given > expected && !function.is_variadic)
+                {
                     return Err(SemanticError::TypeError(format!(
-                        "Type mismatch: expected 'int' for variable '{}'",
-                        assignment.identifier.name
+                        "Function {} expects {}{} arguments but {} were given",
+                        describe(&call.callee),
+                        if function.is_variadic {
+                            "at least "
+                        } else {
+                            ""
+                        },
+                        expected,
+                        given
                     )));
                 }
-            },
-            DataType::Bool => match &assignment.value {
-                Expr::Literal(Literal::Boolean(_)) => Ok(()),
-                _ => {
-                    return Err(SemanticError::TypeError(format!(
-                        "Type mismatch: expected 'bool' for variable '{}'",
-                        assignment.identifier.name
-                    )));
+
+                for (index, argument) in call.arguments.iter().enumerate() {
+                    let value = self.check_value(argument)?;
+                    match function.parameters.get(index) {
+                        Some(parameter) if function.has_prototype => {
+                            self.check_assignable(parameter, &value, argument, "argument")?;
+                        }
+                        _ => {}
+                    }
                 }
-            },
-            DataType::Float => match &assignment.value {
-                Expr::Literal(Literal::Float(_)) => Ok(()),
-                _ => {
-                    return Err(SemanticError::TypeError(format!(
-                        "Type mismatch: expected 'float' for variable '{}'",
-                        assignment.identifier.name
-                    )));
+
+                Ok(ExprType {
+                    data_type: function.return_type.unqualified(),
+                    is_lvalue: false,
+                })
+            }
+            Expr::Cast(cast) => self.check_cast(cast),
+            Expr::SizeofExpr(operand) => {
+                let operand = self.check_expr(operand)?;
+                self.check_sized(&operand.data_type, "sizeof")?;
+                Ok(ExprType::rvalue(DataType::ULong))
+            }
+            Expr::SizeofType(data_type) => {
+                self.check_sized(data_type, "sizeof")?;
+                Ok(ExprType::rvalue(DataType::ULong))
+            }
+            Expr::AlignofType(data_type) => {
+                self.check_sized(data_type, "_Alignof")?;
+                Ok(ExprType::rvalue(DataType::ULong))
+            }
+            Expr::Conditional(conditional) => self.check_conditional(conditional),
+            Expr::Comma(comma) => {
+                self.check_value(&comma.left)?;
+                let right = self.check_value(&comma.right)?;
+                Ok(ExprType {
+                    data_type: right,
+                    is_lvalue: false,
+                })
+            }
+            Expr::Index(index) => self.check_index(index),
+            Expr::Member(member) => self.check_member(member),
+            Expr::Stdarg(stdarg) => self.check_stdarg(stdarg),
+        }
+    }
+
+    fn check_stdarg(&mut self, stdarg: &Stdarg) -> Result<ExprType, SemanticError> {
+        match stdarg {
+            Stdarg::Start(list, last) => {
+                if !self.is_variadic {
+                    return Err(SemanticError::TypeError(
+                        "'va_start' used in a function with fixed arguments".to_string(),
+                    ));
                 }
-            },
-            DataType::Double => match &assignment.value {
-                Expr::Literal(Literal::Float(_)) => Ok(()),
-                _ => {
+                self.check_va_list(list)?;
+                if let Some(last) = last {
+                    match (&**last, &self.last_parameter) {
+                        (Expr::Identifier(identifier), Some(name)) if identifier.name == *name => {}
+                        _ => {
+                            return Err(SemanticError::TypeError(format!(
+                                "Second argument to 'va_start' is not the last named parameter, found {}",
+                                describe(last)
+                            )));
+                        }
+                    }
+                }
+            }
+            Stdarg::Arg(list, data_type) => {
+                self.check_va_list(list)?;
+                if !data_type.data_type.is_scalar() {
                     return Err(SemanticError::TypeError(format!(
-                        "Type mismatch: expected 'double' for variable '{}'",
-                        assignment.identifier.name
+                        "'va_arg' of non-scalar type '{}'",
+                        data_type.data_type
                     )));
                 }
-            },
-            DataType::Char => match &assignment.value {
-                Expr::Literal(Literal::Char(_)) => Ok(()),
+                return Ok(ExprType::rvalue(data_type.data_type.clone()));
+            }
+            Stdarg::End(list) => self.check_va_list(list)?,
+            Stdarg::Copy(destination, source) => {
+                self.check_va_list(destination)?;
+                self.check_va_list(source)?;
+            }
+        }
+        Ok(ExprType::rvalue(DataType::Void))
+    }
+
+    /// A `va_list`, or a parameter declared as one, is a pointer to its
+    /// struct once it decays.
+    fn check_va_list(&mut self, list: &Expr) -> Result<(), SemanticError> {
+        match self.check_value(list)?.data_type {
+            DataType::Pointer(pointee)
+                if pointee.data_type == DataType::Struct(VA_LIST_TAG.to_string()) =>
+            {
+                Ok(())
+            }
+            _ => Err(SemanticError::TypeError(format!(
+                "Expected a va_list, found {}",
+                describe(list)
+            ))),
+        }
+    }
+
+    /// `a[i]` is `*(a + i)`, so either operand may be the pointer.
+    fn check_index(&mut self, index: &ast::Index) -> Result<ExprType, SemanticError> {
+        let array = self.check_value(&index.array)?.data_type;
+        let subscript = self.check_value(&index.index)?.data_type;
+
+        let element = match (&array, &subscript) {
+            (DataType::Pointer(element), s) if s.is_integer() => element,
+            (a, DataType::Pointer(element)) if a.is_integer() => element,
+            _ => {
+                return Err(SemanticError::TypeError(format!(
+                    "Subscripted value {} is not an array or pointer",
+                    describe(&index.array)
+                )));
+            }
+        };
+
+        if !self.types.is_complete(&element.data_type) {
+            return Err(SemanticError::TypeError(format!(
+                "Subscript of pointer to incomplete type '{}'",
+                element.data_type
+            )));
+        }
+
+        Ok(ExprType {
+            data_type: (**element).clone(),
+            is_lvalue: true,
+        })
+    }
+
+    /// Members inherit the qualifiers of the structure they are accessed
+    /// through; `.` on an rvalue is an rvalue while `->` always designates an
+    /// object.
+    fn check_member(&mut self, member: &ast::Member) -> Result<ExprType, SemanticError> {
+        let (object, is_lvalue) = if member.is_arrow {
+            match self.check_value(&member.object)?.data_type {
+                DataType::Pointer(pointee) => (*pointee, true),
                 _ => {
                     return Err(SemanticError::TypeError(format!(
-                        "Type mismatch: expected 'char' for variable '{}'",
-                        assignment.identifier.name
+                        "Member reference {} is not a pointer",
+                        describe(&member.object)
                     )));
                 }
+            }
+        } else {
+            let object = self.check_expr(&member.object)?;
+            (object.data_type, object.is_lvalue)
+        };
+
+        let name = match &object.data_type {
+            DataType::Struct(name) => name,
+            data_type => {
+                return Err(SemanticError::TypeError(format!(
+                    "Member reference base type '{}' is not a structure",
+                    data_type
+                )));
+            }
+        };
+
+        if !self.types.is_complete(&object.data_type) {
+            return Err(SemanticError::TypeError(format!(
+                "Member access into incomplete type 'struct {}'",
+                name
+            )));
+        }
+
+        let (_, member_type) = self.types.member(name, &member.member).ok_or_else(|| {
+            SemanticError::TypeError(format!(
+                "No member named '{}' in 'struct {}'",
+                member.member, name
+            ))
+        })?;
+
+        Ok(ExprType {
+            data_type: QualifiedType {
+                qualifiers: merge_qualifiers(&object.qualifiers, &member_type.qualifiers),
+                data_type: member_type.data_type,
             },
-            DataType::Void => Err(SemanticError::TypeError(format!(
-                "Cannot assign void type to variable '{}'",
-                assignment.identifier.name
+            is_lvalue,
+        })
+    }
+
+    /// Whether assigning a whole structure would write a const member.
+    fn has_const_member(&self, data_type: &DataType) -> bool {
+        match data_type {
+            DataType::Struct(name) => {
+                self.types.structs[name]
+                    .members
+                    .iter()
+                    .flatten()
+                    .any(|member| {
+                        member.data_type.qualifiers.is_const
+                            || self.has_const_member(&member.data_type.data_type)
+                    })
+            }
+            DataType::Array(element, _) => {
+                element.qualifiers.is_const || self.has_const_member(&element.data_type)
+            }
+            _ => false,
+        }
+    }
+
+    /// `sizeof` and `_Alignof` only apply to complete object types.
+    fn check_sized(&self, data_type: &QualifiedType, operator: &str) -> Result<(), SemanticError> {
+        match &data_type.data_type {
+            DataType::Function(_) => Err(SemanticError::TypeError(format!(
+                "Invalid application of '{}' to a function type",
+                operator
             ))),
+            data_type if !self.types.is_complete(data_type) => {
+                Err(SemanticError::TypeError(format!(
+                    "Invalid application of '{}' to incomplete type '{}'",
+                    operator, data_type
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_cast(&mut self, cast: &ast::Cast) -> Result<ExprType, SemanticError> {
+        validate_restrict(&cast.target_type)?;
+        let value = self.check_value(&cast.value)?.data_type;
+        let target = &cast.target_type.data_type;
+
+        let valid = match (target, &value) {
+            (DataType::Void, _) => true,
+            (t, v) if t.is_arithmetic() && v.is_arithmetic() => true,
+            (DataType::Pointer(_), DataType::Pointer(_)) => true,
+            (DataType::Pointer(_), v) => v.is_integer(),
+            (t, DataType::Pointer(_)) => t.is_integer(),
+            _ => false,
         };
 
+        if !valid {
+            return Err(SemanticError::TypeError(format!(
+                "Invalid cast from '{}' to '{}'",
+                value, target
+            )));
+        }
+
+        Ok(ExprType::rvalue(target.clone()))
+    }
+
+    /// Result type of `?:` following C11 6.5.15.
+    fn check_conditional(
+        &mut self,
+        conditional: &ast::Conditional,
+    ) -> Result<ExprType, SemanticError> {
+        self.check_scalar(&conditional.condition)?;
+        let then_type = self.check_value(&conditional.then_value)?.data_type;
+        let else_type = self.check_value(&conditional.else_value)?.data_type;
+
+        let result = match (&then_type, &else_type) {
+            (t, e) if t.is_arithmetic() && e.is_arithmetic() => t.common_type(e),
+            (DataType::Void, DataType::Void) => DataType::Void,
+            (DataType::Struct(t), DataType::Struct(e)) if t == e => then_type.clone(),
+            (DataType::Pointer(_), _) if is_null_pointer_constant(&conditional.else_value) => {
+                then_type.clone()
+            }
+            (_, DataType::Pointer(_)) if is_null_pointer_constant(&conditional.then_value) => {
+                else_type.clone()
+            }
+            (DataType::Pointer(t), DataType::Pointer(e)) => {
+                let qualifiers = merge_qualifiers(&t.qualifiers, &e.qualifiers);
+                let pointee = if t.data_type == DataType::Void || e.data_type == DataType::Void {
+                    DataType::Void
+                } else if compatible_types(&t.unqualified(), &e.unqualified()) {
+                    t.data_type.clone()
+                } else {
+                    return Err(SemanticError::TypeError(
+                        "Pointer type mismatch in conditional expression".to_string(),
+                    ));
+                };
+
+                DataType::Pointer(Box::new(QualifiedType {
+                    data_type: pointee,
+                    qualifiers,
+                }))
+            }
+            _ => {
+                return Err(SemanticError::TypeError(format!(
+                    "Type mismatch in conditional expression ('{}' and '{}')",
+                    then_type, else_type
+                )));
+            }
+        };
+
+        Ok(ExprType::rvalue(result))
+    }
+
+    fn check_unary(&mut self, unary: &ast::UnaryExpr) -> Result<ExprType, SemanticError> {
+        match unary.operator {
+            UnaryOperator::Negate | UnaryOperator::Plus => {
+                let operand = self.check_value(&unary.operand)?;
+                if !operand.data_type.is_arithmetic() {
+                    return Err(SemanticError::TypeError(
+                        "Unary '+'/'-' requires an arithmetic operand".to_string(),
+                    ));
+                }
+                Ok(ExprType::rvalue(operand.data_type.promoted()))
+            }
+            UnaryOperator::BitwiseNot => {
+                let operand = self.check_value(&unary.operand)?;
+                if !operand.data_type.is_integer() {
+                    return Err(SemanticError::TypeError(
+                        "'~' requires an integer operand".to_string(),
+                    ));
+                }
+                Ok(ExprType::rvalue(operand.data_type.promoted()))
+            }
+            UnaryOperator::LogicalNot => {
+                self.check_scalar(&unary.operand)?;
+                Ok(ExprType::rvalue(DataType::Int))
+            }
+            UnaryOperator::Dereference => {
+                let operand = self.check_value(&unary.operand)?;
+                match operand.data_type {
+                    DataType::Pointer(pointee) => {
+                        if pointee.data_type == DataType::Void {
+                            return Err(SemanticError::TypeError(
+                                "Dereferencing 'void *' pointer".to_string(),
+                            ));
+                        }
+                        Ok(ExprType {
+                            is_lvalue: !pointee.data_type.is_function(),
+                            data_type: *pointee,
+                        })
+                    }
+                    _ => Err(SemanticError::TypeError(
+                        "Cannot dereference a non-pointer value".to_string(),
+                    )),
+                }
+            }
+            UnaryOperator::AddressOf => {
+                let operand = self.check_expr(&unary.operand)?;
+                if !operand.is_lvalue && !operand.data_type.data_type.is_function() {
+                    return Err(SemanticError::TypeError(
+                        "Cannot take the address of an rvalue".to_string(),
+                    ));
+                }
+
+                if let Expr::Identifier(identifier) = unary.operand.as_ref() {
+                    let symbol = self.get_variable(&identifier.name).unwrap();
+                    if symbol.storage_class == Some(StorageClass::Register) {
+                        return Err(SemanticError::InvalidStorageClass(format!(
+                            "Cannot take the address of register variable '{}'",
+                            identifier.name
+                        )));
+                    }
+                }
+
+                Ok(ExprType::rvalue(DataType::Pointer(Box::new(
+                    operand.data_type,
+                ))))
+            }
+            UnaryOperator::PreIncrement
+            | UnaryOperator::PreDecrement
+            | UnaryOperator::PostIncrement
+            | UnaryOperator::PostDecrement => {
+                let operand = self.check_expr(&unary.operand)?;
+                self.require_modifiable_lvalue(&unary.operand, &operand)?;
+                if !operand.data_type.data_type.is_scalar() {
+                    return Err(SemanticError::TypeError(
+                        "Increment and decrement require a scalar operand".to_string(),
+                    ));
+                }
+                Ok(ExprType {
+                    data_type: operand.data_type.unqualified(),
+                    is_lvalue: false,
+                })
+            }
+        }
+    }
+
+    fn check_binary(&mut self, binary: &ast::BinaryExpr) -> Result<ExprType, SemanticError> {
+        let data_type = self.check_binary_operands(binary.operator, &binary.left, &binary.right)?;
+        Ok(ExprType {
+            data_type,
+            is_lvalue: false,
+        })
+    }
+
+    fn check_binary_operands(
+        &mut self,
+        operator: BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<QualifiedType, SemanticError> {
+        let left_type = self.check_value(left)?.data_type;
+        let right_type = self.check_value(right)?.data_type;
+
+        let invalid = || {
+            SemanticError::TypeError(format!(
+                "Invalid operands to binary {} ('{}' and '{}')",
+                operator.symbol(),
+                left_type,
+                right_type
+            ))
+        };
+
+        let is_object_pointer = |data_type: &DataType| match data_type.pointee() {
+            Some(pointee) => {
+                pointee.data_type != DataType::Void && !pointee.data_type.is_function()
+            }
+            None => false,
+        };
+
+        let result = match operator {
+            BinaryOperator::Add => {
+                if left_type.is_arithmetic() && right_type.is_arithmetic() {
+                    left_type.common_type(&right_type)
+                } else if is_object_pointer(&left_type) && right_type.is_integer() {
+                    left_type.clone()
+                } else if left_type.is_integer() && is_object_pointer(&right_type) {
+                    right_type.clone()
+                } else {
+                    return Err(invalid());
+                }
+            }
+            BinaryOperator::Subtract => {
+                if left_type.is_arithmetic() && right_type.is_arithmetic() {
+                    left_type.common_type(&right_type)
+                } else if is_object_pointer(&left_type) && right_type.is_integer() {
+                    left_type.clone()
+                } else if is_object_pointer(&left_type)
+                    && is_object_pointer(&right_type)
+                    && compatible_types(
+                        &left_type.pointee().unwrap().unqualified(),
+                        &right_type.pointee().unwrap().unqualified(),
+                    )
+                {
+                    DataType::Long
+                } else {
+                    return Err(invalid());
+                }
+            }
+            BinaryOperator::Multiply | BinaryOperator::Divide => {
+                if !left_type.is_arithmetic() || !right_type.is_arithmetic() {
+                    return Err(invalid());
+                }
+                left_type.common_type(&right_type)
+            }
+            BinaryOperator::Remainder
+            | BinaryOperator::BitwiseAnd
+            | BinaryOperator::BitwiseOr
+            | BinaryOperator::BitwiseXor => {
+                if !left_type.is_integer() || !right_type.is_integer() {
+                    return Err(invalid());
+                }
+                left_type.common_type(&right_type)
+            }
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+                if !left_type.is_integer() || !right_type.is_integer() {
+                    return Err(invalid());
+                }
+                left_type.promoted()
+            }
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                if !left_type.is_scalar() || !right_type.is_scalar() {
+                    return Err(invalid());
+                }
+                DataType::Int
+            }
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::Less
+            | BinaryOperator::LessEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual => {
+                let valid = match (&left_type, &right_type) {
+                    (l, r) if l.is_arithmetic() && r.is_arithmetic() => true,
+                    (DataType::Pointer(l), DataType::Pointer(r)) => {
+                        l.data_type == DataType::Void
+                            || r.data_type == DataType::Void
+                            || compatible_types(&l.unqualified(), &r.unqualified())
+                    }
+                    (DataType::Pointer(_), _) => is_null_pointer_constant(right),
+                    (_, DataType::Pointer(_)) => is_null_pointer_constant(left),
+                    _ => false,
+                };
+                if !valid {
+                    return Err(invalid());
+                }
+                DataType::Int
+            }
+        };
+
+        Ok(QualifiedType::new(result))
+    }
+
+    fn visit_condition(&mut self, condition: &Expr) -> Result<(), SemanticError> {
+        self.check_scalar(condition)
+    }
+
+    fn visit_switch(&mut self, switch: &ast::Switch) -> Result<(), SemanticError> {
+        let condition = self.check_value(&switch.condition)?;
+        if !condition.data_type.is_integer() {
+            return Err(SemanticError::TypeError(
+                "Switch condition must have integer type".to_string(),
+            ));
+        }
+
+        self.switches.push(Switch {
+            data_type: condition.data_type.promoted(),
+            values: HashSet::new(),
+            has_default: false,
+        });
+        let result = self.visit_instruction(&switch.body);
+        self.switches.pop();
+        result
+    }
+
+    fn visit_case(&mut self, case: &ast::Case) -> Result<(), SemanticError> {
+        let value_type = self.check_value(&case.value)?;
+        let (value, _) = case
+            .value
+            .evaluate_typed_constant(self.types)
+            .filter(|_| value_type.data_type.is_integer())
+            .ok_or_else(|| {
+                SemanticError::TypeError(
+                    "Case label is not an integer constant expression".to_string(),
+                )
+            })?;
+
+        let switch = self.switches.last_mut().ok_or_else(|| {
+            SemanticError::InvalidStatement("'case' label not within a switch".to_string())
+        })?;
+        let value = switch.data_type.wrap_integer(value);
+        if !switch.values.insert(value) {
+            return Err(SemanticError::InvalidStatement(format!(
+                "Duplicate case value {}",
+                value
+            )));
+        }
+
+        self.visit_instruction(&case.body)
+    }
+
+    fn visit_loop_body(&mut self, body: &ast::Instruction) -> Result<(), SemanticError> {
+        self.loop_depth += 1;
+        let result = self.visit_instruction(body);
+        self.loop_depth -= 1;
+        result
+    }
+}
+
+impl ast::Visitor<Result<(), SemanticError>> for SemanticChecker<'_> {
+    fn visit_type(&mut self, data_type: &ast::DataType) -> Result<(), SemanticError> {
+        match data_type {
+            DataType::Function(function) => {
+                if function.return_type.data_type.is_function() {
+                    return Err(SemanticError::TypeError(
+                        "Functions cannot return function types".to_string(),
+                    ));
+                }
+                if function.return_type.data_type.is_array() {
+                    return Err(SemanticError::TypeError(
+                        "Functions cannot return array types".to_string(),
+                    ));
+                }
+                self.visit_type(&function.return_type.data_type)
+            }
+            DataType::Array(element, _) => {
+                if element.data_type.is_function() {
+                    return Err(SemanticError::TypeError(
+                        "Arrays of functions are not allowed".to_string(),
+                    ));
+                }
+                if !self.types.is_complete(&element.data_type) {
+                    return Err(SemanticError::TypeError(format!(
+                        "Array has incomplete element type '{}'",
+                        element.data_type
+                    )));
+                }
+                self.visit_type(&element.data_type)
+            }
+            DataType::Pointer(pointee) => self.visit_type(&pointee.data_type),
+            _ => Ok(()),
+        }
+    }
+
+    fn visit_literal(&mut self, _literal: &ast::Literal) -> Result<(), SemanticError> {
+        Ok(())
+    }
+
+    fn visit_identifier(&mut self, identifier: &ast::Identifier) -> Result<(), SemanticError> {
+        if self.get_variable(&identifier.name).is_none() {
+            return Err(SemanticError::UndefinedVariable(format!(
+                "Variable '{}' is not defined",
+                identifier.name
+            )));
+        }
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expr) -> Result<(), SemanticError> {
+        self.check_expr(expr).map(|_| ())
+    }
+
+    fn visit_declaration(&mut self, declaration: &ast::Declaration) -> Result<(), SemanticError> {
+        let name = &declaration.identifier.name;
+        let mut data_type = declaration.data_type.clone();
+        self.visit_type(&data_type.data_type)?;
+
+        if data_type.data_type == DataType::Void {
+            return Err(SemanticError::TypeError(format!(
+                "Variable '{}' declared void",
+                name
+            )));
+        }
+
+        let is_extern = declaration.storage_class == Some(StorageClass::Extern);
+        let mut elements = vec![];
+        if let Some(initializer) = &declaration.initializer {
+            if data_type.data_type.is_function() {
+                return Err(SemanticError::TypeError(format!(
+                    "Function '{}' is initialized like a variable",
+                    name
+                )));
+            }
+            if is_extern && !self.at_file_scope() {
+                return Err(SemanticError::InvalidStorageClass(format!(
+                    "Block-scope 'extern' declaration of '{}' cannot have an initializer",
+                    name
+                )));
+            }
+
+            let types = self.types;
+            let resolved = initializer::resolve(&data_type, initializer, types, &mut |expr| {
+                self.check_value(expr).ok().map(|value| value.data_type)
+            })
+            .map_err(SemanticError::TypeError)?;
+            data_type = resolved.data_type;
+            elements = resolved.elements;
+        }
+
+        // File-scope arrays of unknown size are tentative definitions that
+        // end up with one element.
+        let is_tentative_array = self.at_file_scope() && data_type.data_type.is_array();
+        if !data_type.data_type.is_function()
+            && !is_extern
+            && !is_tentative_array
+            && !self.types.is_complete(&data_type.data_type)
+        {
+            return Err(SemanticError::TypeError(format!(
+                "Variable '{}' has incomplete type '{}'",
+                name, data_type.data_type
+            )));
+        }
+
+        self.declare_variable(
+            name,
+            &data_type,
+            declaration.storage_class,
+            declaration.initializer.is_some(),
+        )?;
+
+        let has_static_storage =
+            self.at_file_scope() || declaration.storage_class == Some(StorageClass::Static);
+        for element in &elements {
+            // A string literal initializing a char array was already checked
+            // against the array's length.
+            if !element.data_type.data_type.is_array() {
+                let value_type = self.check_value(element.value)?;
+                self.check_assignable(
+                    &element.data_type,
+                    &value_type,
+                    element.value,
+                    "initialization",
+                )?;
+            }
+            if has_static_storage {
+                self.check_constant(element.value)?;
+            }
+        }
+
         Ok(())
     }
 
     fn visit_return(&mut self, return_stmt: &ast::Return) -> Result<(), SemanticError> {
-        self.visit_expr(&return_stmt.value)
+        let return_type = self.return_type.clone().unwrap();
+
+        match &return_stmt.value {
+            Some(value) => {
+                let value_type = self.check_value(value)?;
+                if return_type.data_type == DataType::Void {
+                    return Err(SemanticError::TypeError(
+                        "Void function should not return a value".to_string(),
+                    ));
+                }
+                self.check_assignable(&return_type, &value_type, value, "return")
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn visit_block(&mut self, block: &ast::Block) -> Result<(), SemanticError> {
+        self.enter_scope();
+        let result = block
+            .instructions
+            .iter()
+            .try_for_each(|instruction| self.visit_instruction(instruction));
+        self.exit_scope();
+        result
     }
 
     fn visit_instruction(&mut self, instruction: &ast::Instruction) -> Result<(), SemanticError> {
         match instruction {
-            ast::Instruction::Assignment(assignment) => self.visit_assignment(assignment),
+            ast::Instruction::Declaration(declaration) => self.visit_declaration(declaration),
             ast::Instruction::Return(return_stmt) => self.visit_return(return_stmt),
+            ast::Instruction::Expression(expr) => self.visit_expr(expr),
+            ast::Instruction::Block(block) => self.visit_block(block),
+            ast::Instruction::If(if_stmt) => {
+                self.visit_condition(&if_stmt.condition)?;
+                self.visit_instruction(&if_stmt.then_branch)?;
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    self.visit_instruction(else_branch)?;
+                }
+                Ok(())
+            }
+            ast::Instruction::While(while_stmt) => {
+                self.visit_condition(&while_stmt.condition)?;
+                self.visit_loop_body(&while_stmt.body)
+            }
+            ast::Instruction::DoWhile(do_while) => {
+                self.visit_loop_body(&do_while.body)?;
+                self.visit_condition(&do_while.condition)
+            }
+            ast::Instruction::For(for_stmt) => {
+                self.enter_scope();
+                let result = (|| {
+                    for instruction in &for_stmt.initializer {
+                        self.visit_instruction(instruction)?;
+                    }
+                    if let Some(condition) = &for_stmt.condition {
+                        self.visit_condition(condition)?;
+                    }
+                    if let Some(increment) = &for_stmt.increment {
+                        self.visit_expr(increment)?;
+                    }
+                    self.visit_loop_body(&for_stmt.body)
+                })();
+                self.exit_scope();
+                result
+            }
+            ast::Instruction::Switch(switch) => self.visit_switch(switch),
+            ast::Instruction::Case(case) => self.visit_case(case),
+            ast::Instruction::Default(body) => {
+                let switch = self.switches.last_mut().ok_or_else(|| {
+                    SemanticError::InvalidStatement(
+                        "'default' label not within a switch".to_string(),
+                    )
+                })?;
+                if switch.has_default {
+                    return Err(SemanticError::InvalidStatement(
+                        "Multiple default labels in one switch".to_string(),
+                    ));
+                }
+                switch.has_default = true;
+
+                self.visit_instruction(body)
+            }
+            ast::Instruction::Labeled(labeled) => {
+                if !self.labels.insert(labeled.label.clone()) {
+                    return Err(SemanticError::Redeclaration(format!(
+                        "Redefinition of label '{}'",
+                        labeled.label
+                    )));
+                }
+                self.visit_instruction(&labeled.body)
+            }
+            ast::Instruction::Goto(label) => {
+                self.gotos.push(label.clone());
+                Ok(())
+            }
+            ast::Instruction::Break => {
+                if self.loop_depth == 0 && self.switches.is_empty() {
+                    return Err(SemanticError::InvalidStatement(
+                        "'break' outside of a loop or switch".to_string(),
+                    ));
+                }
+                Ok(())
+            }
+            ast::Instruction::Continue => {
+                if self.loop_depth == 0 {
+                    return Err(SemanticError::InvalidStatement(
+                        "'continue' outside of a loop".to_string(),
+                    ));
+                }
+                Ok(())
+            }
+            ast::Instruction::Empty => Ok(()),
         }
     }
 
     fn visit_function(&mut self, function: &ast::Function) -> Result<(), SemanticError> {
-        for instruction in &function.instructions {
-            self.visit_instruction(instruction)?;
-        }
-        Ok(())
+        let function_type = QualifiedType::new(DataType::Function(function.function_type()));
+        self.visit_type(&function_type.data_type)?;
+        self.declare_variable(&function.name, &function_type, function.storage_class, true)?;
+
+        self.return_type = Some(function.return_type.clone());
+        self.is_variadic = function.is_variadic;
+        self.last_parameter = function
+            .parameters
+            .last()
+            .and_then(|parameter| parameter.identifier.as_ref())
+            .map(|identifier| identifier.name.clone());
+
+        // Parameters share a scope with the outermost block of the body.
+        self.enter_scope();
+        let result = (|| {
+            for parameter in &function.parameters {
+                let identifier = parameter.identifier.as_ref().ok_or_else(|| {
+                    SemanticError::TypeError(format!(
+                        "Parameter name omitted in definition of '{}'",
+                        function.name
+                    ))
+                })?;
+                self.declare_variable(
+                    &identifier.name,
+                    &parameter.data_type,
+                    parameter.storage_class,
+                    true,
+                )?;
+            }
+
+            for instruction in &function.instructions {
+                self.visit_instruction(instruction)?;
+            }
+
+            match self
+                .gotos
+                .iter()
+                .find(|label| !self.labels.contains(*label))
+            {
+                Some(label) => Err(SemanticError::UndefinedLabel(format!(
+                    "Label '{}' used but not defined",
+                    label
+                ))),
+                None => Ok(()),
+            }
+        })();
+        self.exit_scope();
+
+        self.return_type = None;
+        self.is_variadic = false;
+        self.last_parameter = None;
+        self.labels.clear();
+        self.gotos.clear();
+        result
     }
 
     fn visit_program(&mut self, program: &ast::Program) -> Result<(), SemanticError> {
-        for function in &program.functions {
-            self.visit_function(function)?;
+        for declaration in &program.declarations {
+            match declaration {
+                ast::ExternalDeclaration::Function(function) => self.visit_function(function)?,
+                ast::ExternalDeclaration::Declaration(declaration) => {
+                    self.visit_declaration(declaration)?
+                }
+            }
         }
         Ok(())
     }
 }
 
 pub fn check(program: &ast::Program) -> Result<(), SemanticError> {
-    let mut checker = SemanticChecker::new();
+    let mut checker = SemanticChecker::new(&program.types);
 
     checker.visit_program(program)
 }
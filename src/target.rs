@@ -0,0 +1,40 @@
+/*
+  Data layout of the x86-64 System V target (LP64): sizes, alignments and
+  integer conversion ranks (C11 6.3.1.1) of the scalar types.
+*/
+
+use crate::ast::DataType;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScalarLayout {
+    pub size: usize,
+    pub alignment: usize,
+    /// Integer conversion rank; `None` for floating and pointer types.
+    pub rank: Option<usize>,
+}
+
+/// Plain `char` has the representation of `signed char`.
+pub const CHAR_IS_SIGNED: bool = true;
+
+pub const POINTER_SIZE: usize = 8;
+
+pub fn scalar_layout(data_type: &DataType) -> Option<ScalarLayout> {
+    let (size, alignment, rank) = match data_type {
+        DataType::Bool => (1, 1, Some(1)),
+        DataType::Char | DataType::SChar | DataType::UChar => (1, 1, Some(2)),
+        DataType::Short | DataType::UShort => (2, 2, Some(3)),
+        DataType::Int | DataType::UInt => (4, 4, Some(4)),
+        DataType::Long | DataType::ULong => (8, 8, Some(5)),
+        DataType::LongLong | DataType::ULongLong => (8, 8, Some(6)),
+        DataType::Float => (4, 4, None),
+        DataType::Double => (8, 8, None),
+        DataType::Pointer(_) => (POINTER_SIZE, POINTER_SIZE, None),
+        _ => return None,
+    };
+
+    Some(ScalarLayout {
+        size,
+        alignment,
+        rank,
+    })
+}
@@ -0,0 +1,97 @@
+use compiler::ast::{DataType, ExternalDeclaration, Initializer};
+use compiler::{lexer, parser};
+
+fn evaluate(expr: &str) -> Option<(i128, DataType)> {
+    let tokens = lexer::tokenize(format!("int x = {};", expr), false).unwrap();
+    let program = parser::parse(tokens).unwrap();
+    let ExternalDeclaration::Declaration(x) = &program.declarations[0] else {
+        panic!("expected a declaration");
+    };
+    let Some(Initializer::Expr(expr)) = &x.initializer else {
+        panic!("expected an expression initializer");
+    };
+    expr.evaluate_typed_constant(&program.types)
+}
+
+#[test]
+fn test_integer_conversions() {
+    assert_eq!(DataType::UChar.wrap_integer(-1), 255);
+    assert_eq!(DataType::SChar.wrap_integer(200), -56);
+    assert_eq!(DataType::Short.wrap_integer(0x12345), 0x2345);
+    assert_eq!(DataType::UInt.wrap_integer(-1), 0xffff_ffff);
+    assert_eq!(DataType::Long.wrap_integer(0xffff_ffff), 0xffff_ffff);
+    assert_eq!(DataType::Bool.wrap_integer(256), 1);
+
+    assert_eq!(DataType::Char.promoted(), DataType::Int);
+    assert_eq!(DataType::UShort.promoted(), DataType::Int);
+    assert_eq!(DataType::Int.common_type(&DataType::UInt), DataType::UInt);
+    assert_eq!(DataType::Long.common_type(&DataType::UInt), DataType::Long);
+    assert_eq!(
+        DataType::LongLong.common_type(&DataType::ULong),
+        DataType::ULongLong
+    );
+    assert_eq!(
+        DataType::ULong.common_type(&DataType::Float),
+        DataType::Float
+    );
+}
+
+#[test]
+fn test_constant_evaluation_follows_c_semantics() {
+    assert_eq!(evaluate("-1 < 0u"), Some((0, DataType::Int)));
+    assert_eq!(evaluate("0u - 1"), Some((0xffff_ffff, DataType::UInt)));
+    assert_eq!(evaluate("(unsigned char)300"), Some((44, DataType::UChar)));
+    assert_eq!(
+        evaluate("(signed char)255 + 0L"),
+        Some((-1, DataType::Long))
+    );
+    assert_eq!(
+        evaluate("(unsigned short)-1 + 1"),
+        Some((65536, DataType::Int))
+    );
+    assert_eq!(evaluate("sizeof(long) * 2"), Some((16, DataType::ULong)));
+    assert_eq!(evaluate("-1 >> 1"), Some((-1, DataType::Int)));
+
+    // Undefined behavior is never a constant.
+    assert_eq!(evaluate("2147483647 + 1"), None);
+    assert_eq!(evaluate("1 / 0"), None);
+    assert_eq!(evaluate("1 << 32"), None);
+    assert_eq!(evaluate("-1 << 1"), None);
+    assert_eq!(evaluate("(-2147483647 - 1) / -1"), None);
+    assert_eq!(evaluate("4294967295u + 1"), Some((0, DataType::UInt)));
+}
+
+#[test]
+fn test_types_display_as_c() {
+    let source = "
+        const char *a;
+        int (*b)[3];
+        struct point { int x; } c;
+        unsigned long *const *d;
+        int (*e)(int, ...);
+        volatile double f[2];
+        void (*g)(void);
+    ";
+    let tokens = lexer::tokenize(source.to_string(), false).unwrap();
+    let program = parser::parse(tokens).unwrap();
+    let spelled: Vec<String> = program
+        .declarations
+        .iter()
+        .map(|declaration| match declaration {
+            ExternalDeclaration::Declaration(declaration) => declaration.data_type.to_string(),
+            _ => panic!("expected a declaration"),
+        })
+        .collect();
+    assert_eq!(
+        spelled,
+        [
+            "const char *",
+            "int (*)[3]",
+            "struct point",
+            "unsigned long *const *",
+            "int (*)(int, ...)",
+            "volatile double[2]",
+            "void (*)(void)",
+        ]
+    );
+}
@@ -0,0 +1,200 @@
+use compiler::ir::cfg::{self, Cfg};
+use compiler::ir::dominators::DominatorTree;
+use compiler::ir::loops::{self, find_loops};
+use compiler::ir::{Instr, IrType, Label, Temp, Value, parser};
+
+fn build(source: &str) -> Cfg {
+    let program = parser::parse(source).unwrap();
+    Cfg::build(&program.functions[0])
+}
+
+fn block(cfg: &Cfg, label: u32) -> usize {
+    cfg.label_indices()[&Label(label)]
+}
+
+/// entry -> L1 (loop header) -> L2 / L3 (diamond) -> L4 (latch) -> L1 or L5.
+const NESTED: &str = "
+    function i32 @f(i32 %0) {
+      %1 = copy i32 0
+      jmp L1
+    L1:
+      br i32 %0, L2, L3
+    L2:
+      jmp L4
+    L3:
+      br i32 %1, L6, L4
+    L6:
+      jmp L6
+    L4:
+      br i32 %0, L1, L5
+    L5:
+      ret i32 %1
+    L7:
+      jmp L5
+    }
+";
+
+#[test]
+fn test_blocks_and_edges() {
+    let cfg = build(
+        "
+        function void @f(i32 %0) {
+          br i32 %0, L0, L1
+        L0:
+          %1 = add i32 %0, 1
+        L1:
+          ret
+          %2 = add i32 %0, 2
+        }
+        ",
+    );
+
+    assert_eq!(cfg.blocks.len(), 4);
+    let (l0, l1) = (block(&cfg, 0), block(&cfg, 1));
+    assert_eq!(cfg.successors[0], vec![l0, l1]);
+    assert_eq!(cfg.predecessors[l1], vec![0, l0]);
+    // Falling through into a label becomes a jump.
+    assert_eq!(cfg.blocks[l0].terminator(), &Instr::Jump(Label(1)));
+    // The unreachable tail of L1 gets its own block and a return.
+    assert!(cfg.predecessors[3].is_empty());
+    assert_eq!(cfg.blocks[3].terminator(), &Instr::Return(None));
+}
+
+#[test]
+fn test_remove_unreachable() {
+    let mut cfg = build(NESTED);
+    assert_eq!(cfg.blocks.len(), 8);
+    assert!(cfg.remove_unreachable());
+    assert_eq!(cfg.blocks.len(), 7);
+    assert!(!cfg.label_indices().contains_key(&Label(7)));
+    assert!(!cfg.remove_unreachable());
+
+    let body = cfg.to_body();
+    assert_eq!(body.first(), Some(&Instr::Label(cfg.blocks[0].label)));
+}
+
+#[test]
+fn test_dominators_and_frontiers() {
+    let cfg = build(NESTED);
+    let dominators = DominatorTree::build(&cfg);
+    let [l1, l2, l3, l4, l5, l6, l7] = [1, 2, 3, 4, 5, 6, 7].map(|label| block(&cfg, label));
+
+    assert_eq!(dominators.idom[l1], Some(0));
+    assert_eq!(dominators.idom[l2], Some(l1));
+    assert_eq!(dominators.idom[l3], Some(l1));
+    assert_eq!(dominators.idom[l4], Some(l1));
+    assert_eq!(dominators.idom[l5], Some(l4));
+    assert_eq!(dominators.idom[l6], Some(l3));
+    assert_eq!(dominators.idom[l7], None);
+
+    assert!(dominators.dominates(l1, l5));
+    assert!(dominators.dominates(l4, l4));
+    assert!(!dominators.dominates(l2, l4));
+    assert!(!dominators.dominates(l7, l5));
+
+    let mut frontier = dominators.frontiers[l2].clone();
+    frontier.sort();
+    assert_eq!(frontier, vec![l4]);
+    assert_eq!(dominators.frontiers[l4], vec![l1]);
+    assert_eq!(dominators.frontiers[l6], vec![l6]);
+    assert!(dominators.frontiers[0].is_empty());
+}
+
+#[test]
+fn test_natural_loops() {
+    let cfg = build(NESTED);
+    let dominators = DominatorTree::build(&cfg);
+    let [l1, l2, l3, l4, l6] = [1, 2, 3, 4, 6].map(|label| block(&cfg, label));
+
+    let found = find_loops(&cfg, &dominators);
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].header, l1);
+    assert_eq!(found[0].latches, vec![l4]);
+    assert_eq!(found[0].blocks.iter().copied().collect::<Vec<_>>(), {
+        let mut blocks = vec![l1, l2, l3, l4];
+        blocks.sort();
+        blocks
+    });
+    assert_eq!(found[1].header, l6);
+    assert_eq!(found[0].exits(&cfg).len(), 2);
+
+    let depths = loops::loop_depths(&cfg, &found);
+    assert_eq!(depths[0], 0);
+    assert_eq!(depths[l3], 1);
+    assert_eq!(depths[l6], 1);
+}
+
+#[test]
+fn test_dot_output() {
+    let program = parser::parse(NESTED).unwrap();
+    let dot = cfg::to_dot(&program.functions);
+    assert!(dot.starts_with("digraph cfg {"));
+    assert!(dot.contains("\"f.L1\" -> \"f.L2\" [label = \"T\"];"));
+    assert!(dot.contains("\"f.L4\" -> \"f.L1\" [label = \"T\"];"));
+    assert!(dot.trim_end().ends_with('}'));
+
+    let program = parser::parse(
+        "
+        function i32 @g() {
+          ret i32 0
+        L1:
+          jmp L2
+        L2:
+          ret i32 1
+        }
+        ",
+    )
+    .unwrap();
+    let dot = cfg::to_dot(&program.functions);
+    assert!(dot.contains("\"g.L1\" [shape = box, label = \"L1 (unreachable):\\l"));
+    assert!(dot.contains("\"g.L1\" -> \"g.L2\" [style = dashed];"));
+    assert_eq!(dot.matches("(unreachable)").count(), 2);
+}
+
+#[test]
+fn test_preheaders_and_inductions() {
+    // The loop at L3 is entered from both L1 and L2.
+    let source = "
+        function i32 @g(i32 %0) {
+          br i32 %0, L1, L2
+        L1:
+          jmp L3
+        L2:
+          jmp L3
+        L3:
+          %1 = phi i32 [0, L1], [5, L2], [%2, L3]
+          %2 = add i32 %1, 3
+          %3 = cmp slt i32 %2, 20
+          br i32 %3, L3, L4
+        L4:
+          ret i32 %2
+        }
+    ";
+    let mut cfg = build(source);
+    let found = find_loops(&cfg, &DominatorTree::build(&cfg));
+    assert_eq!(found[0].preheader(&cfg), None);
+
+    let mut next_temp = 4;
+    let (found, changed) = loops::with_preheaders(&mut cfg, found, &mut next_temp);
+    assert!(changed);
+    assert_eq!(next_temp, 5);
+    let preheader = found[0].preheader(&cfg).unwrap();
+    assert_eq!(cfg.successors[preheader], vec![found[0].header]);
+    assert_eq!(
+        cfg.blocks[preheader].instrs[0],
+        Instr::Phi {
+            dst: Temp(4),
+            ty: IrType::I32,
+            incoming: vec![(Label(1), Value::Int(0)), (Label(2), Value::Int(5))],
+        }
+    );
+
+    let inductions = loops::inductions(&cfg, &found[0], cfg.blocks[preheader].label);
+    let induction = &inductions[&Temp(1)];
+    assert_eq!(induction.init, Value::Temp(Temp(4)));
+    assert_eq!(induction.step, 3);
+
+    // A loop that already has one keeps it.
+    let (_, changed) = loops::with_preheaders(&mut cfg, found, &mut next_temp);
+    assert!(!changed);
+}
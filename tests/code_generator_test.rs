@@ -0,0 +1,652 @@
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use compiler::code_generator::machine::{Base, Instruction, PhysicalRegister, Register};
+use compiler::code_generator::{self, Allocator, CodegenError, Syntax};
+use compiler::ir::interpreter::run;
+use compiler::ir::passes::Analyses;
+use compiler::ir::{self, dce, fold, gvn, sccp, ssa};
+
+mod common;
+use common::lower;
+
+fn optimize(program: &mut ir::Program) {
+    for function in &mut program.functions {
+        ssa::construct(function);
+        sccp::run(function, &mut Analyses::default());
+        gvn::run(function, &mut Analyses::default());
+        fold::run(function);
+        dce::run(function, &mut Analyses::default());
+    }
+}
+
+/// Assembles and links the program with gcc, runs it and returns its exit
+/// status and output.
+fn execute(program: &ir::Program, allocator: Allocator) -> (i32, String) {
+    execute_assembly(code_generator::generate(program, allocator, Syntax::Att).unwrap())
+}
+
+fn execute_assembly(assembly_text: String) -> (i32, String) {
+    execute_with_harness(assembly_text, None)
+}
+
+/// Like `execute_assembly`, linking in a C file compiled by gcc as well.
+fn execute_with_harness(assembly_text: String, harness: Option<&str>) -> (i32, String) {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let directory: PathBuf = std::env::temp_dir().join(format!(
+        "code_generator_test_{}_{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&directory).unwrap();
+    let (assembly, binary) = (directory.join("program.s"), directory.join("program"));
+
+    std::fs::write(&assembly, assembly_text).unwrap();
+    let mut gcc = Command::new("gcc");
+    gcc.arg(&assembly);
+    if let Some(harness) = harness {
+        let source = directory.join("harness.c");
+        std::fs::write(&source, harness).unwrap();
+        gcc.arg("-w").arg(&source);
+    }
+    let gcc = gcc.arg("-o").arg(&binary).output().unwrap();
+    assert!(
+        gcc.status.success(),
+        "{}",
+        String::from_utf8_lossy(&gcc.stderr)
+    );
+    let output = Command::new(&binary).output().unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+/// Checks that the compiled program behaves like the interpreted one, with
+/// and without optimization and with every allocator.
+fn check(source: &str) -> (i32, String) {
+    let program = lower(source);
+    let mut output = vec![];
+    let status = run(&program, &mut output).unwrap();
+    let expected = (status & 0xff, String::from_utf8(output).unwrap());
+
+    let mut optimized = program.clone();
+    optimize(&mut optimized);
+    for program in [&program, &optimized] {
+        for allocator in [
+            Allocator::Graph,
+            Allocator::Linear,
+            Allocator::SpillEverything,
+        ] {
+            assert_eq!(execute(program, allocator), expected, "{:?}", allocator);
+        }
+    }
+    expected
+}
+
+fn compile(
+    source: &str,
+    name: &str,
+    allocator: Allocator,
+) -> code_generator::machine::MachineFunction {
+    let mut program = lower(source);
+    optimize(&mut program);
+    let function = program
+        .functions
+        .iter()
+        .find(|function| function.name == name)
+        .unwrap();
+    code_generator::compile_function(function, allocator).unwrap()
+}
+
+#[test]
+fn test_arithmetic_and_control_flow() {
+    let source = "
+        int printf();
+        int collatz(long n) {
+            int steps = 0;
+            while (n != 1) {
+                n = n % 2 ? 3 * n + 1 : n / 2;
+                steps++;
+            }
+            return steps;
+        }
+        int classify(int n) {
+            switch (n % 4) {
+            case 0: return 10;
+            case 1:
+            case 2: n += 100;
+            default: return n;
+            }
+        }
+        int main(void) {
+            int total = 0;
+            for (int i = 1; i <= 10 && total < 1000; i++) {
+                if (i == 3) continue;
+                total += collatz(i);
+            }
+            unsigned u = 4000000000u;
+            long big = 1l << 40;
+            int negative = -17;
+            printf(\"%d %d %d %u %u\\n\", negative / 5, negative % 5, negative >> 2,
+                   u / 3, u >> 28);
+            printf(\"%ld %ld %d\\n\", big * 3 + 5000000000, -big / 7, (int)(big >> 38));
+            printf(\"%d %d %d\\n\", u > 5, negative < 3u, (negative ^ 0xff) & ~7);
+            return total + classify(8) + classify(5) + classify(3);
+        }
+    ";
+    assert_eq!(
+        check(source),
+        (
+            (60 + 10 + 105 + 3) & 0xff,
+            "-3 -2 -5 1333333333 14\n3303534883328 -157073089682 4\n1 0 -240\n".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_memory_and_narrow_types() {
+    let source = "
+        int printf();
+        struct Pair { char tag; long value; };
+        struct Pair pairs[3] = { { 'a', 1 }, [2] = { 'c', -3 } };
+        int *cursor;
+        char *name = \"pairs\";
+        struct Pair swap(struct Pair p) {
+            struct Pair q = p;
+            q.value = -p.value;
+            return q;
+        }
+        int apply(int (*f)(int), int x) { return f(x); }
+        int twice(int x) { return 2 * x; }
+        int main(void) {
+            int numbers[4] = { 5, 6, 7, 8 };
+            cursor = numbers + 1;
+            *cursor += 10;
+            unsigned char wrapped = 254;
+            wrapped += 257;
+            signed char small = -100;
+            short narrow = (short)70000;
+            struct Pair flipped = swap(pairs[2]);
+            printf(\"%s %c %d %d %d\\n\", name, pairs[0].tag, small / 3, small >> 1,
+                   (unsigned char)small > 100);
+            printf(\"%d %d\\n\", apply(twice, numbers[3]), sizeof(struct Pair));
+            return numbers[1] + wrapped + (narrow == 4464) + flipped.value + (pairs[1].tag == 0);
+        }
+    ";
+    assert_eq!(
+        check(source),
+        (
+            16 + 255 + 1 + 3 + 1 - 256,
+            "pairs a -33 -50 1\n16 16\n".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_conditional_structs() {
+    let source = "
+        int printf();
+        struct A { int x; char name[12]; };
+        struct A pick(int f, struct A a, struct A b) { return f ? a : b; }
+        int main(void) {
+            int f = 1;
+            struct A a = { 1, \"first\" }, b = { 2, \"second\" };
+            struct A c = f ? a : b;
+            a.x = 10;
+            struct A d = pick(0, a, b);
+            a = !f ? a : b;
+            printf(\"%d %s %d %s %d %s\\n\", c.x, c.name, d.x, d.name, a.x, a.name);
+            return (f ? c : d).x;
+        }
+    ";
+    assert_eq!(
+        check(source),
+        (1, "1 first 2 second 2 second\n".to_string())
+    );
+}
+
+#[test]
+fn test_register_pressure() {
+    let source = "
+        int printf();
+        long mix(long a, long b, long c, long d, long e, long f) {
+            long g = a * b, h = c * d, i = e * f, j = a + f, k = b + e, l = c + d;
+            long m = g - h, n = h - i, o = i - j, p = j - k, q = k - l, r = l - g;
+            long s = m * n + o, t = p * q + r, u = g + h + i + j + k + l;
+            long v = s ^ t, w = u % 1000, x = m + n + o + p + q + r;
+            for (int z = 0; z < 3; z++) {
+                a += g + h + i + j + k + l + m + n + o + p + q + r + s + t + u + v + w + x;
+                g = a % 97;
+            }
+            printf(\"%ld\\n\", a);
+            return a + b + c + d + e + f + g + h + i + j + k + l + m + n + o + p + q + r
+                + s + t + u + v + w + x;
+        }
+        int main(void) {
+            printf(\"%ld\\n\", mix(1, 2, 3, 4, 5, 6) % 1000);
+            return 0;
+        }
+    ";
+    check(source);
+
+    // More values are live in the loop than there are registers.
+    for allocator in [Allocator::Graph, Allocator::Linear] {
+        let function = compile(source, "mix", allocator);
+        assert!(!function.slots.is_empty());
+    }
+}
+
+#[test]
+fn test_many_live_values() {
+    // Hundreds of values live through a loop make an interference graph
+    // with tens of thousands of edges, most of whose nodes are spilled.
+    let (ints, doubles) = (200, 40);
+    let mut source = String::from("int printf();\nint f(int a, int b) {\n");
+    for i in 0..ints {
+        source += &format!("    int v{} = a * {} + b;\n", i, i);
+    }
+    for i in 0..doubles {
+        source += &format!("    double d{} = b - {};\n", i, i);
+    }
+    source += "    for (int it = 0; it < 3; it++) {\n";
+    for i in 0..600 {
+        let (x, y, z) = (i * 7 % ints, i * 13 % ints, i * 31 % ints);
+        if i % 5 == 0 {
+            let (p, q) = (i * 11 % doubles, i * 17 % doubles);
+            source += &format!("        d{} = d{} * 0.5 + v{};\n", p, q, x);
+        } else {
+            source += &format!("        v{} = (v{} ^ v{}) + {};\n", z, x, y, i);
+        }
+    }
+    source += "    }\n    int sum = 0;\n";
+    for i in 0..ints {
+        source += &format!("    sum += v{};\n", i);
+    }
+    for i in 0..doubles {
+        source += &format!("    sum += (int)d{};\n", i);
+    }
+    source += "    return sum & 255;\n}\n";
+    source += "int main(void) { printf(\"%d\\n\", f(3, 5)); return f(1, 2); }\n";
+    check(&source);
+}
+
+#[test]
+fn test_allocation() {
+    let source = "
+        int g(int x);
+        int f(int a, int b) {
+            int kept = a * b;
+            int result = g(a) + kept;
+            return result / b;
+        }
+    ";
+    for allocator in [Allocator::Graph, Allocator::Linear] {
+        let function = compile(source, "f", allocator);
+        let registers: Vec<Register> = function
+            .instructions
+            .iter()
+            .flat_map(|instruction| instruction.uses().into_iter().chain(instruction.defs()))
+            .collect();
+        assert!(
+            registers
+                .iter()
+                .all(|register| matches!(register, Register::Physical(_)))
+        );
+        // Values live across the call are kept in callee-saved registers,
+        // which the prologue saves, rather than in stack slots of their own.
+        assert!(registers.contains(&Register::Physical(PhysicalRegister::Rbx)));
+        let saves = function
+            .instructions
+            .iter()
+            .filter(|instruction| match instruction {
+                Instruction::Mov {
+                    dst: code_generator::machine::Operand::Memory(memory, _),
+                    ..
+                } => matches!(memory.base, Base::Register(_)),
+                _ => false,
+            })
+            .count();
+        assert_eq!(saves, 2, "{:?}", allocator);
+    }
+}
+
+#[test]
+fn test_tail_calls() {
+    let source = "
+        int printf();
+        long sum(long n, long total) {
+            if (n == 0) return total;
+            return sum(n - 1, total + n);
+        }
+        static int odd(int n);
+        static int even(int n) { if (n == 0) return 1; return odd(n - 1); }
+        static int odd(int n) { if (n == 0) return 0; return even(n - 1); }
+        int peek(int *p) { return *p; }
+        int local(int n) { int x = n; return peek(&x); }
+        int main(void) {
+            printf(\"%ld %d %d\\n\", sum(10000000, 0), odd(10000001), local(7));
+            return 0;
+        }
+    ";
+    // Ten million frames would overflow the stack.
+    let expected = (0, "50000005000000 1 7\n".to_string());
+    let mut optimized = lower(source);
+    optimize(&mut optimized);
+    for program in [lower(source), optimized] {
+        assert_eq!(execute(&program, Allocator::Graph), expected);
+        assert_eq!(execute(&program, Allocator::Linear), expected);
+    }
+
+    let tail_call = |function: &code_generator::machine::MachineFunction| {
+        function
+            .instructions
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::TailCall { .. }))
+    };
+    assert!(tail_call(&compile(source, "even", Allocator::Graph)));
+    assert!(tail_call(&compile(source, "odd", Allocator::Linear)));
+    // The address of `x` is passed down, so its frame must stay.
+    assert!(!tail_call(&compile(source, "local", Allocator::Graph)));
+}
+
+#[test]
+fn test_intel_syntax() {
+    let source = "
+        int printf();
+        long total = 40;
+        char bytes[3] = {1, 2, 3};
+        int f(int a, long b, char c) {
+            long x = a * 3 + b / 7 - c;
+            if (x > 5 && a != 0) x = -x;
+            return (int)(x + total + bytes[a % 3]);
+        }
+        int main(void) {
+            printf(\"%d %d\\n\", f(5, 100, 'a'), f(0, -8, 2));
+            return f(1, 2, 3) & 255;
+        }
+    ";
+    let mut program = lower(source);
+    let mut output = vec![];
+    let status = run(&program, &mut output).unwrap();
+    let expected = (status & 0xff, String::from_utf8(output).unwrap());
+    assert_eq!(
+        execute_assembly(
+            code_generator::generate(&program, Allocator::Graph, Syntax::Intel).unwrap()
+        ),
+        expected
+    );
+    optimize(&mut program);
+    let assembly = code_generator::generate(&program, Allocator::Linear, Syntax::Intel).unwrap();
+    assert!(assembly.starts_with("\t.intel_syntax noprefix\n"));
+    for line in [
+        "\tpush rbp\n",
+        "\tmov rbp, rsp\n",
+        "\tmov rax, QWORD PTR [rip + total]\n",
+        "\tcall printf@PLT\n",
+    ] {
+        assert!(assembly.contains(line), "{}", line);
+    }
+    assert!(!assembly.contains('%'));
+    assert_eq!(execute_assembly(assembly), expected);
+}
+
+#[test]
+fn test_floating_point() {
+    let source = "
+        int printf();
+        double scale(double x, float y, int n, double z) { return x * y + n - z / 4; }
+        float halve(float f) { return f / 2; }
+        int main(void) {
+            double zero = 0, one = 1, nan = zero / zero;
+            printf(\"%d %d %d \", nan == nan, nan != nan, nan < one);
+            printf(\"%d %d %d\\n\", nan >= one, one > zero, zero <= one);
+            if (nan) printf(\"nan\\n\");
+            double x = scale(3, halve(5), 2, -one);
+            unsigned long big = 18446744073709551615ul;
+            double wide = big;
+            unsigned long back = wide / 2 * 3 / 2;
+            unsigned u = 4000000000u;
+            float f = u;
+            signed char c = -x;
+            printf(\"%f %f %lu\\n\", x, wide, back);
+            printf(\"%f %u %d %ld\\n\", (double)f, (unsigned)(double)u, c, (long)-x);
+            return (int)(x * 4);
+        }
+    ";
+    assert_eq!(
+        check(source),
+        (
+            39,
+            "0 1 0 0 1 1\nnan\n9.750000 18446744073709551616.000000 13835058055282163712\n\
+             4000000000.000000 4000000000 -9 -9\n"
+                .to_string()
+        )
+    );
+
+    // Constants are loaded from read-only data, and the Intel syntax
+    // prints the widths of their memory operands.
+    let program = lower(source);
+    let assembly = code_generator::generate(&program, Allocator::Graph, Syntax::Att).unwrap();
+    assert!(assembly.contains("\t.section .rodata\n\t.balign 8\n.Lmain_c0:\n\t.quad "));
+    assert!(assembly.contains("ucomisd"));
+    let assembly = code_generator::generate(&program, Allocator::Linear, Syntax::Intel).unwrap();
+    assert!(assembly.contains("QWORD PTR [rip + .Lmain_c"));
+    assert!(!assembly.contains('%'));
+    assert_eq!(execute_assembly(assembly), check(source));
+}
+
+#[test]
+fn test_calling_convention() {
+    // Compiled by us, calling into and called from the harness.
+    let source = "
+        struct Pair { long a; long b; };
+        struct Mixed { double x; int y; };
+        struct Floats { float a; float b; float c; };
+        struct Odd { char a; char b; char c; };
+        struct Big { long a; long b; long c; };
+        int printf();
+        long weigh(long, long, long, long, long, long, long, long);
+        struct Mixed make_mixed(double, int);
+        struct Big make_big(long);
+        double sum(struct Mixed, struct Floats, struct Big);
+
+        double ours_doubles(double a, double b, double c, double d, double e,
+                            double f, double g, double h, double i, int j, double k) {
+            return a + b * 2 + c * 3 + d * 4 + e * 5 + f * 6 + g * 7 + h * 8 + i * 9
+                + j * 10 + k * 11;
+        }
+        struct Floats ours_floats(struct Floats f) {
+            f.a = f.a + 1;
+            f.c = f.c * 2;
+            return f;
+        }
+        struct Odd ours_odd(struct Odd o) {
+            struct Odd r;
+            r.a = o.c;
+            r.b = o.a;
+            r.c = o.b;
+            return r;
+        }
+        struct Big ours_big(int a, int b, int c, int d, int e, struct Pair p, int f,
+                            struct Pair q, struct Big big) {
+            big.a = big.a + a + b + c + d + e + f;
+            big.b = big.b * p.a + p.b;
+            big.c = big.c * q.a + q.b;
+            return big;
+        }
+        int ours(void) {
+            printf(\"%ld\\n\", weigh(1, 2, 3, 4, 5, 6, 7, 8));
+            struct Mixed m = make_mixed(0.5, 7);
+            struct Floats f;
+            f.a = 1;
+            f.b = 2;
+            f.c = .5f;
+            struct Big big = make_big(5);
+            printf(\"%f %d %f\\n\", m.x, m.y, sum(m, f, big));
+            printf(\"%d %f %ld %f %f %f %f %f %f %f %f %f\\n\", 1, 0.5, 3L, 1.5, .5, 5e-1,
+                   0.5f, 50E-2, 0.5L, 0.5, 0.5, 3.5);
+            return 0;
+        }
+    ";
+    let harness = r#"
+        #include <stdio.h>
+        struct Pair { long a; long b; };
+        struct Mixed { double x; int y; };
+        struct Floats { float a; float b; float c; };
+        struct Odd { char a; char b; char c; };
+        struct Big { long a; long b; long c; };
+        double ours_doubles(double, double, double, double, double, double, double,
+                            double, double, int, double);
+        struct Floats ours_floats(struct Floats);
+        struct Odd ours_odd(struct Odd);
+        struct Big ours_big(int, int, int, int, int, struct Pair, int, struct Pair,
+                            struct Big);
+        int ours(void);
+
+        long weigh(long a, long b, long c, long d, long e, long f, long g, long h) {
+            return a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f + 7 * g + 8 * h;
+        }
+        struct Mixed make_mixed(double x, int y) { struct Mixed m = {x, y}; return m; }
+        struct Big make_big(long k) { struct Big b = {k, k * 2, k * 3}; return b; }
+        double sum(struct Mixed m, struct Floats f, struct Big b) {
+            return m.x + m.y + f.a * 10 + f.b * 100 + f.c * 1000 + b.a + b.b + b.c;
+        }
+
+        int main(void) {
+            printf("%f\n", ours_doubles(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 0.5));
+            struct Floats f = ours_floats((struct Floats){1, 2, 3});
+            printf("%f %f %f\n", f.a, f.b, f.c);
+            struct Odd o = ours_odd((struct Odd){'x', 'y', 'z'});
+            printf("%c%c%c\n", o.a, o.b, o.c);
+            struct Pair p = {3, 4}, q = {5, 6};
+            struct Big b = ours_big(1, 2, 3, 4, 5, p, 6, q, (struct Big){100, 200, 300});
+            printf("%ld %ld %ld\n", b.a, b.b, b.c);
+            return ours();
+        }
+    "#;
+    let expected = (
+        0,
+        "390.500000\n2.000000 2.000000 6.000000\nzxy\n121 604 1506\n204\n\
+         0.500000 7 747.500000\n\
+         1 0.500000 3 1.500000 0.500000 0.500000 0.500000 0.500000 0.500000 0.500000 \
+         0.500000 3.500000\n"
+            .to_string(),
+    );
+    let mut program = lower(source);
+    for allocator in [Allocator::Graph, Allocator::Linear] {
+        let assembly = code_generator::generate(&program, allocator, Syntax::Att).unwrap();
+        assert_eq!(execute_with_harness(assembly, Some(harness)), expected);
+    }
+    optimize(&mut program);
+    let assembly = code_generator::generate(&program, Allocator::Graph, Syntax::Att).unwrap();
+    assert_eq!(execute_with_harness(assembly, Some(harness)), expected);
+}
+
+#[test]
+fn test_variadic_functions() {
+    let source = "
+        int printf();
+        int next(va_list ap) {
+            return va_arg(ap, int);
+        }
+        double sum(int count, ...) {
+            va_list ap;
+            va_start(ap, count);
+            double total = 0;
+            for (int i = 0; i < count; i++) {
+                int kind = next(ap);
+                if (kind == 0)
+                    total = total + va_arg(ap, int);
+                else if (kind == 1)
+                    total = total + va_arg(ap, double);
+                else
+                    total = total + *va_arg(ap, char *);
+            }
+            va_end(ap);
+            return total;
+        }
+        void show(char *format, ...) {
+            va_list ap, again;
+            va_start(ap, format);
+            va_copy(again, ap);
+            for (char *p = format; *p; p++) {
+                if (*p == 'd')
+                    printf(\"%d \", va_arg(ap, int));
+                else if (*p == 'f')
+                    printf(\"%f \", va_arg(ap, double));
+                else
+                    printf(\"%s \", va_arg(ap, char *));
+            }
+            printf(\"| %d\\n\", va_arg(again, int));
+            va_end(again);
+            va_end(ap);
+        }
+        int main(void) {
+            printf(\"%f\\n\", sum(3, 0, 5, 1, 0.5, 2, \"a\"));
+            printf(\"%f\\n\", sum(13, 0, 1, 1, 0.5, 0, 2, 1, 0.5, 0, 3, 1, 0.5, 0, 4, 1,
+                                  0.5, 1, 0.5, 1, 0.5, 1, 0.5, 1, 0.5, 2, \"A\"));
+            show(\"dfsdfdddddddfffffffff\", 1, 0.5, \"x\", 2, 0.5f, 3, 4, 5, 6, 7, 8, 9,
+                 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5);
+            return 0;
+        }
+    ";
+    let (status, output) = check(source);
+    assert_eq!(status, 0);
+    assert_eq!(
+        output,
+        "102.500000\n79.000000\n1 0.500000 x 2 0.500000 3 4 5 6 7 8 9 0.500000 0.500000 \
+         0.500000 0.500000 0.500000 0.500000 0.500000 0.500000 0.500000 | 1\n"
+    );
+
+    // Called from C, and handing the list on to the C library.
+    let source = "
+        int vprintf();
+        long total(int count, ...) {
+            va_list ap;
+            va_start(ap, count);
+            long sum = 0;
+            for (int i = 0; i < count; i++)
+                sum = sum + va_arg(ap, long);
+            va_end(ap);
+            return sum;
+        }
+        void say(char *format, ...) {
+            va_list ap;
+            va_start(ap, format);
+            vprintf(format, ap);
+            va_end(ap);
+        }
+    ";
+    let harness = r#"
+        #include <stdio.h>
+        long total(int count, ...);
+        void say(char *format, ...);
+        int main(void) {
+            printf("%ld\n", total(9, 1L, 2L, 3L, 4L, 5L, 6L, 7L, 8L, 1000000000000L));
+            say("%s %d %f %d %d %d %d %d %f\n", "c", 3, 2.5, 4, 5, 6, 7, 8, 0.25);
+            return 0;
+        }
+    "#;
+    let expected = (
+        0,
+        "1000000000036\nc 3 2.500000 4 5 6 7 8 0.250000\n".to_string(),
+    );
+    let mut program = lower(source);
+    let assembly = code_generator::generate(&program, Allocator::Linear, Syntax::Att).unwrap();
+    assert_eq!(execute_with_harness(assembly, Some(harness)), expected);
+    optimize(&mut program);
+    let assembly = code_generator::generate(&program, Allocator::Graph, Syntax::Att).unwrap();
+    assert_eq!(execute_with_harness(assembly, Some(harness)), expected);
+}
+
+#[test]
+fn test_unsupported() {
+    let program = ir::parser::parse("function f64 @f() { ret f64 @f }").unwrap();
+    assert!(matches!(
+        code_generator::generate(&program, Allocator::Graph, Syntax::Att),
+        Err(CodegenError::Unsupported(_))
+    ));
+}
@@ -0,0 +1,49 @@
+// Each test crate uses its own subset of these helpers.
+#![allow(dead_code)]
+
+use compiler::ir::interpreter::run;
+use compiler::ir::passes::Analyses;
+use compiler::ir::verify::verify;
+use compiler::ir::{self, Instr, dce, ssa};
+use compiler::{lexer, parser, semantic_checker};
+
+/// Lexes, parses, checks and lowers a C program to IR.
+pub fn lower(source: &str) -> ir::Program {
+    let tokens = lexer::tokenize(source.to_string(), false).unwrap();
+    let program = parser::parse(tokens).unwrap();
+    semantic_checker::check(&program).unwrap();
+    ir::lower::lower(&program)
+}
+
+/// How many instructions of the function match the predicate.
+pub fn count(function: &ir::Function, predicate: impl Fn(&Instr) -> bool) -> usize {
+    function
+        .body
+        .iter()
+        .filter(|instr| predicate(instr))
+        .count()
+}
+
+/// Lowers the program to SSA form and runs `pass` on every function after
+/// dead code elimination, checking that each still verifies and that the
+/// program still prints and returns the same. Returns the first function
+/// and what `pass` returned for it.
+pub fn optimize<T>(
+    source: &str,
+    mut pass: impl FnMut(&mut ir::Function, &mut Analyses) -> T,
+) -> (ir::Function, T) {
+    let mut program = lower(source);
+    let mut expected = vec![];
+    let status = run(&program, &mut expected).unwrap();
+    let mut results = vec![];
+    for function in &mut program.functions {
+        ssa::construct(function);
+        dce::run(function, &mut Analyses::default());
+        results.push(pass(function, &mut Analyses::default()));
+        assert_eq!(verify(function), Ok(()));
+    }
+    let mut output = vec![];
+    assert_eq!(run(&program, &mut output), Ok(status));
+    assert_eq!(output, expected);
+    (program.functions.remove(0), results.remove(0))
+}
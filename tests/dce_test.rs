@@ -0,0 +1,186 @@
+use compiler::ir::cfg::Cfg;
+use compiler::ir::interpreter::run;
+use compiler::ir::passes::Analyses;
+use compiler::ir::verify::verify;
+use compiler::ir::{self, Instr, Value, dce, fold, parser, ssa};
+
+mod common;
+use common::{count, lower};
+
+#[test]
+fn test_removes_dead_code_after_return() {
+    let mut program = lower(
+        "
+        int f(int x) {
+            int unused = x * 2;
+            return x;
+            x = 5;
+            return unused;
+        }
+        ",
+    );
+    let function = &mut program.functions[0];
+    assert!(dce::run(function, &mut Analyses::default()));
+    assert_eq!(Cfg::build(function).blocks.len(), 1);
+    // `unused` is never read, so its slot and the multiplication go.
+    assert_eq!(
+        count(function, |instr| matches!(instr, Instr::Alloca { .. })),
+        1
+    );
+    assert_eq!(
+        count(function, |instr| matches!(instr, Instr::Binary { .. })),
+        0
+    );
+    assert!(!dce::run(function, &mut Analyses::default()));
+}
+
+#[test]
+fn test_simplifies_constant_branches() {
+    let mut program = lower(
+        "
+        int f(int x) {
+            if (2 * 3 > 4) {
+                x = x + 1;
+            } else {
+                x = x - 1;
+            }
+            while (0) {
+                x = 100;
+            }
+            return x;
+        }
+        ",
+    );
+    let function = &mut program.functions[0];
+    ssa::construct(function);
+    fold::run(function);
+    dce::run(function, &mut Analyses::default());
+    assert_eq!(verify(function), Ok(()));
+
+    // Only the `then` branch is left, in a single block.
+    assert_eq!(Cfg::build(function).blocks.len(), 1);
+    let arithmetic: Vec<String> = function
+        .body
+        .iter()
+        .filter(|instr| matches!(instr, Instr::Binary { .. }))
+        .map(|instr| instr.to_string().trim().to_string())
+        .collect();
+    assert_eq!(arithmetic, ["%5 = add i32 %0, 1"]);
+}
+
+#[test]
+fn test_removes_dead_cycles() {
+    let mut program = lower(
+        "
+        int f(int n) {
+            int sum = 0;
+            for (int i = 0; i < n; i++) {
+                sum += i;
+            }
+            return n;
+        }
+        ",
+    );
+    let function = &mut program.functions[0];
+    ssa::construct(function);
+    dce::run(function, &mut Analyses::default());
+    assert_eq!(verify(function), Ok(()));
+
+    // Only the loop counter survives; `sum` fed nothing but itself.
+    assert_eq!(
+        count(function, |instr| matches!(instr, Instr::Phi { .. })),
+        1
+    );
+    assert_eq!(
+        count(function, |instr| matches!(instr, Instr::Binary { .. })),
+        1
+    );
+}
+
+#[test]
+fn test_threads_empty_blocks_into_phis() {
+    let mut function = parser::parse(
+        "
+        function i32 @f(i32 %0) {
+        L0:
+          br i32 %0, L1, L2
+        L1:
+          jmp L3
+        L2:
+          jmp L3
+        L3:
+          %1 = phi i32 [1, L1], [2, L2]
+          ret i32 %1
+        }
+        ",
+    )
+    .unwrap()
+    .functions
+    .remove(0);
+    dce::run(&mut function, &mut Analyses::default());
+    assert_eq!(verify(&function), Ok(()));
+
+    // The first empty block is bypassed; the second has to stay, because
+    // the branch would otherwise need two inputs to the same phi.
+    let cfg = Cfg::build(&function);
+    assert_eq!(cfg.blocks.len(), 3);
+    let Instr::Phi { incoming, .. } = &cfg.blocks[2].instrs[0] else {
+        panic!("the phi was removed");
+    };
+    assert!(incoming.contains(&(ir::Label(0), Value::Int(1))));
+}
+
+#[test]
+fn test_preserves_behavior() {
+    let source = "
+        int printf();
+        int collatz(int n) {
+            int steps = 0;
+            int wasted = 0;
+            while (n != 1) {
+                wasted = wasted * 3 + n;
+                if (n % 2) n = 3 * n + 1; else n = n / 2;
+                steps++;
+                continue;
+                steps = 1000;
+            }
+            return steps;
+        }
+        int main(void) {
+            int total = 0;
+            for (int i = 1; i < 20; i++) {
+                if (1) total += collatz(i);
+                else total -= 1;
+            }
+            printf(\"%d\\n\", total);
+            switch (total) {
+            case 0: return 1;
+            default: break;
+            }
+            return total % 256;
+        }
+    ";
+    let program = lower(source);
+    let mut expected = vec![];
+    let status = run(&program, &mut expected).unwrap();
+
+    for ssa_form in [false, true] {
+        let mut optimized = program.clone();
+        for function in &mut optimized.functions {
+            if ssa_form {
+                ssa::construct(function);
+            }
+            fold::run(function);
+            dce::run(function, &mut Analyses::default());
+            if ssa_form {
+                ssa::destruct(function);
+                dce::run(function, &mut Analyses::default());
+            }
+        }
+        let mut output = vec![];
+        assert_eq!(run(&optimized, &mut output), Ok(status));
+        assert_eq!(output, expected);
+    }
+
+    assert_eq!(String::from_utf8(expected).unwrap(), "189\n");
+}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Each tests/ir/<name>.ir starts with a `; ARGS:` line giving the driver
+/// flags; the IR it prints must match tests/ir/<name>.expected. Inputs
+/// with a tests/ir/<name>.error file instead must fail with that message.
+#[test]
+fn test_ir_files() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ir");
+    let mut inputs: Vec<_> = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ir"))
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty());
+
+    for input in inputs {
+        let contents = fs::read_to_string(&input).unwrap();
+        let args = contents
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("; ARGS:"))
+            .unwrap_or_else(|| panic!("{} has no ARGS line", input.display()));
+        let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+            .args(args.split_whitespace())
+            .arg("-c")
+            .arg(&input)
+            .output()
+            .unwrap();
+        if let Ok(error) = fs::read_to_string(input.with_extension("error")) {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(!output.status.success(), "{}", input.display());
+            assert!(
+                stderr.contains(error.trim()),
+                "{}: {}",
+                input.display(),
+                stderr
+            );
+            continue;
+        }
+        assert!(
+            output.status.success(),
+            "{}: {}",
+            input.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let expected = fs::read_to_string(input.with_extension("expected")).unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            expected,
+            "{}",
+            input.display()
+        );
+    }
+}
+
+#[test]
+fn test_unknown_optimization_level() {
+    let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ir/licm_loop.ir");
+    for level in ["-O3", "-O02", "-Ofast"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+            .args([level, "--emit=ir", "-c"])
+            .arg(&input)
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{}", level);
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("Unknown optimization level"),
+            "{}",
+            level
+        );
+    }
+}
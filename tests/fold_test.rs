@@ -0,0 +1,214 @@
+use compiler::ir::interpreter::run;
+use compiler::ir::{self, Instr, IrType, Value, fold, parser, ssa};
+
+mod common;
+use common::lower;
+
+/// Folds a function given as IR text and returns its body.
+fn fold_function(source: &str) -> Vec<Instr> {
+    let mut function = parser::parse(source).unwrap().functions.remove(0);
+    fold::run(&mut function);
+    function.body
+}
+
+fn returned(body: &[Instr]) -> Option<&Value> {
+    body.iter().find_map(|instr| match instr {
+        Instr::Return(Some((_, value))) => Some(value),
+        _ => None,
+    })
+}
+
+#[test]
+fn test_folds_to_a_constant() {
+    let mut program = lower("int f(int x) { return 2 * 3 + x * 0; }");
+    let function = &mut program.functions[0];
+    assert!(fold::run(function));
+    assert_eq!(returned(&function.body), Some(&Value::Int(6)));
+    assert!(!fold::run(function));
+}
+
+#[test]
+fn test_integer_widths_and_wraparound() {
+    let body = fold_function(
+        "
+        function i32 @f() {
+          %0 = add i8 127, 1
+          %1 = mul i32 65536, 65536
+          %2 = udiv i32 -1, 2
+          %3 = lshr i16 -1, 12
+          %4 = ashr i16 -32768, 15
+          %5 = sext i8 200 to i64
+          %6 = zext i8 -1 to i32
+          %7 = trunc i32 70000 to i16
+          %8 = cmp ult i32 -1, 0
+          %9 = cmp slt i32 -1, 0
+          %10 = neg i64 -9223372036854775808
+          %11 = srem i32 -7, 2
+          ret i32 0
+        }
+        ",
+    );
+    let values: Vec<_> = body
+        .iter()
+        .filter_map(|instr| match instr {
+            Instr::Copy { src, .. } => Some(src.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        values,
+        [
+            -128,
+            0,
+            i32::MAX as i64,
+            15,
+            -1,
+            -56,
+            255,
+            4464,
+            0,
+            1,
+            i64::MIN,
+            -1
+        ]
+        .map(Value::Int)
+    );
+}
+
+#[test]
+fn test_float_semantics() {
+    let body = fold_function(
+        "
+        function i32 @f(f64 %9) {
+          %0 = fadd f32 0.1, 0.2
+          %1 = fdiv f64 1.0, 0.0
+          %2 = fsub f64 inf, inf
+          %3 = cmp feq f64 %2, %2
+          %4 = cmp fne f64 %2, %2
+          %5 = fptosi f64 -2.75 to i32
+          %6 = sitofp i64 9007199254740993 to f64
+          %7 = fptrunc f64 0.1 to f32
+          %8 = fadd f64 %9, 0.0
+          %10 = fadd f64 %9, -0.0
+          ret i32 0
+        }
+        ",
+    );
+    let value = |index: usize| match &body[index] {
+        Instr::Copy { src, .. } => src.clone(),
+        instr => panic!("{} was not folded", instr),
+    };
+    assert_eq!(value(0), Value::Float((0.1f32 + 0.2f32) as f64));
+    assert_eq!(value(1), Value::Float(f64::INFINITY));
+    assert!(matches!(value(2), Value::Float(nan) if nan.is_nan()));
+    assert_eq!(value(3), Value::Int(0));
+    assert_eq!(value(4), Value::Int(1));
+    assert_eq!(value(5), Value::Int(-2));
+    assert_eq!(value(6), Value::Float(9007199254740992.0));
+    assert_eq!(value(7), Value::Float(0.1f32 as f64));
+    // Adding 0.0 would turn -0.0 into 0.0, so only -0.0 is an identity.
+    assert!(matches!(body[8], Instr::Binary { .. }));
+    assert_eq!(value(9), Value::Temp(ir::Temp(9)));
+}
+
+#[test]
+fn test_identities() {
+    let body = fold_function(
+        "
+        function i32 @f(i32 %0, i32 %1) {
+          %2 = mul i32 %0, 1
+          %3 = add i32 0, %1
+          %4 = sub i32 %0, %0
+          %5 = xor i32 %1, %1
+          %6 = and i32 %0, -1
+          %7 = or i32 %1, -1
+          %8 = cmp sle i32 %0, %0
+          %9 = mul i32 %2, %3
+          ret i32 %9
+        }
+        ",
+    );
+    assert_eq!(
+        body[7],
+        Instr::Binary {
+            dst: ir::Temp(9),
+            op: ir::BinaryOp::Mul,
+            ty: IrType::I32,
+            left: Value::Temp(ir::Temp(0)),
+            right: Value::Temp(ir::Temp(1)),
+        }
+    );
+    let folded: Vec<_> = body[..7]
+        .iter()
+        .map(|instr| match instr {
+            Instr::Copy { src, .. } => src.clone(),
+            instr => panic!("{} was not folded", instr),
+        })
+        .collect();
+    assert_eq!(
+        folded,
+        [
+            Value::Temp(ir::Temp(0)),
+            Value::Temp(ir::Temp(1)),
+            Value::Int(0),
+            Value::Int(0),
+            Value::Temp(ir::Temp(0)),
+            Value::Int(-1),
+            Value::Int(1),
+        ]
+    );
+}
+
+#[test]
+fn test_leaves_undefined_operations() {
+    let source = "
+        function i32 @f() {
+          %0 = sdiv i32 1, 0
+          %1 = sdiv i32 -2147483648, -1
+          %2 = urem i64 5, 0
+          %3 = shl i32 1, 32
+          %4 = fptosi f64 3e10 to i32
+          %5 = fptoui f64 -1.0 to i64
+          %6 = fptosi f64 NaN to i32
+          ret i32 0
+        }
+    ";
+    // Compared as text, since NaN is not equal to itself.
+    let body = fold_function(source);
+    assert_eq!(
+        format!("{:?}", body),
+        format!("{:?}", parser::parse(source).unwrap().functions[0].body)
+    );
+}
+
+#[test]
+fn test_preserves_behavior() {
+    let source = "
+        int printf();
+        int main(void) {
+            unsigned char byte = 250;
+            int shifted = (1 << 30) * 4;
+            long wide = 2147483647 + 1l;
+            unsigned small = -1;
+            double half = 5 / (double)2;
+            int check = (short)(32767 + 1) == -32768;
+            byte += 10;
+            printf(\"%d %d %ld %u %d %d\\n\", byte, shifted, wide, small / 3, (int)(half * 4), check);
+            return byte * 1 + 0;
+        }
+    ";
+    let mut program = lower(source);
+    let mut expected = vec![];
+    let status = run(&program, &mut expected).unwrap();
+    for function in &mut program.functions {
+        ssa::construct(function);
+        fold::run(function);
+    }
+    let mut output = vec![];
+    assert_eq!(run(&program, &mut output), Ok(status));
+    assert_eq!(output, expected);
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "4 0 2147483648 1431655765 10 1\n"
+    );
+}
@@ -0,0 +1,208 @@
+use compiler::ir::interpreter::run;
+use compiler::ir::passes::Analyses;
+use compiler::ir::verify::verify;
+use compiler::ir::{self, BinaryOp, Instr, Value, dce, gvn, parser, ssa};
+
+mod common;
+use common::{count, lower};
+
+/// Lowers the named function to SSA form and numbers its values.
+fn number(source: &str, name: &str) -> ir::Function {
+    let mut program = lower(source);
+    let index = program
+        .functions
+        .iter()
+        .position(|function| function.name == name)
+        .unwrap();
+    let mut function = program.functions.remove(index);
+    ssa::construct(&mut function);
+    gvn::run(&mut function, &mut Analyses::default());
+    assert_eq!(verify(&function), Ok(()));
+    function
+}
+
+#[test]
+fn test_removes_redundant_expressions() {
+    let function = number(
+        "
+        int f(int a, int b, int c) {
+            int x = a * b;
+            if (c)
+                return b * a + x;
+            return a * b - x;
+        }
+        ",
+        "f",
+    );
+    let multiplications = |function: &ir::Function| {
+        count(function, |instr| {
+            matches!(
+                instr,
+                Instr::Binary {
+                    op: BinaryOp::Mul,
+                    ..
+                }
+            )
+        })
+    };
+    assert_eq!(multiplications(&function), 1);
+
+    // Neither branch dominates the other, so both products stay.
+    let function = number(
+        "
+        int g(int a, int b, int c) {
+            int x;
+            if (c) x = a * b; else x = a * b + 1;
+            return x;
+        }
+        ",
+        "g",
+    );
+    assert_eq!(multiplications(&function), 2);
+}
+
+#[test]
+fn test_shares_address_arithmetic() {
+    let function = number(
+        "
+        long f(long *values, int i) {
+            values[i] = values[i] + values[i + 1];
+            return values[i];
+        }
+        ",
+        "f",
+    );
+    // `&values[i]` is computed once for all three accesses, so only it,
+    // `i + 1`, `&values[i + 1]` and the sum remain.
+    assert_eq!(
+        count(&function, |instr| matches!(
+            instr,
+            Instr::Binary {
+                op: BinaryOp::Add,
+                ..
+            }
+        )),
+        4
+    );
+    // The final load reads back the stored sum.
+    assert_eq!(
+        count(&function, |instr| matches!(instr, Instr::Load { .. })),
+        2
+    );
+}
+
+#[test]
+fn test_respects_memory_and_volatile() {
+    let function = number(
+        "
+        void touch(void);
+        int f(int *p, volatile int *v) {
+            int a = *p;
+            int b = *p;
+            *p = 5;
+            int c = *p;
+            touch();
+            int d = *p;
+            return a + b + c + d + *v + *v;
+        }
+        ",
+        "f",
+    );
+    // `b` reuses `a`, `c` is the stored 5 and `d` must be reloaded after the
+    // call; both volatile loads stay.
+    let loads: Vec<bool> = function
+        .body
+        .iter()
+        .filter_map(|instr| match instr {
+            Instr::Load { volatile, .. } => Some(*volatile),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(loads, [false, false, true, true]);
+    assert!(function.body.iter().any(|instr| matches!(
+        instr,
+        Instr::Binary {
+            right: Value::Int(5),
+            ..
+        }
+    )));
+}
+
+#[test]
+fn test_propagates_copies() {
+    let mut function = parser::parse(
+        "
+        function i32 @f(i32 %0) {
+        L0:
+          %1 = copy i32 %0
+          %2 = copy i32 %1
+          jmp L1
+        L1:
+          %3 = phi i32 [%2, L0], [%3, L1]
+          %4 = add i32 %3, %1
+          %5 = cmp slt i32 %4, 10
+          br i32 %5, L1, L2
+        L2:
+          ret i32 %4
+        }
+        ",
+    )
+    .unwrap()
+    .functions
+    .remove(0);
+    assert!(gvn::run(&mut function, &mut Analyses::default()));
+    assert_eq!(verify(&function), Ok(()));
+    assert_eq!(
+        count(&function, |instr| matches!(
+            instr,
+            Instr::Copy { .. } | Instr::Phi { .. }
+        )),
+        0
+    );
+    assert!(function.body.contains(&Instr::Binary {
+        dst: ir::Temp(4),
+        op: BinaryOp::Add,
+        ty: ir::IrType::I32,
+        left: Value::Temp(ir::Temp(0)),
+        right: Value::Temp(ir::Temp(0)),
+    }));
+}
+
+#[test]
+fn test_preserves_behavior() {
+    let source = "
+        int printf();
+        struct Point { int x; int y; };
+        int grid[4][4];
+        int walk(struct Point *p, int steps) {
+            int total = 0;
+            for (int i = 0; i < steps; i++) {
+                grid[p->x % 4][p->y % 4] += i;
+                total += grid[p->x % 4][p->y % 4] * (p->x + p->y) + (p->y + p->x);
+                p->x = p->x + 1;
+                if (i % 3 == 0) p->y = p->y + 2;
+            }
+            return total;
+        }
+        int main(void) {
+            struct Point p = { 1, 2 };
+            int result = walk(&p, 10);
+            printf(\"%d %d %d\\n\", result, p.x, p.y);
+            return result % 100;
+        }
+    ";
+    let program = lower(source);
+    let mut expected = vec![];
+    let status = run(&program, &mut expected).unwrap();
+
+    let mut optimized = program.clone();
+    for function in &mut optimized.functions {
+        ssa::construct(function);
+        gvn::run(function, &mut Analyses::default());
+        dce::run(function, &mut Analyses::default());
+        assert_eq!(verify(function), Ok(()));
+    }
+    let mut output = vec![];
+    assert_eq!(run(&optimized, &mut output), Ok(status));
+    assert_eq!(output, expected);
+}
@@ -0,0 +1,186 @@
+use compiler::ir::inline::{run, strongly_connected_components};
+use compiler::ir::passes::{OptLevel, PassManager};
+use compiler::ir::verify::verify;
+use compiler::ir::{self, Inlining, Instr, Value, interpreter, ssa};
+
+mod common;
+use common::lower;
+
+fn names(program: &ir::Program) -> Vec<&str> {
+    program
+        .functions
+        .iter()
+        .map(|function| function.name.as_str())
+        .collect()
+}
+
+fn calls<'a>(program: &'a ir::Program, name: &str) -> Vec<&'a str> {
+    let function = program
+        .functions
+        .iter()
+        .find(|function| function.name == name)
+        .unwrap();
+    function
+        .body
+        .iter()
+        .filter_map(|instr| match instr {
+            Instr::Call {
+                callee: Value::Global(callee),
+                ..
+            } => Some(callee.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Inlines with the given threshold, in and out of SSA form, checking that
+/// the program still prints and returns the same. Returns the program
+/// inlined in SSA form.
+fn inline(source: &str, threshold: usize) -> ir::Program {
+    let program = lower(source);
+    let mut expected = vec![];
+    let status = interpreter::run(&program, &mut expected).unwrap();
+
+    let mut inlined = program.clone();
+    run(&mut inlined, threshold);
+    let mut output = vec![];
+    assert_eq!(interpreter::run(&inlined, &mut output), Ok(status));
+    assert_eq!(output, expected);
+
+    let mut inlined = program;
+    for function in &mut inlined.functions {
+        ssa::construct(function);
+    }
+    run(&mut inlined, threshold);
+    for function in &inlined.functions {
+        assert_eq!(verify(function), Ok(()));
+    }
+    let mut output = vec![];
+    assert_eq!(interpreter::run(&inlined, &mut output), Ok(status));
+    assert_eq!(output, expected);
+    inlined
+}
+
+const SOURCE: &str = "
+    int printf();
+    static int square(int x) { return x * x; }
+    static int clamp(int x, int lo, int hi) {
+        if (x < lo) return lo;
+        if (x > hi) return hi;
+        return x;
+    }
+    int sum(int *values, int n) {
+        int total = 0;
+        for (int i = 0; i < n; i++) total += clamp(square(values[i]), 2, 40);
+        return total;
+    }
+    int main(void) {
+        int values[4] = {1, 3, 5, 7};
+        printf(\"%d\\n\", sum(values, 4));
+        return square(3);
+    }
+";
+
+#[test]
+fn test_inlines_small_functions() {
+    let program = inline(SOURCE, 40);
+    // The static functions are gone once every call has been inlined.
+    assert_eq!(names(&program), ["sum", "main"]);
+    assert!(calls(&program, "sum").is_empty());
+    assert_eq!(calls(&program, "main"), ["printf"]);
+
+    // With no room at all only the function called once is inlined, and
+    // functions still called are kept.
+    let program = inline(SOURCE, 0);
+    assert_eq!(names(&program), ["square", "sum", "main"]);
+    assert_eq!(calls(&program, "main"), ["sum", "printf", "square"]);
+}
+
+#[test]
+fn test_attributes() {
+    let source = "
+        inline int twice(int x) { return x * x + x; }
+        __attribute__((noinline)) int opaque(int x) { return x ^ 1; }
+        static int __attribute__((__always_inline__, unused)) add(int a, int b);
+        static int add(int a, int b) {
+            int total = a;
+            for (int i = 0; i < b; i++) total++;
+            return total;
+        }
+        int main(void) { return opaque(add(twice(3), 4)) + add(1, 2); }
+    ";
+    let program = lower(source);
+    let inlining: Vec<Inlining> = program
+        .functions
+        .iter()
+        .map(|function| function.inlining)
+        .collect();
+    assert_eq!(
+        inlining,
+        [
+            Inlining::Hint,
+            Inlining::Never,
+            Inlining::Always,
+            Inlining::Default
+        ]
+    );
+    // The attributes survive the textual form.
+    let text = program.to_string();
+    assert_eq!(ir::parser::parse(&text).unwrap(), program);
+
+    // `inline` doubles the threshold, `always_inline` ignores it.
+    let program = inline(source, 2);
+    assert_eq!(names(&program), ["twice", "opaque", "main"]);
+    assert_eq!(calls(&program, "main"), ["opaque"]);
+    let program = inline(source, 1);
+    assert_eq!(names(&program), ["twice", "opaque", "main"]);
+    assert_eq!(calls(&program, "main"), ["twice", "opaque"]);
+}
+
+#[test]
+fn test_recursion() {
+    let source = "
+        static int fact(int n) { return n <= 1 ? 1 : n * fact(n - 1); }
+        static int odd(int n);
+        static int even(int n) { return n == 0 ? 1 : odd(n - 1); }
+        static int odd(int n) { return n == 0 ? 0 : even(n - 1); }
+        static int leaf(int n) { return n + 1; }
+        static int twice(int n) { return leaf(n) + leaf(n); }
+        int main(void) { return fact(5) + even(6) + twice(2); }
+    ";
+    // Callees come before their callers, and a cycle is one component.
+    let graph = vec![vec![0], vec![2], vec![1], vec![], vec![3, 3], vec![0, 2, 4]];
+    assert_eq!(
+        strongly_connected_components(&graph),
+        [vec![0], vec![2, 1], vec![3], vec![4], vec![5]]
+    );
+
+    let program = inline(source, 1000);
+    assert_eq!(names(&program), ["fact", "even", "odd", "main"]);
+    assert_eq!(calls(&program, "fact"), ["fact"]);
+    assert_eq!(calls(&program, "main"), ["fact", "even"]);
+}
+
+#[test]
+fn test_pipelines_inline() {
+    let source = "
+        static int mix(int a, int b) {
+            int x = a * 31 + b;
+            x ^= x >> 7;
+            x *= 17;
+            x += a - b;
+            x ^= x << 3;
+            return x & 65535;
+        }
+        int main(void) { return mix(1, 2) + mix(3, 4) + 6 * 7; }
+    ";
+    let mut program = lower(source);
+    PassManager::for_level(OptLevel::O2).run(&mut program, &mut vec![]);
+    assert_eq!(names(&program), ["main"]);
+
+    // At -Os only callees about the size of a call are inlined, besides
+    // those called once.
+    let mut program = lower(source);
+    PassManager::for_level(OptLevel::Os).run(&mut program, &mut vec![]);
+    assert_eq!(names(&program), ["mix", "main"]);
+}
@@ -0,0 +1,129 @@
+use compiler::ir::interpreter::{RuntimeError, run};
+use compiler::ir::{Instr, Label, ssa};
+
+mod common;
+use common::lower;
+
+/// Runs the program as lowered and again in SSA form, checking that both
+/// agree, and returns the exit status and output.
+fn execute(source: &str) -> Result<(i32, String), RuntimeError> {
+    let mut program = lower(source);
+    let mut output = vec![];
+    let status = run(&program, &mut output)?;
+
+    for function in &mut program.functions {
+        ssa::construct(function);
+    }
+    let mut ssa_output = vec![];
+    assert_eq!(run(&program, &mut ssa_output), Ok(status));
+    assert_eq!(output, ssa_output);
+
+    Ok((status, String::from_utf8(output).unwrap()))
+}
+
+#[test]
+fn test_control_flow_and_calls() {
+    let source = "
+        int collatz(long n) {
+            int steps = 0;
+            while (n != 1) {
+                n = n % 2 ? 3 * n + 1 : n / 2;
+                steps++;
+            }
+            return steps;
+        }
+        int classify(int n) {
+            switch (n % 4) {
+            case 0: return 10;
+            case 1:
+            case 2: n += 100;
+            default: return n;
+            }
+        }
+        int main(void) {
+            int total = 0;
+            for (int i = 1; i <= 10 && total < 1000; i++) {
+                if (i == 3) continue;
+                total += collatz(i);
+            }
+            return total + classify(8) + classify(5) + classify(3);
+        }
+    ";
+    assert_eq!(execute(source), Ok((60 + 10 + 105 + 3, String::new())));
+}
+
+#[test]
+fn test_memory_and_conversions() {
+    let source = "
+        struct Pair { char tag; long value; };
+        struct Pair pairs[3] = { { 'a', 1 }, [2] = { 'c', -3 } };
+        int *cursor;
+        struct Pair swap(struct Pair p) {
+            struct Pair q = p;
+            q.value = -p.value;
+            return q;
+        }
+        int main(void) {
+            int numbers[4] = { 5, 6, 7, 8 };
+            cursor = numbers + 1;
+            *cursor += 10;
+            unsigned char wrapped = 254;
+            wrapped += 257;
+            short narrow = (short)70000;
+            struct Pair flipped = swap(pairs[2]);
+            return numbers[1] + wrapped + (narrow == 4464) + flipped.value + (pairs[1].tag == 0);
+        }
+    ";
+    assert_eq!(execute(source), Ok((16 + 255 + 1 + 3 + 1, String::new())));
+}
+
+#[test]
+fn test_builtin_output() {
+    let source = "
+        int printf();
+        int putchar(int c);
+        int main(void) {
+            char *word = \"hey\";
+            for (int i = 0; word[i]; i++) putchar(word[i] - 32);
+            printf(\" %d|%-4s|%05d|%x|%ld\\n\", -12, \"ab\", -42, 255, 1l << 40);
+            return 0;
+        }
+    ";
+    assert_eq!(
+        execute(source),
+        Ok((0, "HEY -12|ab  |-0042|ff|1099511627776\n".to_string()))
+    );
+}
+
+#[test]
+fn test_runtime_errors() {
+    let mut output = vec![];
+    let divide = lower("int main(void) { int zero = 0; return 1 / zero; }");
+    assert!(matches!(
+        run(&divide, &mut output),
+        Err(RuntimeError::DivisionByZero(_))
+    ));
+
+    let null = lower("int main(void) { int *p = 0; return *p; }");
+    assert!(matches!(
+        run(&null, &mut output),
+        Err(RuntimeError::InvalidAccess(_))
+    ));
+
+    let recursion = lower("int f(int n) { return f(n + 1); } int main(void) { return f(0); }");
+    assert_eq!(
+        run(&recursion, &mut output),
+        Err(RuntimeError::StackOverflow)
+    );
+
+    // IR from the text parser cannot name a missing label, but a pass could.
+    let mut jump = lower("int main(void) { return 0; }");
+    jump.functions[0].body.insert(0, Instr::Jump(Label(99)));
+    assert!(matches!(
+        run(&jump, &mut output),
+        Err(RuntimeError::InvalidInstruction(_))
+    ));
+
+    let exit = lower("void exit(int status); int main(void) { exit(3); return 0; }");
+    assert_eq!(run(&exit, &mut output), Ok(3));
+}
@@ -0,0 +1,234 @@
+use compiler::ir::{self, BinaryOp, ConvertOp, GlobalInit, Instr, IrType};
+
+mod common;
+use common::lower;
+
+fn function<'a>(program: &'a ir::Program, name: &str) -> &'a ir::Function {
+    program
+        .functions
+        .iter()
+        .find(|function| function.name == name)
+        .unwrap()
+}
+
+#[test]
+fn test_logical_operators_short_circuit() {
+    let program = lower("int f(int a, int b) { return a && b || !a; }");
+    let body = &function(&program, "f").body;
+
+    let branches = body
+        .iter()
+        .filter(|instr| matches!(instr, Instr::Branch { .. }))
+        .count();
+    assert_eq!(branches, 3);
+    assert!(!body.iter().any(|instr| matches!(
+        instr,
+        Instr::Binary {
+            op: BinaryOp::And | BinaryOp::Or,
+            ..
+        }
+    )));
+}
+
+#[test]
+fn test_loops_and_conversions() {
+    let program = lower(
+        "
+        long f(unsigned char c, int n) {
+            long total = 0;
+            while (n-- > 0) {
+                if (n == 3) break;
+                total += c;
+            }
+            return total;
+        }
+        ",
+    );
+    let body = &function(&program, "f").body;
+
+    let jumps = body
+        .iter()
+        .filter(|instr| matches!(instr, Instr::Jump(_)))
+        .count();
+    assert!(jumps >= 3);
+    assert!(body.iter().any(|instr| matches!(
+        instr,
+        Instr::Convert {
+            op: ConvertOp::ZExt,
+            from: IrType::I8,
+            to: IrType::I64,
+            ..
+        }
+    )));
+    assert!(matches!(body.last(), Some(Instr::Return(_))));
+}
+
+#[test]
+fn test_static_data() {
+    let program = lower(
+        "
+        struct Point { char tag; int x; };
+        struct Point origin = { 'o', 7 };
+        int values[4] = { [2] = 5 };
+        int *third = &values[2];
+        static char message[] = \"hi\";
+        int tentative;
+        int tentative;
+        ",
+    );
+
+    let global = |name: &str| {
+        program
+            .globals
+            .iter()
+            .find(|global| global.name == name)
+            .unwrap()
+    };
+
+    assert_eq!(
+        global("origin").init,
+        vec![
+            GlobalInit::Int(IrType::I8, 'o' as i64),
+            GlobalInit::Zero(3),
+            GlobalInit::Int(IrType::I32, 7)
+        ]
+    );
+    assert_eq!(
+        global("values").init,
+        vec![
+            GlobalInit::Zero(8),
+            GlobalInit::Int(IrType::I32, 5),
+            GlobalInit::Zero(4)
+        ]
+    );
+    assert_eq!(
+        global("third").init,
+        vec![GlobalInit::Address("values".to_string(), 8)]
+    );
+    assert_eq!(
+        global("message").init,
+        vec![GlobalInit::Bytes(b"hi\0".to_vec())]
+    );
+    assert!(!global("message").is_global);
+    assert_eq!(
+        program
+            .globals
+            .iter()
+            .filter(|global| global.name == "tentative")
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn test_text_round_trip() {
+    let program = lower(
+        "
+        struct Pair { int a; double b; };
+        static const char *greeting = \"say \\\"hi\\\"\\n\";
+        float ratio = 1 / 3;
+        double huge = -7;
+        struct Pair swap(struct Pair p, volatile int *flag) {
+            struct Pair q = { (int)p.b, p.a };
+            *flag = -1;
+            return q;
+        }
+        double first(int count, ...) {
+            va_list ap;
+            va_start(ap, count);
+            return va_arg(ap, double);
+        }
+        int main(void) {
+            int flag;
+            struct Pair p = { 1, 5 / (double)2 };
+            unsigned long shift = 1ul << 63;
+            swap(p, &flag);
+            return shift > 0 ? flag : 0;
+        }
+        ",
+    );
+
+    let text = program.to_string();
+    assert!(text.contains("(i64 sret 16 align 8 { 0: i32, 8: f64 } %0, i64 byval 16 align 8"));
+    assert!(text.contains("function f64 @first(i32 %0, ...) {"));
+    assert!(text.contains("= call f64 @__builtin_va_arg(i64 %"));
+    assert_eq!(ir::parser::parse(&text), Ok(program));
+}
+
+#[test]
+fn test_parse_handwritten_ir() {
+    let source = "
+        global @count internal align 4 { i32 -5 }
+
+        ; Counts down to zero.
+        function i32 @main() {
+          %0 = load i32 @count
+        L0:
+          %1 = cmp sgt i32 %0, 0
+          br i32 %1, L1, L2
+        L1:
+          %2 = sub i32 %0, 1
+          store i32 %2, @count
+          call void @tick(i64 @count, f64 -0.5)
+          jmp L0
+        L2:
+          ret i32 %0
+        }
+    ";
+
+    let program = ir::parser::parse(source).unwrap();
+    assert_eq!(
+        program.globals[0].init,
+        vec![GlobalInit::Int(IrType::I32, -5)]
+    );
+    assert!(!program.globals[0].is_global);
+
+    let main = function(&program, "main");
+    assert_eq!(main.body.len(), 11);
+    assert_eq!(ir::parser::parse(&program.to_string()), Ok(program.clone()));
+
+    assert!(matches!(
+        ir::parser::parse("function i32 @f() { %0 = frobnicate i32 1 }"),
+        Err(ir::parser::ParseError::UnexpectedToken(_, 1))
+    ));
+}
+
+#[test]
+fn test_non_finite_floats_round_trip() {
+    let source = "
+        global @limits internal align 8 { f64 -NaN, f64 inf }
+
+        function void @main() {
+          call void @show(f64 0xfff8000000000000, f64 -inf, f64 0x7ff0000000000001)
+          ret
+        }
+    ";
+    let bits = |program: &ir::Program| -> Vec<u64> {
+        let mut bits: Vec<u64> = program.globals[0]
+            .init
+            .iter()
+            .map(|init| match init {
+                GlobalInit::Float(_, value) => value.to_bits(),
+                init => panic!("unexpected {:?}", init),
+            })
+            .collect();
+        match &program.functions[0].body[0] {
+            Instr::Call { arguments, .. } => {
+                bits.extend(arguments.iter().map(|(_, value)| match value {
+                    ir::Value::Float(value) => value.to_bits(),
+                    value => panic!("unexpected {:?}", value),
+                }))
+            }
+            instr => panic!("unexpected {:?}", instr),
+        }
+        bits
+    };
+
+    let program = ir::parser::parse(source).unwrap();
+    let text = program.to_string();
+    assert!(
+        text.contains("f64 0xfff8000000000000, f64 0xfff0000000000000, f64 0x7ff0000000000001")
+    );
+    assert_eq!(bits(&ir::parser::parse(&text).unwrap()), bits(&program));
+    assert_eq!(bits(&program)[0], (-f64::NAN).to_bits());
+}
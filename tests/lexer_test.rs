@@ -1,4 +1,4 @@
-use compiler::lexer;
+use compiler::lexer::{self, LexerError, token::TokenType};
 
 #[test]
 fn test_tokenize_empty_source() {
@@ -6,3 +6,131 @@ fn test_tokenize_empty_source() {
     let tokens = lexer::tokenize(source, true).unwrap();
     assert_eq!(tokens.len(), 0);
 }
+
+#[test]
+fn test_tokenize_numbers() {
+    let source = "1.5f .5 1e-3 2.E+4L 0x1e+1 s.x 7".to_string();
+    let tokens = lexer::tokenize(source, false).unwrap();
+    let lexemes: Vec<&str> = tokens.iter().map(|token| token.lexeme.as_str()).collect();
+    assert_eq!(
+        lexemes,
+        [
+            "1.5f", ".5", "1e-3", "2.E+4L", "0x1e", "+", "1", "s", ".", "x", "7"
+        ]
+    );
+}
+
+#[test]
+fn test_tokenize_comments() {
+    let source = "a // b\n/* c\n d */ e /**/f".to_string();
+    let tokens = lexer::tokenize(source, false).unwrap();
+    let lexemes: Vec<(&str, usize)> = tokens
+        .iter()
+        .map(|token| (token.lexeme.as_str(), token.line))
+        .collect();
+    assert_eq!(lexemes, [("a", 1), ("e", 3), ("f", 3)]);
+
+    assert!(matches!(
+        lexer::tokenize("a\n/* b".to_string(), false).unwrap_err()[..],
+        [LexerError::UnterminatedComment(2)]
+    ));
+}
+
+#[test]
+fn test_tokenize_operators() {
+    let source = "+= -= *= /= %= &= |= ^= <<= >>= << >> <= >= == != && || ++ -- -> ... ? : ~ . !"
+        .to_string();
+    let tokens = lexer::tokenize(source, false).unwrap();
+    let types: Vec<TokenType> = tokens.into_iter().map(|token| token.token_type).collect();
+    assert_eq!(
+        types,
+        [
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+            TokenType::PercentEqual,
+            TokenType::AmpersandEqual,
+            TokenType::PipeEqual,
+            TokenType::CaretEqual,
+            TokenType::LessLessEqual,
+            TokenType::GreaterGreaterEqual,
+            TokenType::LessLess,
+            TokenType::GreaterGreater,
+            TokenType::LessEqual,
+            TokenType::GreaterEqual,
+            TokenType::EqualEqual,
+            TokenType::BangEqual,
+            TokenType::AmpersandAmpersand,
+            TokenType::PipePipe,
+            TokenType::PlusPlus,
+            TokenType::MinusMinus,
+            TokenType::Arrow,
+            TokenType::Ellipsis,
+            TokenType::Question,
+            TokenType::Colon,
+            TokenType::Tilde,
+            TokenType::Dot,
+            TokenType::Bang,
+        ]
+    );
+}
+
+#[test]
+fn test_tokenize_character_literals() {
+    let source = r"'a' '\n' '\'' '\\' '\0' '\101' '\x41' '\xff' '\377'".to_string();
+    let tokens = lexer::tokenize(source, false).unwrap();
+    assert!(
+        tokens
+            .iter()
+            .all(|token| token.token_type == TokenType::Character)
+    );
+    let values: Vec<u32> = tokens
+        .iter()
+        .map(|token| token.lexeme.chars().next().unwrap() as u32)
+        .collect();
+    assert_eq!(values, [97, 10, 39, 92, 0, 65, 65, 255, 255]);
+
+    for source in ["'ab' x", "'' x", "'a\nx"] {
+        let errors = lexer::tokenize(source.to_string(), false).unwrap_err();
+        assert!(
+            matches!(errors[..], [LexerError::UnterminatedCharacter(1)]),
+            "{:?}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_tokenize_escapes() {
+    let source = r#""\101\x42\12" "\a\b\f\n\r\t\v\?\"\'\\" "\0777\x7e" "é""#.to_string();
+    let tokens = lexer::tokenize(source, false).unwrap();
+    let bytes: Vec<Vec<u8>> = tokens
+        .iter()
+        .map(|token| token.lexeme.chars().map(|c| c as u8).collect())
+        .collect();
+    assert_eq!(
+        bytes,
+        [
+            b"AB\n".to_vec(),
+            b"\x07\x08\x0c\n\r\t\x0b?\"'\\".to_vec(),
+            b"\x3f7~".to_vec(),
+            "é".as_bytes().to_vec(),
+        ]
+    );
+
+    for (source, sequence) in [
+        (r#""\q""#, r"\q"),
+        (r"'\x41' '\x'", r"\x"),
+        (r#""\x100""#, r"\x100"),
+        (r"'\400'", r"\400"),
+    ] {
+        let errors = lexer::tokenize(source.to_string(), false).unwrap_err();
+        assert!(
+            matches!(&errors[..], [LexerError::InvalidEscape(found, 1)] if found == sequence),
+            "{:?}: {:?}",
+            source,
+            errors
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use compiler::ir::cfg::Cfg;
+use compiler::ir::dominators::DominatorTree;
+use compiler::ir::loops::{self, find_loops};
+use compiler::ir::{self, BinaryOp, Instr, licm};
+
+mod common;
+use common::optimize;
+
+/// Hoists the invariant code out of the loops of every function and
+/// returns the first one.
+fn hoist(source: &str) -> ir::Function {
+    optimize(source, licm::run).0
+}
+
+/// How deeply nested in loops each matching instruction is.
+fn depths(function: &ir::Function, predicate: impl Fn(&Instr) -> bool) -> Vec<usize> {
+    let cfg = Cfg::build(function);
+    let loops = find_loops(&cfg, &DominatorTree::build(&cfg));
+    let depths = loops::loop_depths(&cfg, &loops);
+    let mut found = vec![];
+    for (block, depth) in cfg.blocks.iter().zip(depths) {
+        found.extend(
+            block
+                .instrs
+                .iter()
+                .filter(|instr| predicate(instr))
+                .map(|_| depth),
+        );
+    }
+    found
+}
+
+fn binary(op: BinaryOp) -> impl Fn(&Instr) -> bool {
+    move |instr| matches!(instr, Instr::Binary { op: found, .. } if *found == op)
+}
+
+#[test]
+fn test_hoists_invariant_code() {
+    let function = hoist(
+        "
+        int f(int n, int m) {
+            int s = 0;
+            for (int i = 0; i < n; i++) {
+                for (int j = 0; j < n; j++) {
+                    s += (n * m) ^ i;
+                }
+            }
+            return s;
+        }
+        int main(void) { return f(6, 7); }
+    ",
+    );
+    // `n * m` leaves both loops; `^ i` only the inner one, so it still
+    // runs in the outer one.
+    assert_eq!(depths(&function, binary(BinaryOp::Mul)), [0]);
+    assert_eq!(depths(&function, binary(BinaryOp::Xor)), [1]);
+}
+
+#[test]
+fn test_keeps_code_that_may_trap() {
+    // The loop may not run at all, so neither the division nor the load
+    // can be done before it.
+    let function = hoist(
+        "
+        int f(int n, int d, int *p) {
+            int s = 0;
+            for (int i = 0; i < n; i++) s += 100 / d + *p + n / 4;
+            return s;
+        }
+        int main(void) { return f(0, 0, 0); }
+    ",
+    );
+    let mut divisions = depths(&function, binary(BinaryOp::SDiv));
+    divisions.sort();
+    assert_eq!(divisions, [0, 1]);
+    let loads = depths(&function, |instr| matches!(instr, Instr::Load { .. }));
+    assert_eq!(loads, [1]);
+}
@@ -0,0 +1,279 @@
+use compiler::ast::{
+    DataType, Designator, Expr, ExternalDeclaration, Initializer, Instruction, Literal,
+    StorageClass,
+};
+use compiler::{lexer, parser};
+
+fn parse(source: &str) -> compiler::ast::Program {
+    let tokens = lexer::tokenize(source.to_string(), false).unwrap();
+    parser::parse(tokens).unwrap()
+}
+
+#[test]
+fn test_parse_declaration_specifiers() {
+    let program = parse("static const volatile int x = 1; extern int *restrict p;");
+
+    let ExternalDeclaration::Declaration(x) = &program.declarations[0] else {
+        panic!("expected a declaration");
+    };
+    assert_eq!(x.storage_class, Some(StorageClass::Static));
+    assert!(x.data_type.qualifiers.is_const && x.data_type.qualifiers.is_volatile);
+
+    let ExternalDeclaration::Declaration(p) = &program.declarations[1] else {
+        panic!("expected a declaration");
+    };
+    assert_eq!(p.storage_class, Some(StorageClass::Extern));
+    assert!(p.data_type.qualifiers.is_restrict);
+    assert_eq!(
+        p.data_type.data_type.pointee().map(|t| &t.data_type),
+        Some(&DataType::Int)
+    );
+}
+
+#[test]
+fn test_parse_pointer_to_const_versus_const_pointer() {
+    let program = parse("int main(void) { const int *a; int *const b = 0; return 0; }");
+
+    let ExternalDeclaration::Function(main) = &program.declarations[0] else {
+        panic!("expected a function");
+    };
+    let Instruction::Declaration(a) = &main.instructions[0] else {
+        panic!("expected a declaration");
+    };
+    let Instruction::Declaration(b) = &main.instructions[1] else {
+        panic!("expected a declaration");
+    };
+
+    assert!(!a.data_type.qualifiers.is_const);
+    assert!(a.data_type.data_type.pointee().unwrap().qualifiers.is_const);
+    assert!(b.data_type.qualifiers.is_const);
+    assert!(!b.data_type.data_type.pointee().unwrap().qualifiers.is_const);
+}
+
+#[test]
+fn test_parse_rejects_multiple_storage_classes() {
+    let tokens = lexer::tokenize("static extern int x;".to_string(), false).unwrap();
+    assert!(matches!(
+        parser::parse(tokens),
+        Err(parser::ParseError::InvalidDeclaration(_, 1))
+    ));
+}
+
+#[test]
+fn test_parse_cast_versus_parenthesized_expression() {
+    let program = parse(
+        "typedef int T;
+         int main(void) { int a = 1; int b = (T)a; { int T = 2; b = (T) - a; } return b; }",
+    );
+
+    let ExternalDeclaration::Function(main) = &program.declarations[0] else {
+        panic!("expected a function");
+    };
+    let Instruction::Declaration(b) = &main.instructions[1] else {
+        panic!("expected a declaration");
+    };
+    assert!(matches!(
+        b.initializer,
+        Some(Initializer::Expr(Expr::Cast(_)))
+    ));
+
+    let Instruction::Block(block) = &main.instructions[2] else {
+        panic!("expected a block");
+    };
+    let Instruction::Expression(Expr::Assignment(assignment)) = &block.instructions[1] else {
+        panic!("expected an assignment");
+    };
+    assert!(matches!(*assignment.value, Expr::Binary(_)));
+}
+
+#[test]
+fn test_parse_sizeof_forms_and_conditional() {
+    let program = parse("int x; int y = sizeof(int *) + sizeof x ? 1 : 2, z = (x, y);");
+
+    let ExternalDeclaration::Declaration(y) = &program.declarations[1] else {
+        panic!("expected a declaration");
+    };
+    let Some(Initializer::Expr(Expr::Conditional(conditional))) = &y.initializer else {
+        panic!("expected a conditional");
+    };
+    let Expr::Binary(sum) = conditional.condition.as_ref() else {
+        panic!("expected a sum");
+    };
+    assert!(matches!(*sum.left, Expr::SizeofType(_)));
+    assert!(matches!(*sum.right, Expr::SizeofExpr(_)));
+
+    let ExternalDeclaration::Declaration(z) = &program.declarations[2] else {
+        panic!("expected a declaration");
+    };
+    assert!(matches!(
+        z.initializer,
+        Some(Initializer::Expr(Expr::Comma(_)))
+    ));
+}
+
+#[test]
+fn test_parse_designated_initializer() {
+    let program = parse("struct P { int x; int y[2]; }; struct P p = { .y[1] = 2, 3, [0] = 1 };");
+
+    let ExternalDeclaration::Declaration(p) = &program.declarations[0] else {
+        panic!("expected a declaration");
+    };
+    assert!(matches!(p.data_type.data_type, DataType::Struct(_)));
+
+    let Some(Initializer::List(items)) = &p.initializer else {
+        panic!("expected an initializer list");
+    };
+    assert_eq!(items.len(), 3);
+    assert!(matches!(
+        items[0].designators.as_slice(),
+        [Designator::Member(y), Designator::Index(1)] if y == "y"
+    ));
+    assert!(items[1].designators.is_empty());
+    assert!(matches!(
+        items[2].designators.as_slice(),
+        [Designator::Index(0)]
+    ));
+}
+
+#[test]
+fn test_parse_switch_and_labels() {
+    let program = parse(
+        "typedef int T;
+        int main(void) {
+            switch (1) { case 1 + 1: default: break; }
+            T: goto T;
+        }",
+    );
+
+    let ExternalDeclaration::Function(main) = &program.declarations[0] else {
+        panic!("expected a function");
+    };
+    let Instruction::Switch(switch) = &main.instructions[0] else {
+        panic!("expected a switch");
+    };
+    let Instruction::Block(body) = switch.body.as_ref() else {
+        panic!("expected a block");
+    };
+    let Instruction::Case(case) = &body.instructions[0] else {
+        panic!("expected a case label");
+    };
+    assert!(matches!(case.body.as_ref(), Instruction::Default(_)));
+
+    let Instruction::Labeled(labeled) = &main.instructions[1] else {
+        panic!("expected a labeled statement");
+    };
+    assert_eq!(labeled.label, "T");
+    assert!(matches!(labeled.body.as_ref(), Instruction::Goto(label) if label == "T"));
+}
+
+#[test]
+fn test_parse_integer_type_specifiers_in_any_order() {
+    let program = parse(
+        "long unsigned int long a; short signed b; unsigned c; signed char d; char e;
+        int long f; _Bool g; long long int h;",
+    );
+
+    let types: Vec<DataType> = program
+        .declarations
+        .iter()
+        .map(|declaration| match declaration {
+            ExternalDeclaration::Declaration(declaration) => {
+                declaration.data_type.data_type.clone()
+            }
+            ExternalDeclaration::Function(_) => panic!("expected a declaration"),
+        })
+        .collect();
+    assert_eq!(
+        types,
+        vec![
+            DataType::ULongLong,
+            DataType::Short,
+            DataType::UInt,
+            DataType::SChar,
+            DataType::Char,
+            DataType::Long,
+            DataType::Bool,
+            DataType::LongLong,
+        ]
+    );
+
+    for source in [
+        "signed unsigned x;",
+        "long short x;",
+        "long long long x;",
+        "unsigned float x;",
+    ] {
+        let tokens = lexer::tokenize(source.to_string(), false).unwrap();
+        assert!(matches!(
+            parser::parse(tokens),
+            Err(parser::ParseError::InvalidDeclaration(_, _))
+        ));
+    }
+}
+
+#[test]
+fn test_parse_integer_literal_types() {
+    let literal = |source: &str| {
+        let program = parse(&format!("int x = {};", source));
+        let ExternalDeclaration::Declaration(x) = &program.declarations[0] else {
+            panic!("expected a declaration");
+        };
+        match &x.initializer {
+            Some(Initializer::Expr(Expr::Literal(Literal::Integer(literal)))) => {
+                (literal.value, literal.data_type.clone())
+            }
+            _ => panic!("expected an integer literal"),
+        }
+    };
+
+    assert_eq!(literal("2147483647"), (2147483647, DataType::Int));
+    assert_eq!(literal("2147483648"), (2147483648, DataType::Long));
+    assert_eq!(literal("0x80000000"), (0x80000000, DataType::UInt));
+    assert_eq!(literal("017u"), (15, DataType::UInt));
+    assert_eq!(literal("1LU"), (1, DataType::ULong));
+    assert_eq!(literal("0xffffffffffffffff"), (u64::MAX, DataType::ULong));
+    assert_eq!(literal("0"), (0, DataType::Int));
+
+    for source in [
+        "int x = 18446744073709551616;",
+        "int x = 1lul;",
+        "int x = 09;",
+    ] {
+        let tokens = lexer::tokenize(source.to_string(), false).unwrap();
+        assert!(matches!(
+            parser::parse(tokens),
+            Err(parser::ParseError::InvalidLiteral(_, _))
+        ));
+    }
+}
+
+#[test]
+fn test_parse_float_literal_types() {
+    let literal = |source: &str| {
+        let program = parse(&format!("double x = {};", source));
+        let ExternalDeclaration::Declaration(x) = &program.declarations[0] else {
+            panic!("expected a declaration");
+        };
+        match &x.initializer {
+            Some(Initializer::Expr(Expr::Literal(Literal::Float(literal)))) => {
+                (literal.value, literal.data_type.clone())
+            }
+            _ => panic!("expected a floating literal"),
+        }
+    };
+
+    assert_eq!(literal("0.5"), (0.5, DataType::Double));
+    assert_eq!(literal(".25e1"), (2.5, DataType::Double));
+    assert_eq!(literal("3."), (3.0, DataType::Double));
+    assert_eq!(literal("1e-3L"), (1e-3, DataType::Double));
+    assert_eq!(literal("0.1f"), (0.1f32 as f64, DataType::Float));
+    assert_eq!(literal("2E+2F"), (200.0, DataType::Float));
+
+    for source in ["double x = 1.5q;", "double x = 1e;", "double x = 1.2.3;"] {
+        let tokens = lexer::tokenize(source.to_string(), false).unwrap();
+        assert!(matches!(
+            parser::parse(tokens),
+            Err(parser::ParseError::InvalidLiteral(_, _))
+        ));
+    }
+}
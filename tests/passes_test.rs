@@ -0,0 +1,154 @@
+use compiler::ir::cfg::Cfg;
+use compiler::ir::interpreter::run;
+use compiler::ir::passes::{
+    Analyses, Analysis, OptLevel, PassError, PassManager, Transform, lookup,
+};
+use compiler::ir::{Function, Instr};
+
+mod common;
+use common::lower;
+
+const SOURCE: &str = "
+    int printf();
+    int sum(int n) {
+        int total = 0;
+        for (int i = 0; i < n; i++) {
+            total += i * 2 + (3 - 3);
+        }
+        return total;
+    }
+    int main(void) {
+        printf(\"%d\\n\", sum(10));
+        return sum(4);
+    }
+";
+
+#[test]
+fn test_pipelines() {
+    assert!(OptLevel::O0.pipeline().is_empty());
+    let program = lower(SOURCE);
+    let mut expected = vec![];
+    let status = run(&program, &mut expected).unwrap();
+
+    for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::Os] {
+        assert!(level.pipeline().iter().all(|name| lookup(name).is_some()));
+        let mut optimized = program.clone();
+        let mut dump = vec![];
+        let changed = PassManager::for_level(level).run(&mut optimized, &mut dump);
+        assert_eq!(changed, level != OptLevel::O0);
+        assert!(dump.is_empty());
+
+        let mut output = vec![];
+        assert_eq!(run(&optimized, &mut output), Ok(status));
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn test_custom_passes() {
+    assert_eq!(
+        PassManager::new(&["ssa", "unroll-everything"]).err(),
+        Some(PassError::UnknownPass("unroll-everything".to_string()))
+    );
+
+    let mut program = lower(SOURCE);
+    let mut manager = PassManager::new(&["ssa", "fold", "dce", "fold"]).unwrap();
+    manager.print_after("fold").unwrap();
+    assert!(manager.print_after("licm-everything").is_err());
+    let mut dump = vec![];
+    manager.run(&mut program, &mut dump);
+
+    // Each function is printed after both runs of `fold`.
+    let dump = String::from_utf8(dump).unwrap();
+    assert_eq!(dump.matches("; *** IR after fold on @sum ***").count(), 2);
+    assert_eq!(dump.matches("; *** IR after fold on @main ***").count(), 2);
+    assert!(!dump.contains("after dce"));
+    let sum = &program.functions[0];
+    assert!(
+        sum.body
+            .iter()
+            .any(|instr| matches!(instr, Instr::Phi { .. }))
+    );
+
+    // Timings are reported once per pass, however often it ran.
+    let report = manager.time_report();
+    for name in ["ssa", "fold", "dce", "total"] {
+        assert_eq!(
+            report
+                .lines()
+                .filter(|line| line.split_whitespace().next() == Some(name))
+                .count(),
+            1
+        );
+    }
+}
+
+#[test]
+fn test_analysis_invalidation() {
+    let mut program = lower(SOURCE);
+    let function = &mut program.functions[0];
+    let mut analyses = Analyses::default();
+    assert_eq!(analyses.loops(function).len(), 1);
+    for analysis in [Analysis::Cfg, Analysis::Dominators, Analysis::Loops] {
+        assert!(analyses.is_cached(analysis));
+    }
+
+    // Folding keeps the blocks, so only the graph with its instructions
+    // has to be rebuilt.
+    let fold = lookup("fold").unwrap();
+    let Transform::Function(run) = fold.run else {
+        panic!("fold runs on functions");
+    };
+    assert!(run(function, &mut analyses));
+    analyses.invalidate(fold.preserves);
+    assert!(!analyses.is_cached(Analysis::Cfg));
+    assert!(analyses.is_cached(Analysis::Dominators));
+    assert!(analyses.is_cached(Analysis::Loops));
+    let blocks = analyses.cfg(function).blocks.len();
+    assert_eq!(analyses.dominators(function).idom.len(), blocks);
+
+    let dce = lookup("dce").unwrap();
+    let Transform::Function(run) = dce.run else {
+        panic!("dce runs on functions");
+    };
+    assert!(run(function, &mut analyses));
+    analyses.invalidate(dce.preserves);
+    for analysis in [Analysis::Cfg, Analysis::Dominators, Analysis::Loops] {
+        assert!(!analyses.is_cached(analysis));
+    }
+}
+
+/// Runs the named function pass as the pass manager does.
+fn run_pass(name: &str, function: &mut Function, analyses: &mut Analyses) -> bool {
+    let pass = lookup(name).unwrap();
+    let Transform::Function(run) = pass.run else {
+        panic!("{} runs on functions", name);
+    };
+    let changed = run(function, analyses);
+    if changed {
+        analyses.invalidate(pass.preserves);
+    }
+    changed
+}
+
+#[test]
+fn test_passes_share_analyses() {
+    let mut program = lower(SOURCE);
+    let function = &mut program.functions[0];
+    let mut analyses = Analyses::default();
+    assert!(run_pass("ssa", function, &mut analyses));
+
+    // A pass that changes nothing leaves what it asked for to the next.
+    while ["sccp", "gvn", "licm"]
+        .iter()
+        .any(|name| run_pass(name, function, &mut analyses))
+    {}
+    for analysis in [Analysis::Cfg, Analysis::Dominators, Analysis::Loops] {
+        assert!(analyses.is_cached(analysis));
+    }
+    assert!(!run_pass("sccp", function, &mut analyses));
+    assert!(!run_pass("loop-unroll", function, &mut analyses));
+    // What is cached still describes the function.
+    assert_eq!(analyses.cfg(function), &Cfg::build(function));
+    assert_eq!(analyses.loops(function).len(), 1);
+}
@@ -0,0 +1,220 @@
+use compiler::code_generator::machine::{
+    ArithmeticOp, Base, ConditionCode, Instruction, MachineFunction, Memory, Operand,
+    PhysicalRegister, Register, ShiftOp, Size,
+};
+use compiler::code_generator::peephole::optimize;
+
+use PhysicalRegister::*;
+
+fn optimized(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut function = MachineFunction {
+        name: "f".to_string(),
+        is_global: true,
+        instructions,
+        slots: vec![],
+        constants: vec![],
+        next_virtual: 0,
+        vector_registers: Default::default(),
+        outgoing: 0,
+    };
+    optimize(&mut function);
+    function.instructions
+}
+
+fn register(register: PhysicalRegister) -> Operand {
+    Operand::register(register, Size::Long)
+}
+
+fn memory(base: PhysicalRegister, displacement: i64) -> Operand {
+    Operand::Memory(
+        Memory {
+            base: Base::Register(Register::Physical(base)),
+            displacement,
+        },
+        Size::Long,
+    )
+}
+
+fn mov(src: Operand, dst: Operand) -> Instruction {
+    Instruction::Mov { src, dst }
+}
+
+fn ret() -> Instruction {
+    Instruction::Ret(vec![Rax])
+}
+
+#[test]
+fn test_jumps() {
+    let instructions = vec![
+        Instruction::Cmp {
+            left: register(Rdi),
+            right: Operand::Immediate(0),
+        },
+        // Over a jump, to a jump.
+        Instruction::Jcc {
+            condition: ConditionCode::L,
+            target: 0,
+        },
+        Instruction::Jmp(1),
+        Instruction::Label(0),
+        Instruction::Jmp(2),
+        mov(Operand::Immediate(5), register(Rax)),
+        Instruction::Label(1),
+        mov(Operand::Immediate(1), register(Rax)),
+        Instruction::Jmp(3),
+        Instruction::Label(2),
+        mov(Operand::Immediate(2), register(Rax)),
+        Instruction::Label(3),
+        ret(),
+    ];
+    assert_eq!(
+        optimized(instructions),
+        [
+            Instruction::Cmp {
+                left: register(Rdi),
+                right: Operand::Immediate(0),
+            },
+            Instruction::Jcc {
+                condition: ConditionCode::L,
+                target: 2,
+            },
+            mov(Operand::Immediate(1), register(Rax)),
+            Instruction::Jmp(3),
+            Instruction::Label(2),
+            mov(Operand::Immediate(2), register(Rax)),
+            Instruction::Label(3),
+            ret(),
+        ]
+    );
+}
+
+#[test]
+fn test_loads_and_stores() {
+    let instructions = vec![
+        mov(register(Rax), memory(Rbp, -4)),
+        mov(memory(Rbp, -4), register(Rax)),
+        mov(memory(Rbp, -4), register(Rcx)),
+        mov(memory(Rbp, -8), register(Rdx)),
+        mov(register(Rdx), memory(Rbp, -8)),
+        // Memory behind a pointer is left alone.
+        mov(register(Rcx), memory(Rdi, 0)),
+        mov(memory(Rdi, 0), register(Rcx)),
+        ret(),
+    ];
+    assert_eq!(
+        optimized(instructions),
+        [
+            mov(register(Rax), memory(Rbp, -4)),
+            mov(register(Rax), register(Rcx)),
+            mov(memory(Rbp, -8), register(Rdx)),
+            mov(register(Rcx), memory(Rdi, 0)),
+            mov(memory(Rdi, 0), register(Rcx)),
+            ret(),
+        ]
+    );
+}
+
+#[test]
+fn test_compare_and_branch() {
+    let compare = |live: bool| {
+        let mut instructions = vec![
+            Instruction::Cmp {
+                left: register(Rdi),
+                right: register(Rsi),
+            },
+            Instruction::SetCC {
+                condition: ConditionCode::B,
+                dst: Operand::register(Rcx, Size::Byte),
+            },
+            Instruction::MovZx {
+                src: Operand::register(Rcx, Size::Byte),
+                dst: register(Rcx),
+            },
+            Instruction::Test {
+                left: register(Rcx),
+                right: register(Rcx),
+            },
+            Instruction::Jcc {
+                condition: ConditionCode::E,
+                target: 0,
+            },
+            mov(register(Rdi), register(Rax)),
+            ret(),
+            Instruction::Label(0),
+        ];
+        // Whether the result of the comparison is used after the branch.
+        instructions.push(mov(register(if live { Rcx } else { Rsi }), register(Rax)));
+        instructions.push(ret());
+        optimized(instructions)
+    };
+    assert_eq!(
+        compare(false)[..2],
+        [
+            Instruction::Cmp {
+                left: register(Rdi),
+                right: register(Rsi),
+            },
+            Instruction::Jcc {
+                condition: ConditionCode::Ae,
+                target: 0,
+            },
+        ]
+    );
+    assert!(matches!(compare(true)[1], Instruction::SetCC { .. }));
+}
+
+#[test]
+fn test_cheaper_instructions() {
+    let instructions = vec![
+        mov(Operand::Immediate(0), Operand::register(Rdx, Size::Quad)),
+        Instruction::Arithmetic {
+            op: ArithmeticOp::Imul,
+            src: Operand::Immediate(8),
+            dst: register(Rdi),
+        },
+        Instruction::Arithmetic {
+            op: ArithmeticOp::Imul,
+            src: Operand::Immediate(6),
+            dst: register(Rdi),
+        },
+        Instruction::Cmp {
+            left: register(Rdi),
+            right: Operand::Immediate(0),
+        },
+        // The flags are still to be read.
+        mov(Operand::Immediate(0), register(Rax)),
+        Instruction::Jcc {
+            condition: ConditionCode::G,
+            target: 0,
+        },
+        ret(),
+        Instruction::Label(0),
+        mov(register(Rdx), register(Rax)),
+        ret(),
+    ];
+    assert_eq!(
+        optimized(instructions)[..5],
+        [
+            Instruction::Arithmetic {
+                op: ArithmeticOp::Xor,
+                src: register(Rdx),
+                dst: register(Rdx),
+            },
+            Instruction::Shift {
+                op: ShiftOp::Shl,
+                count: Operand::Immediate(3),
+                dst: register(Rdi),
+            },
+            Instruction::Arithmetic {
+                op: ArithmeticOp::Imul,
+                src: Operand::Immediate(6),
+                dst: register(Rdi),
+            },
+            Instruction::Cmp {
+                left: register(Rdi),
+                right: Operand::Immediate(0),
+            },
+            mov(Operand::Immediate(0), register(Rax)),
+        ]
+    );
+}
@@ -0,0 +1,107 @@
+use compiler::ir::cfg::Cfg;
+use compiler::ir::dominators::DominatorTree;
+use compiler::ir::loops::{self, find_loops};
+use compiler::ir::passes::Analyses;
+use compiler::ir::{self, BinaryOp, Instr, IrType, Value, dce, gvn, reduce};
+
+mod common;
+use common::optimize;
+
+/// Strength-reduces every function, cleaning up after it, and returns the
+/// first one.
+fn reduce(source: &str) -> ir::Function {
+    let pass = |function: &mut ir::Function, analyses: &mut Analyses| {
+        reduce::run(function, analyses);
+        gvn::run(function, &mut Analyses::default());
+        dce::run(function, &mut Analyses::default());
+    };
+    optimize(source, pass).0
+}
+
+/// The instructions of the function inside loops.
+fn in_loops(function: &ir::Function) -> Vec<Instr> {
+    let cfg = Cfg::build(function);
+    let found = find_loops(&cfg, &DominatorTree::build(&cfg));
+    let depths = loops::loop_depths(&cfg, &found);
+    cfg.blocks
+        .iter()
+        .zip(depths)
+        .filter(|(_, depth)| *depth > 0)
+        .flat_map(|(block, _)| block.instrs.clone())
+        .collect()
+}
+
+#[test]
+fn test_array_indexing_becomes_pointer_increments() {
+    let function = reduce(
+        "
+        int printf();
+        long sum(long *values, int n) {
+            long total = 0;
+            for (int i = 0; i < n; i += 2) total += values[i];
+            return total;
+        }
+        int main(void) {
+            long values[9] = {1, 2, 3, 4, 5, 6, 7, 8, 9};
+            printf(\"%ld\\n\", sum(values, 9));
+            return 0;
+        }
+    ",
+    );
+    let body = in_loops(&function);
+    assert!(!body.iter().any(|instr| matches!(
+        instr,
+        Instr::Binary {
+            op: BinaryOp::Mul,
+            ..
+        }
+    )));
+    // The pointer advances by two elements per trip.
+    assert!(body.iter().any(|instr| matches!(
+        instr,
+        Instr::Binary {
+            op: BinaryOp::Add,
+            ty: IrType::I64,
+            right: Value::Int(16),
+            ..
+        }
+    )));
+}
+
+#[test]
+fn test_multiples_used_directly() {
+    // `i * 3` is used on its own and `j << 2` is added to a variant value,
+    // so both get a variable of their own; `k * k` is not a multiple.
+    let function = reduce(
+        "
+        int f(int n) {
+            int s = 0;
+            for (int i = 10; i > n; i--) s += i * 3;
+            for (int j = 0, k = 0; j < n; j++, k++) s = s + (j << 2) + k * k;
+            return s;
+        }
+        int main(void) { return f(5) + f(-3); }
+    ",
+    );
+    let multiplications: Vec<Instr> = in_loops(&function)
+        .into_iter()
+        .filter(|instr| {
+            matches!(
+                instr,
+                Instr::Binary {
+                    op: BinaryOp::Mul | BinaryOp::Shl,
+                    ..
+                }
+            )
+        })
+        .collect();
+    assert_eq!(multiplications.len(), 1);
+    assert!(matches!(
+        &multiplications[0],
+        Instr::Binary {
+            left: Value::Temp(left),
+            right: Value::Temp(right),
+            ..
+        } if left == right
+    ));
+}
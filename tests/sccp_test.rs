@@ -0,0 +1,156 @@
+use compiler::ir::cfg::Cfg;
+use compiler::ir::interpreter::run;
+use compiler::ir::passes::Analyses;
+use compiler::ir::verify::verify;
+use compiler::ir::{self, Instr, Value, dce, fold, parser, sccp, ssa};
+
+mod common;
+use common::lower;
+
+/// Lowers the first function of the source to SSA form and propagates
+/// constants through it.
+fn propagate(source: &str) -> ir::Function {
+    let mut function = lower(source).functions.remove(0);
+    ssa::construct(&mut function);
+    sccp::run(&mut function, &mut Analyses::default());
+    assert_eq!(verify(&function), Ok(()));
+    function
+}
+
+fn returned(function: &ir::Function) -> Vec<&Value> {
+    function
+        .body
+        .iter()
+        .filter_map(|instr| match instr {
+            Instr::Return(Some((_, value))) => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_constant_through_loop_phis() {
+    // `x` only changes on a path that is never taken, which folding alone
+    // cannot see because the loop header merges `x` with itself.
+    let source = "
+        int f(int n) {
+            int x = 1;
+            for (int i = 0; i < n; i++) {
+                if (x != 1) x = 2;
+                x = x * 1;
+            }
+            return x;
+        }
+    ";
+    let function = propagate(source);
+    assert_eq!(returned(&function), [&Value::Int(1)]);
+    assert!(
+        function
+            .body
+            .iter()
+            .all(|instr| { instr.uses().iter().all(|value| **value != Value::Int(2)) })
+    );
+
+    let mut folded = lower(source).functions.remove(0);
+    ssa::construct(&mut folded);
+    fold::run(&mut folded);
+    assert!(matches!(returned(&folded)[..], [Value::Temp(_)]));
+}
+
+#[test]
+fn test_conditions_constant_after_phis() {
+    let mut function = propagate(
+        "
+        int f(int c) {
+            int a;
+            if (c) a = 3; else a = 1 + 2;
+            if (a == 3) return 10;
+            return 20;
+        }
+        ",
+    );
+    dce::run(&mut function, &mut Analyses::default());
+    assert_eq!(returned(&function), [&Value::Int(10)]);
+    assert_eq!(Cfg::build(&function).blocks.len(), 1);
+}
+
+#[test]
+fn test_removes_unexecuted_blocks() {
+    let mut function = parser::parse(
+        "
+        function i32 @f(i32 %0) {
+        L0:
+          %1 = copy i32 0
+          br i32 %1, L1, L2
+        L1:
+          %2 = add i32 %0, 1
+          jmp L3
+        L2:
+          %3 = sdiv i32 %0, 0
+          jmp L3
+        L3:
+          %4 = phi i32 [%2, L1], [%1, L2]
+          %5 = add i32 %4, %0
+          ret i32 %5
+        }
+        ",
+    )
+    .unwrap()
+    .functions
+    .remove(0);
+    assert!(sccp::run(&mut function, &mut Analyses::default()));
+    assert_eq!(verify(&function), Ok(()));
+
+    let cfg = Cfg::build(&function);
+    let labels: Vec<_> = cfg.blocks.iter().map(|block| block.label.0).collect();
+    assert_eq!(labels, [0, 2, 3]);
+    // The phi has a single input left, the constant 0; the division stays,
+    // as it is never folded.
+    assert!(cfg.blocks[2].instrs.contains(&Instr::Binary {
+        dst: ir::Temp(5),
+        op: ir::BinaryOp::Add,
+        ty: ir::IrType::I32,
+        left: Value::Int(0),
+        right: Value::Temp(ir::Temp(0)),
+    }));
+    assert!(!sccp::run(&mut function, &mut Analyses::default()));
+}
+
+#[test]
+fn test_preserves_behavior() {
+    let source = "
+        int printf();
+        int pick(int flag) {
+            int value = 7;
+            int scale = 1;
+            while (flag > 0) {
+                if (scale == 1) value = value + flag; else value = -1;
+                flag = flag - 1;
+            }
+            return value * scale;
+        }
+        int main(void) {
+            int sum = 0;
+            for (int i = 0; i < 5; i++) {
+                sum += pick(i);
+            }
+            printf(\"%d\\n\", sum);
+            return sum;
+        }
+    ";
+    let program = lower(source);
+    let mut expected = vec![];
+    let status = run(&program, &mut expected).unwrap();
+    assert_eq!(String::from_utf8(expected.clone()).unwrap(), "55\n");
+
+    let mut optimized = program.clone();
+    for function in &mut optimized.functions {
+        ssa::construct(function);
+        sccp::run(function, &mut Analyses::default());
+        dce::run(function, &mut Analyses::default());
+        assert_eq!(verify(function), Ok(()));
+    }
+    let mut output = vec![];
+    assert_eq!(run(&optimized, &mut output), Ok(status));
+    assert_eq!(output, expected);
+}
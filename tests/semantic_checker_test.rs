@@ -0,0 +1,387 @@
+use compiler::ast::{DataType, ExternalDeclaration};
+use compiler::semantic_checker::{SemanticError, initializer};
+use compiler::{lexer, parser, semantic_checker};
+
+fn check(source: &str) -> Result<(), SemanticError> {
+    let tokens = lexer::tokenize(source.to_string(), false).unwrap();
+    let program = parser::parse(tokens).unwrap();
+    semantic_checker::check(&program)
+}
+
+#[test]
+fn test_linkage_across_redeclarations() {
+    let source = "
+        static int hidden;
+        extern int hidden;
+        int shared;
+        int use(void) {
+            extern int hidden;
+            extern int shared;
+            return hidden + shared;
+        }
+        static int helper(void);
+        int helper(void) { return 1; }
+    ";
+    assert!(check(source).is_ok());
+}
+
+#[test]
+fn test_internal_after_external_linkage_conflicts() {
+    assert!(matches!(
+        check("extern int x; static int x;"),
+        Err(SemanticError::LinkageConflict(_))
+    ));
+    assert!(matches!(
+        check("static int x; int x;"),
+        Err(SemanticError::LinkageConflict(_))
+    ));
+}
+
+#[test]
+fn test_block_scope_extern_conflicts_with_shadowed_static() {
+    let source = "
+        static int x;
+        int main(void) {
+            int x = 0;
+            {
+                extern int x;
+                return x;
+            }
+        }
+    ";
+    assert!(matches!(
+        check(source),
+        Err(SemanticError::LinkageConflict(_))
+    ));
+}
+
+#[test]
+fn test_no_linkage_redeclaration() {
+    assert!(matches!(
+        check("int main(void) { int a; static int a; return 0; }"),
+        Err(SemanticError::Redeclaration(_))
+    ));
+    assert!(check("int main(void) { int a = 1; { static int a; } return a; }").is_ok());
+}
+
+#[test]
+fn test_invalid_storage_classes() {
+    assert!(matches!(
+        check("auto int x;"),
+        Err(SemanticError::InvalidStorageClass(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { static int f(void); return 0; }"),
+        Err(SemanticError::InvalidStorageClass(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { extern int x = 1; return x; }"),
+        Err(SemanticError::InvalidStorageClass(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { register int r = 1; int *p = &r; return *p; }"),
+        Err(SemanticError::InvalidStorageClass(_))
+    ));
+}
+
+#[test]
+fn test_writes_through_const_lvalues() {
+    assert!(matches!(
+        check("int main(void) { const int a = 1; a = 2; return a; }"),
+        Err(SemanticError::ReadOnlyAssignment(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { int a = 1; const int *p = &a; *p += 1; return a; }"),
+        Err(SemanticError::ReadOnlyAssignment(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { int a = 1; int *const p = &a; p++; return a; }"),
+        Err(SemanticError::ReadOnlyAssignment(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { const int a = 1; int *p = &a; return *p; }"),
+        Err(SemanticError::ReadOnlyAssignment(_))
+    ));
+    assert!(check("int main(void) { int a = 1; int *const p = &a; *p = 2; return a; }").is_ok());
+}
+
+#[test]
+fn test_restrict_requires_object_pointer() {
+    assert!(matches!(
+        check("restrict int x;"),
+        Err(SemanticError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_static_initializer_must_be_constant() {
+    assert!(matches!(
+        check("int f(void); int main(void) { static int a = f(); return a; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(check("int g; int *p = &g; int main(void) { static int *q = &g; return *q; }").is_ok());
+}
+
+#[test]
+fn test_conditional_result_types() {
+    assert!(
+        check("int main(void) { int a = 1; const int *p = a ? &a : (const int *)0; return *p; }")
+            .is_ok()
+    );
+    assert!(matches!(
+        check("int main(void) { int a = 1; int *p = a ? &a : (const int *)0; return *p; }"),
+        Err(SemanticError::ReadOnlyAssignment(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { int a = 1; return a ? &a : 1; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(
+        check("struct A { int x; }; int f; struct A a, b; struct A c(void) { return f ? a : b; }")
+            .is_ok()
+    );
+    assert!(matches!(
+        check(
+            "struct A { int x; }; struct B { int x; }; int f; struct A a; struct B b; int g(void) { return (f ? a : b).x; }"
+        ),
+        Err(SemanticError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_sizeof_rejects_function_and_incomplete_types() {
+    assert!(
+        check("int main(void) { return sizeof(int) + sizeof(char *) + _Alignof(double); }").is_ok()
+    );
+    assert!(matches!(
+        check("int main(void) { return sizeof main; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { return sizeof(void); }"),
+        Err(SemanticError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_invalid_casts() {
+    assert!(check("int main(void) { int a = 1; return (int)(char *)&a != 0; }").is_ok());
+    assert!(matches!(
+        check("int main(void) { double d = 1; int *p = (int *)d; return 0; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("struct A { int a; }; int main(void) { struct A a; int *p = &a; return 0; }"),
+        Err(SemanticError::TypeError(message)) if message
+            == "Type mismatch: initialization of 'int *' from incompatible type 'struct A *'"
+    ));
+}
+
+#[test]
+fn test_initializer_layout() {
+    let source = "struct P { char c; int v[2]; }; struct P p[] = { 'a', 1, 2, [2].v[1] = 3, 4 };";
+    let tokens = lexer::tokenize(source.to_string(), false).unwrap();
+    let program = parser::parse(tokens).unwrap();
+    let ExternalDeclaration::Declaration(declaration) = &program.declarations[0] else {
+        panic!("expected a declaration");
+    };
+    let initializer = declaration.initializer.as_ref().unwrap();
+
+    let resolved = initializer::resolve(
+        &declaration.data_type,
+        initializer,
+        &program.types,
+        &mut |_| None,
+    )
+    .unwrap();
+
+    // `4` has no subobject left in p[2] and starts p[3].
+    assert!(matches!(
+        resolved.data_type.data_type,
+        DataType::Array(_, Some(4))
+    ));
+    let offsets: Vec<usize> = resolved.elements.iter().map(|e| e.offset).collect();
+    assert_eq!(offsets, vec![0, 4, 8, 32, 36]);
+}
+
+#[test]
+fn test_initializer_errors() {
+    assert!(check("int a[2] = { 1, 2 }; char s[] = \"hi\"; char t[2] = \"hi\";").is_ok());
+    assert!(matches!(
+        check("int a[2] = { 1, 2, 3 };"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("char s[2] = \"abc\";"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("struct P { int x; }; struct P p = { .z = 1 };"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("int x; struct P { int *p; double d; }; struct P p = { &x, &x };"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { int x = 1; static int a[2] = { 1, x }; return a[0]; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_arrays_and_members() {
+    let source = "
+        struct P { int x; const int y; };
+        int a[3];
+        struct P s = { 1, 2 };
+        int *p = &a[1];
+        int *q = &s.x;
+        int main(void) {
+            struct P *ps = &s;
+            a[0] = ps->x + s.y;
+            return 2[a];
+        }
+    ";
+    assert!(check(source).is_ok());
+    assert!(matches!(
+        check("int a[3]; int b[3]; int main(void) { a = b; return 0; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("struct P { const int y; }; struct P s, t; int main(void) { s = t; return 0; }"),
+        Err(SemanticError::ReadOnlyAssignment(_))
+    ));
+    assert!(matches!(
+        check("struct P { int x; }; struct P s; int main(void) { return s.z; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("struct Q; int main(void) { struct Q q; return 0; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_switch_cases() {
+    let source = "
+        int main(void) {
+            int x = 2;
+            switch (x) {
+            case 1:
+            case 1 + 1:
+                x = 3;
+            default:
+                switch (x) { case 1: break; }
+                break;
+            }
+            return x;
+        }
+    ";
+    assert!(check(source).is_ok());
+    assert!(matches!(
+        check("int main(void) { switch (1) { case 2: case 1 + 1: break; } return 0; }"),
+        Err(SemanticError::InvalidStatement(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { case 1: return 0; }"),
+        Err(SemanticError::InvalidStatement(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { int x = 1; switch (1) { case x: break; } return 0; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { switch (1) { default: default: break; } return 0; }"),
+        Err(SemanticError::InvalidStatement(_))
+    ));
+}
+
+#[test]
+fn test_goto_labels() {
+    assert!(check("int main(void) { goto end; { end: ; } return 0; }").is_ok());
+    assert!(matches!(
+        check("int main(void) { goto end; return 0; }"),
+        Err(SemanticError::UndefinedLabel(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { a: ; a: return 0; }"),
+        Err(SemanticError::Redeclaration(_))
+    ));
+    assert!(matches!(
+        check("int f(void) { a: return 0; } int main(void) { goto a; }"),
+        Err(SemanticError::UndefinedLabel(_))
+    ));
+}
+
+#[test]
+fn test_variadic_functions() {
+    let source = "
+        int printf(const char *format, ...);
+        double average(int count, ...) {
+            va_list ap, copy;
+            va_start(ap, count);
+            va_copy(copy, ap);
+            double total = 0;
+            for (int i = 0; i < count; i++)
+                total += va_arg(ap, double);
+            va_end(copy);
+            va_end(ap);
+            return total / count;
+        }
+        int main(void) { printf(\"%f\\n\", average(2, (double)1, (double)2)); return 0; }
+    ";
+    assert!(check(source).is_ok());
+    assert!(matches!(
+        check("int f(int n, ...); int main(void) { return f(); }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("int f(int n) { va_list ap; va_start(ap, n); return 0; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("struct s { int a; }; int f(int n, ...) { va_list ap; va_arg(ap, struct s); }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("int f(int n, ...) { int ap; va_start(ap, n); return 0; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("int f(int n, int m, ...) { va_list ap; va_start(ap, n); return 0; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(matches!(
+        check("int f(int n, ...) { va_list ap; va_start(ap, n + 1); return 0; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+    assert!(check("int f(int n, ...) { va_list ap; va_start(ap); return 0; }").is_ok());
+    assert!(check("int f(int n, ...); int f(int n);").is_err());
+}
+
+#[test]
+fn test_integer_types() {
+    let source = "
+        unsigned long long big = 18446744073709551615u;
+        short s = -1;
+        int main(void) {
+            unsigned char c = 255;
+            long l = c + s;
+            _Bool b = &l;
+            switch (c) { case 255: case -1: break; }
+            return (int)(l + b);
+        }
+    ";
+    assert!(check(source).is_ok());
+    assert!(matches!(
+        check(
+            "int main(void) { unsigned char c = 1; switch (c) { case 1: case 1u: break; } return 0; }"
+        ),
+        Err(SemanticError::InvalidStatement(_))
+    ));
+    assert!(matches!(
+        check("int main(void) { switch (1) { case 0: case 4294967296 / 0: break; } return 0; }"),
+        Err(SemanticError::TypeError(_))
+    ));
+}
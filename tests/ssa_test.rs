@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use compiler::ir::cfg::Cfg;
+use compiler::ir::verify::{VerifyError, verify};
+use compiler::ir::{self, Instr, Label, Temp, Value, parser, ssa};
+
+mod common;
+use common::{count, lower};
+
+fn parse_function(source: &str) -> ir::Function {
+    parser::parse(source).unwrap().functions.remove(0)
+}
+
+#[test]
+fn test_promotes_locals() {
+    let mut program = lower(
+        "
+        int f(int n) {
+            int total = 0;
+            int *escaped = &n;
+            for (int i = 0; i < n; i++) {
+                if (i % 3 == 0) continue;
+                total += i;
+            }
+            return total + *escaped;
+        }
+        ",
+    );
+    let function = &mut program.functions[0];
+    ssa::construct(function);
+    assert_eq!(verify(function), Ok(()));
+
+    // Only `n`, whose address is taken, keeps its slot.
+    assert_eq!(
+        count(function, |instr| matches!(instr, Instr::Alloca { .. })),
+        1
+    );
+    assert_eq!(
+        count(function, |instr| matches!(instr, Instr::Store { .. })),
+        1
+    );
+    // `total` and `i` merge at the loop header, and `total` again at the
+    // increment.
+    assert_eq!(
+        count(function, |instr| matches!(instr, Instr::Phi { .. })),
+        3
+    );
+}
+
+#[test]
+fn test_swap_problem() {
+    let mut function = parse_function(
+        "
+        function i32 @f(i32 %0, i32 %1, i32 %2) {
+        L0:
+          jmp L1
+        L1:
+          %3 = phi i32 [%0, L0], [%4, L1]
+          %4 = phi i32 [%1, L0], [%3, L1]
+          br i32 %2, L1, L2
+        L2:
+          ret i32 %3
+        }
+        ",
+    );
+    assert_eq!(verify(&function), Ok(()));
+    ssa::destruct(&mut function);
+
+    let cfg = Cfg::build(&function);
+    assert!(
+        cfg.blocks
+            .iter()
+            .all(|block| !matches!(block.instrs[0], Instr::Phi { .. }))
+    );
+
+    // The back edge was split; run its copies and check that the values
+    // were swapped.
+    let latch = cfg
+        .blocks
+        .iter()
+        .find(|block| block.label != Label(0) && block.terminator() == &Instr::Jump(Label(1)))
+        .unwrap();
+    let mut values: HashMap<Temp, i64> = HashMap::from([(Temp(3), 30), (Temp(4), 40)]);
+    for instr in &latch.instrs {
+        if let Instr::Copy {
+            dst,
+            src: Value::Temp(src),
+            ..
+        } = instr
+        {
+            values.insert(*dst, values[src]);
+        }
+    }
+    assert_eq!((values[&Temp(3)], values[&Temp(4)]), (40, 30));
+}
+
+#[test]
+fn test_lost_copy_problem() {
+    // `%2` is live after the loop, so the copy into it for the next
+    // iteration must not happen on the exit edge.
+    let mut function = parse_function(
+        "
+        function i32 @f(i32 %0) {
+        L0:
+          jmp L1
+        L1:
+          %2 = phi i32 [%0, L0], [%3, L1]
+          %3 = add i32 %2, 1
+          %4 = cmp slt i32 %3, 10
+          br i32 %4, L1, L2
+        L2:
+          ret i32 %2
+        }
+        ",
+    );
+    ssa::destruct(&mut function);
+
+    let cfg = Cfg::build(&function);
+    let loop_block = &cfg.blocks[cfg.label_indices()[&Label(1)]];
+    assert!(
+        !loop_block
+            .instrs
+            .iter()
+            .any(|instr| matches!(instr, Instr::Copy { dst: Temp(2), .. }))
+    );
+    assert_eq!(cfg.blocks.len(), 4);
+}
+
+#[test]
+fn test_round_trip_through_ssa() {
+    let mut program = lower(
+        "
+        int fib(int n) {
+            int a = 0, b = 1;
+            while (n-- > 0) {
+                int next = a + b;
+                a = b;
+                b = next;
+            }
+            return a;
+        }
+        ",
+    );
+    let function = &mut program.functions[0];
+    ssa::construct(function);
+    assert_eq!(verify(function), Ok(()));
+    ssa::destruct(function);
+    assert_eq!(
+        count(function, |instr| matches!(instr, Instr::Phi { .. })),
+        0
+    );
+    assert!(count(function, |instr| matches!(instr, Instr::Copy { .. })) > 0);
+}
+
+#[test]
+fn test_verifier_errors() {
+    let not_dominated = parse_function(
+        "
+        function i32 @f(i32 %0) {
+          br i32 %0, L1, L2
+        L1:
+          %1 = add i32 %0, 1
+          jmp L2
+        L2:
+          ret i32 %1
+        }
+        ",
+    );
+    assert!(matches!(
+        verify(&not_dominated),
+        Err(VerifyError::UseNotDominated(_))
+    ));
+
+    let missing_incoming = parse_function(
+        "
+        function i32 @f(i32 %0) {
+          br i32 %0, L1, L2
+        L1:
+          jmp L2
+        L2:
+          %1 = phi i32 [%0, L1]
+          ret i32 %1
+        }
+        ",
+    );
+    assert!(matches!(
+        verify(&missing_incoming),
+        Err(VerifyError::InvalidPhi(_))
+    ));
+
+    let redefined = parse_function(
+        "
+        function i32 @f(i32 %0) {
+          %0 = add i32 %0, 1
+          ret i32 %0
+        }
+        ",
+    );
+    assert!(matches!(
+        verify(&redefined),
+        Err(VerifyError::Redefinition(_))
+    ));
+
+    // The parser rejects such a jump, so the body is built by hand.
+    let mut undefined = parse_function("function void @f() { ret }");
+    undefined.body = vec![Instr::Jump(Label(3))];
+    assert!(matches!(
+        verify(&undefined),
+        Err(VerifyError::UndefinedLabel(_))
+    ));
+}
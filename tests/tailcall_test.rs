@@ -0,0 +1,66 @@
+use compiler::ir::cfg::Cfg;
+use compiler::ir::dominators::DominatorTree;
+use compiler::ir::loops::find_loops;
+use compiler::ir::{self, Instr, tailcall};
+
+mod common;
+use common::{count, optimize};
+
+/// Eliminates tail recursion in every function and returns the first
+/// function and whether it changed.
+fn eliminate(source: &str) -> (ir::Function, bool) {
+    optimize(source, tailcall::run)
+}
+
+fn calls(function: &ir::Function) -> usize {
+    count(function, |instr| matches!(instr, Instr::Call { .. }))
+}
+
+#[test]
+fn test_self_recursion_becomes_a_loop() {
+    let (function, changed) = eliminate(
+        "
+        int printf();
+        unsigned gcd(unsigned a, unsigned b) {
+            if (b == 0) return a;
+            if (a < b) return gcd(b, a);
+            return gcd(b, a % b);
+        }
+        int main(void) {
+            printf(\"%u %u\\n\", gcd(1071, 462), gcd(17, 5));
+            return 0;
+        }
+    ",
+    );
+    assert!(changed);
+    assert_eq!(calls(&function), 0);
+    let cfg = Cfg::build(&function);
+    assert_eq!(find_loops(&cfg, &DominatorTree::build(&cfg)).len(), 1);
+}
+
+#[test]
+fn test_keeps_other_calls() {
+    // Not in tail position.
+    let (function, changed) = eliminate(
+        "
+        int fact(int n) { if (n <= 1) return 1; return n * fact(n - 1); }
+        int main(void) { return fact(5); }
+    ",
+    );
+    assert!(!changed);
+    assert_eq!(calls(&function), 1);
+
+    // The next call would overwrite the slot `p` points to.
+    let (function, changed) = eliminate(
+        "
+        int walk(int n, int *p) {
+            int x = n;
+            if (n == 0) return *p;
+            return walk(n - 1, &x);
+        }
+        int main(void) { int x = 9; return walk(3, &x); }
+    ",
+    );
+    assert!(!changed);
+    assert_eq!(calls(&function), 1);
+}
@@ -0,0 +1,87 @@
+use compiler::ir::cfg::Cfg;
+use compiler::ir::dominators::DominatorTree;
+use compiler::ir::loops::find_loops;
+use compiler::ir::passes::Analyses;
+use compiler::ir::{self, Instr, sccp, unroll};
+
+mod common;
+use common::{count, optimize};
+
+/// Unrolls the loops of every function and returns the first one.
+fn unroll(source: &str) -> ir::Function {
+    optimize(source, unroll::run).0
+}
+
+fn loop_count(function: &ir::Function) -> usize {
+    let cfg = Cfg::build(function);
+    find_loops(&cfg, &DominatorTree::build(&cfg)).len()
+}
+
+fn loads(function: &ir::Function) -> usize {
+    count(function, |instr| matches!(instr, Instr::Load { .. }))
+}
+
+#[test]
+fn test_full_unrolling() {
+    let mut function = unroll(
+        "
+        int f(int *a) {
+            int s = 0;
+            for (int i = 0; i < 4; i++) s += a[i];
+            return s;
+        }
+        int main(void) { int a[4] = {1, 2, 3, 4}; return f(a); }
+    ",
+    );
+    assert_eq!(loop_count(&function), 0);
+    assert_eq!(loads(&function), 4);
+    // The conditions of the copies fold away.
+    sccp::run(&mut function, &mut Analyses::default());
+    assert!(
+        !function
+            .body
+            .iter()
+            .any(|instr| matches!(instr, Instr::Branch { .. }))
+    );
+}
+
+#[test]
+fn test_partial_unrolling() {
+    let function = unroll(
+        "
+        int printf();
+        int f(int *a) {
+            int s = 0;
+            for (int i = 0; i < 64; i++) s = s * 3 + a[i];
+            return s;
+        }
+        int main(void) {
+            int a[64];
+            for (int i = 0; i < 64; i++) a[i] = i;
+            printf(\"%d\\n\", f(a));
+            return 0;
+        }
+    ",
+    );
+    assert_eq!(loop_count(&function), 1);
+    assert_eq!(loads(&function), 4);
+}
+
+#[test]
+fn test_keeps_unknown_trip_counts() {
+    let function = unroll(
+        "
+        int f(int *a, int n) {
+            int s = 0;
+            for (int i = 0; i < n; i++) s += a[i];
+            for (int i = 0; i < 3; i++) {
+                if (a[i] > 1) break;
+                s += a[i];
+            }
+            return s;
+        }
+        int main(void) { int a[3] = {1, 1, 5}; return f(a, 3); }
+    ",
+    );
+    assert_eq!(loop_count(&function), 2);
+}